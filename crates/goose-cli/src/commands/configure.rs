@@ -259,9 +259,7 @@ pub async fn configure_provider_dialog() -> Result<bool, Box<dyn Error>> {
 
     // Select model, defaulting to the provider's recommended model UNLESS there is an env override
     let default_model = std::env::var("GOOSE_MODEL").unwrap_or(provider_meta.default_model.clone());
-    let model: String = cliclack::input("Enter a model from that provider:")
-        .default_input(&default_model)
-        .interact()?;
+    let model = select_model(provider_name, &default_model).await?;
 
     // Update config with new values
     config.set("GOOSE_PROVIDER", Value::String(provider_name.to_string()))?;
@@ -310,6 +308,48 @@ pub async fn configure_provider_dialog() -> Result<bool, Box<dyn Error>> {
     }
 }
 
+/// Pick a model from those the provider lists, or type one in if it can't list them
+async fn select_model(provider_name: &str, default_model: &str) -> Result<String, Box<dyn Error>> {
+    let spin = spinner();
+    spin.start("Looking up the available models...");
+    let models = match create(
+        provider_name,
+        goose::model::ModelConfig::new(default_model.to_string()),
+    ) {
+        Ok(provider) => provider.list_models().await.ok().flatten(),
+        Err(_) => None,
+    }
+    .unwrap_or_default();
+    spin.stop("");
+
+    if !models.is_empty() {
+        let mut select = cliclack::select("Which model should we use?")
+            .filter_mode()
+            .max_rows(10);
+        if models.iter().any(|model| model.id == default_model) {
+            select = select.initial_value(default_model.to_string());
+        }
+        for model in &models {
+            let hint = model
+                .context_limit
+                .map(|limit| format!("{} token context", limit))
+                .unwrap_or_default();
+            select = select.item(model.id.clone(), &model.id, hint);
+        }
+        // An empty value stands for a model that isn't listed
+        let model: String = select
+            .item(String::new(), "Other", "enter a model name")
+            .interact()?;
+        if !model.is_empty() {
+            return Ok(model);
+        }
+    }
+
+    Ok(cliclack::input("Enter a model from that provider:")
+        .default_input(default_model)
+        .interact()?)
+}
+
 /// Configure extensions that can be used with goose
 /// Dialog for toggling which extensions are enabled/disabled
 pub fn toggle_extensions_dialog() -> Result<(), Box<dyn Error>> {
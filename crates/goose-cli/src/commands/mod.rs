@@ -1,5 +1,8 @@
 pub mod agent_version;
 pub mod configure;
 pub mod mcp;
+pub mod models;
 pub mod session;
+pub mod sessions;
+pub mod usage;
 pub mod version;
@@ -0,0 +1,104 @@
+use anyhow::{anyhow, Result};
+use console::style;
+use goose::config::Config;
+use goose::model::ModelConfig;
+use goose::providers::base::ModelInfo;
+use goose::providers::{create, providers};
+use std::fmt::Write;
+
+/// List the models a provider serves, the configured provider by default
+pub async fn list_models(provider: Option<String>) -> Result<()> {
+    let config = Config::global();
+    let configured: Option<String> = config.get("GOOSE_PROVIDER").ok();
+    let name = provider.or(configured.clone()).ok_or_else(|| {
+        anyhow!("No provider configured. Pass --provider or run 'goose configure' first")
+    })?;
+
+    // The model only matters for creating the provider, the configured one if it's the same
+    let metadata = providers().into_iter().find(|p| p.name == name);
+    let model = match config.get::<String>("GOOSE_MODEL") {
+        Ok(model) if configured.as_deref() == Some(name.as_str()) => model,
+        _ => metadata
+            .as_ref()
+            .map(|metadata| metadata.default_model.clone())
+            .unwrap_or_default(),
+    };
+    let provider = create(&name, ModelConfig::new(model))?;
+
+    match provider.list_models().await? {
+        Some(models) => print!("{}", models_table(&models)?),
+        None => {
+            println!("The {} provider can't list its models", name);
+            if let Some(metadata) = metadata.filter(|m| !m.known_models.is_empty()) {
+                println!("Known models: {}", metadata.known_models.join(", "));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn models_table(models: &[ModelInfo]) -> Result<String> {
+    let mut output = String::new();
+    if models.is_empty() {
+        writeln!(output, "No models found")?;
+        return Ok(output);
+    }
+
+    let id_width = models
+        .iter()
+        .map(|model| model.id.len())
+        .max()
+        .unwrap_or(0)
+        .max(5);
+    writeln!(
+        output,
+        "{}",
+        style(format!(
+            "{:<id_width$}  {:>10}  {:>5}",
+            "Model", "Context", "Tools"
+        ))
+        .bold()
+    )?;
+    let mut models: Vec<&ModelInfo> = models.iter().collect();
+    models.sort_by(|a, b| a.id.cmp(&b.id));
+    for model in models {
+        let context = model
+            .context_limit
+            .map_or("-".to_string(), |limit| limit.to_string());
+        let tools = match model.supports_tools {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "-",
+        };
+        writeln!(
+            output,
+            "{:<id_width$}  {:>10}  {:>5}",
+            model.id, context, tools
+        )?;
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_models_table() -> Result<()> {
+        console::set_colors_enabled(false);
+        let models = vec![
+            ModelInfo::new("qwen2.5")
+                .with_context_limit(Some(32_768))
+                .with_supports_tools(Some(true)),
+            ModelInfo::new("gpt-4o"),
+        ];
+        assert_eq!(
+            models_table(&models)?,
+            "Model       Context  Tools\n\
+             gpt-4o            -      -\n\
+             qwen2.5       32768    yes\n"
+        );
+        assert_eq!(models_table(&[])?, "No models found\n");
+        Ok(())
+    }
+}
@@ -5,9 +5,9 @@ use crate::prompt::rustyline::RustylinePrompt;
 use crate::session::{ensure_session_dir, get_most_recent_session, Session};
 use console::style;
 use goose::agents::extension::{Envs, ExtensionError};
-use goose::agents::AgentFactory;
+use goose::agents::{AgentFactory, Budget};
 use goose::config::{Config, ExtensionConfig, ExtensionManager};
-use goose::providers::create;
+use goose::providers::create_with_discovery;
 use std::path::Path;
 
 use mcp_client::transport::Error as McpClientError;
@@ -17,6 +17,7 @@ pub async fn build_session(
     resume: bool,
     extension: Option<String>,
     builtin: Option<String>,
+    budget: Budget,
 ) -> Session<'static> {
     // Load config and get provider/model
     let config = Config::global();
@@ -30,7 +31,9 @@ pub async fn build_session(
         .get("GOOSE_MODEL")
         .expect("No model configured. Run 'goose configure' first");
     let model_config = goose::model::ModelConfig::new(model.clone());
-    let provider = create(&provider_name, model_config).expect("Failed to create provider");
+    let provider = create_with_discovery(&provider_name, model_config)
+        .await
+        .expect("Failed to create provider");
 
     // Create the agent
     let agent_version: Option<String> = config.get("GOOSE_AGENT").ok();
@@ -40,6 +43,9 @@ pub async fn build_session(
     }
     .expect("Failed to create agent");
 
+    // Limits given on the command line take precedence over those in the config
+    agent.set_budget(budget.or(Budget::from_config())).await;
+
     // Setup extensions for the agent
     for extension in ExtensionManager::get_all().expect("should load extensions") {
         if extension.enabled {
@@ -120,7 +126,8 @@ pub async fn build_session(
             let session_file = session_dir.join(format!("{}.jsonl", session_name));
             if session_file.exists() {
                 let prompt = Box::new(RustylinePrompt::new());
-                return Session::new(agent, prompt, session_file);
+                return Session::new(agent, prompt, session_file)
+                    .with_provider(&provider_name, &model);
             } else {
                 eprintln!("Session '{}' not found, starting new session", session_name);
             }
@@ -128,7 +135,8 @@ pub async fn build_session(
             // Try to resume most recent session
             if let Ok(session_file) = get_most_recent_session() {
                 let prompt = Box::new(RustylinePrompt::new());
-                return Session::new(agent, prompt, session_file);
+                return Session::new(agent, prompt, session_file)
+                    .with_provider(&provider_name, &model);
             } else {
                 eprintln!("No previous sessions found, starting new session");
             }
@@ -153,7 +161,7 @@ pub async fn build_session(
     let prompt = Box::new(RustylinePrompt::new());
 
     display_session_info(resume, &provider_name, &model, &session_file);
-    Session::new(agent, prompt, session_file)
+    Session::new(agent, prompt, session_file).with_provider(&provider_name, &model)
 }
 
 fn display_session_info(resume: bool, provider: &str, model: &str, session_file: &Path) {
@@ -0,0 +1,262 @@
+use anyhow::Result;
+use chrono::Local;
+use clap::Subcommand;
+use console::style;
+use goose::session::export::{export_session, ExportFormat};
+use goose::session::{fork_name, SessionInfo, SessionStore};
+use std::fmt::Write;
+use std::path::PathBuf;
+
+#[derive(Subcommand)]
+pub enum SessionCommand {
+    /// List saved sessions, most recent first
+    #[command(about = "List saved sessions, most recent first")]
+    List {},
+
+    /// Search the messages of saved sessions
+    #[command(about = "Search the messages of saved sessions")]
+    Search {
+        /// Text to search for, ignoring case
+        query: String,
+    },
+
+    /// Copy a saved session into a new session, to try another approach from some point
+    #[command(
+        about = "Copy a saved session into a new session, optionally from an earlier message"
+    )]
+    Fork {
+        /// Name of the session to fork
+        name: String,
+
+        /// Number of messages to copy, defaults to all of them
+        #[arg(
+            long,
+            value_name = "N",
+            help = "Fork before message N, keeping messages 0 to N-1 (defaults to all messages)"
+        )]
+        at: Option<usize>,
+
+        /// Name of the new session
+        #[arg(
+            short = 'n',
+            long = "name",
+            value_name = "NEW_NAME",
+            help = "Name of the new session (defaults to a generated name)"
+        )]
+        new_name: Option<String>,
+    },
+
+    /// Export a saved session as a transcript
+    #[command(about = "Export a saved session as a Markdown, HTML or JSON transcript")]
+    Export {
+        /// Name of the session to export
+        name: String,
+
+        /// Format of the transcript
+        #[arg(
+            short,
+            long,
+            default_value = "md",
+            help = "Format of the transcript: md, html or json"
+        )]
+        format: ExportFormat,
+
+        /// File to write the transcript to
+        #[arg(
+            short,
+            long,
+            value_name = "FILE",
+            help = "File to write the transcript to (defaults to stdout)"
+        )]
+        output: Option<PathBuf>,
+
+        /// Leave out tool output with a lower priority
+        #[arg(
+            long,
+            default_value_t = 0.0,
+            help = "Leave out tool output with a priority below this value"
+        )]
+        min_priority: f32,
+
+        /// Include the model's thinking
+        #[arg(long, help = "Include the model's thinking in the transcript")]
+        include_thinking: bool,
+    },
+
+    /// Delete a saved session
+    #[command(about = "Delete a saved session")]
+    Delete {
+        /// Name of the session to delete
+        name: String,
+
+        /// Delete without asking for confirmation
+        #[arg(short, long, help = "Delete without asking for confirmation")]
+        force: bool,
+    },
+}
+
+impl SessionCommand {
+    pub fn run(&self) -> Result<()> {
+        let store = SessionStore::global()?;
+        match self {
+            SessionCommand::List {} => print!("{}", list_sessions(&store)?),
+            SessionCommand::Search { query } => print!("{}", search_sessions(&store, query)?),
+            SessionCommand::Fork { name, at, new_name } => {
+                let new_name = new_name.clone().unwrap_or_else(|| fork_name(name));
+                let at = match at {
+                    Some(at) => *at,
+                    None => store.read(name)?.messages.len(),
+                };
+                let fork = store.fork(name, &new_name, at)?;
+                println!(
+                    "Forked '{}' at message {} into '{}'. Resume it with: goose session --resume --name {}",
+                    name,
+                    at,
+                    fork.name,
+                    fork.name
+                );
+            }
+            SessionCommand::Export {
+                name,
+                format,
+                output,
+                min_priority,
+                include_thinking,
+            } => {
+                let transcript = export_session(
+                    &store.read(name)?,
+                    *format,
+                    *min_priority,
+                    *include_thinking,
+                )?;
+                match output {
+                    Some(path) => {
+                        std::fs::write(path, transcript)?;
+                        println!("Exported '{}' to {}", name, path.display());
+                    }
+                    None => print!("{}", transcript),
+                }
+            }
+            SessionCommand::Delete { name, force } => {
+                // Check it exists before asking
+                store.read(name)?;
+                if !force
+                    && !cliclack::confirm(format!("Delete session '{}'?", name))
+                        .initial_value(false)
+                        .interact()?
+                {
+                    return Ok(());
+                }
+                store.delete(name)?;
+                println!("Deleted session '{}'", name);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_session_line(output: &mut String, session: &SessionInfo) -> Result<()> {
+    let metadata = &session.metadata;
+    let model = match (&metadata.provider, &metadata.model) {
+        (Some(provider), Some(model)) => format!("{}/{}", provider, model),
+        (None, Some(model)) => model.clone(),
+        _ => "-".to_string(),
+    };
+    writeln!(
+        output,
+        "{}  {}  {} messages  {} tokens  {}",
+        style(&session.name).cyan().bold(),
+        metadata
+            .updated
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M"),
+        metadata.message_count,
+        metadata.total_tokens,
+        style(model).dim(),
+    )?;
+    if let Some(working_dir) = &metadata.working_dir {
+        writeln!(output, "    {}", style(working_dir.display()).dim())?;
+    }
+    if let Some(parent) = &metadata.parent {
+        writeln!(
+            output,
+            "    {}",
+            style(format!(
+                "forked from {} at message {}",
+                parent.name, parent.message_index
+            ))
+            .dim()
+        )?;
+    }
+    Ok(())
+}
+
+fn list_sessions(store: &SessionStore) -> Result<String> {
+    let mut output = String::new();
+    let sessions = store.list()?;
+    if sessions.is_empty() {
+        writeln!(output, "No sessions found in {}", store.dir().display())?;
+    }
+    for session in sessions {
+        write_session_line(&mut output, &session)?;
+    }
+    Ok(output)
+}
+
+fn search_sessions(store: &SessionStore, query: &str) -> Result<String> {
+    let mut output = String::new();
+    let results = store.search(query)?;
+    if results.is_empty() {
+        writeln!(output, "No sessions match '{}'", query)?;
+    }
+    for result in results {
+        write_session_line(&mut output, &result.session)?;
+        for found in result.matches {
+            writeln!(
+                output,
+                "    {} {}",
+                style(format!("#{}", found.message_index)).dim(),
+                found.snippet
+            )?;
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use goose::message::Message;
+    use goose::session::SessionMetadata;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_list_and_search() -> Result<()> {
+        console::set_colors_enabled(false);
+        let dir = tempdir()?;
+        let store = SessionStore::new(dir.path());
+        assert!(list_sessions(&store)?.starts_with("No sessions found"));
+
+        let mut metadata =
+            SessionMetadata::new(Some("openai".to_string()), Some("gpt-4o".to_string()));
+        store.write(
+            "release-notes",
+            &mut metadata,
+            &[Message::user().with_text("Draft the release notes for 1.0")],
+        )?;
+
+        let listing = list_sessions(&store)?;
+        assert!(listing.contains("release-notes"));
+        assert!(listing.contains("1 messages"));
+        assert!(listing.contains("openai/gpt-4o"));
+
+        let found = search_sessions(&store, "release NOTES")?;
+        assert!(found.contains("#0 Draft the release notes for 1.0"));
+        assert!(search_sessions(&store, "changelog")?.starts_with("No sessions match"));
+
+        store.fork("release-notes", "release-notes-short", 0)?;
+        let listing = list_sessions(&store)?;
+        assert!(listing.contains("forked from release-notes at message 0"));
+        Ok(())
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone, Utc};
+use clap::Subcommand;
+use console::style;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+
+use crate::log_usage::{read_usage_log, usage_log_path, SessionLog};
+
+#[derive(Subcommand)]
+pub enum UsageCommand {
+    /// Total the tokens and spend of past sessions from the usage log
+    #[command(about = "Total the tokens and spend of past sessions")]
+    Report {
+        /// Only count sessions closed since this time
+        #[arg(
+            long,
+            value_name = "WHEN",
+            help = "Only count sessions closed since a date (e.g. 2025-01-31) or for a period (e.g. 12h, 7d, 2w)"
+        )]
+        since: Option<String>,
+    },
+}
+
+impl UsageCommand {
+    pub fn run(&self) -> Result<()> {
+        match self {
+            UsageCommand::Report { since } => {
+                let since = since.as_deref().map(parse_since).transpose()?;
+                let path = usage_log_path()
+                    .ok_or_else(|| anyhow!("Failed to determine home directory"))?;
+                print!("{}", usage_report(&read_usage_log(&path)?, since)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Format a cost in dollars for display
+pub fn format_cost(cost: f64) -> String {
+    format!("${:.4}", cost)
+}
+
+/// Parse a point in time given as a date, an RFC 3339 timestamp, or a period before now
+fn parse_since(since: &str) -> Result<DateTime<Utc>> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(since) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        let midnight = date.and_hms_opt(0, 0, 0).expect("midnight is a valid time");
+        return Local
+            .from_local_datetime(&midnight)
+            .earliest()
+            .map(|time| time.with_timezone(&Utc))
+            .ok_or_else(|| anyhow!("'{}' is not a valid local date", since));
+    }
+
+    let invalid = || {
+        anyhow!(
+            "Invalid time '{}', expected a date like 2025-01-31 or a period like 12h, 7d or 2w",
+            since
+        )
+    };
+    let (split, unit) = since.char_indices().next_back().ok_or_else(invalid)?;
+    let count: i64 = since[..split].parse().map_err(|_| invalid())?;
+    let period = match unit {
+        'm' => Duration::minutes(count),
+        'h' => Duration::hours(count),
+        'd' => Duration::days(count),
+        'w' => Duration::weeks(count),
+        _ => return Err(invalid()),
+    };
+    Ok(Utc::now() - period)
+}
+
+#[derive(Default)]
+struct ModelTotals {
+    input_tokens: i64,
+    cached_tokens: i64,
+    output_tokens: i64,
+    total_tokens: i64,
+    cost: Option<f64>,
+}
+
+fn usage_report(logs: &[SessionLog], since: Option<DateTime<Utc>>) -> Result<String> {
+    let mut output = String::new();
+    // Entries from older versions have no timestamp, they can only be counted without --since
+    let logs: Vec<&SessionLog> = logs
+        .iter()
+        .filter(|log| match since {
+            Some(since) => log.timestamp.is_some_and(|timestamp| timestamp >= since),
+            None => true,
+        })
+        .collect();
+
+    match since {
+        Some(since) => writeln!(
+            output,
+            "Usage since {}",
+            since.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+        )?,
+        None => writeln!(output, "All recorded usage")?,
+    }
+    if logs.is_empty() {
+        writeln!(output, "No sessions found in the usage log")?;
+        return Ok(output);
+    }
+
+    let mut models: BTreeMap<&str, ModelTotals> = BTreeMap::new();
+    for usage in logs.iter().flat_map(|log| &log.usage) {
+        let totals = models.entry(usage.model.as_str()).or_default();
+        totals.input_tokens += usage.usage.input_tokens.unwrap_or(0) as i64;
+        totals.cached_tokens += usage.usage.cache_read_input_tokens.unwrap_or(0) as i64;
+        totals.output_tokens += usage.usage.output_tokens.unwrap_or(0) as i64;
+        totals.total_tokens += usage.usage.total_tokens.unwrap_or(0) as i64;
+        if let Some(cost) = usage.cost {
+            totals.cost = Some(totals.cost.unwrap_or(0.0) + cost);
+        }
+    }
+    let sessions: HashSet<&str> = logs.iter().map(|log| log.session_file.as_str()).collect();
+
+    writeln!(output, "{} sessions, {} runs\n", sessions.len(), logs.len())?;
+    let model_width = models
+        .keys()
+        .map(|model| model.len())
+        .max()
+        .unwrap_or(0)
+        .max(5);
+    writeln!(
+        output,
+        "{}",
+        style(format!(
+            "{:<model_width$}  {:>12}  {:>12}  {:>12}  {:>12}  {:>10}",
+            "Model", "Input", "Cached", "Output", "Total", "Cost"
+        ))
+        .bold()
+    )?;
+    let mut total_cost = 0.0;
+    let mut unpriced = false;
+    for (model, totals) in &models {
+        match totals.cost {
+            Some(cost) => total_cost += cost,
+            None => unpriced = true,
+        }
+        writeln!(
+            output,
+            "{:<model_width$}  {:>12}  {:>12}  {:>12}  {:>12}  {:>10}",
+            model,
+            totals.input_tokens,
+            totals.cached_tokens,
+            totals.output_tokens,
+            totals.total_tokens,
+            totals.cost.map_or("-".to_string(), format_cost),
+        )?;
+    }
+    writeln!(output, "\nTotal cost: {}", format_cost(total_cost))?;
+    if unpriced {
+        writeln!(
+            output,
+            "{}",
+            style("Models without a price are not included, set their prices under model_pricing in the config").dim()
+        )?;
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use goose::providers::base::{ProviderUsage, Usage};
+
+    fn log(session_file: &str, days_ago: Option<i64>, usage: Vec<ProviderUsage>) -> SessionLog {
+        SessionLog {
+            session_file: session_file.to_string(),
+            usage,
+            timestamp: days_ago.map(|days| Utc::now() - Duration::days(days)),
+        }
+    }
+
+    fn usage(model: &str, tokens: i32, cost: Option<f64>) -> ProviderUsage {
+        ProviderUsage {
+            cost,
+            ..ProviderUsage::new(
+                model.to_string(),
+                Usage::new(Some(tokens), Some(tokens), Some(tokens * 2)),
+            )
+        }
+    }
+
+    #[test]
+    fn test_usage_report() -> Result<()> {
+        console::set_colors_enabled(false);
+        let logs = vec![
+            log("a.jsonl", None, vec![usage("gpt-4o", 100, Some(0.5))]),
+            log("a.jsonl", Some(10), vec![usage("gpt-4o", 100, Some(0.25))]),
+            log(
+                "b.jsonl",
+                Some(1),
+                vec![usage("gpt-4o", 50, Some(0.125)), usage("llama3", 10, None)],
+            ),
+        ];
+
+        let report = usage_report(&logs, None)?;
+        assert!(report.contains("2 sessions, 3 runs"));
+        assert!(report.contains("Cached"));
+        assert!(report.contains("Total cost: $0.8750"));
+        assert!(report.contains("Models without a price"));
+
+        let report = usage_report(&logs, Some(parse_since("7d")?))?;
+        assert!(report.contains("1 sessions, 1 runs"));
+        assert!(report.contains("Total cost: $0.1250"));
+
+        let report = usage_report(&logs, Some(Utc::now()))?;
+        assert!(report.contains("No sessions found"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let week_ago = parse_since("1w").unwrap();
+        assert!(
+            (Utc::now() - week_ago - Duration::weeks(1))
+                .num_seconds()
+                .abs()
+                < 5
+        );
+        assert_eq!(
+            parse_since("2025-01-31T12:00:00Z").unwrap(),
+            Utc.with_ymd_and_hms(2025, 1, 31, 12, 0, 0).unwrap()
+        );
+        assert!(parse_since("2025-01-31").is_ok());
+        assert!(parse_since("yesterday").is_err());
+        assert!(parse_since("7y").is_err());
+        assert!(parse_since("").is_err());
+        assert!(parse_since("7д").is_err());
+    }
+}
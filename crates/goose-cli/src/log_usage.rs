@@ -1,26 +1,59 @@
+use chrono::{DateTime, Utc};
 use goose::providers::base::ProviderUsage;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
-struct SessionLog {
-    session_file: String,
-    usage: Vec<ProviderUsage>,
+pub struct SessionLog {
+    pub session_file: String,
+    pub usage: Vec<ProviderUsage>,
+    /// When the session was closed, missing from entries written by older versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+/// The usage log in ~/.config/goose/logs
+pub fn usage_log_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home_dir| {
+        home_dir
+            .join(".config")
+            .join("goose")
+            .join("logs")
+            .join("goose.log")
+    })
+}
+
+/// Read the entries of a usage log, skipping lines that aren't usage entries
+pub fn read_usage_log(path: &Path) -> anyhow::Result<Vec<SessionLog>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    let mut logs = Vec::new();
+    for line in reader.lines() {
+        if let Ok(log) = serde_json::from_str::<SessionLog>(&line?) {
+            logs.push(log);
+        }
+    }
+    Ok(logs)
 }
 
 pub fn log_usage(session_file: String, usage: Vec<ProviderUsage>) {
     let log = SessionLog {
         session_file,
         usage,
+        timestamp: Some(Utc::now()),
     };
 
     // Ensure log directory exists
-    if let Some(home_dir) = dirs::home_dir() {
-        let log_dir = home_dir.join(".config").join("goose").join("logs");
-        if let Err(e) = std::fs::create_dir_all(&log_dir) {
-            eprintln!("Failed to create log directory: {}", e);
-            return;
+    if let Some(log_file) = usage_log_path() {
+        if let Some(log_dir) = log_file.parent() {
+            if let Err(e) = std::fs::create_dir_all(log_dir) {
+                eprintln!("Failed to create log directory: {}", e);
+                return;
+            }
         }
 
-        let log_file = log_dir.join("goose.log");
         let serialized = match serde_json::to_string(&log) {
             Ok(s) => s,
             Err(e) => {
@@ -52,7 +85,7 @@ mod tests {
     use goose::providers::base::{ProviderUsage, Usage};
 
     use crate::{
-        log_usage::{log_usage, SessionLog},
+        log_usage::{log_usage, read_usage_log, SessionLog},
         test_helpers::run_with_tmp_dir,
     };
 
@@ -85,6 +118,8 @@ mod tests {
             assert_eq!(log.usage[0].usage.output_tokens, Some(20));
             assert_eq!(log.usage[0].usage.total_tokens, Some(30));
             assert_eq!(log.usage[0].model, "model");
+            assert!(log.timestamp.is_some());
+            assert_eq!(read_usage_log(&log_file).unwrap().len(), 1);
 
             // Remove the log file after test
             std::fs::remove_file(&log_file).ok();
@@ -10,9 +10,13 @@ mod session;
 use commands::agent_version::AgentCommand;
 use commands::configure::handle_configure;
 use commands::mcp::run_server;
+use commands::models::list_models;
 use commands::session::build_session;
+use commands::sessions::SessionCommand;
+use commands::usage::UsageCommand;
 use commands::version::print_version;
 use console::style;
+use goose::agents::Budget;
 use goose::config::Config;
 use logging::setup_logging;
 use std::io::{self, Read};
@@ -41,8 +45,15 @@ enum Command {
     Mcp { name: String },
 
     /// Start or resume interactive chat sessions
-    #[command(about = "Start or resume interactive chat sessions", alias = "s")]
+    #[command(
+        about = "Start or resume interactive chat sessions",
+        alias = "s",
+        args_conflicts_with_subcommands = true
+    )]
     Session {
+        #[command(subcommand)]
+        command: Option<SessionCommand>,
+
         /// Name for the chat session
         #[arg(
             short,
@@ -142,6 +153,58 @@ enum Command {
             long_help = "Add a builtin extension that is compiled into goose by specifying its name"
         )]
         builtin: Option<String>,
+
+        /// Stop once this many tokens have been used
+        #[arg(
+            long,
+            value_name = "TOKENS",
+            help = "Stop once this many tokens have been used (overrides GOOSE_BUDGET_MAX_TOKENS)"
+        )]
+        max_tokens: Option<i64>,
+
+        /// Stop once this many dollars have been spent
+        #[arg(
+            long,
+            value_name = "DOLLARS",
+            help = "Stop once this many dollars have been spent (overrides GOOSE_BUDGET_MAX_COST)"
+        )]
+        max_cost: Option<f64>,
+
+        /// Stop after this many model turns
+        #[arg(
+            long,
+            value_name = "TURNS",
+            help = "Stop after this many model turns (overrides GOOSE_BUDGET_MAX_TURNS)"
+        )]
+        max_turns: Option<usize>,
+
+        /// Stop after this many tool calls
+        #[arg(
+            long,
+            value_name = "CALLS",
+            help = "Stop after this many tool calls (overrides GOOSE_BUDGET_MAX_TOOL_CALLS)"
+        )]
+        max_tool_calls: Option<usize>,
+    },
+
+    /// Report token usage and spend
+    #[command(about = "Report token usage and spend across sessions")]
+    Usage {
+        #[command(subcommand)]
+        command: UsageCommand,
+    },
+
+    /// List the models a provider serves
+    #[command(about = "List the models a provider serves, with their context windows")]
+    Models {
+        /// Provider to list the models of
+        #[arg(
+            short,
+            long,
+            value_name = "PROVIDER",
+            help = "Provider to list the models of (defaults to the configured provider)"
+        )]
+        provider: Option<String>,
     },
 
     /// List available agent versions
@@ -173,12 +236,21 @@ async fn main() -> Result<()> {
             let _ = run_server(&name).await;
         }
         Some(Command::Session {
+            command: Some(command),
+            ..
+        }) => {
+            command.run()?;
+            return Ok(());
+        }
+        Some(Command::Session {
+            command: None,
             name,
             resume,
             extension,
             builtin,
         }) => {
-            let mut session = build_session(name, resume, extension, builtin).await;
+            let mut session =
+                build_session(name, resume, extension, builtin, Budget::default()).await;
             setup_logging(session.session_file().file_stem().and_then(|s| s.to_str()))?;
 
             let _ = session.start().await;
@@ -191,6 +263,10 @@ async fn main() -> Result<()> {
             resume,
             extension,
             builtin,
+            max_tokens,
+            max_cost,
+            max_turns,
+            max_tool_calls,
         }) => {
             // Validate that we have some input source
             if instructions.is_none() && input_text.is_none() {
@@ -210,8 +286,25 @@ async fn main() -> Result<()> {
                     .expect("Failed to read from stdin");
                 stdin
             };
-            let mut session = build_session(name, resume, extension, builtin).await;
-            let _ = session.headless_start(contents.clone()).await;
+            let budget = Budget {
+                max_tokens,
+                max_cost,
+                max_turns,
+                max_tool_calls,
+            };
+            let mut session = build_session(name, resume, extension, builtin, budget).await;
+            if let Err(e) = session.headless_start(contents.clone()).await {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Usage { command }) => {
+            command.run()?;
+            return Ok(());
+        }
+        Some(Command::Models { provider }) => {
+            list_models(provider).await?;
             return Ok(());
         }
         Some(Command::Agents(cmd)) => {
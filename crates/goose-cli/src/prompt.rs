@@ -1,5 +1,8 @@
 use anyhow::Result;
+use goose::agents::Plan;
 use goose::message::Message;
+use goose::providers::base::MessageDelta;
+use mcp_core::tool::ToolCall;
 
 pub mod renderer;
 pub mod rustyline;
@@ -7,6 +10,21 @@ pub mod thinking;
 
 pub trait Prompt {
     fn render(&mut self, message: Box<Message>);
+    /// Render partial content of a message while it is still being generated.
+    /// The complete message is passed to `render` afterwards.
+    fn render_delta(&mut self, _delta: &MessageDelta) {}
+    /// Ask the user whether a tool call the tool policy marks as "ask" may run.
+    /// Prompts that can't ask deny the call.
+    fn confirm_tool_call(&mut self, _tool_call: &ToolCall) -> bool {
+        false
+    }
+    /// Ask the user whether the agent may go ahead with a plan.
+    /// Prompts that can't ask reject the plan.
+    fn confirm_plan(&mut self, _plan: &Plan) -> bool {
+        false
+    }
+    /// Render the progress of the plan the agent is working through.
+    fn render_plan(&mut self, _plan: &Plan) {}
     fn get_input(&mut self) -> Result<Input>;
     fn show_busy(&mut self);
     fn hide_busy(&self);
@@ -30,6 +48,7 @@ pub enum InputType {
     AskAgain, // Ask the user for input again. Control flow command.
     Message,  // User sent a message
     Exit,     // User wants to exit the session
+    Fork,     // User wants to continue in a copy of the session, content holds the /fork arguments
 }
 
 pub enum Theme {
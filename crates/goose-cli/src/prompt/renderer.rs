@@ -4,7 +4,7 @@ use std::path::PathBuf;
 
 use bat::WrappingMode;
 use console::style;
-use goose::message::{Message, MessageContent, ToolRequest, ToolResponse};
+use goose::message::{Message, MessageContent, ThinkingContent, ToolRequest, ToolResponse};
 use mcp_core::role::Role;
 use mcp_core::{content::Content, tool::ToolCall};
 use serde_json::Value;
@@ -13,6 +13,7 @@ use super::Theme;
 
 const MAX_STRING_LENGTH: usize = 40;
 const MAX_PATH_LENGTH: usize = 60;
+const MAX_THINKING_PREVIEW_LENGTH: usize = 80;
 const INDENT: &str = "    ";
 
 /// Shortens a path string by abbreviating directory names while keeping the last two components intact.
@@ -227,7 +228,12 @@ impl ToolRenderer for BashDeveloperExtensionRenderer {
     }
 }
 
-pub fn render(message: &Message, theme: &Theme, renderers: HashMap<String, Box<dyn ToolRenderer>>) {
+pub fn render(
+    message: &Message,
+    theme: &Theme,
+    renderers: HashMap<String, Box<dyn ToolRenderer>>,
+    show_thinking: bool,
+) {
     let theme = match theme {
         Theme::Light => "GitHub",
         Theme::Dark => "zenburn",
@@ -259,6 +265,7 @@ pub fn render(message: &Message, theme: &Theme, renderers: HashMap<String, Box<d
             MessageContent::Image(image) => {
                 println!("Image: [data: {}, type: {}]", image.data, image.mime_type);
             }
+            MessageContent::Thinking(thinking) => print_thinking(thinking, show_thinking),
         }
     }
 
@@ -266,6 +273,34 @@ pub fn render(message: &Message, theme: &Theme, renderers: HashMap<String, Box<d
     io::stdout().flush().expect("Failed to flush stdout");
 }
 
+/// Print the model's thinking dimmed, or only its first line unless it's expanded
+pub fn print_thinking(thinking: &ThinkingContent, expanded: bool) {
+    if thinking.redacted {
+        println!("{}", style("Thinking (redacted)").dim());
+    } else if expanded {
+        println!("{}", style("Thinking:").dim());
+        for line in thinking.thinking.trim().lines() {
+            println!("{}", style(line).dim());
+        }
+    } else {
+        let first_line = thinking.thinking.trim().lines().next().unwrap_or_default();
+        let preview = if first_line.chars().count() > MAX_THINKING_PREVIEW_LENGTH {
+            first_line
+                .chars()
+                .take(MAX_THINKING_PREVIEW_LENGTH)
+                .collect::<String>()
+        } else {
+            first_line.to_string()
+        };
+        println!(
+            "{} {}",
+            style(format!("Thinking: {}…", preview)).dim(),
+            style("(/thinking to expand)").dim().italic()
+        );
+    }
+    print_newline();
+}
+
 pub fn default_response_renderer(tool_response: &ToolResponse, theme: &str) {
     match &tool_response.tool_result {
         Ok(contents) => {
@@ -1,8 +1,10 @@
 use std::collections::HashMap;
+use std::io::Write;
 
 use super::{
     renderer::{
-        render, BashDeveloperExtensionRenderer, DefaultRenderer, TextEditorRenderer, ToolRenderer,
+        print_thinking, render, BashDeveloperExtensionRenderer, DefaultRenderer,
+        TextEditorRenderer, ToolRenderer,
     },
     thinking::get_random_thinking_message,
     Input, InputType, Prompt, Theme,
@@ -10,7 +12,11 @@ use super::{
 
 use anyhow::Result;
 use cliclack::spinner;
-use goose::message::Message;
+use console::style;
+use goose::agents::{Plan, TaskStatus};
+use goose::message::{Message, ThinkingContent};
+use goose::providers::base::MessageDelta;
+use mcp_core::tool::ToolCall;
 use mcp_core::Role;
 use rustyline::{DefaultEditor, EventHandler, KeyCode, KeyEvent, Modifiers};
 
@@ -21,6 +27,10 @@ pub struct RustylinePrompt {
     theme: Theme,
     renderers: HashMap<String, Box<dyn ToolRenderer>>,
     editor: DefaultEditor,
+    /// Whether thinking is shown in full rather than only its first line
+    show_thinking: bool,
+    /// Thinking streamed so far, shown once the text of the message starts
+    pending_thinking: String,
 }
 
 impl RustylinePrompt {
@@ -61,13 +71,72 @@ impl RustylinePrompt {
                 .unwrap_or(Theme::Dark),
             renderers,
             editor,
+            show_thinking: false,
+            pending_thinking: String::new(),
         }
     }
 }
 
 impl Prompt for RustylinePrompt {
     fn render(&mut self, message: Box<Message>) {
-        render(&message, &self.theme, self.renderers.clone());
+        // Thinking that was never followed by text is part of the complete message
+        self.pending_thinking.clear();
+        render(
+            &message,
+            &self.theme,
+            self.renderers.clone(),
+            self.show_thinking,
+        );
+    }
+
+    fn render_delta(&mut self, delta: &MessageDelta) {
+        // Tool calls are shown once complete, with their arguments formatted by the renderers
+        match delta {
+            MessageDelta::Thinking { thinking } => self.pending_thinking.push_str(thinking),
+            MessageDelta::Text { text } => {
+                if !self.pending_thinking.is_empty() {
+                    let thinking = ThinkingContent {
+                        thinking: std::mem::take(&mut self.pending_thinking),
+                        signature: None,
+                        redacted: false,
+                    };
+                    print_thinking(&thinking, self.show_thinking);
+                }
+                print!("{}", text);
+                std::io::stdout().flush().expect("Failed to flush stdout");
+            }
+            MessageDelta::ToolCall { .. } => {}
+        }
+    }
+
+    fn confirm_tool_call(&mut self, tool_call: &ToolCall) -> bool {
+        cliclack::confirm(format!("Allow goose to run {}?", tool_call.name))
+            .initial_value(false)
+            .interact()
+            .unwrap_or(false)
+    }
+
+    fn confirm_plan(&mut self, plan: &Plan) -> bool {
+        println!("\n{}", style("Plan").bold());
+        self.render_plan(plan);
+        cliclack::confirm("Go ahead with this plan?")
+            .initial_value(true)
+            .interact()
+            .unwrap_or(false)
+    }
+
+    fn render_plan(&mut self, plan: &Plan) {
+        for (i, task) in plan.tasks.iter().enumerate() {
+            let line = format!("{}. {}", i + 1, task.description);
+            let line = match task.status {
+                TaskStatus::Pending => style(format!("[ ] {}", line)).dim(),
+                TaskStatus::InProgress => style(format!("[>] {}", line)).cyan(),
+                TaskStatus::Done => style(format!("[x] {}", line)).green(),
+                TaskStatus::Failed => style(format!("[!] {}", line)).red(),
+            };
+            println!("{}", line);
+        }
+        println!();
     }
 
     fn show_busy(&mut self) {
@@ -127,12 +196,34 @@ impl Prompt for RustylinePrompt {
                 input_type: InputType::AskAgain,
                 content: None,
             });
+        } else if message_text.eq_ignore_ascii_case("/thinking") {
+            self.show_thinking = !self.show_thinking;
+            if self.show_thinking {
+                println!("Showing the model's thinking in full");
+            } else {
+                println!("Showing only the first line of the model's thinking");
+            }
+            Ok(Input {
+                input_type: InputType::AskAgain,
+                content: None,
+            })
+        } else if message_text.eq_ignore_ascii_case("/fork")
+            || message_text
+                .get(.."/fork ".len())
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case("/fork "))
+        {
+            Ok(Input {
+                input_type: InputType::Fork,
+                content: Some(message_text["/fork".len()..].trim().to_string()),
+            })
         } else if message_text.eq_ignore_ascii_case("/?")
             || message_text.eq_ignore_ascii_case("/help")
         {
             println!("Commands:");
             println!("/exit - Exit the session");
             println!("/t - Toggle Light/Dark theme");
+            println!("/thinking - Toggle showing the model's thinking in full");
+            println!("/fork [name] [--at N] - Continue in a copy of this session, optionally keeping only the first N messages");
             println!("/? | /help - Display this help message");
             println!("Ctrl+C - Interrupt goose (resets the interaction to before the interrupted user request)");
             println!("Ctrl+j - Adds a newline");
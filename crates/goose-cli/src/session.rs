@@ -1,94 +1,107 @@
 use anyhow::Result;
+use console::style;
 use core::panic;
 use futures::StreamExt;
-use std::fs::{self, File};
-use std::io::{self, BufRead, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use crate::commands::usage::format_cost;
 use crate::log_usage::log_usage;
 use crate::prompt::{InputType, Prompt};
-use goose::agents::Agent;
+use goose::agents::{Agent, AgentEvent, BudgetExceeded};
 use goose::message::{Message, MessageContent};
+use goose::providers::base::{MessageDelta, ProviderUsage, Usage};
+use goose::session::{fork_name, read_session_file, SessionMetadata, SessionStore};
 use mcp_core::handler::ToolError;
 use mcp_core::role::Role;
 
 // File management functions
 pub fn ensure_session_dir() -> Result<PathBuf> {
-    let home_dir = dirs::home_dir().ok_or(anyhow::anyhow!("Could not determine home directory"))?;
-    let config_dir = home_dir.join(".config").join("goose").join("sessions");
-
-    if !config_dir.exists() {
-        fs::create_dir_all(&config_dir)?;
-    }
-
-    Ok(config_dir)
+    Ok(SessionStore::global()?.dir().to_path_buf())
 }
 
 pub fn get_most_recent_session() -> Result<PathBuf> {
-    let session_dir = ensure_session_dir()?;
-    let mut entries = fs::read_dir(&session_dir)?
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "jsonl"))
-        .collect::<Vec<_>>();
-
-    if entries.is_empty() {
-        return Err(anyhow::anyhow!("No session files found"));
-    }
-
-    // Sort by modification time, most recent first
-    entries.sort_by(|a, b| {
-        b.metadata()
-            .and_then(|m| m.modified())
-            .unwrap_or(std::time::SystemTime::UNIX_EPOCH)
-            .cmp(
-                &a.metadata()
-                    .and_then(|m| m.modified())
-                    .unwrap_or(std::time::SystemTime::UNIX_EPOCH),
-            )
-    });
-
-    Ok(entries[0].path())
+    let store = SessionStore::global()?;
+    Ok(store.path(&store.most_recent()?))
 }
 
-pub fn readable_session_file(session_file: &PathBuf) -> Result<File> {
-    match fs::OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .truncate(false)
-        .open(session_file)
-    {
-        Ok(file) => Ok(file),
-        Err(e) => Err(anyhow::anyhow!("Failed to open session file: {}", e)),
-    }
+/// The store a session file is in, along with the name of the session
+fn session_store(session_file: &Path) -> Result<(SessionStore, String)> {
+    let name = session_file
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| anyhow::anyhow!("The session file has no name"))?
+        .to_string();
+    let dir = session_file
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("The session file has no directory"))?;
+    Ok((SessionStore::new(dir), name))
 }
 
-pub fn persist_messages(session_file: &PathBuf, messages: &[Message]) -> Result<()> {
-    let file = fs::File::create(session_file)?; // Create or truncate the file
-    persist_messages_internal(file, messages)
+pub fn persist_messages(
+    session_file: &Path,
+    metadata: &mut SessionMetadata,
+    messages: &[Message],
+) -> Result<()> {
+    let (store, name) = session_store(session_file)?;
+    store.write(&name, metadata, messages)
 }
 
-fn persist_messages_internal(session_file: File, messages: &[Message]) -> Result<()> {
-    let mut writer = std::io::BufWriter::new(session_file);
-
-    for message in messages {
-        serde_json::to_writer(&mut writer, &message)?;
-        writeln!(writer)?;
-    }
-
-    writer.flush()?;
-    Ok(())
+/// The usage in `current` that isn't in `previous`, both being totals per model
+fn usage_since(previous: &[ProviderUsage], current: &[ProviderUsage]) -> Vec<ProviderUsage> {
+    let difference = |now: Option<i32>, before: Option<i32>| now.map(|n| n - before.unwrap_or(0));
+    current
+        .iter()
+        .map(
+            |usage| match previous.iter().find(|p| p.model == usage.model) {
+                Some(before) => ProviderUsage {
+                    cost: usage.cost.map(|cost| cost - before.cost.unwrap_or(0.0)),
+                    ..ProviderUsage::new(
+                        usage.model.clone(),
+                        Usage::new(
+                            difference(usage.usage.input_tokens, before.usage.input_tokens),
+                            difference(usage.usage.output_tokens, before.usage.output_tokens),
+                            difference(usage.usage.total_tokens, before.usage.total_tokens),
+                        )
+                        .with_cache_tokens(
+                            difference(
+                                usage.usage.cache_read_input_tokens,
+                                before.usage.cache_read_input_tokens,
+                            ),
+                            difference(
+                                usage.usage.cache_write_input_tokens,
+                                before.usage.cache_write_input_tokens,
+                            ),
+                        ),
+                    )
+                },
+                None => usage.clone(),
+            },
+        )
+        .collect()
 }
 
-pub fn deserialize_messages(file: File) -> Result<Vec<Message>> {
-    let reader = io::BufReader::new(file);
-    let mut messages = Vec::new();
-
-    for line in reader.lines() {
-        messages.push(serde_json::from_str::<Message>(&line?)?);
+/// Parse `[name] [--at N]` into the name and message index of a fork
+fn parse_fork_args(args: &str) -> Result<(Option<String>, Option<usize>)> {
+    let mut name = None;
+    let mut at = None;
+    let mut words = args.split_whitespace();
+    while let Some(word) = words.next() {
+        if word == "--at" {
+            let index = words
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("--at needs a message number"))?;
+            at = Some(
+                index
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("'{}' is not a message number", index))?,
+            );
+        } else if name.is_none() {
+            name = Some(word.to_string());
+        } else {
+            return Err(anyhow::anyhow!("Usage: /fork [name] [--at N]"));
+        }
     }
-
-    Ok(messages)
+    Ok((name, at))
 }
 
 // Session management
@@ -96,7 +109,10 @@ pub struct Session<'a> {
     agent: Box<dyn Agent>,
     prompt: Box<dyn Prompt + 'a>,
     session_file: PathBuf,
+    metadata: SessionMetadata,
     messages: Vec<Message>,
+    // The agent's usage totals when they were last added to the metadata
+    recorded_usage: Vec<ProviderUsage>,
 }
 
 #[allow(dead_code)]
@@ -106,18 +122,16 @@ impl<'a> Session<'a> {
         mut prompt: Box<dyn Prompt + 'a>,
         session_file: PathBuf,
     ) -> Self {
-        let messages = match readable_session_file(&session_file) {
-            Ok(file) => deserialize_messages(file).unwrap_or_else(|e| {
+        let (metadata, messages) = if session_file.exists() {
+            read_session_file(&session_file).unwrap_or_else(|e| {
                 eprintln!(
                     "Failed to read messages from session file. Starting fresh.\n{}",
                     e
                 );
-                Vec::<Message>::new()
-            }),
-            Err(e) => {
-                eprintln!("Failed to load session file. Starting fresh.\n{}", e);
-                Vec::<Message>::new()
-            }
+                (SessionMetadata::default(), Vec::<Message>::new())
+            })
+        } else {
+            (SessionMetadata::default(), Vec::<Message>::new())
         };
 
         prompt.load_user_message_history(messages.clone());
@@ -126,10 +140,71 @@ impl<'a> Session<'a> {
             agent,
             prompt,
             session_file,
+            metadata,
             messages,
+            recorded_usage: Vec::new(),
         }
     }
 
+    /// Record the provider and model the session is continued with
+    pub fn with_provider(mut self, provider: &str, model: &str) -> Self {
+        self.metadata.provider = Some(provider.to_string());
+        self.metadata.model = Some(model.to_string());
+        self
+    }
+
+    fn persist(&mut self) -> Result<()> {
+        persist_messages(&self.session_file, &mut self.metadata, &self.messages)
+    }
+
+    /// Add the usage since the last update to the session totals, returning the cost of it
+    async fn update_usage(&mut self) -> Option<f64> {
+        let usage = self.agent.usage().await;
+        let new_usage = usage_since(&self.recorded_usage, &usage);
+        self.metadata.add_usage(&new_usage);
+        self.recorded_usage = usage;
+        self.persist()
+            .unwrap_or_else(|e| eprintln!("Failed to persist messages: {}", e));
+        new_usage
+            .iter()
+            .filter_map(|usage| usage.cost)
+            .reduce(|a, b| a + b)
+    }
+
+    /// Show the cost of the last turn and of the whole session, when the model has a price
+    fn show_cost(&self, turn_cost: Option<f64>) {
+        if let (Some(turn_cost), Some(session_cost)) = (turn_cost, self.metadata.cost) {
+            println!(
+                "{}",
+                style(format!(
+                    "{} this turn, {} this session",
+                    format_cost(turn_cost),
+                    format_cost(session_cost)
+                ))
+                .dim()
+            );
+        }
+    }
+
+    /// Switch to a copy of this session, parsing the arguments of `/fork [name] [--at N]`
+    fn fork(&mut self, args: &str) -> Result<()> {
+        let (new_name, at) = parse_fork_args(args)?;
+        self.persist()?;
+
+        let (store, name) = session_store(&self.session_file)?;
+        let new_name = new_name.unwrap_or_else(|| fork_name(&name));
+        let fork = store.fork(&name, &new_name, at.unwrap_or(self.messages.len()))?;
+
+        println!(
+            "Forked '{}' at message {}, now continuing in '{}'",
+            name, fork.metadata.message_count, fork.name
+        );
+        self.session_file = store.path(&fork.name);
+        self.metadata = fork.metadata;
+        self.messages = fork.messages;
+        Ok(())
+    }
+
     pub async fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.prompt.goose_ready();
 
@@ -139,16 +214,25 @@ impl<'a> Session<'a> {
                 InputType::Message => {
                     if let Some(content) = &input.content {
                         self.messages.push(Message::user().with_text(content));
-                        persist_messages(&self.session_file, &self.messages)?;
+                        self.persist()?;
                     }
                 }
                 InputType::Exit => break,
                 InputType::AskAgain => continue,
+                InputType::Fork => {
+                    let args = input.content.unwrap_or_default();
+                    if let Err(e) = self.fork(&args) {
+                        eprintln!("Failed to fork the session: {}", e);
+                    }
+                    continue;
+                }
             }
 
             self.prompt.show_busy();
             self.agent_process_messages().await;
+            let turn_cost = self.update_usage().await;
             self.prompt.hide_busy();
+            self.show_cost(turn_cost);
         }
         self.close_session().await;
         Ok(())
@@ -160,33 +244,79 @@ impl<'a> Session<'a> {
     ) -> Result<(), Box<dyn std::error::Error>> {
         self.messages
             .push(Message::user().with_text(initial_message.as_str()));
-        persist_messages(&self.session_file, &self.messages)?;
+        self.persist()?;
 
-        self.agent_process_messages().await;
+        let exceeded = self.agent_process_messages().await;
+        self.update_usage().await;
 
         self.close_session().await;
-        Ok(())
+        match exceeded {
+            Some(exceeded) => Err(Box::new(exceeded)),
+            None => Ok(()),
+        }
     }
 
-    async fn agent_process_messages(&mut self) {
+    /// Run the agent on the conversation, returning the budget limit if one stopped it
+    async fn agent_process_messages(&mut self) -> Option<BudgetExceeded> {
         let mut stream = match self.agent.reply(&self.messages).await {
             Ok(stream) => stream,
             Err(e) => {
                 eprintln!("Error starting reply stream: {}", e);
-                return;
+                return None;
             }
         };
+        // Whether the text of the message being generated has already been shown
+        let mut streamed_text = false;
         loop {
             tokio::select! {
                 response = stream.next() => {
                     match response {
-                        Some(Ok(message)) => {
+                        Some(Ok(AgentEvent::Delta(delta))) => {
+                            if matches!(delta, MessageDelta::Text { .. }) && !streamed_text {
+                                self.prompt.hide_busy();
+                                streamed_text = true;
+                            }
+                            self.prompt.render_delta(&delta);
+                        }
+                        Some(Ok(AgentEvent::Message(message))) => {
                             self.messages.push(message.clone());
-                            persist_messages(&self.session_file, &self.messages).unwrap_or_else(|e| eprintln!("Failed to persist messages: {}", e));
+                            persist_messages(&self.session_file, &mut self.metadata, &self.messages).unwrap_or_else(|e| eprintln!("Failed to persist messages: {}", e));
+                            if streamed_text {
+                                // Only render what was not already streamed
+                                println!();
+                                let mut remaining = message.clone();
+                                remaining.content.retain(|c| !matches!(c, MessageContent::Text(_) | MessageContent::Thinking(_)));
+                                self.prompt.render(Box::new(remaining));
+                                streamed_text = false;
+                            } else {
+                                self.prompt.hide_busy();
+                                self.prompt.render(Box::new(message.clone()));
+                            }
+                            self.prompt.show_busy();
+                        }
+                        Some(Ok(AgentEvent::ToolApproval(approval))) => {
+                            self.prompt.hide_busy();
+                            let approved = self.prompt.confirm_tool_call(&approval.tool_call);
+                            approval.respond(approved);
+                            self.prompt.show_busy();
+                        }
+                        Some(Ok(AgentEvent::PlanApproval(approval))) => {
                             self.prompt.hide_busy();
-                            self.prompt.render(Box::new(message.clone()));
+                            let approved = self.prompt.confirm_plan(&approval.plan);
+                            approval.respond(approved);
                             self.prompt.show_busy();
                         }
+                        Some(Ok(AgentEvent::Plan(plan))) => {
+                            self.prompt.hide_busy();
+                            self.prompt.render_plan(&plan);
+                            self.prompt.show_busy();
+                        }
+                        Some(Err(e)) if e.is::<BudgetExceeded>() => {
+                            // The agent already explained why it stopped, keep the conversation
+                            self.prompt.hide_busy();
+                            eprintln!("{}", style(format!("Stopped: {}", e)).yellow());
+                            return e.downcast::<BudgetExceeded>().ok();
+                        }
                         Some(Err(e)) => {
                             eprintln!("Error: {}", e);
                             drop(stream);
@@ -211,6 +341,7 @@ We've removed the conversation up to the most recent user message
                 }
             }
         }
+        None
     }
 
     /// Rewind the messages to before the last user message (they have cancelled it).
@@ -316,6 +447,12 @@ We've removed the conversation up to the most recent user message
             )
             .as_str(),
         ));
+        if let Some(cost) = self.metadata.cost {
+            println!(
+                "{}",
+                style(format!("Session cost: {}", format_cost(cost))).dim()
+            );
+        }
         self.prompt.close();
         let usage = self.agent.usage().await;
         log_usage(self.session_file.to_string_lossy().to_string(), usage);
@@ -329,3 +466,44 @@ We've removed the conversation up to the most recent user message
 fn raw_message(content: &str) -> Box<Message> {
     Box::new(Message::assistant().with_text(content))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fork_args() {
+        assert_eq!(parse_fork_args("").unwrap(), (None, None));
+        assert_eq!(
+            parse_fork_args("retry --at 3").unwrap(),
+            (Some("retry".to_string()), Some(3))
+        );
+        assert_eq!(parse_fork_args("--at 0").unwrap(), (None, Some(0)));
+        assert!(parse_fork_args("--at").is_err());
+        assert!(parse_fork_args("--at three").is_err());
+        assert!(parse_fork_args("one two").is_err());
+    }
+
+    #[test]
+    fn test_usage_since() {
+        let usage = |model: &str, tokens: i32, cost: Option<f64>| ProviderUsage {
+            cost,
+            ..ProviderUsage::new(
+                model.to_string(),
+                Usage::new(Some(tokens), Some(tokens), Some(tokens * 2)),
+            )
+        };
+        let previous = vec![usage("gpt-4o", 100, Some(1.0))];
+        let current = vec![
+            usage("gpt-4o", 150, Some(1.5)),
+            usage("gpt-4o-mini", 10, None),
+        ];
+
+        let new_usage = usage_since(&previous, &current);
+        assert_eq!(new_usage[0].usage.input_tokens, Some(50));
+        assert_eq!(new_usage[0].usage.total_tokens, Some(100));
+        assert_eq!(new_usage[0].cost, Some(0.5));
+        assert_eq!(new_usage[1].usage.input_tokens, Some(10));
+        assert_eq!(new_usage[1].cost, None);
+    }
+}
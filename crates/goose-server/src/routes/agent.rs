@@ -1,11 +1,12 @@
 use crate::state::AppState;
 use axum::{
-    extract::State,
+    extract::{Path, State},
     http::{HeaderMap, StatusCode},
     routing::{get, post},
     Json, Router,
 };
 use goose::config::Config;
+use goose::providers::base::ModelInfo;
 use goose::{agents::AgentFactory, model::ModelConfig, providers};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -90,17 +91,20 @@ async fn create_agent(
             .expect("Did not find a model on payload or in env")
     });
     let model_config = ModelConfig::new(model);
-    let provider =
-        providers::create(&payload.provider, model_config).expect("Failed to create provider");
+    let provider = providers::create_with_discovery(&payload.provider, model_config)
+        .await
+        .expect("Failed to create provider");
 
     let version = payload
         .version
         .unwrap_or_else(|| AgentFactory::default_version().to_string());
 
     let new_agent = AgentFactory::create(&version, provider).expect("Failed to create agent");
+    let extension_clients = new_agent.extension_clients().await;
 
     let mut agent = state.agent.lock().await;
     *agent = Some(new_agent);
+    *state.extension_clients.lock().await = Some(extension_clients);
 
     Ok(Json(CreateAgentResponse { version }))
 }
@@ -128,10 +132,94 @@ async fn list_providers() -> Json<Vec<ProviderList>> {
     Json(response)
 }
 
+/// The models a provider serves, from its models endpoint
+///
+/// Responds with 501 when the provider can't list its models.
+async fn list_models(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(provider): Path<String>,
+) -> Result<Json<Vec<ModelInfo>>, StatusCode> {
+    // Verify secret key
+    let secret_key = headers
+        .get("X-Secret-Key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    if secret_key != state.secret_key {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    // The model is only needed to create the provider
+    let model = providers::providers()
+        .into_iter()
+        .find(|metadata| metadata.name == provider)
+        .map(|metadata| metadata.default_model)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let provider = providers::create(&provider, ModelConfig::new(model)).map_err(|e| {
+        tracing::warn!("Failed to create provider: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+
+    match provider.list_models().await {
+        Ok(Some(models)) => Ok(Json(models)),
+        Ok(None) => Err(StatusCode::NOT_IMPLEMENTED),
+        Err(e) => {
+            tracing::warn!("Failed to list models: {}", e);
+            Err(StatusCode::BAD_GATEWAY)
+        }
+    }
+}
+
 pub fn routes(state: AppState) -> Router {
     Router::new()
         .route("/agent/versions", get(get_versions))
         .route("/agent/providers", get(list_providers))
+        .route("/agent/providers/:provider/models", get(list_models))
         .route("/agent", post(create_agent))
         .with_state(state)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request};
+    use goose::session::SessionStore;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+    use tower::ServiceExt;
+
+    fn request(uri: &str, secret_key: &str) -> Request<Body> {
+        Request::builder()
+            .uri(uri)
+            .header("x-secret-key", secret_key)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_list_models_route() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = routes(AppState {
+            agent: Arc::new(Mutex::new(None)),
+            extension_clients: Arc::new(Mutex::new(None)),
+            secret_key: "test-secret".to_string(),
+            pending_approvals: Arc::new(Mutex::new(HashMap::new())),
+            pending_plans: Arc::new(Mutex::new(HashMap::new())),
+            session_store: SessionStore::new(dir.path()),
+        });
+
+        let response = app
+            .clone()
+            .oneshot(request("/agent/providers/ollama/models", "wrong"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app
+            .oneshot(request("/agent/providers/unknown/models", "test-secret"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
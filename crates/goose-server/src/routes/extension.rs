@@ -3,11 +3,28 @@ use std::collections::HashMap;
 use crate::state::AppState;
 use axum::{extract::State, routing::post, Json, Router};
 use goose::{
-    agents::{extension::Envs, ExtensionConfig},
+    agents::{
+        extension::{Envs, ExtensionError},
+        ExtensionConfig,
+    },
     config::Config,
 };
 use http::{HeaderMap, StatusCode};
+use mcp_client::client::Error as ClientError;
+use mcp_core::protocol::INTERNAL_ERROR;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// The config key for the JSON-RPC methods that can be passed through to extensions
+const PASSTHROUGH_METHODS_CONFIG_KEY: &str = "GOOSE_PASSTHROUGH_METHODS";
+
+/// The methods passed through when none are configured, these only read from the extension
+const DEFAULT_PASSTHROUGH_METHODS: &[&str] = &[
+    "prompts/list",
+    "prompts/get",
+    "completion/complete",
+    "resources/templates/list",
+];
 
 /// Enum representing the different types of extension configuration requests.
 #[derive(Deserialize)]
@@ -202,10 +219,204 @@ async fn remove_extension(
     }))
 }
 
+/// Load the methods that can be passed through, either as a list or a comma separated string
+fn passthrough_methods() -> Vec<String> {
+    let config = Config::global();
+    config
+        .get::<Vec<String>>(PASSTHROUGH_METHODS_CONFIG_KEY)
+        .or_else(|_| {
+            config
+                .get::<String>(PASSTHROUGH_METHODS_CONFIG_KEY)
+                .map(|methods| {
+                    methods
+                        .split(',')
+                        .map(|method| method.trim().to_string())
+                        .filter(|method| !method.is_empty())
+                        .collect()
+                })
+        })
+        .unwrap_or_else(|_| {
+            DEFAULT_PASSTHROUGH_METHODS
+                .iter()
+                .map(|method| method.to_string())
+                .collect()
+        })
+}
+
+/// Whether a method is allowed, a pattern ending in `*` allows every method that starts with the rest
+fn is_method_allowed(allowed: &[String], method: &str) -> bool {
+    allowed
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => method.starts_with(prefix),
+            None => pattern == method,
+        })
+}
+
+/// Request structure for passing a JSON-RPC request through to an extension.
+#[derive(Deserialize, Serialize)]
+struct PassthroughRequest {
+    /// The name of the extension to send the request to
+    extension: String,
+    /// The JSON-RPC request, with at least a `method`
+    request: Value,
+}
+
+/// Handler for passing a raw JSON-RPC request through to an extension
+///
+/// Only methods allowed by `GOOSE_PASSTHROUGH_METHODS` are passed through. The response is a
+/// JSON-RPC response with the id of the request, errors from the extension are returned in it.
+async fn passthrough(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<PassthroughRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    // Verify the presence and validity of the secret key
+    let secret_key = headers
+        .get("X-Secret-Key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    if secret_key != state.secret_key {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let method = request
+        .request
+        .get("method")
+        .and_then(|v| v.as_str())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    if !is_method_allowed(&passthrough_methods(), method) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let id = request.request.get("id").cloned().unwrap_or(Value::Null);
+
+    // The agent stays locked while it replies, so the request goes through its clients instead
+    let clients = state
+        .extension_clients
+        .lock()
+        .await
+        .clone()
+        .ok_or(StatusCode::PRECONDITION_REQUIRED)?;
+    let error = |code: i32, message: String| {
+        Json(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": code, "message": message},
+        }))
+    };
+    match clients
+        .passthrough(&request.extension, request.request)
+        .await
+    {
+        Ok(result) => Ok(Json(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "result": result,
+        }))),
+        Err(ExtensionError::NotFound(_)) => Err(StatusCode::NOT_FOUND),
+        Err(ExtensionError::InvalidRequest(_)) => Err(StatusCode::BAD_REQUEST),
+        Err(ExtensionError::Client(ClientError::RpcError { code, message })) => {
+            Ok(error(code, message))
+        }
+        Err(e) => Ok(error(INTERNAL_ERROR, e.to_string())),
+    }
+}
+
 /// Registers the extension management routes with the Axum router.
 pub fn routes(state: AppState) -> Router {
     Router::new()
         .route("/extensions/add", post(add_extension))
         .route("/extensions/remove", post(remove_extension))
+        .route("/extensions/passthrough", post(passthrough))
         .with_state(state)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request};
+    use goose::agents::ExtensionClients;
+    use goose::session::SessionStore;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+    use tower::ServiceExt;
+
+    fn passthrough_request(method: &str) -> Request<Body> {
+        Request::builder()
+            .uri("/extensions/passthrough")
+            .method("POST")
+            .header("content-type", "application/json")
+            .header("x-secret-key", "test-secret")
+            .body(Body::from(
+                serde_json::to_string(&PassthroughRequest {
+                    extension: "developer".to_string(),
+                    request: json!({"jsonrpc": "2.0", "id": 1, "method": method}),
+                })
+                .unwrap(),
+            ))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_is_method_allowed() {
+        let allowed = vec!["prompts/*".to_string(), "custom/reload".to_string()];
+        assert!(is_method_allowed(&allowed, "prompts/list"));
+        assert!(is_method_allowed(&allowed, "prompts/get"));
+        assert!(is_method_allowed(&allowed, "custom/reload"));
+        assert!(!is_method_allowed(&allowed, "custom/reload/all"));
+        assert!(!is_method_allowed(&allowed, "tools/call"));
+        assert!(is_method_allowed(&["*".to_string()], "tools/call"));
+    }
+
+    #[tokio::test]
+    async fn test_passthrough_allow_list() {
+        let state = AppState {
+            agent: Arc::new(Mutex::new(None)),
+            extension_clients: Arc::new(Mutex::new(None)),
+            secret_key: "test-secret".to_string(),
+            pending_approvals: Arc::new(Mutex::new(HashMap::new())),
+            pending_plans: Arc::new(Mutex::new(HashMap::new())),
+            session_store: SessionStore::new(std::env::temp_dir()),
+        };
+        let app = routes(state);
+
+        // Tool calls would skip the tool policy, so they aren't passed through by default
+        let response = app
+            .clone()
+            .oneshot(passthrough_request("tools/call"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = app
+            .oneshot(passthrough_request("prompts/list"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+    }
+
+    #[tokio::test]
+    async fn test_passthrough_while_agent_is_locked() {
+        let state = AppState {
+            agent: Arc::new(Mutex::new(None)),
+            extension_clients: Arc::new(Mutex::new(Some(ExtensionClients::default()))),
+            secret_key: "test-secret".to_string(),
+            pending_approvals: Arc::new(Mutex::new(HashMap::new())),
+            pending_plans: Arc::new(Mutex::new(HashMap::new())),
+            session_store: SessionStore::new(std::env::temp_dir()),
+        };
+        // A reply keeps the agent locked until it is done
+        let _agent = state.agent.lock().await;
+        let app = routes(state.clone());
+
+        let response = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            app.oneshot(passthrough_request("prompts/list")),
+        )
+        .await
+        .expect("the passthrough waited for the agent")
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -8,7 +8,9 @@ use axum::{
 };
 use bytes::Bytes;
 use futures::{stream::StreamExt, Stream};
+use goose::agents::{AgentEvent, Plan};
 use goose::message::{Message, MessageContent};
+use goose::providers::base::MessageDelta;
 
 use mcp_core::{content::Content, role::Role};
 use serde::Deserialize;
@@ -141,6 +143,12 @@ impl ProtocolFormatter {
         format!("0:{}\n", encoded_text)
     }
 
+    fn format_reasoning(reasoning: &str) -> String {
+        // Reasoning starts with "g:"
+        let encoded_reasoning = serde_json::to_string(reasoning).unwrap_or_else(|_| String::new());
+        format!("g:{}\n", encoded_reasoning)
+    }
+
     fn format_tool_call(id: &str, name: &str, args: &Value) -> String {
         // Tool calls start with "9:"
         let tool_call = json!({
@@ -151,6 +159,24 @@ impl ProtocolFormatter {
         format!("9:{}\n", tool_call)
     }
 
+    fn format_tool_call_start(id: &str, name: &str) -> String {
+        // Streamed tool calls start with "b:"
+        let tool_call = json!({
+            "toolCallId": id,
+            "toolName": name,
+        });
+        format!("b:{}\n", tool_call)
+    }
+
+    fn format_tool_call_delta(id: &str, args_delta: &str) -> String {
+        // Deltas of streamed tool call arguments start with "c:"
+        let delta = json!({
+            "toolCallId": id,
+            "argsTextDelta": args_delta,
+        });
+        format!("c:{}\n", delta)
+    }
+
     fn format_tool_response(id: &str, result: &Vec<Content>) -> String {
         // Tool responses start with "a:"
         let response = json!({
@@ -160,6 +186,34 @@ impl ProtocolFormatter {
         format!("a:{}\n", response)
     }
 
+    fn format_tool_approval(id: &str, name: &str, args: &Value) -> String {
+        // Custom data parts start with "2:" and hold an array of values
+        let approval = json!([{
+            "type": "toolApproval",
+            "toolCallId": id,
+            "toolName": name,
+            "args": args,
+        }]);
+        format!("2:{}\n", approval)
+    }
+
+    fn format_plan_approval(id: &str, plan: &Plan) -> String {
+        let approval = json!([{
+            "type": "planApproval",
+            "planId": id,
+            "tasks": plan.tasks,
+        }]);
+        format!("2:{}\n", approval)
+    }
+
+    fn format_plan(plan: &Plan) -> String {
+        let progress = json!([{
+            "type": "plan",
+            "tasks": plan.tasks,
+        }]);
+        format!("2:{}\n", progress)
+    }
+
     fn format_error(error: &str) -> String {
         // Error messages start with "3:" in the new protocol.
         let encoded_error = serde_json::to_string(error).unwrap_or_else(|_| String::new());
@@ -179,8 +233,40 @@ impl ProtocolFormatter {
     }
 }
 
+async fn stream_delta(
+    delta: MessageDelta,
+    tx: &mpsc::Sender<String>,
+) -> Result<(), mpsc::error::SendError<String>> {
+    match delta {
+        MessageDelta::Text { text } => {
+            tx.send(ProtocolFormatter::format_text(&text)).await?;
+        }
+        MessageDelta::Thinking { thinking } => {
+            tx.send(ProtocolFormatter::format_reasoning(&thinking))
+                .await?;
+        }
+        MessageDelta::ToolCall {
+            id,
+            name,
+            arguments,
+        } => {
+            if let Some(name) = name {
+                tx.send(ProtocolFormatter::format_tool_call_start(&id, &name))
+                    .await?;
+            }
+            if !arguments.is_empty() {
+                tx.send(ProtocolFormatter::format_tool_call_delta(&id, &arguments))
+                    .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Send a complete message, skipping text and thinking that were already sent as deltas
 async fn stream_message(
     message: Message,
+    streamed_text: bool,
     tx: &mpsc::Sender<String>,
 ) -> Result<(), mpsc::error::SendError<String>> {
     match message.role {
@@ -240,6 +326,7 @@ async fn stream_message(
                             }
                         }
                     }
+                    MessageContent::Text(_) if streamed_text => continue,
                     MessageContent::Text(text) => {
                         for line in text.text.lines() {
                             let modified_line = format!("{}\n", line);
@@ -247,6 +334,13 @@ async fn stream_message(
                                 .await?;
                         }
                     }
+                    MessageContent::Thinking(_) if streamed_text => continue,
+                    MessageContent::Thinking(thinking) => {
+                        if !thinking.redacted {
+                            tx.send(ProtocolFormatter::format_reasoning(&thinking.thinking))
+                                .await?;
+                        }
+                    }
                     MessageContent::Image(_) => {
                         // TODO
                         continue;
@@ -293,6 +387,8 @@ async fn handler(
 
     // Get a lock on the shared agent
     let agent = state.agent.clone();
+    let pending_approvals = state.pending_approvals.clone();
+    let pending_plans = state.pending_plans.clone();
 
     // Spawn task to handle streaming
     tokio::spawn(async move {
@@ -320,12 +416,60 @@ async fn handler(
             }
         };
 
+        // Whether the text and thinking of the message being generated have already been sent as deltas
+        let mut streamed_text = false;
+        // Approvals requested during this reply, which are denied if it ends without an answer
+        let mut approval_ids = Vec::new();
+        let mut plan_ids = Vec::new();
         loop {
             tokio::select! {
                 response = timeout(Duration::from_millis(500), stream.next()) => {
                     match response {
-                        Ok(Some(Ok(message))) => {
-                            if let Err(e) = stream_message(message, &tx).await {
+                        Ok(Some(Ok(AgentEvent::Delta(delta)))) => {
+                            streamed_text |= matches!(
+                                delta,
+                                MessageDelta::Text { .. } | MessageDelta::Thinking { .. }
+                            );
+                            if let Err(e) = stream_delta(delta, &tx).await {
+                                tracing::error!("Error sending message through channel: {}", e);
+                                let _ = tx.send(ProtocolFormatter::format_error(&e.to_string())).await;
+                                break;
+                            }
+                        }
+                        Ok(Some(Ok(AgentEvent::Message(message)))) => {
+                            let sent_text = std::mem::take(&mut streamed_text);
+                            if let Err(e) = stream_message(message, sent_text, &tx).await {
+                                tracing::error!("Error sending message through channel: {}", e);
+                                let _ = tx.send(ProtocolFormatter::format_error(&e.to_string())).await;
+                                break;
+                            }
+                        }
+                        Ok(Some(Ok(AgentEvent::ToolApproval(approval)))) => {
+                            let part = ProtocolFormatter::format_tool_approval(
+                                &approval.id,
+                                &approval.tool_call.name,
+                                &approval.tool_call.arguments,
+                            );
+                            approval_ids.push(approval.id.clone());
+                            pending_approvals.lock().await.insert(approval.id.clone(), approval);
+                            if let Err(e) = tx.send(part).await {
+                                tracing::error!("Error sending message through channel: {}", e);
+                                let _ = tx.send(ProtocolFormatter::format_error(&e.to_string())).await;
+                                break;
+                            }
+                        }
+                        Ok(Some(Ok(AgentEvent::PlanApproval(approval)))) => {
+                            let part = ProtocolFormatter::format_plan_approval(&approval.id, &approval.plan);
+                            plan_ids.push(approval.id.clone());
+                            pending_plans.lock().await.insert(approval.id.clone(), approval);
+                            if let Err(e) = tx.send(part).await {
+                                tracing::error!("Error sending message through channel: {}", e);
+                                let _ = tx.send(ProtocolFormatter::format_error(&e.to_string())).await;
+                                break;
+                            }
+                        }
+                        Ok(Some(Ok(AgentEvent::Plan(plan)))) => {
+                            if let Err(e) = tx.send(ProtocolFormatter::format_plan(&plan)).await {
                                 tracing::error!("Error sending message through channel: {}", e);
                                 let _ = tx.send(ProtocolFormatter::format_error(&e.to_string())).await;
                                 break;
@@ -353,6 +497,22 @@ async fn handler(
             }
         }
 
+        // Deny whatever was left unanswered so the approvals don't outlive the reply
+        let mut pending = pending_approvals.lock().await;
+        for id in approval_ids {
+            if let Some(approval) = pending.remove(&id) {
+                approval.respond(false);
+            }
+        }
+        drop(pending);
+        let mut pending = pending_plans.lock().await;
+        for id in plan_ids {
+            if let Some(approval) = pending.remove(&id) {
+                approval.respond(false);
+            }
+        }
+        drop(pending);
+
         // Send finish message
         let _ = tx.send(ProtocolFormatter::format_finish("stop")).await;
     });
@@ -405,7 +565,7 @@ async fn ask_handler(
 
     while let Some(response) = stream.next().await {
         match response {
-            Ok(message) => {
+            Ok(AgentEvent::Message(message)) => {
                 if message.role == Role::Assistant {
                     for content in message.content {
                         if let MessageContent::Text(text) = content {
@@ -415,6 +575,16 @@ async fn ask_handler(
                     }
                 }
             }
+            Ok(AgentEvent::Delta(_)) => {}
+            Ok(AgentEvent::ToolApproval(approval)) => {
+                // There's no one to ask here, so tools that need approval are not run
+                approval.respond(false);
+            }
+            Ok(AgentEvent::PlanApproval(approval)) => {
+                // The plan still only runs the tools the tool policy allows
+                approval.respond(true);
+            }
+            Ok(AgentEvent::Plan(_)) => {}
             Err(e) => {
                 tracing::error!("Error processing as_ai message: {}", e);
                 return Err(StatusCode::INTERNAL_SERVER_ERROR);
@@ -427,10 +597,80 @@ async fn ask_handler(
     }))
 }
 
+#[derive(Debug, Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ApprovalRequest {
+    tool_call_id: String,
+    approved: bool,
+}
+
+// approve or deny a tool call that is waiting in a /reply stream
+async fn approval_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ApprovalRequest>,
+) -> Result<StatusCode, StatusCode> {
+    // Verify secret key
+    let secret_key = headers
+        .get("X-Secret-Key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    if secret_key != state.secret_key {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let approval = state
+        .pending_approvals
+        .lock()
+        .await
+        .remove(&request.tool_call_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    approval.respond(request.approved);
+
+    Ok(StatusCode::OK)
+}
+
+#[derive(Debug, Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PlanApprovalBody {
+    plan_id: String,
+    approved: bool,
+}
+
+// approve or reject a plan that is waiting in a /reply stream
+async fn plan_approval_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<PlanApprovalBody>,
+) -> Result<StatusCode, StatusCode> {
+    // Verify secret key
+    let secret_key = headers
+        .get("X-Secret-Key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    if secret_key != state.secret_key {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let approval = state
+        .pending_plans
+        .lock()
+        .await
+        .remove(&request.plan_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    approval.respond(request.approved);
+
+    Ok(StatusCode::OK)
+}
+
 // Configure routes for this module
 pub fn routes(state: AppState) -> Router {
     Router::new()
         .route("/reply", post(handler))
+        .route("/reply/approval", post(approval_handler))
+        .route("/reply/plan_approval", post(plan_approval_handler))
         .route("/ask", post(ask_handler))
         .with_state(state)
 }
@@ -531,6 +771,10 @@ mod tests {
         let formatted = ProtocolFormatter::format_text(text);
         assert_eq!(formatted, "0:\"Hello world\"\n");
 
+        // Test reasoning formatting
+        let formatted = ProtocolFormatter::format_reasoning("Let me think");
+        assert_eq!(formatted, "g:\"Let me think\"\n");
+
         // Test tool call formatting
         let formatted =
             ProtocolFormatter::format_tool_call("123", "test_tool", &json!({"key": "value"}));
@@ -559,6 +803,10 @@ mod tests {
     mod integration_tests {
         use super::*;
         use axum::{body::Body, http::Request};
+        use goose::agents::{PlanApprovalRequest, PlanTask, ToolApprovalRequest};
+        use goose::session::SessionStore;
+        use mcp_core::tool::ToolCall;
+        use std::collections::HashMap;
         use std::sync::Arc;
         use tokio::sync::Mutex;
         use tower::ServiceExt;
@@ -574,7 +822,11 @@ mod tests {
             let agent = AgentFactory::create("reference", mock_provider).unwrap();
             let state = AppState {
                 agent: Arc::new(Mutex::new(Some(agent))),
+                extension_clients: Arc::new(Mutex::new(None)),
                 secret_key: "test-secret".to_string(),
+                pending_approvals: Arc::new(Mutex::new(HashMap::new())),
+                pending_plans: Arc::new(Mutex::new(HashMap::new())),
+                session_store: SessionStore::new(std::env::temp_dir()),
             };
 
             // Build router
@@ -600,5 +852,89 @@ mod tests {
             // Assert response status
             assert_eq!(response.status(), StatusCode::OK);
         }
+
+        #[tokio::test]
+        async fn test_approval_endpoint() {
+            let (approval, decision) = ToolApprovalRequest::new(
+                "call-1".to_string(),
+                ToolCall::new("developer__shell", json!({"command": "ls"})),
+            );
+            let state = AppState {
+                agent: Arc::new(Mutex::new(None)),
+                extension_clients: Arc::new(Mutex::new(None)),
+                secret_key: "test-secret".to_string(),
+                pending_approvals: Arc::new(Mutex::new(HashMap::from([(
+                    "call-1".to_string(),
+                    approval,
+                )]))),
+                pending_plans: Arc::new(Mutex::new(HashMap::new())),
+                session_store: SessionStore::new(std::env::temp_dir()),
+            };
+            let app = routes(state.clone());
+
+            let approve = |id: &str| {
+                Request::builder()
+                    .uri("/reply/approval")
+                    .method("POST")
+                    .header("content-type", "application/json")
+                    .header("x-secret-key", "test-secret")
+                    .body(Body::from(
+                        serde_json::to_string(&ApprovalRequest {
+                            tool_call_id: id.to_string(),
+                            approved: true,
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap()
+            };
+
+            let response = app.clone().oneshot(approve("call-1")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(decision.await.unwrap());
+            assert!(state.pending_approvals.lock().await.is_empty());
+
+            // The approval can only be answered once
+            let response = app.oneshot(approve("call-1")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+
+        #[tokio::test]
+        async fn test_plan_approval_endpoint() {
+            let plan = Plan::new(vec![PlanTask::new("run the tests")]);
+            let (approval, decision) = PlanApprovalRequest::new(plan.clone());
+            let id = approval.id.clone();
+            let part = ProtocolFormatter::format_plan_approval(&id, &plan);
+            assert!(part.starts_with("2:"));
+            assert!(part.contains("\"type\":\"planApproval\""));
+            assert!(part.contains("\"status\":\"pending\""));
+
+            let state = AppState {
+                agent: Arc::new(Mutex::new(None)),
+                extension_clients: Arc::new(Mutex::new(None)),
+                secret_key: "test-secret".to_string(),
+                pending_approvals: Arc::new(Mutex::new(HashMap::new())),
+                pending_plans: Arc::new(Mutex::new(HashMap::from([(id.clone(), approval)]))),
+                session_store: SessionStore::new(std::env::temp_dir()),
+            };
+            let app = routes(state.clone());
+
+            let request = Request::builder()
+                .uri("/reply/plan_approval")
+                .method("POST")
+                .header("content-type", "application/json")
+                .header("x-secret-key", "test-secret")
+                .body(Body::from(
+                    serde_json::to_string(&PlanApprovalBody {
+                        plan_id: id,
+                        approved: false,
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(!decision.await.unwrap());
+            assert!(state.pending_plans.lock().await.is_empty());
+        }
     }
 }
@@ -0,0 +1,352 @@
+use crate::state::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+    Json, Router,
+};
+use goose::message::Message;
+use goose::session::{
+    fork_name, SearchResult, SessionError, SessionInfo, SessionMetadata, SessionStore,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize)]
+struct SessionResponse {
+    name: String,
+    metadata: SessionMetadata,
+    messages: Vec<Message>,
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    query: String,
+}
+
+#[derive(Deserialize)]
+struct SaveSessionRequest {
+    messages: Vec<Message>,
+    provider: Option<String>,
+    model: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ForkSessionRequest {
+    /// Name of the new session, generated from the forked session's name if not set
+    name: Option<String>,
+    /// Number of messages to keep, all of them if not set
+    at: Option<usize>,
+}
+
+fn verify_secret_key(state: &AppState, headers: &HeaderMap) -> Result<(), StatusCode> {
+    let secret_key = headers
+        .get("X-Secret-Key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    if secret_key != state.secret_key {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(())
+}
+
+/// Check that the named session can exist in the store, and optionally that it does
+fn verify_name(store: &SessionStore, name: &str, must_exist: bool) -> Result<(), StatusCode> {
+    if !SessionStore::is_valid_name(name) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if must_exist && !store.exists(name) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(())
+}
+
+fn internal_error(error: anyhow::Error) -> StatusCode {
+    tracing::error!("Session store error: {}", error);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+async fn list_sessions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<SessionInfo>>, StatusCode> {
+    verify_secret_key(&state, &headers)?;
+    let sessions = state.session_store.list().map_err(internal_error)?;
+    Ok(Json(sessions))
+}
+
+async fn search_sessions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(search): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchResult>>, StatusCode> {
+    verify_secret_key(&state, &headers)?;
+    if search.query.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let results = state
+        .session_store
+        .search(&search.query)
+        .map_err(internal_error)?;
+    Ok(Json(results))
+}
+
+async fn get_session(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Result<Json<SessionResponse>, StatusCode> {
+    verify_secret_key(&state, &headers)?;
+    verify_name(&state.session_store, &name, true)?;
+    let session = state.session_store.read(&name).map_err(internal_error)?;
+    Ok(Json(SessionResponse {
+        name: session.name,
+        metadata: session.metadata,
+        messages: session.messages,
+    }))
+}
+
+// Create or replace a session, keeping the metadata of an existing one
+async fn save_session(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    Json(request): Json<SaveSessionRequest>,
+) -> Result<Json<SessionMetadata>, StatusCode> {
+    verify_secret_key(&state, &headers)?;
+    verify_name(&state.session_store, &name, false)?;
+
+    let store = &state.session_store;
+    let mut metadata = if store.exists(&name) {
+        store.read(&name).map_err(internal_error)?.metadata
+    } else {
+        SessionMetadata::default()
+    };
+    if request.provider.is_some() {
+        metadata.provider = request.provider;
+    }
+    if request.model.is_some() {
+        metadata.model = request.model;
+    }
+
+    store
+        .write(&name, &mut metadata, &request.messages)
+        .map_err(internal_error)?;
+    Ok(Json(metadata))
+}
+
+// Copy the first `at` messages of a session into a new session
+async fn fork_session(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    Json(request): Json<ForkSessionRequest>,
+) -> Result<Json<SessionResponse>, StatusCode> {
+    verify_secret_key(&state, &headers)?;
+    let store = &state.session_store;
+    verify_name(store, &name, true)?;
+
+    let new_name = request.name.unwrap_or_else(|| fork_name(&name));
+    verify_name(store, &new_name, false)?;
+    if store.exists(&new_name) {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let at = match request.at {
+        Some(at) => at,
+        None => store.read(&name).map_err(internal_error)?.messages.len(),
+    };
+    let fork =
+        store
+            .fork(&name, &new_name, at)
+            .map_err(|e| match e.downcast_ref::<SessionError>() {
+                Some(SessionError::NotFound(_)) => StatusCode::NOT_FOUND,
+                Some(SessionError::AlreadyExists(_)) => StatusCode::CONFLICT,
+                Some(SessionError::InvalidName(_) | SessionError::InvalidFork(_)) => {
+                    tracing::warn!("Failed to fork session {}: {}", name, e);
+                    StatusCode::BAD_REQUEST
+                }
+                None => internal_error(e),
+            })?;
+    Ok(Json(SessionResponse {
+        name: fork.name,
+        metadata: fork.metadata,
+        messages: fork.messages,
+    }))
+}
+
+async fn delete_session(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    verify_secret_key(&state, &headers)?;
+    verify_name(&state.session_store, &name, true)?;
+    state.session_store.delete(&name).map_err(internal_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn routes(state: AppState) -> Router {
+    Router::new()
+        .route("/sessions", get(list_sessions))
+        .route("/sessions/search", get(search_sessions))
+        .route(
+            "/sessions/:name",
+            get(get_session).put(save_session).delete(delete_session),
+        )
+        .route("/sessions/:name/fork", post(fork_session))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+    use tower::ServiceExt;
+
+    fn state(store: SessionStore) -> AppState {
+        AppState {
+            agent: Arc::new(Mutex::new(None)),
+            extension_clients: Arc::new(Mutex::new(None)),
+            secret_key: "test-secret".to_string(),
+            pending_approvals: Arc::new(Mutex::new(HashMap::new())),
+            pending_plans: Arc::new(Mutex::new(HashMap::new())),
+            session_store: store,
+        }
+    }
+
+    fn request(method: &str, uri: &str, body: Body) -> Request<Body> {
+        Request::builder()
+            .uri(uri)
+            .method(method)
+            .header("content-type", "application/json")
+            .header("x-secret-key", "test-secret")
+            .body(body)
+            .unwrap()
+    }
+
+    async fn body_json(response: axum::response::Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_session_routes() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path());
+        let app = routes(state(store.clone()));
+
+        let save = serde_json::json!({
+            "messages": [Message::user().with_text("Fix the login page")],
+            "provider": "openai",
+            "model": "gpt-4o",
+        });
+        let response = app
+            .clone()
+            .oneshot(request(
+                "PUT",
+                "/sessions/login",
+                Body::from(save.to_string()),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(store.exists("login"));
+
+        let response = app
+            .clone()
+            .oneshot(request("GET", "/sessions", Body::empty()))
+            .await
+            .unwrap();
+        let sessions = body_json(response).await;
+        assert_eq!(sessions[0]["name"], "login");
+        assert_eq!(sessions[0]["metadata"]["model"], "gpt-4o");
+
+        let response = app
+            .clone()
+            .oneshot(request(
+                "GET",
+                "/sessions/search?query=login",
+                Body::empty(),
+            ))
+            .await
+            .unwrap();
+        let results = body_json(response).await;
+        assert_eq!(results[0]["matches"][0]["message_index"], 0);
+
+        let response = app
+            .clone()
+            .oneshot(request("GET", "/sessions/login", Body::empty()))
+            .await
+            .unwrap();
+        let session = body_json(response).await;
+        assert_eq!(session["messages"].as_array().unwrap().len(), 1);
+
+        let fork = serde_json::json!({ "name": "login-retry", "at": 0 });
+        let response = app
+            .clone()
+            .oneshot(request(
+                "POST",
+                "/sessions/login/fork",
+                Body::from(fork.to_string()),
+            ))
+            .await
+            .unwrap();
+        let session = body_json(response).await;
+        assert_eq!(session["name"], "login-retry");
+        assert_eq!(session["metadata"]["parent"]["name"], "login");
+        assert!(session["messages"].as_array().unwrap().is_empty());
+
+        let response = app
+            .clone()
+            .oneshot(request(
+                "POST",
+                "/sessions/login/fork",
+                Body::from(serde_json::json!({ "at": 5 }).to_string()),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .clone()
+            .oneshot(request(
+                "POST",
+                "/sessions/login/fork",
+                Body::from(serde_json::json!({}).to_string()),
+            ))
+            .await
+            .unwrap();
+        let session = body_json(response).await;
+        assert!(session["name"].as_str().unwrap().starts_with("login-fork-"));
+
+        let response = app
+            .clone()
+            .oneshot(request(
+                "POST",
+                "/sessions/missing/fork",
+                Body::from(serde_json::json!({}).to_string()),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app
+            .clone()
+            .oneshot(request("DELETE", "/sessions/login", Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let response = app
+            .oneshot(request("GET", "/sessions/login", Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -1,5 +1,7 @@
 use anyhow::Result;
-use goose::agents::Agent;
+use goose::agents::{Agent, ExtensionClients, PlanApprovalRequest, ToolApprovalRequest};
+use goose::session::SessionStore;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
@@ -8,14 +10,26 @@ use tokio::sync::Mutex;
 #[derive(Clone)]
 pub struct AppState {
     pub agent: Arc<Mutex<Option<Box<dyn Agent>>>>,
+    /// The extension clients of the agent, usable while the agent is locked for a reply
+    pub extension_clients: Arc<Mutex<Option<ExtensionClients>>>,
     pub secret_key: String,
+    /// Tool calls waiting for approval, by tool request id
+    pub pending_approvals: Arc<Mutex<HashMap<String, ToolApprovalRequest>>>,
+    /// Plans waiting for approval, by plan id
+    pub pending_plans: Arc<Mutex<HashMap<String, PlanApprovalRequest>>>,
+    /// Saved sessions, shared with the CLI
+    pub session_store: SessionStore,
 }
 
 impl AppState {
     pub async fn new(secret_key: String) -> Result<Self> {
         Ok(Self {
             agent: Arc::new(Mutex::new(None)),
+            extension_clients: Arc::new(Mutex::new(None)),
             secret_key,
+            pending_approvals: Arc::new(Mutex::new(HashMap::new())),
+            pending_plans: Arc::new(Mutex::new(HashMap::new())),
+            session_store: SessionStore::global()?,
         })
     }
 }
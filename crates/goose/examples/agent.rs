@@ -1,6 +1,6 @@
 use dotenv::dotenv;
 use futures::StreamExt;
-use goose::agents::{AgentFactory, ExtensionConfig};
+use goose::agents::{AgentEvent, AgentFactory, ExtensionConfig};
 use goose::message::Message;
 use goose::providers::databricks::DatabricksProvider;
 
@@ -26,11 +26,10 @@ async fn main() {
         .with_text("can you summarize the readme.md in this dir using just a haiku?")];
 
     let mut stream = agent.reply(&messages).await.unwrap();
-    while let Some(message) = stream.next().await {
-        println!(
-            "{}",
-            serde_json::to_string_pretty(&message.unwrap()).unwrap()
-        );
-        println!("\n");
+    while let Some(event) = stream.next().await {
+        if let AgentEvent::Message(message) = event.unwrap() {
+            println!("{}", serde_json::to_string_pretty(&message).unwrap());
+            println!("\n");
+        }
     }
 }
@@ -3,15 +3,36 @@ use async_trait::async_trait;
 use futures::stream::BoxStream;
 use serde_json::Value;
 
+use super::budget::Budget;
+use super::capabilities::ExtensionClients;
 use super::extension::{ExtensionConfig, ExtensionResult};
+use super::plan::{Plan, PlanApprovalRequest};
+use super::policy::ToolApprovalRequest;
 use crate::message::Message;
-use crate::providers::base::ProviderUsage;
+use crate::providers::base::{MessageDelta, ProviderUsage};
+
+/// An event produced by an agent while it generates a reply
+#[derive(Debug, Clone)]
+pub enum AgentEvent {
+    /// A complete message, which belongs in the conversation history
+    Message(Message),
+    /// Partial content of the assistant message that is currently being generated
+    /// The complete message always follows as an `AgentEvent::Message`
+    Delta(MessageDelta),
+    /// A tool call that needs the user's approval, the reply waits until it is answered
+    ToolApproval(ToolApprovalRequest),
+    /// A plan that needs the user's approval, the reply waits until it is answered
+    PlanApproval(PlanApprovalRequest),
+    /// The current state of the plan the agent is working through, sent whenever a task changes
+    Plan(Plan),
+}
 
 /// Core trait defining the behavior of an Agent
 #[async_trait]
 pub trait Agent: Send + Sync {
-    /// Create a stream that yields each message as it's generated by the agent
-    async fn reply(&self, messages: &[Message]) -> Result<BoxStream<'_, Result<Message>>>;
+    /// Create a stream that yields each message as it's generated by the agent, along with
+    /// the deltas of assistant messages while they are being generated
+    async fn reply(&self, messages: &[Message]) -> Result<BoxStream<'_, Result<AgentEvent>>>;
 
     /// Add a new MCP client to the agent
     async fn add_extension(&mut self, config: ExtensionConfig) -> ExtensionResult<()>;
@@ -26,6 +47,12 @@ pub trait Agent: Send + Sync {
     /// Pass through a JSON-RPC request to a specific extension
     async fn passthrough(&self, extension: &str, request: Value) -> ExtensionResult<Value>;
 
+    /// The clients of the extensions, to pass requests through to them while the agent replies
+    async fn extension_clients(&self) -> ExtensionClients;
+
+    /// Set the limits on what the agent can spend in this session
+    async fn set_budget(&mut self, budget: Budget);
+
     /// Get the total usage of the agent
     async fn usage(&self) -> Vec<ProviderUsage>;
 }
@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config::Config;
+use crate::providers::base::ProviderUsage;
+
+/// The config keys for each of the budget limits
+pub const MAX_TOKENS_CONFIG_KEY: &str = "GOOSE_BUDGET_MAX_TOKENS";
+pub const MAX_COST_CONFIG_KEY: &str = "GOOSE_BUDGET_MAX_COST";
+pub const MAX_TURNS_CONFIG_KEY: &str = "GOOSE_BUDGET_MAX_TURNS";
+pub const MAX_TOOL_CALLS_CONFIG_KEY: &str = "GOOSE_BUDGET_MAX_TOOL_CALLS";
+
+/// Caps on what an agent can spend in a session, a limit that isn't set is unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Budget {
+    /// Total tokens across all completions
+    pub max_tokens: Option<i64>,
+    /// Total cost in dollars, counted for models with a known price
+    pub max_cost: Option<f64>,
+    /// Number of completions requested from the provider
+    pub max_turns: Option<usize>,
+    /// Number of tool calls that are run
+    pub max_tool_calls: Option<usize>,
+}
+
+/// What an agent has spent so far in a session
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BudgetUsage {
+    pub tokens: i64,
+    pub cost: f64,
+    pub turns: usize,
+    pub tool_calls: usize,
+}
+
+impl BudgetUsage {
+    /// The usage of the provider completions along with the number of tool calls
+    pub fn new(usage: &[ProviderUsage], tool_calls: usize) -> Self {
+        Self {
+            tokens: usage
+                .iter()
+                .map(|usage| usage.usage.total_tokens.unwrap_or(0) as i64)
+                .sum(),
+            cost: usage.iter().filter_map(|usage| usage.cost).sum(),
+            turns: usage.len(),
+            tool_calls,
+        }
+    }
+}
+
+/// The error an agent stops its reply with once a budget limit is reached
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum BudgetExceeded {
+    #[error("the token budget of {limit} tokens was used up ({used} tokens used)")]
+    Tokens { used: i64, limit: i64 },
+    #[error("the cost budget of ${limit:.2} was used up (${used:.4} spent)")]
+    Cost { used: f64, limit: f64 },
+    #[error("the limit of {limit} turns was reached")]
+    Turns { used: usize, limit: usize },
+    #[error("the limit of {limit} tool calls was reached")]
+    ToolCalls { used: usize, limit: usize },
+}
+
+impl Budget {
+    /// Load the limits set in the config, e.g. `GOOSE_BUDGET_MAX_TOKENS`
+    pub fn from_config() -> Self {
+        let config = Config::global();
+        Self {
+            max_tokens: config.get(MAX_TOKENS_CONFIG_KEY).ok(),
+            max_cost: config.get(MAX_COST_CONFIG_KEY).ok(),
+            max_turns: config.get(MAX_TURNS_CONFIG_KEY).ok(),
+            max_tool_calls: config.get(MAX_TOOL_CALLS_CONFIG_KEY).ok(),
+        }
+    }
+
+    /// Use the limits of this budget, falling back to those of `other` for limits that aren't set
+    pub fn or(self, other: Budget) -> Self {
+        Self {
+            max_tokens: self.max_tokens.or(other.max_tokens),
+            max_cost: self.max_cost.or(other.max_cost),
+            max_turns: self.max_turns.or(other.max_turns),
+            max_tool_calls: self.max_tool_calls.or(other.max_tool_calls),
+        }
+    }
+
+    /// Check whether the agent can request another completion
+    pub fn check(&self, used: &BudgetUsage) -> Result<(), BudgetExceeded> {
+        if let Some(limit) = self.max_tokens.filter(|limit| used.tokens >= *limit) {
+            return Err(BudgetExceeded::Tokens {
+                used: used.tokens,
+                limit,
+            });
+        }
+        if let Some(limit) = self.max_cost.filter(|limit| used.cost >= *limit) {
+            return Err(BudgetExceeded::Cost {
+                used: used.cost,
+                limit,
+            });
+        }
+        if let Some(limit) = self.max_turns.filter(|limit| used.turns >= *limit) {
+            return Err(BudgetExceeded::Turns {
+                used: used.turns,
+                limit,
+            });
+        }
+        if let Some(limit) = self
+            .max_tool_calls
+            .filter(|limit| used.tool_calls >= *limit)
+        {
+            return Err(BudgetExceeded::ToolCalls {
+                used: used.tool_calls,
+                limit,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::base::Usage;
+
+    #[test]
+    fn test_check_limits() {
+        let usage = |total| ProviderUsage {
+            cost: Some(0.5),
+            ..ProviderUsage::new("mock".to_string(), Usage::new(None, None, Some(total)))
+        };
+        let used = BudgetUsage::new(&[usage(100), usage(50)], 3);
+        assert_eq!(used.tokens, 150);
+        assert_eq!(used.cost, 1.0);
+        assert_eq!(used.turns, 2);
+
+        assert!(Budget::default().check(&used).is_ok());
+        let budget = Budget {
+            max_tokens: Some(1000),
+            max_cost: Some(5.0),
+            max_turns: Some(10),
+            max_tool_calls: Some(5),
+        };
+        assert!(budget.check(&used).is_ok());
+
+        assert_eq!(
+            Budget {
+                max_tokens: Some(150),
+                ..budget
+            }
+            .check(&used),
+            Err(BudgetExceeded::Tokens {
+                used: 150,
+                limit: 150
+            })
+        );
+        assert!(matches!(
+            Budget {
+                max_cost: Some(1.0),
+                ..budget
+            }
+            .check(&used),
+            Err(BudgetExceeded::Cost { .. })
+        ));
+        assert!(matches!(
+            Budget {
+                max_turns: Some(2),
+                ..budget
+            }
+            .check(&used),
+            Err(BudgetExceeded::Turns { used: 2, limit: 2 })
+        ));
+        assert!(matches!(
+            Budget {
+                max_tool_calls: Some(3),
+                ..budget
+            }
+            .check(&used),
+            Err(BudgetExceeded::ToolCalls { used: 3, limit: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_or() {
+        let flags = Budget {
+            max_turns: Some(3),
+            ..Default::default()
+        };
+        let config = Budget {
+            max_turns: Some(10),
+            max_cost: Some(2.0),
+            ..Default::default()
+        };
+        let budget = flags.or(config);
+        assert_eq!(budget.max_turns, Some(3));
+        assert_eq!(budget.max_cost, Some(2.0));
+        assert_eq!(budget.max_tokens, None);
+    }
+}
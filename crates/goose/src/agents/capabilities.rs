@@ -2,17 +2,25 @@ use chrono::{DateTime, TimeZone, Utc};
 use futures::stream::{FuturesUnordered, StreamExt};
 use mcp_client::McpService;
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::sync::LazyLock;
 use std::time::Duration;
 use tokio::sync::Mutex;
-use tracing::{debug, instrument};
+use tracing::{debug, instrument, warn};
 
+use super::budget::{Budget, BudgetExceeded, BudgetUsage};
+use super::delegate::{self, DEFAULT_DELEGATE_MAX_TOKENS, DELEGATE_TOOL_NAME};
 use super::extension::{ExtensionConfig, ExtensionError, ExtensionInfo, ExtensionResult};
+use super::factory::AgentFactory;
+use super::policy::{PendingToolCalls, ToolApprovalRequest, ToolPolicy};
+use crate::config::Config;
+use crate::message::{Message, ToolRequest};
 use crate::prompt_template::load_prompt_file;
 use crate::providers::base::{Provider, ProviderUsage};
+use crate::providers::utils::HttpClientConfig;
 use mcp_client::client::{ClientCapabilities, ClientInfo, McpClient, McpClientTrait};
-use mcp_client::transport::{SseTransport, StdioTransport, Transport};
+use mcp_client::transport::{Error as TransportError, SseTransport, StdioTransport, Transport};
 use mcp_core::{Content, Tool, ToolCall, ToolError, ToolResult};
 use serde_json::Value;
 
@@ -23,13 +31,69 @@ static DEFAULT_TIMESTAMP: LazyLock<DateTime<Utc>> =
 
 type McpClientBox = Arc<Mutex<Box<dyn McpClientTrait>>>;
 
+/// The clients of the extensions of an agent, shared so that requests can be passed through
+/// to an extension while the agent is locked for a reply
+#[derive(Clone, Default)]
+pub struct ExtensionClients(Arc<std::sync::RwLock<HashMap<String, McpClientBox>>>);
+
+impl ExtensionClients {
+    fn insert(&self, name: String, client: McpClientBox) {
+        self.0
+            .write()
+            .expect("extension clients lock poisoned")
+            .insert(name, client);
+    }
+
+    fn remove(&self, name: &str) {
+        self.0
+            .write()
+            .expect("extension clients lock poisoned")
+            .remove(name);
+    }
+
+    /// Send a raw JSON-RPC request to an extension and return the result
+    ///
+    /// Only the method and params of the request are used, the client assigns its own id.
+    /// Only the client of the extension is locked while the request is sent.
+    pub async fn passthrough(&self, extension: &str, request: Value) -> ExtensionResult<Value> {
+        let client = self
+            .0
+            .read()
+            .expect("extension clients lock poisoned")
+            .get(&normalize(extension.to_string()))
+            .cloned()
+            .ok_or_else(|| ExtensionError::NotFound(extension.to_string()))?;
+        let method = request
+            .get("method")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ExtensionError::InvalidRequest("missing 'method'".to_string()))?;
+        let params = match request.get("params") {
+            Some(Value::Null) | None => Value::Object(Default::default()),
+            Some(params @ (Value::Object(_) | Value::Array(_))) => params.clone(),
+            Some(_) => {
+                return Err(ExtensionError::InvalidRequest(
+                    "'params' must be an object or an array".to_string(),
+                ))
+            }
+        };
+
+        let client_guard = client.lock().await;
+        Ok(client_guard.request(method, params).await?)
+    }
+}
+
 /// Manages MCP clients and their interactions
 pub struct Capabilities {
     clients: HashMap<String, McpClientBox>,
+    shared_clients: ExtensionClients,
     instructions: HashMap<String, String>,
     resource_capable_extensions: HashSet<String>,
-    provider: Box<dyn Provider>,
+    extension_configs: HashMap<String, ExtensionConfig>,
+    provider: Arc<dyn Provider>,
     provider_usage: Mutex<Vec<ProviderUsage>>,
+    tool_policy: ToolPolicy,
+    budget: Budget,
+    tool_calls: AtomicUsize,
 }
 
 /// A flattened representation of a resource used by the agent to prepare inference
@@ -81,16 +145,93 @@ fn normalize(input: String) -> String {
 
 impl Capabilities {
     /// Create a new Capabilities with the specified provider
+    ///
+    /// The tool policy and budget are loaded from the config, if the policy is invalid every
+    /// tool call asks for approval
     pub fn new(provider: Box<dyn Provider>) -> Self {
+        let tool_policy = ToolPolicy::from_config().unwrap_or_else(|e| {
+            warn!("Asking for approval of every tool call: {}", e);
+            ToolPolicy::ask_always()
+        });
         Self {
             clients: HashMap::new(),
+            shared_clients: ExtensionClients::default(),
             instructions: HashMap::new(),
             resource_capable_extensions: HashSet::new(),
-            provider,
+            extension_configs: HashMap::new(),
+            provider: Arc::from(provider),
             provider_usage: Mutex::new(Vec::new()),
+            tool_policy,
+            budget: Budget::from_config(),
+            tool_calls: AtomicUsize::new(0),
         }
     }
 
+    /// Get a reference to the policy that decides which tool calls can run
+    pub fn tool_policy(&self) -> &ToolPolicy {
+        &self.tool_policy
+    }
+
+    /// Replace the policy that decides which tool calls can run
+    pub fn set_tool_policy(&mut self, tool_policy: ToolPolicy) {
+        self.tool_policy = tool_policy;
+    }
+
+    /// Check the tool calls of the requests against the tool policy
+    ///
+    /// The returned approval requests have to be passed on to the user before the pending
+    /// calls know whether they are permitted.
+    pub fn check_tool_calls(
+        &self,
+        requests: &[&ToolRequest],
+    ) -> (PendingToolCalls, Vec<ToolApprovalRequest>) {
+        PendingToolCalls::new(
+            &self.tool_policy,
+            requests.iter().filter_map(|request| {
+                request
+                    .tool_call
+                    .as_ref()
+                    .ok()
+                    .map(|tool_call| (request.id.clone(), tool_call.clone()))
+            }),
+        )
+    }
+
+    /// Get the limits on what the agent can spend
+    pub fn budget(&self) -> &Budget {
+        &self.budget
+    }
+
+    /// Replace the limits on what the agent can spend
+    pub fn set_budget(&mut self, budget: Budget) {
+        self.budget = budget;
+    }
+
+    /// What the agent has spent so far
+    pub async fn budget_usage(&self) -> BudgetUsage {
+        BudgetUsage::new(
+            &self.provider_usage.lock().await,
+            self.tool_calls.load(Ordering::SeqCst),
+        )
+    }
+
+    /// Check that the agent can request another completion within its budget
+    pub async fn check_budget(&self) -> Result<(), BudgetExceeded> {
+        self.budget.check(&self.budget_usage().await)
+    }
+
+    /// Check the budget before another completion, returning the final message of the reply
+    /// along with the error to end it with once the budget is used up
+    pub async fn budget_stop(&self) -> Option<(Message, BudgetExceeded)> {
+        let exceeded = self.check_budget().await.err()?;
+        warn!("Stopping the reply: {}", exceeded);
+        let message = Message::assistant().with_text(format!(
+            "I stopped here because {}. Raise the budget limits to let me continue.",
+            exceeded
+        ));
+        Some((message, exceeded))
+    }
+
     pub fn supports_resources(&self) -> bool {
         !self.resource_capable_extensions.is_empty()
     }
@@ -100,7 +241,15 @@ impl Capabilities {
     pub async fn add_extension(&mut self, config: ExtensionConfig) -> ExtensionResult<()> {
         let mut client: Box<dyn McpClientTrait> = match &config {
             ExtensionConfig::Sse { uri, envs, .. } => {
-                let transport = SseTransport::new(uri, envs.get_env());
+                // The event stream can stay quiet between messages, so it has no read timeout
+                let http_client = HttpClientConfig {
+                    read_timeout: None,
+                    ..HttpClientConfig::from_config(Config::global())
+                }
+                .build()
+                .map_err(|e| TransportError::SseConnection(e.to_string()))?;
+                let transport =
+                    SseTransport::new(uri, envs.get_env()).with_http_client(http_client);
                 let handle = transport.start().await?;
                 let service = McpService::with_timeout(handle, Duration::from_secs(300));
                 Box::new(McpClient::new(service))
@@ -158,8 +307,10 @@ impl Capabilities {
         }
 
         // Store the client using the provided name
-        self.clients
-            .insert(sanitized_name.clone(), Arc::new(Mutex::new(client)));
+        self.insert_client(sanitized_name.clone(), Arc::new(Mutex::new(client)));
+
+        // Keep the config so child agents can start the same extension
+        self.extension_configs.insert(sanitized_name, config);
 
         Ok(())
     }
@@ -180,8 +331,10 @@ impl Capabilities {
         let sanitized_name = normalize(name.to_string());
 
         self.clients.remove(&sanitized_name);
+        self.shared_clients.remove(&sanitized_name);
         self.instructions.remove(&sanitized_name);
         self.resource_capable_extensions.remove(&sanitized_name);
+        self.extension_configs.remove(&sanitized_name);
         Ok(())
     }
 
@@ -189,9 +342,32 @@ impl Capabilities {
         Ok(self.clients.keys().cloned().collect())
     }
 
+    fn insert_client(&mut self, name: String, client: McpClientBox) {
+        self.shared_clients
+            .insert(name.clone(), Arc::clone(&client));
+        self.clients.insert(name, client);
+    }
+
+    /// The clients of the extensions, which stay up to date as extensions are added and removed
+    pub fn extension_clients(&self) -> ExtensionClients {
+        self.shared_clients.clone()
+    }
+
+    /// Send a raw JSON-RPC request to an extension and return the result
+    ///
+    /// Only the method and params of the request are used, the client assigns its own id.
+    pub async fn passthrough(&self, extension: &str, request: Value) -> ExtensionResult<Value> {
+        self.shared_clients.passthrough(extension, request).await
+    }
+
     pub async fn get_usage(&self) -> Vec<ProviderUsage> {
         let provider_usage = self.provider_usage.lock().await.clone();
         let mut usage_map: HashMap<String, ProviderUsage> = HashMap::new();
+        // Cache counts stay unset for providers that don't report them
+        let add_cached = |a: Option<i32>, b: Option<i32>| match (a, b) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+        };
 
         provider_usage.iter().for_each(|usage| {
             usage_map
@@ -206,6 +382,18 @@ impl Capabilities {
                     e.usage.total_tokens = Some(
                         e.usage.total_tokens.unwrap_or(0) + usage.usage.total_tokens.unwrap_or(0),
                     );
+                    e.usage.cache_read_input_tokens = add_cached(
+                        e.usage.cache_read_input_tokens,
+                        usage.usage.cache_read_input_tokens,
+                    );
+                    e.usage.cache_write_input_tokens = add_cached(
+                        e.usage.cache_write_input_tokens,
+                        usage.usage.cache_write_input_tokens,
+                    );
+                    e.cost = match (e.cost, usage.cost) {
+                        (None, None) => None,
+                        (a, b) => Some(a.unwrap_or(0.0) + b.unwrap_or(0.0)),
+                    };
                 })
                 .or_insert_with(|| usage.clone());
         });
@@ -472,6 +660,95 @@ impl Capabilities {
         }
     }
 
+    /// The budget of a child agent, which can't spend more than what is left of this budget
+    async fn child_budget(&self, max_tokens: Option<i64>) -> Budget {
+        let used = self.budget_usage().await;
+        let max_tokens = max_tokens.unwrap_or(DEFAULT_DELEGATE_MAX_TOKENS);
+        Budget {
+            max_tokens: Some(match self.budget.max_tokens {
+                Some(limit) => max_tokens.min(limit - used.tokens).max(0),
+                None => max_tokens,
+            }),
+            max_cost: self
+                .budget
+                .max_cost
+                .map(|limit| (limit - used.cost).max(0.0)),
+            max_tool_calls: self
+                .budget
+                .max_tool_calls
+                .map(|limit| limit.saturating_sub(used.tool_calls)),
+            ..Budget::default()
+        }
+    }
+
+    // Function that gets executed for the delegate tool
+    async fn delegate(&self, params: Value) -> Result<Vec<Content>, ToolError> {
+        if !delegate::can_delegate() {
+            return Err(ToolError::ExecutionError(
+                "Subagents can't delegate tasks any further".to_string(),
+            ));
+        }
+
+        let task = params
+            .get("task")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ToolError::InvalidParameters("Missing 'task' parameter".to_string()))?;
+
+        let extensions = match params.get("extensions").and_then(|v| v.as_array()) {
+            Some(names) => names
+                .iter()
+                .filter_map(|v| v.as_str())
+                .map(|name| {
+                    self.extension_configs
+                        .get(&normalize(name.to_string()))
+                        .cloned()
+                        .ok_or_else(|| {
+                            ToolError::InvalidParameters(format!(
+                                "Extension '{}' not found. Here are the available extensions: {}",
+                                name,
+                                self.extension_configs
+                                    .keys()
+                                    .map(|s| s.as_str())
+                                    .collect::<Vec<&str>>()
+                                    .join(", ")
+                            ))
+                        })
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            // Starting every extension again for each task is slow, so the tool has to ask
+            None => Vec::new(),
+        };
+
+        let budget = self
+            .child_budget(params.get("max_tokens").and_then(|v| v.as_i64()))
+            .await;
+
+        // The child shares our provider but keeps its own message history and usage
+        let mut agent = AgentFactory::create(
+            AgentFactory::default_version(),
+            Box::new(Arc::clone(&self.provider)),
+        )
+        .ok_or_else(|| ToolError::ExecutionError("No agent to delegate to".to_string()))?;
+        agent.set_budget(budget).await;
+        for config in extensions {
+            agent
+                .add_extension(config)
+                .await
+                .map_err(|e| ToolError::ExecutionError(e.to_string()))?;
+        }
+
+        let result = delegate::run_child(agent.as_ref(), task).await;
+
+        // Roll the usage of the child up into ours, whether or not it finished
+        for usage in agent.usage().await {
+            self.record_usage(usage).await;
+        }
+
+        result
+            .map(|summary| vec![Content::text(summary)])
+            .map_err(|e| ToolError::ExecutionError(e.to_string()))
+    }
+
     /// Dispatch a single tool call to the appropriate client
     #[instrument(skip(self, tool_call), fields(input, output))]
     pub async fn dispatch_tool_call(&self, tool_call: ToolCall) -> ToolResult<Vec<Content>> {
@@ -480,6 +757,8 @@ impl Capabilities {
             self.read_resource(tool_call.arguments.clone()).await
         } else if tool_call.name == "platform__list_resources" {
             self.list_resources(tool_call.arguments.clone()).await
+        } else if tool_call.name == DELEGATE_TOOL_NAME {
+            self.delegate(tool_call.arguments.clone()).await
         } else {
             // Else, dispatch tool call based on the prefix naming convention
             let (client_name, client) = self
@@ -509,12 +788,41 @@ impl Capabilities {
 
         result
     }
+
+    /// Dispatch a tool call if it is permitted, otherwise respond that it was denied
+    ///
+    /// Calls past the tool call limit of the budget are denied as well.
+    pub async fn dispatch_permitted_tool_call(
+        &self,
+        tool_call: ToolCall,
+        permitted: bool,
+    ) -> ToolResult<Vec<Content>> {
+        if !permitted {
+            return Err(ToolError::ExecutionError(format!(
+                "The call to {} was denied by the tool policy or the user",
+                tool_call.name
+            )));
+        }
+        let limit = self.budget.max_tool_calls.unwrap_or(usize::MAX);
+        if self
+            .tool_calls
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                (used < limit).then_some(used + 1)
+            })
+            .is_err()
+        {
+            return Err(ToolError::ExecutionError(format!(
+                "The call to {} was not run, the budget of {} tool calls was used up",
+                tool_call.name, limit
+            )));
+        }
+        self.dispatch_tool_call(tool_call).await
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::message::Message;
     use crate::model::ModelConfig;
     use crate::providers::base::{Provider, ProviderMetadata, ProviderUsage, Usage};
     use crate::providers::errors::ProviderError;
@@ -581,6 +889,16 @@ mod tests {
             Err(Error::NotInitialized)
         }
 
+        async fn request(&self, method: &str, params: Value) -> Result<Value, Error> {
+            match method {
+                "prompts/list" => Ok(json!({"prompts": [], "params": params})),
+                _ => Err(Error::RpcError {
+                    code: mcp_core::protocol::METHOD_NOT_FOUND,
+                    message: format!("Method not found: {}", method),
+                }),
+            }
+        }
+
         async fn call_tool(&self, name: &str, _arguments: Value) -> Result<CallToolResult, Error> {
             match name {
                 "tool" | "test__tool" => Ok(CallToolResult {
@@ -731,4 +1049,166 @@ mod tests {
         let result = capabilities.dispatch_tool_call(invalid_tool_call).await;
         assert!(matches!(result.err().unwrap(), ToolError::NotFound(_)));
     }
+
+    #[tokio::test]
+    async fn test_tool_call_budget() {
+        let mut capabilities = Capabilities::new(Box::new(MockProvider {
+            model_config: ModelConfig::new("test-model".to_string()),
+        }));
+        capabilities.clients.insert(
+            normalize("test_client".to_string()),
+            Arc::new(Mutex::new(Box::new(MockClient {}))),
+        );
+        capabilities.set_budget(Budget {
+            max_tool_calls: Some(2),
+            ..Default::default()
+        });
+
+        let call = || ToolCall::new("test_client__tool", json!({}));
+        // Denied calls don't count against the budget
+        assert!(capabilities
+            .dispatch_permitted_tool_call(call(), false)
+            .await
+            .is_err());
+        assert!(capabilities.check_budget().await.is_ok());
+
+        assert!(capabilities
+            .dispatch_permitted_tool_call(call(), true)
+            .await
+            .is_ok());
+        assert!(capabilities
+            .dispatch_permitted_tool_call(call(), true)
+            .await
+            .is_ok());
+        let result = capabilities
+            .dispatch_permitted_tool_call(call(), true)
+            .await;
+        assert!(
+            matches!(result, Err(ToolError::ExecutionError(message)) if message.contains("budget"))
+        );
+
+        assert_eq!(capabilities.budget_usage().await.tool_calls, 2);
+        assert_eq!(
+            capabilities.check_budget().await,
+            Err(BudgetExceeded::ToolCalls { used: 2, limit: 2 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delegate() {
+        let capabilities = Capabilities::new(Box::new(MockProvider {
+            model_config: ModelConfig::new("test-model".to_string()),
+        }));
+
+        let call = |arguments| ToolCall::new(DELEGATE_TOOL_NAME, arguments);
+        let result = capabilities
+            .dispatch_tool_call(call(json!({"task": "Summarize the repository"})))
+            .await
+            .unwrap();
+        assert_eq!(result, vec![Content::text("Mock response")]);
+
+        // The completion of the child counts as usage of the parent
+        let usage = capabilities.get_usage().await;
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].model, "mock");
+
+        let result = capabilities.dispatch_tool_call(call(json!({}))).await;
+        assert!(matches!(result, Err(ToolError::InvalidParameters(_))));
+
+        let result = capabilities
+            .dispatch_tool_call(call(
+                json!({"task": "Read the files", "extensions": ["developer"]}),
+            ))
+            .await;
+        assert!(
+            matches!(result, Err(ToolError::InvalidParameters(message)) if message.contains("developer"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_child_budget() {
+        let mut capabilities = Capabilities::new(Box::new(MockProvider {
+            model_config: ModelConfig::new("test-model".to_string()),
+        }));
+        capabilities.set_budget(Budget::default());
+        assert_eq!(
+            capabilities.child_budget(None).await,
+            Budget {
+                max_tokens: Some(DEFAULT_DELEGATE_MAX_TOKENS),
+                ..Default::default()
+            }
+        );
+
+        // The child can't spend more than what is left of the parent's budget
+        capabilities.set_budget(Budget {
+            max_tokens: Some(1000),
+            max_cost: Some(1.0),
+            ..Default::default()
+        });
+        capabilities
+            .record_usage(ProviderUsage::new(
+                "mock".to_string(),
+                Usage::new(Some(300), Some(100), Some(400)),
+            ))
+            .await;
+        let budget = capabilities.child_budget(Some(5000)).await;
+        assert_eq!(budget.max_tokens, Some(600));
+        assert_eq!(budget.max_cost, Some(1.0));
+        assert_eq!(
+            capabilities.child_budget(Some(100)).await.max_tokens,
+            Some(100)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_passthrough() {
+        let mut capabilities = Capabilities::new(Box::new(MockProvider {
+            model_config: ModelConfig::new("test-model".to_string()),
+        }));
+        capabilities.insert_client(
+            normalize("test_client".to_string()),
+            Arc::new(Mutex::new(Box::new(MockClient {}))),
+        );
+
+        let result = capabilities
+            .passthrough(
+                "test_client",
+                json!({"jsonrpc": "2.0", "id": 7, "method": "prompts/list"}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, json!({"prompts": [], "params": {}}));
+
+        let result = capabilities
+            .passthrough("test_client", json!({"method": "custom/thing"}))
+            .await;
+        assert!(matches!(
+            result,
+            Err(ExtensionError::Client(Error::RpcError { .. }))
+        ));
+
+        let result = capabilities
+            .passthrough("missing", json!({"method": "prompts/list"}))
+            .await;
+        assert!(matches!(result, Err(ExtensionError::NotFound(_))));
+
+        let result = capabilities
+            .passthrough(
+                "test_client",
+                json!({"method": "prompts/list", "params": 1}),
+            )
+            .await;
+        assert!(matches!(result, Err(ExtensionError::InvalidRequest(_))));
+
+        // The shared clients follow the extensions, and don't need the capabilities
+        let clients = capabilities.extension_clients();
+        let request = json!({"method": "prompts/list"});
+        assert!(clients
+            .passthrough("test_client", request.clone())
+            .await
+            .is_ok());
+        capabilities.remove_extension("test_client").await.unwrap();
+        let result = clients.passthrough("test_client", request).await;
+        assert!(matches!(result, Err(ExtensionError::NotFound(_))));
+    }
 }
@@ -0,0 +1,141 @@
+/// Delegation of a task to a child agent, which works through it with its own message history
+/// so that only its final summary ends up in the context of the parent
+use futures::StreamExt;
+use indoc::indoc;
+use mcp_core::role::Role;
+use mcp_core::tool::Tool;
+use serde_json::json;
+use tracing::warn;
+
+use super::{Agent, AgentEvent};
+use crate::message::Message;
+
+pub const DELEGATE_TOOL_NAME: &str = "platform__delegate";
+
+/// The token budget of a child agent when the call doesn't set one
+pub const DEFAULT_DELEGATE_MAX_TOKENS: i64 = 200_000;
+
+/// How deeply delegation can nest, a child agent can't delegate any further
+const MAX_DELEGATION_DEPTH: usize = 1;
+
+tokio::task_local! {
+    static DELEGATION_DEPTH: usize;
+}
+
+/// How many delegating agents the current task is running under
+fn delegation_depth() -> usize {
+    DELEGATION_DEPTH.try_with(|depth| *depth).unwrap_or(0)
+}
+
+/// Whether an agent running in the current task can delegate to a child agent
+pub fn can_delegate() -> bool {
+    delegation_depth() < MAX_DELEGATION_DEPTH
+}
+
+pub fn delegate_tool() -> Tool {
+    Tool::new(
+        DELEGATE_TOOL_NAME.to_string(),
+        indoc! {r#"
+            Delegate a self-contained task to a subagent.
+
+            The subagent starts from a fresh conversation with only the task description, works
+            through it with the extensions it is given, and returns a summary of its work. Use this
+            for tasks that need a lot of intermediate reading, such as exploring many files, so that
+            only the result takes up space in this conversation. The task description must contain
+            everything the subagent needs to know.
+
+            Nobody can approve the tool calls of the subagent, so calls that need the user's approval
+            are denied. The summary lists the tool calls that were denied this way.
+        "#}
+        .to_string(),
+        json!({
+            "type": "object",
+            "required": ["task"],
+            "properties": {
+                "task": {"type": "string", "description": "Instructions for the subagent, including what to put in its summary"},
+                "extensions": {
+                    "type": "array",
+                    "items": {"type": "string"},
+                    "description": "Optional names of the extensions the subagent can use, it gets none unless they are named"
+                },
+                "max_tokens": {"type": "integer", "description": "Optional token budget of the subagent"}
+            }
+        }),
+    )
+}
+
+/// Run a child agent on a task until it is done and return its final summary
+///
+/// Nobody can answer the approval requests of the child, so tool calls that need one are denied
+/// and listed in the summary.
+pub async fn run_child(agent: &dyn Agent, task: &str) -> anyhow::Result<String> {
+    let messages = vec![Message::user().with_text(format!(
+        "{}\n\nWhen you are done, reply with a concise summary of what you did and found. \
+         The summary is the only part of your work that is passed on.",
+        task
+    ))];
+
+    DELEGATION_DEPTH
+        .scope(delegation_depth() + 1, async {
+            let mut stream = agent.reply(&messages).await?;
+            let mut summary = None;
+            let mut denied = Vec::new();
+            while let Some(event) = stream.next().await {
+                match event {
+                    Ok(AgentEvent::Message(message))
+                        if message.role == Role::Assistant && !message.is_tool_call() =>
+                    {
+                        summary = Some(message.as_concat_text());
+                    }
+                    Ok(AgentEvent::ToolApproval(approval)) => {
+                        approval.respond(false);
+                        denied.push(approval.tool_call.name.clone());
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!("Subagent stopped early: {}", e);
+                        let summary = format!(
+                            "The subagent stopped before finishing: {}\n\n{}",
+                            e,
+                            summary.unwrap_or_default()
+                        );
+                        return Ok(with_denials(summary, &denied));
+                    }
+                }
+            }
+            let summary = summary
+                .filter(|summary| !summary.is_empty())
+                .unwrap_or_else(|| "The subagent finished without a summary".to_string());
+            Ok(with_denials(summary, &denied))
+        })
+        .await
+}
+
+/// Add the tool calls that were denied for lack of approval to the summary of the child
+fn with_denials(summary: String, denied: &[String]) -> String {
+    if denied.is_empty() {
+        return summary;
+    }
+    format!(
+        "{}\n\nThese tool calls of the subagent needed the user's approval and were denied: {}",
+        summary.trim_end(),
+        denied.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_denials() {
+        assert_eq!(with_denials("Done".to_string(), &[]), "Done");
+        assert_eq!(
+            with_denials(
+                "Done\n".to_string(),
+                &["developer__shell".to_string(), "developer__shell".to_string()]
+            ),
+            "Done\n\nThese tool calls of the subagent needed the user's approval and were denied: developer__shell, developer__shell"
+        );
+    }
+}
@@ -15,6 +15,10 @@ pub enum ExtensionError {
     ContextLimit,
     #[error("Transport error: {0}")]
     Transport(#[from] mcp_client::transport::Error),
+    #[error("Extension {0} not found")]
+    NotFound(String),
+    #[error("Invalid JSON-RPC request: {0}")]
+    InvalidRequest(String),
 }
 
 pub type ExtensionResult<T> = Result<T, ExtensionError>;
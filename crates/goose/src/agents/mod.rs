@@ -1,11 +1,22 @@
 mod agent;
+pub mod budget;
 mod capabilities;
+mod delegate;
 pub mod extension;
 mod factory;
+pub mod plan;
+mod planner;
+pub mod policy;
 mod reference;
+pub mod scheduler;
+mod summarize;
 mod truncate;
 
-pub use agent::Agent;
-pub use capabilities::Capabilities;
+pub use agent::{Agent, AgentEvent};
+pub use budget::{Budget, BudgetExceeded};
+pub use capabilities::{Capabilities, ExtensionClients};
 pub use extension::ExtensionConfig;
 pub use factory::{register_agent, AgentFactory};
+pub use plan::{Plan, PlanApprovalRequest, PlanTask, TaskStatus};
+pub use policy::{PendingToolCalls, PolicyDecision, ToolApprovalRequest, ToolPolicy};
+pub use scheduler::ToolScheduler;
@@ -0,0 +1,206 @@
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+
+/// The plan for replies that don't need any tools, as described in the plan prompt
+pub const REPLY_TASK: &str = "reply to the user";
+
+/// Where a task of a plan stands
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    #[default]
+    Pending,
+    InProgress,
+    Done,
+    Failed,
+}
+
+/// A single step of a plan
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanTask {
+    pub description: String,
+    #[serde(default)]
+    pub status: TaskStatus,
+}
+
+impl PlanTask {
+    pub fn new<S: Into<String>>(description: S) -> Self {
+        Self {
+            description: description.into(),
+            status: TaskStatus::Pending,
+        }
+    }
+}
+
+/// A list of tasks the agent works through one by one
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    pub tasks: Vec<PlanTask>,
+}
+
+impl Plan {
+    pub fn new(tasks: Vec<PlanTask>) -> Self {
+        Self { tasks }
+    }
+
+    /// The JSON schema of a plan in the format of the plan prompt
+    pub fn schema() -> Value {
+        json!({
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {"description": {"type": "string"}},
+                "required": ["description"]
+            },
+            "minItems": 1
+        })
+    }
+
+    /// Read the tasks of a plan that was checked against `Plan::schema`, they all start out pending
+    pub fn from_value(value: Value) -> serde_json::Result<Self> {
+        let tasks: Vec<PlanTask> = serde_json::from_value(value)?;
+        Ok(Self::new(
+            tasks
+                .into_iter()
+                .map(|task| PlanTask::new(task.description))
+                .collect(),
+        ))
+    }
+
+    /// Whether the plan is just to reply, in which case there is nothing to approve or track
+    pub fn is_reply_only(&self) -> bool {
+        matches!(self.tasks.as_slice(), [task] if task.description.trim().eq_ignore_ascii_case(REPLY_TASK))
+    }
+
+    /// The index of the first task that still has to be done
+    pub fn next_pending(&self) -> Option<usize> {
+        self.tasks
+            .iter()
+            .position(|task| task.status == TaskStatus::Pending)
+    }
+
+    /// Replace the tasks that were not done yet with those of a new plan
+    pub fn replan(&mut self, plan: Plan) {
+        self.tasks
+            .retain(|task| matches!(task.status, TaskStatus::Done | TaskStatus::Failed));
+        self.tasks.extend(plan.tasks);
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, task) in self.tasks.iter().enumerate() {
+            let marker = match task.status {
+                TaskStatus::Pending => "[ ]",
+                TaskStatus::InProgress => "[>]",
+                TaskStatus::Done => "[x]",
+                TaskStatus::Failed => "[!]",
+            };
+            writeln!(f, "{} {}. {}", marker, i + 1, task.description)?;
+        }
+        Ok(())
+    }
+}
+
+/// A plan that is waiting for the user to approve or reject it
+///
+/// The agent pauses the reply until `respond` is called. Dropping every copy of the
+/// request without responding rejects the plan.
+#[derive(Debug, Clone)]
+pub struct PlanApprovalRequest {
+    pub id: String,
+    pub plan: Plan,
+    responder: Arc<std::sync::Mutex<Option<oneshot::Sender<bool>>>>,
+}
+
+impl PlanApprovalRequest {
+    /// Create a request along with the receiver for the user's decision
+    pub fn new(plan: Plan) -> (Self, oneshot::Receiver<bool>) {
+        let (tx, rx) = oneshot::channel();
+        let request = Self {
+            id: format!("plan_{}", uuid::Uuid::new_v4()),
+            plan,
+            responder: Arc::new(std::sync::Mutex::new(Some(tx))),
+        };
+        (request, rx)
+    }
+
+    /// Approve or reject the plan, only the first response is used
+    pub fn respond(&self, approved: bool) {
+        if let Some(tx) = self.responder.lock().unwrap().take() {
+            let _ = tx.send(approved);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_from_value() {
+        let plan = Plan::from_value(json!([
+            {"description": "create a directory 'demo'"},
+            {"description": "run python demo/fibonacci.py", "status": "done"}
+        ]))
+        .unwrap();
+        assert_eq!(plan.tasks.len(), 2);
+        assert!(plan
+            .tasks
+            .iter()
+            .all(|task| task.status == TaskStatus::Pending));
+        assert!(!plan.is_reply_only());
+
+        let plan = Plan::from_value(json!([{"description": "Reply to the user"}])).unwrap();
+        assert!(plan.is_reply_only());
+    }
+
+    #[test]
+    fn test_replan_keeps_finished_tasks() {
+        let mut plan = Plan::new(vec![
+            PlanTask::new("write the file"),
+            PlanTask::new("run the tests"),
+            PlanTask::new("commit"),
+        ]);
+        plan.tasks[0].status = TaskStatus::Done;
+        plan.tasks[1].status = TaskStatus::Failed;
+        assert_eq!(plan.next_pending(), Some(2));
+
+        plan.replan(Plan::new(vec![
+            PlanTask::new("fix the failing test"),
+            PlanTask::new("run the tests again"),
+        ]));
+        let descriptions: Vec<_> = plan.tasks.iter().map(|t| t.description.as_str()).collect();
+        assert_eq!(
+            descriptions,
+            vec![
+                "write the file",
+                "run the tests",
+                "fix the failing test",
+                "run the tests again"
+            ]
+        );
+        assert_eq!(plan.next_pending(), Some(2));
+        assert_eq!(
+            plan.to_string(),
+            "[x] 1. write the file\n[!] 2. run the tests\n[ ] 3. fix the failing test\n[ ] 4. run the tests again\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_approval_request() {
+        let (request, decision) = PlanApprovalRequest::new(Plan::default());
+        assert!(request.id.starts_with("plan_"));
+        request.clone().respond(true);
+        request.respond(false);
+        assert!(decision.await.unwrap());
+
+        let (request, decision) = PlanApprovalRequest::new(Plan::default());
+        drop(request);
+        assert!(decision.await.is_err());
+    }
+}
@@ -0,0 +1,435 @@
+/// A planner agent that first plans its reply as a list of tasks, using the plan prompt
+/// Once the user approves the plan it works through the tasks one by one, and re-plans when a task fails
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+use tracing::{debug, error, instrument, warn};
+
+use super::{Agent, AgentEvent};
+use crate::agents::budget::Budget;
+use crate::agents::capabilities::{Capabilities, ExtensionClients};
+use crate::agents::extension::{ExtensionConfig, ExtensionResult};
+use crate::agents::plan::{Plan, PlanApprovalRequest, PlanTask, TaskStatus, REPLY_TASK};
+use crate::agents::scheduler::ToolScheduler;
+use crate::agents::truncate::{reply_loop, reply_tools, LoopEvent, TruncateToFit};
+use crate::message::Message;
+use crate::prompt_template::load_prompt_file;
+use crate::providers::base::Provider;
+use crate::providers::base::ProviderUsage;
+use crate::providers::errors::ProviderError;
+use crate::register_agent;
+use crate::token_counter::TokenCounter;
+use mcp_core::role::Role;
+use mcp_core::tool::Tool;
+use serde_json::Value;
+
+/// How often a reply is re-planned after a failed task before the agent gives up
+const MAX_REPLANS: usize = 2;
+/// What the model starts its reply with when it can't complete a task
+const TASK_FAILED_MARKER: &str = "TASK FAILED";
+
+/// Planner implementation of an Agent
+pub struct PlannerAgent {
+    capabilities: Mutex<Capabilities>,
+    scheduler: ToolScheduler,
+    token_counter: TokenCounter,
+}
+
+impl PlannerAgent {
+    pub fn new(provider: Box<dyn Provider>) -> Self {
+        let token_counter = TokenCounter::new(provider.get_model_config().tokenizer_name());
+        Self {
+            capabilities: Mutex::new(Capabilities::new(provider)),
+            scheduler: ToolScheduler::from_config(),
+            token_counter,
+        }
+    }
+
+    /// Ask the model for a plan for the conversation, based on the tools it can call
+    async fn plan(
+        &self,
+        capabilities: &Capabilities,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<Plan, ProviderError> {
+        let mut context = HashMap::new();
+        context.insert("tools", tools);
+        let system = load_prompt_file("plan.md", &context)
+            .map_err(|e| ProviderError::ExecutionError(e.to_string()))?;
+
+        let (value, usage) = capabilities
+            .provider()
+            .complete_structured(&system, messages, &Plan::schema())
+            .await?;
+        capabilities.record_usage(usage).await;
+        Plan::from_value(value).map_err(|e| ProviderError::ExecutionError(e.to_string()))
+    }
+}
+
+/// The part of the system prompt that keeps the model on the current task of the plan
+fn task_prompt(plan: &Plan, index: usize) -> String {
+    format!(
+        "# Plan\n\nYou are following this plan, which the user approved:\n\n{}\n\
+         Work on task {} now: {}\n\n\
+         Only do this task, the tasks after it come later. When it is done, reply with a short \
+         summary of the result. If it can't be done, start your reply with \"{}\" and explain what \
+         went wrong.",
+        plan,
+        index + 1,
+        plan.tasks[index].description,
+        TASK_FAILED_MARKER
+    )
+}
+
+/// The request for a new plan after a task failed, added to the conversation sent to the planner
+fn replan_request(plan: &Plan, index: usize, reason: &str) -> Message {
+    Message::user().with_text(format!(
+        "Task {} of the plan failed: {}\n\nThis is the plan so far:\n\n{}\n\
+         Plan the remaining work, taking into account what was already done.",
+        index + 1,
+        reason,
+        plan
+    ))
+}
+
+#[async_trait]
+impl Agent for PlannerAgent {
+    async fn add_extension(&mut self, extension: ExtensionConfig) -> ExtensionResult<()> {
+        let mut capabilities = self.capabilities.lock().await;
+        capabilities.add_extension(extension).await
+    }
+
+    async fn remove_extension(&mut self, name: &str) {
+        let mut capabilities = self.capabilities.lock().await;
+        capabilities
+            .remove_extension(name)
+            .await
+            .expect("Failed to remove extension");
+    }
+
+    async fn list_extensions(&self) -> Vec<String> {
+        let capabilities = self.capabilities.lock().await;
+        capabilities
+            .list_extensions()
+            .await
+            .expect("Failed to list extensions")
+    }
+
+    async fn passthrough(&self, extension: &str, request: Value) -> ExtensionResult<Value> {
+        let capabilities = self.capabilities.lock().await;
+        capabilities.passthrough(extension, request).await
+    }
+
+    async fn extension_clients(&self) -> ExtensionClients {
+        let capabilities = self.capabilities.lock().await;
+        capabilities.extension_clients()
+    }
+
+    #[instrument(skip(self, messages), fields(user_message))]
+    async fn reply(
+        &self,
+        messages: &[Message],
+    ) -> anyhow::Result<BoxStream<'_, anyhow::Result<AgentEvent>>> {
+        let mut messages = messages.to_vec();
+        let reply_span = tracing::Span::current();
+        let mut capabilities = self.capabilities.lock().await;
+        let tools = reply_tools(&mut capabilities).await?;
+        let system_prompt = capabilities.get_system_prompt().await;
+
+        // Set the user_message field in the span instead of creating a new event
+        if let Some(content) = messages
+            .last()
+            .and_then(|msg| msg.content.first())
+            .and_then(|c| c.as_text())
+        {
+            debug!("user_message" = &content);
+        }
+
+        Ok(Box::pin(async_stream::try_stream! {
+            let _reply_guard = reply_span.enter();
+
+            if let Some((message, exceeded)) = capabilities.budget_stop().await {
+                yield AgentEvent::Message(message);
+                Err(exceeded)?;
+            }
+
+            // Without a plan the agent still replies, as if the plan was to reply to the user
+            let mut plan = match self.plan(&capabilities, &messages, &tools).await {
+                Ok(plan) => plan,
+                Err(e) => {
+                    warn!("Replying without a plan: {}", e);
+                    Plan::new(vec![PlanTask::new(REPLY_TASK)])
+                }
+            };
+            let tracked = !plan.is_reply_only();
+            let mut needs_approval = tracked;
+            let mut replans: usize = 0;
+
+            loop {
+                if needs_approval {
+                    let (approval, decision) = PlanApprovalRequest::new(plan.clone());
+                    yield AgentEvent::PlanApproval(approval);
+                    if !decision.await.unwrap_or(false) {
+                        yield AgentEvent::Message(Message::assistant().with_text(
+                            "I won't go ahead with this plan. Let me know what to change and I'll make a new one.",
+                        ));
+                        break;
+                    }
+                    needs_approval = false;
+                }
+
+                let Some(index) = plan.next_pending() else {
+                    break;
+                };
+                plan.tasks[index].status = TaskStatus::InProgress;
+                if tracked {
+                    yield AgentEvent::Plan(plan.clone());
+                }
+
+                // Later tasks are asked for in the conversation sent to the model, so it alternates
+                // between user and assistant, but the request isn't part of the transcript
+                if messages.last().is_some_and(|message| message.role == Role::Assistant) {
+                    messages.push(Message::user().with_text(format!(
+                        "Continue with task {} of the plan: {}",
+                        index + 1,
+                        plan.tasks[index].description
+                    )));
+                }
+
+                let task_system_prompt = if tracked {
+                    format!("{}\n\n{}", system_prompt, task_prompt(&plan, index))
+                } else {
+                    system_prompt.clone()
+                };
+
+                // Work on the task until the model replies without tool calls
+                let mut reply = None;
+                let context = TruncateToFit(&self.token_counter);
+                let mut events = reply_loop(
+                    &capabilities,
+                    &self.scheduler,
+                    &context,
+                    &task_system_prompt,
+                    &mut messages,
+                    &tools,
+                );
+                while let Some(event) = events.next().await {
+                    match event? {
+                        LoopEvent::Agent(event) => yield event,
+                        LoopEvent::Replied(message) => reply = Some(message),
+                    }
+                }
+                drop(events);
+
+                // The loop already told the user why it stopped without a reply
+                let Some(reply) = reply else {
+                    break;
+                };
+                let text = reply.as_concat_text();
+                let failure = (tracked && text.trim_start().starts_with(TASK_FAILED_MARKER))
+                    .then_some(text);
+
+                let Some(reason) = failure else {
+                    plan.tasks[index].status = TaskStatus::Done;
+                    if tracked {
+                        yield AgentEvent::Plan(plan.clone());
+                    }
+                    continue;
+                };
+
+                plan.tasks[index].status = TaskStatus::Failed;
+                if tracked {
+                    yield AgentEvent::Plan(plan.clone());
+                }
+                if replans >= MAX_REPLANS {
+                    yield AgentEvent::Message(Message::assistant().with_text(format!(
+                        "I stopped working on the plan after task {} failed, it was already re-planned {} times.",
+                        index + 1,
+                        MAX_REPLANS
+                    )));
+                    break;
+                }
+                replans += 1;
+
+                // Plan the rest of the work, knowing what was done and why the task failed
+                let mut planning_messages = messages.clone();
+                planning_messages.push(replan_request(&plan, index, &reason));
+                match self.plan(&capabilities, &planning_messages, &tools).await {
+                    Ok(new_plan) => {
+                        plan.replan(new_plan);
+                        needs_approval = true;
+                    }
+                    Err(e) => {
+                        error!("Error: {}", e);
+                        yield AgentEvent::Message(Message::assistant().with_text(format!(
+                            "Ran into this error while making a new plan: {e}."
+                        )));
+                        break;
+                    }
+                }
+            }
+        }))
+    }
+
+    async fn set_budget(&mut self, budget: Budget) {
+        let mut capabilities = self.capabilities.lock().await;
+        capabilities.set_budget(budget);
+    }
+
+    async fn usage(&self) -> Vec<ProviderUsage> {
+        let capabilities = self.capabilities.lock().await;
+        capabilities.get_usage().await
+    }
+}
+
+register_agent!("planner", PlannerAgent);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ModelConfig;
+    use crate::providers::base::{ProviderMetadata, Usage};
+    use std::sync::Arc;
+
+    /// Replies with scripted texts in order and records the system prompts it was sent
+    struct MockProvider {
+        replies: std::sync::Mutex<Vec<&'static str>>,
+        systems: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl MockProvider {
+        fn new(replies: Vec<&'static str>) -> (Self, Arc<std::sync::Mutex<Vec<String>>>) {
+            let systems = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let provider = Self {
+                replies: std::sync::Mutex::new(replies),
+                systems: systems.clone(),
+            };
+            (provider, systems)
+        }
+    }
+
+    #[async_trait]
+    impl Provider for MockProvider {
+        fn metadata() -> ProviderMetadata {
+            ProviderMetadata::empty()
+        }
+
+        fn get_model_config(&self) -> ModelConfig {
+            ModelConfig::new("mock".to_string())
+        }
+
+        async fn complete(
+            &self,
+            system: &str,
+            _messages: &[Message],
+            _tools: &[Tool],
+        ) -> Result<(Message, ProviderUsage), ProviderError> {
+            self.systems.lock().unwrap().push(system.to_string());
+            let reply = self.replies.lock().unwrap().remove(0);
+            Ok((
+                Message::assistant().with_text(reply),
+                ProviderUsage::new("mock".to_string(), Usage::new(Some(10), Some(5), Some(15))),
+            ))
+        }
+    }
+
+    /// Run a reply to the end, answering every plan approval with `approve`
+    async fn run(
+        agent: &PlannerAgent,
+        approve: bool,
+    ) -> anyhow::Result<(Vec<Message>, Vec<Plan>, usize)> {
+        let messages = vec![Message::user().with_text("fix the failing test")];
+        let mut stream = agent.reply(&messages).await?;
+        let (mut replies, mut progress, mut approvals) = (Vec::new(), Vec::new(), 0);
+        while let Some(event) = stream.next().await {
+            match event? {
+                AgentEvent::Message(message) => replies.push(message),
+                AgentEvent::Plan(plan) => progress.push(plan),
+                AgentEvent::PlanApproval(approval) => {
+                    approvals += 1;
+                    approval.respond(approve);
+                }
+                _ => {}
+            }
+        }
+        Ok((replies, progress, approvals))
+    }
+
+    #[tokio::test]
+    async fn test_replans_after_failed_task() -> anyhow::Result<()> {
+        let (provider, systems) = MockProvider::new(vec![
+            r#"[{"description": "find the test"}, {"description": "fix it"}]"#,
+            "TASK FAILED: there are no tests",
+            r#"[{"description": "tell the user there are no tests"}]"#,
+            "There are no tests to fix.",
+        ]);
+        let agent = PlannerAgent::new(Box::new(provider));
+
+        let (replies, progress, approvals) = run(&agent, true).await?;
+        assert_eq!(approvals, 2);
+        // The request to continue with the next task is only sent to the model
+        let texts: Vec<_> = replies.iter().map(|m| m.as_concat_text()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "TASK FAILED: there are no tests",
+                "There are no tests to fix.",
+            ]
+        );
+
+        let statuses: Vec<_> = progress
+            .last()
+            .unwrap()
+            .tasks
+            .iter()
+            .map(|task| task.status)
+            .collect();
+        assert_eq!(statuses, vec![TaskStatus::Failed, TaskStatus::Done]);
+
+        // Planning counts as usage too
+        let usage = agent.usage().await;
+        assert_eq!(usage[0].usage.total_tokens, Some(60));
+
+        // The plan prompt is used for planning, and each task is named in the system prompt
+        let systems = systems.lock().unwrap();
+        assert!(systems[0].starts_with("You prepare plans"));
+        assert!(systems[1].contains("Work on task 1 now: find the test"));
+        assert!(systems[2].starts_with("You prepare plans"));
+        assert!(systems[3].contains("Work on task 2 now: tell the user there are no tests"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rejected_plan() -> anyhow::Result<()> {
+        let (provider, systems) = MockProvider::new(vec![
+            r#"[{"description": "delete the test"}, {"description": "reply to the user"}]"#,
+        ]);
+        let agent = PlannerAgent::new(Box::new(provider));
+
+        let (replies, progress, approvals) = run(&agent, false).await?;
+        assert_eq!(approvals, 1);
+        assert!(progress.is_empty());
+        assert_eq!(replies.len(), 1);
+        assert!(replies[0].as_concat_text().starts_with("I won't go ahead"));
+        assert_eq!(systems.lock().unwrap().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reply_only_plan_is_not_approved() -> anyhow::Result<()> {
+        let (provider, systems) =
+            MockProvider::new(vec![r#"[{"description": "reply to the user"}]"#, "Hello!"]);
+        let agent = PlannerAgent::new(Box::new(provider));
+
+        let (replies, progress, approvals) = run(&agent, false).await?;
+        assert_eq!(approvals, 0);
+        assert!(progress.is_empty());
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].as_concat_text(), "Hello!");
+        assert!(!systems.lock().unwrap()[1].contains("# Plan"));
+        Ok(())
+    }
+}
@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use mcp_core::ToolCall;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+use crate::config::{Config, ConfigError};
+
+/// The config key the tool policy is stored under
+pub const TOOL_POLICY_CONFIG_KEY: &str = "tool_policy";
+
+/// Errors from loading a tool policy
+#[derive(Error, Debug)]
+pub enum PolicyError {
+    #[error("Invalid tool pattern `{0}`: {1}")]
+    InvalidToolPattern(String, regex::Error),
+    #[error("Invalid pattern for argument `{0}`: {1}")]
+    InvalidArgumentPattern(String, regex::Error),
+    #[error("Failed to read the tool policy: {0}")]
+    Config(#[from] ConfigError),
+}
+
+/// What to do with a tool call the model asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyDecision {
+    /// Run the tool without asking
+    #[default]
+    Allow,
+    /// Never run the tool
+    Deny,
+    /// Ask the user before running the tool
+    Ask,
+}
+
+/// A rule as it is written in config.yaml, e.g.
+///
+/// ```yaml
+/// tool_policy:
+///   default: allow
+///   rules:
+///     - tool: developer__shell
+///       decision: deny
+///       arguments:
+///         command: "rm\\s+-rf"
+///     - tool: "developer__*"
+///       decision: ask
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PolicyRule {
+    /// Prefixed tool name the rule applies to, `*` and `?` act as wildcards
+    pub tool: String,
+    pub decision: PolicyDecision,
+    /// Regular expressions that the named arguments must match for the rule to apply
+    /// Arguments that aren't strings are matched against their JSON representation
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub arguments: HashMap<String, String>,
+}
+
+/// The tool policy as it is written in config.yaml
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PolicyConfig {
+    /// Decision for tool calls that no rule matches
+    #[serde(default)]
+    pub default: PolicyDecision,
+    /// Rules in order of precedence, the first matching rule decides
+    #[serde(default)]
+    pub rules: Vec<PolicyRule>,
+}
+
+#[derive(Debug, Clone)]
+struct CompiledRule {
+    tool: Regex,
+    arguments: Vec<(String, Regex)>,
+    decision: PolicyDecision,
+}
+
+impl CompiledRule {
+    fn matches(&self, tool_call: &ToolCall) -> bool {
+        self.tool.is_match(&tool_call.name)
+            && self
+                .arguments
+                .iter()
+                .all(|(name, pattern)| match tool_call.arguments.get(name) {
+                    Some(serde_json::Value::String(value)) => pattern.is_match(value),
+                    Some(value) => pattern.is_match(&value.to_string()),
+                    None => false,
+                })
+    }
+}
+
+/// Decides whether tool calls can run, need the user's approval, or are denied
+#[derive(Debug, Clone, Default)]
+pub struct ToolPolicy {
+    rules: Vec<CompiledRule>,
+    default: PolicyDecision,
+}
+
+/// Convert a tool name pattern with `*` and `?` wildcards into an anchored regex
+fn glob_to_regex(glob: &str) -> Result<Regex, regex::Error> {
+    let mut pattern = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    Regex::new(&pattern)
+}
+
+impl ToolPolicy {
+    pub fn new(config: &PolicyConfig) -> Result<Self, PolicyError> {
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| {
+                let tool = glob_to_regex(&rule.tool)
+                    .map_err(|e| PolicyError::InvalidToolPattern(rule.tool.clone(), e))?;
+                let arguments = rule
+                    .arguments
+                    .iter()
+                    .map(|(name, pattern)| {
+                        Regex::new(pattern)
+                            .map(|regex| (name.clone(), regex))
+                            .map_err(|e| PolicyError::InvalidArgumentPattern(name.clone(), e))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(CompiledRule {
+                    tool,
+                    arguments,
+                    decision: rule.decision,
+                })
+            })
+            .collect::<Result<Vec<_>, PolicyError>>()?;
+
+        Ok(Self {
+            rules,
+            default: config.default,
+        })
+    }
+
+    /// A policy that asks before running any tool
+    pub fn ask_always() -> Self {
+        Self {
+            rules: Vec::new(),
+            default: PolicyDecision::Ask,
+        }
+    }
+
+    /// Load the policy from the `tool_policy` key in the config, allowing all tools if it isn't set
+    pub fn from_config() -> Result<Self, PolicyError> {
+        match Config::global().get::<PolicyConfig>(TOOL_POLICY_CONFIG_KEY) {
+            Ok(config) => Self::new(&config),
+            Err(ConfigError::NotFound(_)) => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Decide what to do with a tool call, the first matching rule wins
+    pub fn evaluate(&self, tool_call: &ToolCall) -> PolicyDecision {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(tool_call))
+            .map(|rule| rule.decision)
+            .unwrap_or(self.default)
+    }
+}
+
+/// A tool call that is waiting for the user to approve or deny it
+///
+/// The agent pauses the reply until `respond` is called. Dropping every copy of the
+/// request without responding denies the tool call.
+#[derive(Debug, Clone)]
+pub struct ToolApprovalRequest {
+    /// The id of the tool request in the assistant message
+    pub id: String,
+    pub tool_call: ToolCall,
+    responder: Arc<std::sync::Mutex<Option<oneshot::Sender<bool>>>>,
+}
+
+impl ToolApprovalRequest {
+    /// Create a request along with the receiver for the user's decision
+    pub fn new(id: String, tool_call: ToolCall) -> (Self, oneshot::Receiver<bool>) {
+        let (tx, rx) = oneshot::channel();
+        let request = Self {
+            id,
+            tool_call,
+            responder: Arc::new(std::sync::Mutex::new(Some(tx))),
+        };
+        (request, rx)
+    }
+
+    /// Approve or deny the tool call, only the first response is used
+    pub fn respond(&self, approved: bool) {
+        if let Some(tx) = self.responder.lock().unwrap().take() {
+            let _ = tx.send(approved);
+        }
+    }
+}
+
+/// Whether a tool call can run, or the user's decision about it that is still to come
+#[derive(Debug)]
+enum Permission {
+    Decided(bool),
+    Asked(oneshot::Receiver<bool>),
+}
+
+/// The tool calls of a response after they were checked against the tool policy
+#[derive(Debug, Default)]
+pub struct PendingToolCalls {
+    tool_calls: Vec<(ToolCall, Permission)>,
+}
+
+impl PendingToolCalls {
+    /// Check the tool calls against the policy, returning the approval requests for the
+    /// calls the user has to decide on
+    pub fn new(
+        policy: &ToolPolicy,
+        tool_calls: impl IntoIterator<Item = (String, ToolCall)>,
+    ) -> (Self, Vec<ToolApprovalRequest>) {
+        let mut pending = Self::default();
+        let mut approvals = Vec::new();
+        for (id, tool_call) in tool_calls {
+            let permission = match policy.evaluate(&tool_call) {
+                PolicyDecision::Allow => Permission::Decided(true),
+                PolicyDecision::Deny => Permission::Decided(false),
+                PolicyDecision::Ask => {
+                    let (approval, decision) = ToolApprovalRequest::new(id, tool_call.clone());
+                    approvals.push(approval);
+                    Permission::Asked(decision)
+                }
+            };
+            pending.tool_calls.push((tool_call, permission));
+        }
+        (pending, approvals)
+    }
+
+    /// Wait for the user's decisions and return each tool call with whether it may run
+    pub async fn permitted(self) -> Vec<(ToolCall, bool)> {
+        let mut tool_calls = Vec::with_capacity(self.tool_calls.len());
+        for (tool_call, permission) in self.tool_calls {
+            let permitted = match permission {
+                Permission::Decided(permitted) => permitted,
+                Permission::Asked(decision) => decision.await.unwrap_or(false),
+            };
+            tool_calls.push((tool_call, permitted));
+        }
+        tool_calls
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn policy(yaml: &str) -> ToolPolicy {
+        let config: PolicyConfig = serde_yaml::from_str(yaml).unwrap();
+        ToolPolicy::new(&config).unwrap()
+    }
+
+    #[test]
+    fn test_evaluate_rules_in_order() {
+        let policy = policy(
+            r#"
+            default: allow
+            rules:
+              - tool: developer__shell
+                decision: deny
+                arguments:
+                  command: "rm\\s+-rf"
+              - tool: "developer__*"
+                decision: ask
+            "#,
+        );
+
+        let shell =
+            |command: &str| ToolCall::new("developer__shell", json!({ "command": command }));
+        assert_eq!(policy.evaluate(&shell("rm -rf /")), PolicyDecision::Deny);
+        assert_eq!(policy.evaluate(&shell("ls -la")), PolicyDecision::Ask);
+        assert_eq!(
+            policy.evaluate(&ToolCall::new("developer__text_editor", json!({}))),
+            PolicyDecision::Ask
+        );
+        assert_eq!(
+            policy.evaluate(&ToolCall::new("memory__remember", json!({}))),
+            PolicyDecision::Allow
+        );
+    }
+
+    #[test]
+    fn test_argument_matchers() {
+        let policy = policy(
+            r#"
+            default: deny
+            rules:
+              - tool: "computercontroller__*"
+                decision: allow
+                arguments:
+                  count: "^[1-9]$"
+            "#,
+        );
+
+        let call = |count| ToolCall::new("computercontroller__scrape", json!({ "count": count }));
+        assert_eq!(policy.evaluate(&call(json!(3))), PolicyDecision::Allow);
+        assert_eq!(policy.evaluate(&call(json!(30))), PolicyDecision::Deny);
+        assert_eq!(
+            policy.evaluate(&ToolCall::new("computercontroller__scrape", json!({}))),
+            PolicyDecision::Deny
+        );
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        let config = PolicyConfig {
+            default: PolicyDecision::Allow,
+            rules: vec![PolicyRule {
+                tool: "developer__shell".to_string(),
+                decision: PolicyDecision::Deny,
+                arguments: HashMap::from([("command".to_string(), "rm (".to_string())]),
+            }],
+        };
+        assert!(matches!(
+            ToolPolicy::new(&config),
+            Err(PolicyError::InvalidArgumentPattern(name, _)) if name == "command"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_approval_request() {
+        let (request, decision) =
+            ToolApprovalRequest::new("1".to_string(), ToolCall::new("tool", json!({})));
+        request.clone().respond(true);
+        request.respond(false);
+        assert!(decision.await.unwrap());
+
+        // Dropping the request without a response closes the channel
+        let (request, decision) =
+            ToolApprovalRequest::new("2".to_string(), ToolCall::new("tool", json!({})));
+        drop(request);
+        assert!(decision.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pending_tool_calls() {
+        let policy = policy(
+            r#"
+            default: allow
+            rules:
+              - tool: developer__shell
+                decision: ask
+              - tool: memory__forget
+                decision: deny
+            "#,
+        );
+        let tool_calls = [
+            "developer__shell",
+            "memory__forget",
+            "memory__remember",
+            "developer__shell",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (i.to_string(), ToolCall::new(*name, json!({}))));
+
+        let (pending, approvals) = PendingToolCalls::new(&policy, tool_calls);
+        let ids: Vec<_> = approvals
+            .iter()
+            .map(|approval| approval.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["0", "3"]);
+        approvals[0].respond(true);
+        drop(approvals);
+
+        let permitted: Vec<_> = pending
+            .permitted()
+            .await
+            .into_iter()
+            .map(|(_, permitted)| permitted)
+            .collect();
+        assert_eq!(permitted, vec![true, false, true, false]);
+    }
+}
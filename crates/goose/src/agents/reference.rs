@@ -2,15 +2,20 @@
 /// It makes no attempt to handle context limits, and cannot read resources
 use async_trait::async_trait;
 use futures::stream::BoxStream;
+use futures::StreamExt;
 use tokio::sync::Mutex;
 use tracing::{debug, instrument};
 
-use super::Agent;
-use crate::agents::capabilities::Capabilities;
+use super::{Agent, AgentEvent};
+use crate::agents::budget::Budget;
+use crate::agents::capabilities::{Capabilities, ExtensionClients};
+use crate::agents::delegate;
 use crate::agents::extension::{ExtensionConfig, ExtensionResult};
+use crate::agents::scheduler::ToolScheduler;
+use crate::agents::truncate::tool_response_message;
 use crate::message::{Message, ToolRequest};
 use crate::providers::base::Provider;
-use crate::providers::base::ProviderUsage;
+use crate::providers::base::{ProviderUsage, StreamEvent};
 use crate::register_agent;
 use crate::token_counter::TokenCounter;
 use indoc::indoc;
@@ -20,6 +25,7 @@ use serde_json::{json, Value};
 /// Reference implementation of an Agent
 pub struct ReferenceAgent {
     capabilities: Mutex<Capabilities>,
+    scheduler: ToolScheduler,
     _token_counter: TokenCounter,
 }
 
@@ -28,6 +34,7 @@ impl ReferenceAgent {
         let token_counter = TokenCounter::new(provider.get_model_config().tokenizer_name());
         Self {
             capabilities: Mutex::new(Capabilities::new(provider)),
+            scheduler: ToolScheduler::from_config(),
             _token_counter: token_counter,
         }
     }
@@ -56,16 +63,21 @@ impl Agent for ReferenceAgent {
             .expect("Failed to list extensions")
     }
 
-    async fn passthrough(&self, _extension: &str, _request: Value) -> ExtensionResult<Value> {
-        // TODO implement
-        Ok(Value::Null)
+    async fn passthrough(&self, extension: &str, request: Value) -> ExtensionResult<Value> {
+        let capabilities = self.capabilities.lock().await;
+        capabilities.passthrough(extension, request).await
+    }
+
+    async fn extension_clients(&self) -> ExtensionClients {
+        let capabilities = self.capabilities.lock().await;
+        capabilities.extension_clients()
     }
 
     #[instrument(skip(self, messages), fields(user_message))]
     async fn reply(
         &self,
         messages: &[Message],
-    ) -> anyhow::Result<BoxStream<'_, anyhow::Result<Message>>> {
+    ) -> anyhow::Result<BoxStream<'_, anyhow::Result<AgentEvent>>> {
         let mut messages = messages.to_vec();
         let reply_span = tracing::Span::current();
         let mut capabilities = self.capabilities.lock().await;
@@ -115,6 +127,11 @@ impl Agent for ReferenceAgent {
             tools.push(list_resources_tool);
         }
 
+        // Child agents can't delegate any further, so they don't get the delegate tool
+        if delegate::can_delegate() {
+            tools.push(delegate::delegate_tool());
+        }
+
         let system_prompt = capabilities.get_system_prompt().await;
 
         // Set the user_message field in the span instead of creating a new event
@@ -129,16 +146,31 @@ impl Agent for ReferenceAgent {
         Ok(Box::pin(async_stream::try_stream! {
             let _reply_guard = reply_span.enter();
             loop {
-                // Get completion from provider
-                let (response, usage) = capabilities.provider().complete(
+                // Stop with a final message once the budget is used up
+                if let Some((message, exceeded)) = capabilities.budget_stop().await {
+                    yield AgentEvent::Message(message);
+                    Err(exceeded)?;
+                }
+
+                // Stream the completion from the provider, forwarding deltas as they arrive
+                let mut stream = capabilities.provider().stream(
                     &system_prompt,
                     &messages,
                     &tools,
                 ).await?;
+                let mut completion = None;
+                while let Some(event) = stream.next().await {
+                    match event? {
+                        StreamEvent::Delta(delta) => yield AgentEvent::Delta(delta),
+                        StreamEvent::Done(response, usage) => completion = Some((response, usage)),
+                    }
+                }
+                let (response, usage) = completion
+                    .ok_or_else(|| anyhow::anyhow!("Provider stream ended without a response"))?;
                 capabilities.record_usage(usage).await;
 
                 // Yield the assistant's response
-                yield response.clone();
+                yield AgentEvent::Message(response.clone());
 
                 tokio::task::yield_now().await;
 
@@ -152,27 +184,22 @@ impl Agent for ReferenceAgent {
                     break;
                 }
 
-                // Then dispatch each in parallel
-                let futures: Vec<_> = tool_requests
-                    .iter()
-                    .filter_map(|request| request.tool_call.clone().ok())
-                    .map(|tool_call| capabilities.dispatch_tool_call(tool_call))
-                    .collect();
-
-                // Process all the futures in parallel but wait until all are finished
-                let outputs = futures::future::join_all(futures).await;
-
-                // Create a message with the responses
-                let mut message_tool_response = Message::user();
-                // Now combine these into MessageContent::ToolResponse using the original ID
-                for (request, output) in tool_requests.iter().zip(outputs.into_iter()) {
-                    message_tool_response = message_tool_response.with_tool_response(
-                        request.id.clone(),
-                        output,
-                    );
+                // Check each tool call against the tool policy, asking the user when the policy requires it
+                let (pending, approvals) = capabilities.check_tool_calls(&tool_requests);
+                for approval in approvals {
+                    yield AgentEvent::ToolApproval(approval);
                 }
+                let tool_calls = pending.permitted().await;
+
+                // Then dispatch them, running calls to different extensions in parallel
+                let outputs = self.scheduler.run(tool_calls, |tool_call, permitted| {
+                    capabilities.dispatch_permitted_tool_call(tool_call, permitted)
+                }).await;
 
-                yield message_tool_response.clone();
+                // Create a message with the responses, using the original IDs
+                let message_tool_response = tool_response_message(&tool_requests, outputs);
+
+                yield AgentEvent::Message(message_tool_response.clone());
 
                 messages.push(response);
                 messages.push(message_tool_response);
@@ -180,6 +207,11 @@ impl Agent for ReferenceAgent {
         }))
     }
 
+    async fn set_budget(&mut self, budget: Budget) {
+        let mut capabilities = self.capabilities.lock().await;
+        capabilities.set_budget(budget);
+    }
+
     async fn usage(&self) -> Vec<ProviderUsage> {
         let capabilities = self.capabilities.lock().await;
         capabilities.get_usage().await
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::future::Future;
+
+use futures::stream::{self, StreamExt};
+use mcp_core::ToolCall;
+
+use crate::config::Config;
+
+/// The config key for the number of tool calls that can run at the same time
+pub const MAX_PARALLEL_TOOLS_CONFIG_KEY: &str = "GOOSE_MAX_PARALLEL_TOOLS";
+pub const DEFAULT_MAX_PARALLEL_TOOLS: usize = 4;
+
+/// Runs the tool calls from one assistant message
+///
+/// Calls to the same extension run one after another in the order they were requested,
+/// since they may depend on each other (e.g. writing and then reading a file). Calls to
+/// different extensions run at the same time, up to `max_parallel` at once.
+#[derive(Debug, Clone)]
+pub struct ToolScheduler {
+    max_parallel: usize,
+}
+
+impl Default for ToolScheduler {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_PARALLEL_TOOLS)
+    }
+}
+
+/// The extension a prefixed tool name belongs to, e.g. `developer` for `developer__shell`
+fn extension_name(tool_name: &str) -> &str {
+    tool_name
+        .split_once("__")
+        .map(|(extension, _)| extension)
+        .unwrap_or(tool_name)
+}
+
+impl ToolScheduler {
+    pub fn new(max_parallel: usize) -> Self {
+        Self {
+            max_parallel: max_parallel.max(1),
+        }
+    }
+
+    /// Create a scheduler using `GOOSE_MAX_PARALLEL_TOOLS` from the config
+    pub fn from_config() -> Self {
+        Config::global()
+            .get::<usize>(MAX_PARALLEL_TOOLS_CONFIG_KEY)
+            .map(Self::new)
+            .unwrap_or_default()
+    }
+
+    pub fn max_parallel(&self) -> usize {
+        self.max_parallel
+    }
+
+    /// Run each tool call with `dispatch` and return the results in the order of the calls
+    pub async fn run<T, F, Fut, R>(&self, tool_calls: Vec<(ToolCall, T)>, dispatch: F) -> Vec<R>
+    where
+        F: Fn(ToolCall, T) -> Fut,
+        Fut: Future<Output = R>,
+    {
+        let count = tool_calls.len();
+
+        // Group the calls by extension, keeping the order within each group
+        let mut groups: Vec<Vec<(usize, ToolCall, T)>> = Vec::new();
+        let mut group_index: HashMap<String, usize> = HashMap::new();
+        for (index, (tool_call, extra)) in tool_calls.into_iter().enumerate() {
+            let extension = extension_name(&tool_call.name).to_string();
+            let group = *group_index.entry(extension).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[group].push((index, tool_call, extra));
+        }
+
+        let dispatch = &dispatch;
+        let outputs: Vec<Vec<(usize, R)>> = stream::iter(groups)
+            .map(|group| async move {
+                let mut outputs = Vec::with_capacity(group.len());
+                for (index, tool_call, extra) in group {
+                    outputs.push((index, dispatch(tool_call, extra).await));
+                }
+                outputs
+            })
+            .buffer_unordered(self.max_parallel)
+            .collect()
+            .await;
+
+        // Put the results back in the order the calls were requested
+        let mut results: Vec<Option<R>> = (0..count).map(|_| None).collect();
+        for (index, output) in outputs.into_iter().flatten() {
+            results[index] = Some(output);
+        }
+        results
+            .into_iter()
+            .map(|result| result.expect("every tool call produces a result"))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    fn calls(names: &[&str]) -> Vec<(ToolCall, ())> {
+        names
+            .iter()
+            .map(|name| (ToolCall::new(*name, json!({})), ()))
+            .collect()
+    }
+
+    #[test]
+    fn test_extension_name() {
+        assert_eq!(extension_name("developer__shell"), "developer");
+        assert_eq!(extension_name("platform__read_resource"), "platform");
+        assert_eq!(extension_name("shell"), "shell");
+    }
+
+    #[tokio::test]
+    async fn test_results_keep_call_order() {
+        let scheduler = ToolScheduler::new(4);
+        let results = scheduler
+            .run(
+                calls(&["slow__a", "fast__b", "slow__c", "fast__d"]),
+                |tool_call, _| async move {
+                    if tool_call.name.starts_with("slow") {
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                    }
+                    tool_call.name
+                },
+            )
+            .await;
+        assert_eq!(results, vec!["slow__a", "fast__b", "slow__c", "fast__d"]);
+    }
+
+    #[tokio::test]
+    async fn test_extensions_run_in_parallel_up_to_cap() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let scheduler = ToolScheduler::new(2);
+        scheduler
+            .run(
+                calls(&["a__1", "b__1", "c__1", "a__2", "d__1"]),
+                |tool_call, _| {
+                    let running = running.clone();
+                    let peak = peak.clone();
+                    let order = order.clone();
+                    async move {
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        order.lock().unwrap().push(tool_call.name);
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    }
+                },
+            )
+            .await;
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        // Calls to the same extension run in the order they were requested
+        let order = order.lock().unwrap();
+        let first = order.iter().position(|name| name == "a__1").unwrap();
+        let second = order.iter().position(|name| name == "a__2").unwrap();
+        assert!(first < second);
+    }
+}
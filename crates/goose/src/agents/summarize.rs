@@ -0,0 +1,594 @@
+/// A summarize agent that condenses older parts of the conversation when it nears the model's context limit
+/// Unlike the truncate agent, facts from the start of a long session are kept in a summary instead of being dropped
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+use tracing::{debug, instrument, warn};
+
+use super::{Agent, AgentEvent};
+use crate::agents::budget::Budget;
+use crate::agents::capabilities::{Capabilities, ExtensionClients};
+use crate::agents::extension::{ExtensionConfig, ExtensionResult};
+use crate::agents::scheduler::ToolScheduler;
+use crate::agents::truncate::{reply_loop, reply_tools, ContextStrategy, LoopEvent};
+use crate::message::{Message, MessageContent};
+use crate::prompt_template::load_prompt_file;
+use crate::providers::base::Provider;
+use crate::providers::base::ProviderUsage;
+use crate::register_agent;
+use crate::token_counter::TokenCounter;
+use crate::truncate::{
+    truncate_messages, OldestFirstSummarization, OldestFirstTruncation, TruncationStrategy,
+};
+use mcp_core::content::Content;
+use mcp_core::role::Role;
+use mcp_core::tool::Tool;
+use serde_json::Value;
+
+const ESTIMATE_FACTOR_DECAY: f32 = 0.9;
+/// Fraction of the context limit at which older messages are summarized ahead of the request
+const SUMMARIZE_THRESHOLD: f32 = 0.8;
+/// Fraction of the context limit the remaining conversation should fit in after summarizing
+const SUMMARIZE_TARGET: f32 = 0.5;
+/// Tool output beyond this many characters is cut from the transcript sent for summarization
+const MAX_TOOL_OUTPUT_CHARS: usize = 2000;
+
+/// Messages at the start of a conversation that were replaced by a summary
+struct ConversationSummary {
+    /// The original messages the summary stands in for
+    replaced: Vec<Message>,
+    /// The summary messages to use in their place
+    summary: Vec<Message>,
+}
+
+/// Summarize implementation of an Agent
+pub struct SummarizeAgent {
+    capabilities: Mutex<Capabilities>,
+    scheduler: ToolScheduler,
+    token_counter: TokenCounter,
+    // Reused across replies, so a long session isn't summarized again on every turn
+    summary: Mutex<Option<ConversationSummary>>,
+}
+
+impl SummarizeAgent {
+    pub fn new(provider: Box<dyn Provider>) -> Self {
+        let token_counter = TokenCounter::new(provider.get_model_config().tokenizer_name());
+        Self {
+            capabilities: Mutex::new(Capabilities::new(provider)),
+            scheduler: ToolScheduler::from_config(),
+            token_counter,
+            summary: Mutex::new(None),
+        }
+    }
+
+    /// Replace the start of the conversation with the previous summary, if it still applies
+    async fn apply_summary(&self, messages: &mut Vec<Message>) {
+        if let Some(summary) = self.summary.lock().await.as_ref() {
+            if messages.starts_with(&summary.replaced) {
+                messages.splice(0..summary.replaced.len(), summary.summary.clone());
+            }
+        }
+    }
+
+    /// Count the tokens available to the messages, after the system prompt and tools
+    fn message_token_limit(
+        &self,
+        context_limit: usize,
+        estimate_factor: f32,
+        system_prompt: &str,
+        tools: &[Tool],
+    ) -> anyhow::Result<usize> {
+        // Our token count is an estimate since model providers often don't provide the tokenizer (eg. Claude)
+        let context_limit = (context_limit as f32 * estimate_factor) as usize;
+        let system_prompt_token_count = self.token_counter.count_tokens(system_prompt);
+        let tools_token_count = self.token_counter.count_tokens_for_tools(tools);
+
+        context_limit
+            .checked_sub(system_prompt_token_count)
+            .and_then(|remaining| remaining.checked_sub(tools_token_count))
+            .ok_or_else(|| {
+                anyhow::anyhow!("System prompt and tools exceed estimated context limit")
+            })
+    }
+
+    fn count_message_tokens(&self, messages: &[Message]) -> Vec<usize> {
+        messages
+            .iter()
+            .map(|msg| {
+                self.token_counter
+                    .count_chat_tokens("", std::slice::from_ref(msg), &[])
+            })
+            .collect()
+    }
+
+    /// Whether the conversation is close enough to the context limit to summarize it proactively
+    fn needs_summary(
+        &self,
+        capabilities: &Capabilities,
+        messages: &[Message],
+        system_prompt: &str,
+        tools: &[Tool],
+    ) -> bool {
+        let context_limit = capabilities.provider().get_model_config().context_limit();
+        match self.message_token_limit(context_limit, SUMMARIZE_THRESHOLD, system_prompt, tools) {
+            Ok(limit) => self.count_message_tokens(messages).iter().sum::<usize>() > limit,
+            Err(_) => true,
+        }
+    }
+
+    /// Condense the oldest messages into a summary so the rest fits within the target fraction
+    /// of the context window, falling back to dropping the oldest messages after the summary
+    async fn condense_messages(
+        &self,
+        capabilities: &Capabilities,
+        messages: &mut Vec<Message>,
+        estimate_factor: f32,
+        system_prompt: &str,
+        tools: &[Tool],
+    ) -> anyhow::Result<()> {
+        let context_limit = capabilities.provider().get_model_config().context_limit();
+        let target =
+            self.message_token_limit(context_limit, estimate_factor, system_prompt, tools)?;
+        let mut token_counts = self.count_message_tokens(messages);
+
+        match self
+            .summarize_messages(capabilities, messages, &token_counts, target)
+            .await
+        {
+            Ok(true) => {
+                token_counts = self.count_message_tokens(messages);
+                if token_counts.iter().sum::<usize>() <= target {
+                    return Ok(());
+                }
+                warn!("Conversation still exceeds the context limit after summarizing");
+            }
+            Ok(false) => debug!("No messages available to summarize"),
+            Err(e) => warn!("Failed to summarize messages: {}", e),
+        }
+
+        // Drop whatever is left over the limit, like the truncate agent would, but keep the
+        // summary so the facts from the start of the session survive
+        let pinned = self.summary_len(messages).await;
+        let pinned_tokens: usize = token_counts[..pinned].iter().sum();
+        let mut rest = messages.split_off(pinned);
+        let mut rest_counts = token_counts.split_off(pinned);
+        let result = truncate_messages(
+            &mut rest,
+            &mut rest_counts,
+            target.saturating_sub(pinned_tokens),
+            &OldestFirstTruncation,
+        );
+        messages.append(&mut rest);
+        result
+    }
+
+    /// Count the messages at the start of the conversation that hold the current summary
+    async fn summary_len(&self, messages: &[Message]) -> usize {
+        match self.summary.lock().await.as_ref() {
+            Some(summary) if messages.starts_with(&summary.summary) => summary.summary.len(),
+            _ => 0,
+        }
+    }
+
+    /// Replace the oldest messages with a summary written by the provider
+    /// Returns false if there was nothing that could be summarized
+    async fn summarize_messages(
+        &self,
+        capabilities: &Capabilities,
+        messages: &mut Vec<Message>,
+        token_counts: &[usize],
+        target: usize,
+    ) -> anyhow::Result<bool> {
+        let indices =
+            OldestFirstSummarization.determine_indices_to_remove(messages, token_counts, target)?;
+        if indices.is_empty() {
+            return Ok(false);
+        }
+        let split = indices.len();
+
+        let system_prompt = load_prompt_file("summarize.md", &HashMap::<String, String>::new())?;
+        let transcript = Message::user().with_text(format_transcript(&messages[..split]));
+        let (response, usage) = capabilities
+            .provider()
+            .complete(&system_prompt, &[transcript], &[])
+            .await?;
+        capabilities.record_usage(usage).await;
+
+        let summary_text = response.as_concat_text();
+        if summary_text.trim().is_empty() {
+            return Err(anyhow::anyhow!("The provider returned an empty summary"));
+        }
+        let summary = vec![
+            Message::user().with_text(format!(
+                "The earlier part of this conversation was condensed into this summary:\n\n{}",
+                summary_text.trim()
+            )),
+            Message::assistant()
+                .with_text("Thanks for the summary, I'll continue from where we left off."),
+        ];
+
+        // Keep track of which original messages the summary replaces, including the ones
+        // behind a previous summary at the start of this conversation
+        let mut cached = self.summary.lock().await;
+        let mut replaced = match cached.as_ref() {
+            Some(previous) if messages.starts_with(&previous.summary) => {
+                let mut replaced = previous.replaced.clone();
+                replaced.extend_from_slice(&messages[previous.summary.len()..split]);
+                replaced
+            }
+            _ => Vec::new(),
+        };
+        if replaced.is_empty() {
+            replaced = messages[..split].to_vec();
+        }
+
+        debug!("Summarized {} messages", split);
+        messages.splice(0..split, summary.clone());
+        *cached = Some(ConversationSummary { replaced, summary });
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl ContextStrategy for SummarizeAgent {
+    fn action(&self) -> &'static str {
+        "summarize"
+    }
+
+    async fn prepare(
+        &self,
+        capabilities: &Capabilities,
+        messages: &mut Vec<Message>,
+        system_prompt: &str,
+        tools: &[Tool],
+    ) -> anyhow::Result<()> {
+        // Summarize ahead of time rather than waiting for the provider to reject the request
+        if self.needs_summary(capabilities, messages, system_prompt, tools) {
+            self.condense_messages(
+                capabilities,
+                messages,
+                SUMMARIZE_TARGET,
+                system_prompt,
+                tools,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn shrink(
+        &self,
+        capabilities: &Capabilities,
+        messages: &mut Vec<Message>,
+        attempt: usize,
+        system_prompt: &str,
+        tools: &[Tool],
+    ) -> anyhow::Result<()> {
+        // Aim lower with every attempt: 0.45, 0.405, 0.3645, ...
+        let estimate_factor: f32 = SUMMARIZE_TARGET * ESTIMATE_FACTOR_DECAY.powi(attempt as i32);
+        self.condense_messages(
+            capabilities,
+            messages,
+            estimate_factor,
+            system_prompt,
+            tools,
+        )
+        .await
+    }
+}
+
+/// Render messages as a plain text transcript to be summarized
+fn format_transcript(messages: &[Message]) -> String {
+    let mut tool_names: HashMap<&str, &str> = HashMap::new();
+    let mut lines = Vec::new();
+
+    for message in messages {
+        let role = match message.role {
+            Role::User => "Human",
+            Role::Assistant => "Agent",
+        };
+        for content in &message.content {
+            match content {
+                MessageContent::Text(text) => lines.push(format!("{}: {}", role, text.text)),
+                MessageContent::Image(_) => lines.push(format!("{}: [image]", role)),
+                // The summary covers what was said and done, not the reasoning behind it
+                MessageContent::Thinking(_) => {}
+                MessageContent::ToolRequest(request) => match &request.tool_call {
+                    Ok(tool_call) => {
+                        tool_names.insert(&request.id, &tool_call.name);
+                        lines.push(format!(
+                            "Agent called tool {} with arguments {}",
+                            tool_call.name, tool_call.arguments
+                        ));
+                    }
+                    Err(e) => lines.push(format!("Agent made an invalid tool call: {}", e)),
+                },
+                MessageContent::ToolResponse(response) => {
+                    let name = tool_names.get(response.id.as_str()).unwrap_or(&"tool");
+                    match &response.tool_result {
+                        Ok(contents) => {
+                            let mut output = contents
+                                .iter()
+                                .filter_map(|c| match c {
+                                    Content::Text(t) => Some(t.text.as_str()),
+                                    _ => None,
+                                })
+                                .collect::<Vec<_>>()
+                                .join("\n");
+                            if let Some((index, _)) =
+                                output.char_indices().nth(MAX_TOOL_OUTPUT_CHARS)
+                            {
+                                output.truncate(index);
+                                output.push_str(" [...]");
+                            }
+                            lines.push(format!("Tool {} returned: {}", name, output));
+                        }
+                        Err(e) => lines.push(format!("Tool {} failed: {}", name, e)),
+                    }
+                }
+            }
+        }
+    }
+
+    lines.join("\n\n")
+}
+
+#[async_trait]
+impl Agent for SummarizeAgent {
+    async fn add_extension(&mut self, extension: ExtensionConfig) -> ExtensionResult<()> {
+        let mut capabilities = self.capabilities.lock().await;
+        capabilities.add_extension(extension).await
+    }
+
+    async fn remove_extension(&mut self, name: &str) {
+        let mut capabilities = self.capabilities.lock().await;
+        capabilities
+            .remove_extension(name)
+            .await
+            .expect("Failed to remove extension");
+    }
+
+    async fn list_extensions(&self) -> Vec<String> {
+        let capabilities = self.capabilities.lock().await;
+        capabilities
+            .list_extensions()
+            .await
+            .expect("Failed to list extensions")
+    }
+
+    async fn passthrough(&self, extension: &str, request: Value) -> ExtensionResult<Value> {
+        let capabilities = self.capabilities.lock().await;
+        capabilities.passthrough(extension, request).await
+    }
+
+    async fn extension_clients(&self) -> ExtensionClients {
+        let capabilities = self.capabilities.lock().await;
+        capabilities.extension_clients()
+    }
+
+    #[instrument(skip(self, messages), fields(user_message))]
+    async fn reply(
+        &self,
+        messages: &[Message],
+    ) -> anyhow::Result<BoxStream<'_, anyhow::Result<AgentEvent>>> {
+        let mut messages = messages.to_vec();
+        self.apply_summary(&mut messages).await;
+
+        let reply_span = tracing::Span::current();
+        let mut capabilities = self.capabilities.lock().await;
+        let tools = reply_tools(&mut capabilities).await?;
+        let system_prompt = capabilities.get_system_prompt().await;
+
+        // Set the user_message field in the span instead of creating a new event
+        if let Some(content) = messages
+            .last()
+            .and_then(|msg| msg.content.first())
+            .and_then(|c| c.as_text())
+        {
+            debug!("user_message" = &content);
+        }
+
+        Ok(Box::pin(async_stream::try_stream! {
+            let _reply_guard = reply_span.enter();
+            let mut events = reply_loop(
+                &capabilities,
+                &self.scheduler,
+                self,
+                &system_prompt,
+                &mut messages,
+                &tools,
+            );
+            while let Some(event) = events.next().await {
+                if let LoopEvent::Agent(event) = event? {
+                    yield event;
+                }
+            }
+        }))
+    }
+
+    async fn set_budget(&mut self, budget: Budget) {
+        let mut capabilities = self.capabilities.lock().await;
+        capabilities.set_budget(budget);
+    }
+
+    async fn usage(&self) -> Vec<ProviderUsage> {
+        let capabilities = self.capabilities.lock().await;
+        capabilities.get_usage().await
+    }
+}
+
+register_agent!("summarize", SummarizeAgent);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::budget::BudgetExceeded;
+    use crate::model::ModelConfig;
+    use crate::providers::base::{ProviderMetadata, Usage};
+    use crate::providers::errors::ProviderError;
+    use mcp_core::tool::ToolCall;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Returns a fixed summary and records the conversations it was asked to complete
+    struct MockProvider {
+        calls: Arc<AtomicUsize>,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl Provider for MockProvider {
+        fn metadata() -> ProviderMetadata {
+            ProviderMetadata::empty()
+        }
+
+        fn get_model_config(&self) -> ModelConfig {
+            ModelConfig::new("mock".to_string()).with_context_limit(Some(10_000))
+        }
+
+        async fn complete(
+            &self,
+            _system: &str,
+            _messages: &[Message],
+            _tools: &[Tool],
+        ) -> Result<(Message, ProviderUsage), ProviderError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.fail {
+                return Err(ProviderError::RequestFailed("unavailable".to_string()));
+            }
+            Ok((
+                Message::assistant().with_text("The human is reading files."),
+                ProviderUsage::new("mock".to_string(), Usage::default()),
+            ))
+        }
+    }
+
+    fn conversation() -> Vec<Message> {
+        vec![
+            Message::user().with_text("read the readme"),
+            Message::assistant().with_tool_request(
+                "1",
+                Ok(ToolCall::new(
+                    "developer__read",
+                    json!({"path": "README.md"}),
+                )),
+            ),
+            Message::user().with_tool_response("1", Ok(vec![Content::text("# Goose")])),
+            Message::assistant().with_text("It is about goose"),
+            Message::user().with_text("thanks, now what?"),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_reply_stops_at_budget() -> anyhow::Result<()> {
+        let mut agent = SummarizeAgent::new(Box::new(MockProvider {
+            calls: Arc::new(AtomicUsize::new(0)),
+            fail: false,
+        }));
+        agent
+            .set_budget(Budget {
+                max_turns: Some(1),
+                ..Default::default()
+            })
+            .await;
+        let messages = vec![Message::user().with_text("hello")];
+
+        let events: Vec<_> = agent.reply(&messages).await?.collect().await;
+        assert!(events.iter().all(|event| event.is_ok()));
+
+        // The budget is used up, so the next reply ends with a message and the budget error
+        let events: Vec<_> = agent.reply(&messages).await?.collect().await;
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            &events[0],
+            Ok(AgentEvent::Message(message)) if message.as_concat_text().contains("limit of 1 turns")
+        ));
+        let error = events[1].as_ref().unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<BudgetExceeded>(),
+            Some(&BudgetExceeded::Turns { used: 1, limit: 1 })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_summarize_messages_keeps_tool_pairs() -> anyhow::Result<()> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let agent = SummarizeAgent::new(Box::new(MockProvider {
+            calls: calls.clone(),
+            fail: false,
+        }));
+        let capabilities = agent.capabilities.lock().await;
+
+        let mut messages = conversation();
+        let token_counts = vec![10; messages.len()];
+        assert!(
+            agent
+                .summarize_messages(&capabilities, &mut messages, &token_counts, 10)
+                .await?
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // The summary replaces the first exchange, and the latest request remains
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0]
+            .as_concat_text()
+            .contains("The human is reading files."));
+        assert_eq!(messages[1].role, Role::Assistant);
+        assert_eq!(messages[2].as_concat_text(), "thanks, now what?");
+        assert!(messages.iter().all(|m| m.get_tool_ids().is_empty()));
+        drop(capabilities);
+
+        // The summary is reused for the same conversation in the next reply
+        let mut next = conversation();
+        next.push(Message::assistant().with_text("Anything else?"));
+        agent.apply_summary(&mut next).await;
+        assert_eq!(next.len(), 4);
+        assert_eq!(next[0], messages[0]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_condense_messages_keeps_summary_when_truncating() -> anyhow::Result<()> {
+        let agent = SummarizeAgent::new(Box::new(MockProvider {
+            calls: Arc::new(AtomicUsize::new(0)),
+            fail: true,
+        }));
+        let summary = vec![
+            Message::user().with_text("The human is reading files."),
+            Message::assistant().with_text("Thanks for the summary."),
+        ];
+        *agent.summary.lock().await = Some(ConversationSummary {
+            replaced: conversation(),
+            summary: summary.clone(),
+        });
+
+        let mut messages = summary.clone();
+        for _ in 0..5 {
+            messages.push(Message::user().with_text("tell me more ".repeat(40)));
+            messages.push(Message::assistant().with_text("here is more ".repeat(40)));
+        }
+        messages.push(Message::user().with_text("and finally?"));
+
+        // Summarizing fails, so the messages after the summary are truncated instead
+        let capabilities = agent.capabilities.lock().await;
+        agent
+            .condense_messages(&capabilities, &mut messages, 0.02, "", &[])
+            .await?;
+        assert!(messages.len() < 13);
+        assert!(messages.starts_with(&summary));
+        assert_eq!(messages.last().unwrap().as_concat_text(), "and finally?");
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_transcript() {
+        let transcript = format_transcript(&conversation());
+        assert!(transcript.contains("Human: read the readme"));
+        assert!(transcript.contains("Agent called tool developer__read"));
+        assert!(transcript.contains("Tool developer__read returned: # Goose"));
+    }
+}
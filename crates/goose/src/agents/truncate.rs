@@ -2,29 +2,36 @@
 /// It makes no attempt to handle context limits, and cannot read resources
 use async_trait::async_trait;
 use futures::stream::BoxStream;
+use futures::StreamExt;
 use tokio::sync::Mutex;
 use tracing::{debug, error, instrument, warn};
 
-use super::Agent;
-use crate::agents::capabilities::Capabilities;
+use super::{Agent, AgentEvent};
+use crate::agents::budget::Budget;
+use crate::agents::capabilities::{Capabilities, ExtensionClients};
+use crate::agents::delegate;
 use crate::agents::extension::{ExtensionConfig, ExtensionResult};
+use crate::agents::scheduler::ToolScheduler;
 use crate::message::{Message, ToolRequest};
 use crate::providers::base::Provider;
-use crate::providers::base::ProviderUsage;
+use crate::providers::base::{ProviderUsage, StreamEvent};
 use crate::providers::errors::ProviderError;
 use crate::register_agent;
 use crate::token_counter::TokenCounter;
 use crate::truncate::{truncate_messages, OldestFirstTruncation};
 use indoc::indoc;
+use mcp_core::content::Content;
+use mcp_core::handler::{ToolError, ToolResult};
 use mcp_core::tool::Tool;
 use serde_json::{json, Value};
 
-const MAX_TRUNCATION_ATTEMPTS: usize = 3;
+const MAX_CONTEXT_ATTEMPTS: usize = 3;
 const ESTIMATE_FACTOR_DECAY: f32 = 0.9;
 
 /// Truncate implementation of an Agent
 pub struct TruncateAgent {
     capabilities: Mutex<Capabilities>,
+    scheduler: ToolScheduler,
     token_counter: TokenCounter,
 }
 
@@ -33,66 +40,337 @@ impl TruncateAgent {
         let token_counter = TokenCounter::new(provider.get_model_config().tokenizer_name());
         Self {
             capabilities: Mutex::new(Capabilities::new(provider)),
+            scheduler: ToolScheduler::from_config(),
             token_counter,
         }
     }
+}
+
+/// The tools of the extensions, along with the platform tools for resources and delegation
+pub(crate) async fn reply_tools(capabilities: &mut Capabilities) -> anyhow::Result<Vec<Tool>> {
+    let mut tools = capabilities.get_prefixed_tools().await?;
+
+    // we add in the read_resource tool by default
+    // TODO: make sure there is no collision with another extension's tool name
+    let read_resource_tool = Tool::new(
+        "platform__read_resource".to_string(),
+        indoc! {r#"
+            Read a resource from an extension.
+
+            Resources allow extensions to share data that provide context to LLMs, such as
+            files, database schemas, or application-specific information. This tool searches for the
+            resource URI in the provided extension, and reads in the resource content. If no extension
+            is provided, the tool will search all extensions for the resource.
+        "#}.to_string(),
+        json!({
+            "type": "object",
+            "required": ["uri"],
+            "properties": {
+                "uri": {"type": "string", "description": "Resource URI"},
+                "extension_name": {"type": "string", "description": "Optional extension name"}
+            }
+        }),
+    );
+
+    let list_resources_tool = Tool::new(
+        "platform__list_resources".to_string(),
+        indoc! {r#"
+            List resources from an extension(s).
+
+            Resources allow extensions to share data that provide context to LLMs, such as
+            files, database schemas, or application-specific information. This tool lists resources
+            in the provided extension, and returns a list for the user to browse. If no extension
+            is provided, the tool will search all extensions for the resource.
+        "#}
+        .to_string(),
+        json!({
+            "type": "object",
+            "properties": {
+                "extension_name": {"type": "string", "description": "Optional extension name"}
+            }
+        }),
+    );
+
+    if capabilities.supports_resources() {
+        tools.push(read_resource_tool);
+        tools.push(list_resources_tool);
+    }
+
+    // Child agents can't delegate any further, so they don't get the delegate tool
+    if delegate::can_delegate() {
+        tools.push(delegate::delegate_tool());
+    }
+    Ok(tools)
+}
+
+/// How a reply loop keeps the conversation within the model's context window
+#[async_trait]
+pub(crate) trait ContextStrategy: Send + Sync {
+    /// What the strategy does to the messages, as named in the errors shown to the user
+    fn action(&self) -> &'static str;
+
+    /// Make room in the messages before each completion, ahead of the provider rejecting them
+    async fn prepare(
+        &self,
+        _capabilities: &Capabilities,
+        _messages: &mut Vec<Message>,
+        _system_prompt: &str,
+        _tools: &[Tool],
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
 
-    /// Truncates the messages to fit within the model's context window
-    /// Ensures the last message is a user message and removes tool call-response pairs
-    async fn truncate_messages(
+    /// Shrink the messages after the provider rejected them as too long
+    /// Later attempts should aim lower, as the token counts are only an estimate
+    async fn shrink(
         &self,
+        capabilities: &Capabilities,
         messages: &mut Vec<Message>,
-        estimate_factor: f32,
+        attempt: usize,
         system_prompt: &str,
-        tools: &mut Vec<Tool>,
-    ) -> anyhow::Result<()> {
-        // Model's actual context limit
-        let context_limit = self
-            .capabilities
-            .lock()
-            .await
-            .provider()
-            .get_model_config()
-            .context_limit();
-
-        // Our conservative estimate of the **target** context limit
-        // Our token count is an estimate since model providers often don't provide the tokenizer (eg. Claude)
-        let context_limit = (context_limit as f32 * estimate_factor) as usize;
-
-        // Take into account the system prompt, and our tools input and subtract that from the
-        // remaining context limit
-        let system_prompt_token_count = self.token_counter.count_tokens(system_prompt);
-        let tools_token_count = self.token_counter.count_tokens_for_tools(tools.as_slice());
-
-        // Check if system prompt + tools exceed our context limit
-        let remaining_tokens = context_limit
-            .checked_sub(system_prompt_token_count)
-            .and_then(|remaining| remaining.checked_sub(tools_token_count))
-            .ok_or_else(|| {
-                anyhow::anyhow!("System prompt and tools exceed estimated context limit")
-            })?;
-
-        let context_limit = remaining_tokens;
-
-        // Calculate current token count of each message, use count_chat_tokens to ensure we
-        // capture the full content of the message, include ToolRequests and ToolResponses
-        let mut token_counts: Vec<usize> = messages
-            .iter()
-            .map(|msg| {
-                self.token_counter
-                    .count_chat_tokens("", std::slice::from_ref(msg), &[])
-            })
-            .collect();
+        tools: &[Tool],
+    ) -> anyhow::Result<()>;
+}
+
+/// Drops the oldest messages until the conversation fits again
+pub(crate) struct TruncateToFit<'a>(pub(crate) &'a TokenCounter);
 
-        truncate_messages(
+#[async_trait]
+impl ContextStrategy for TruncateToFit<'_> {
+    fn action(&self) -> &'static str {
+        "truncate"
+    }
+
+    async fn shrink(
+        &self,
+        capabilities: &Capabilities,
+        messages: &mut Vec<Message>,
+        attempt: usize,
+        system_prompt: &str,
+        tools: &[Tool],
+    ) -> anyhow::Result<()> {
+        // Decay the estimate factor as we make more truncation attempts
+        // Estimate factor decays like this over time: 0.9, 0.81, 0.729, ...
+        let estimate_factor: f32 = ESTIMATE_FACTOR_DECAY.powi(attempt as i32);
+        truncate_to_fit(
+            capabilities,
+            self.0,
             messages,
-            &mut token_counts,
-            context_limit,
-            &OldestFirstTruncation,
+            estimate_factor,
+            system_prompt,
+            tools,
         )
     }
 }
 
+/// Truncates the messages to fit within the model's context window
+/// Ensures the last message is a user message and removes tool call-response pairs
+fn truncate_to_fit(
+    capabilities: &Capabilities,
+    token_counter: &TokenCounter,
+    messages: &mut Vec<Message>,
+    estimate_factor: f32,
+    system_prompt: &str,
+    tools: &[Tool],
+) -> anyhow::Result<()> {
+    // Model's actual context limit
+    let context_limit = capabilities.provider().get_model_config().context_limit();
+
+    // Our conservative estimate of the **target** context limit
+    // Our token count is an estimate since model providers often don't provide the tokenizer (eg. Claude)
+    let context_limit = (context_limit as f32 * estimate_factor) as usize;
+
+    // Take into account the system prompt, and our tools input and subtract that from the
+    // remaining context limit
+    let system_prompt_token_count = token_counter.count_tokens(system_prompt);
+    let tools_token_count = token_counter.count_tokens_for_tools(tools);
+
+    // Check if system prompt + tools exceed our context limit
+    let remaining_tokens = context_limit
+        .checked_sub(system_prompt_token_count)
+        .and_then(|remaining| remaining.checked_sub(tools_token_count))
+        .ok_or_else(|| anyhow::anyhow!("System prompt and tools exceed estimated context limit"))?;
+
+    let context_limit = remaining_tokens;
+
+    // Calculate current token count of each message, use count_chat_tokens to ensure we
+    // capture the full content of the message, include ToolRequests and ToolResponses
+    let mut token_counts: Vec<usize> = messages
+        .iter()
+        .map(|msg| token_counter.count_chat_tokens("", std::slice::from_ref(msg), &[]))
+        .collect();
+
+    truncate_messages(
+        messages,
+        &mut token_counts,
+        context_limit,
+        &OldestFirstTruncation,
+    )
+}
+
+/// Combine the outputs of the dispatched tool calls into a message with a response per request
+///
+/// Requests whose tool call couldn't be parsed never reach the scheduler, so they get their
+/// parse error as the response, keeping every other output paired with its own request.
+pub(crate) fn tool_response_message(
+    requests: &[&ToolRequest],
+    outputs: Vec<ToolResult<Vec<Content>>>,
+) -> Message {
+    let mut outputs = outputs.into_iter();
+    let mut message = Message::user();
+    for request in requests {
+        let output = match &request.tool_call {
+            Ok(_) => outputs.next().unwrap_or_else(|| {
+                Err(ToolError::ExecutionError(
+                    "The tool call produced no output".to_string(),
+                ))
+            }),
+            Err(e) => Err(e.clone()),
+        };
+        message = message.with_tool_response(request.id.clone(), output);
+    }
+    message
+}
+
+/// What a reply loop yields
+pub(crate) enum LoopEvent {
+    /// An event to pass on to the caller of the agent
+    Agent(AgentEvent),
+    /// The reply of the model without tool calls that ended the loop, already in the messages
+    Replied(Message),
+}
+
+/// Get completions and run their tool calls until the model replies without tool calls,
+/// shrinking the messages with the context strategy when they no longer fit in the context window
+///
+/// The messages are updated with the conversation as it goes. When the loop stops early on an
+/// error it yields a message explaining why, and ends without `LoopEvent::Replied`.
+pub(crate) fn reply_loop<'a>(
+    capabilities: &'a Capabilities,
+    scheduler: &'a ToolScheduler,
+    context: &'a dyn ContextStrategy,
+    system_prompt: &'a str,
+    messages: &'a mut Vec<Message>,
+    tools: &'a [Tool],
+) -> BoxStream<'a, anyhow::Result<LoopEvent>> {
+    Box::pin(async_stream::try_stream! {
+        let mut context_attempt: usize = 0;
+        loop {
+            // Stop with a final message once the budget is used up
+            if let Some((message, exceeded)) = capabilities.budget_stop().await {
+                yield LoopEvent::Agent(AgentEvent::Message(message));
+                Err(exceeded)?;
+            }
+
+            if let Err(err) = context.prepare(capabilities, messages, system_prompt, tools).await {
+                warn!("Unable to {} messages ahead of the request: {}", context.action(), err);
+            }
+
+            // Attempt to get completion from provider, forwarding deltas as they arrive
+            let completion = match capabilities.provider().stream(
+                system_prompt,
+                messages,
+                tools,
+            ).await {
+                Ok(mut stream) => {
+                    let mut completion = None;
+                    while let Some(event) = stream.next().await {
+                        match event {
+                            Ok(StreamEvent::Delta(delta)) => yield LoopEvent::Agent(AgentEvent::Delta(delta)),
+                            Ok(StreamEvent::Done(response, usage)) => {
+                                completion = Some(Ok((response, usage)));
+                            }
+                            Err(e) => {
+                                completion = Some(Err(e));
+                                break;
+                            }
+                        }
+                    }
+                    completion.unwrap_or_else(|| Err(ProviderError::RequestFailed(
+                        "Provider stream ended without a response".to_string(),
+                    )))
+                }
+                Err(e) => Err(e),
+            };
+
+            match completion {
+                Ok((response, usage)) => {
+                    capabilities.record_usage(usage).await;
+
+                    // Reset context attempt
+                    context_attempt = 0;
+
+                    // Yield the assistant's response
+                    yield LoopEvent::Agent(AgentEvent::Message(response.clone()));
+
+                    tokio::task::yield_now().await;
+
+                    // First collect any tool requests
+                    let tool_requests: Vec<&ToolRequest> = response.content
+                        .iter()
+                        .filter_map(|content| content.as_tool_request())
+                        .collect();
+
+                    if tool_requests.is_empty() {
+                        messages.push(response.clone());
+                        yield LoopEvent::Replied(response);
+                        break;
+                    }
+
+                    // Check each tool call against the tool policy, asking the user when the policy requires it
+                    let (pending, approvals) = capabilities.check_tool_calls(&tool_requests);
+                    for approval in approvals {
+                        yield LoopEvent::Agent(AgentEvent::ToolApproval(approval));
+                    }
+                    let tool_calls = pending.permitted().await;
+
+                    // Then dispatch them, running calls to different extensions in parallel
+                    let outputs = scheduler.run(tool_calls, |tool_call, permitted| {
+                        capabilities.dispatch_permitted_tool_call(tool_call, permitted)
+                    }).await;
+
+                    // Create a message with the responses, using the original IDs
+                    let message_tool_response = tool_response_message(&tool_requests, outputs);
+
+                    yield LoopEvent::Agent(AgentEvent::Message(message_tool_response.clone()));
+
+                    messages.push(response);
+                    messages.push(message_tool_response);
+                },
+                Err(ProviderError::ContextLengthExceeded(_)) => {
+                    if context_attempt >= MAX_CONTEXT_ATTEMPTS {
+                        // Create an error message & terminate the stream
+                        // the previous message would have been a user message (e.g. before any tool calls, this is just after the input message.
+                        // at the start of a loop after a tool call, it would be after a tool_use assistant followed by a tool_result user)
+                        yield LoopEvent::Agent(AgentEvent::Message(Message::assistant().with_text(format!("Error: Context length exceeds limits even after multiple attempts to {}. Please start a new session with fresh context and try again.", context.action()))));
+                        break;
+                    }
+
+                    context_attempt += 1;
+                    warn!("Context length exceeded. Attempt to {}: {}/{}.", context.action(), context_attempt, MAX_CONTEXT_ATTEMPTS);
+
+                    if let Err(err) = context.shrink(capabilities, messages, context_attempt, system_prompt, tools).await {
+                        yield LoopEvent::Agent(AgentEvent::Message(Message::assistant().with_text(format!("Error: Unable to {} messages to stay within context limit. \n\nRan into this error: {}.\n\nPlease start a new session with fresh context and try again.", context.action(), err))));
+                        break;
+                    }
+
+                    // Retry the loop after shrinking the messages
+                    continue;
+                },
+                Err(e) => {
+                    // Create an error message & terminate the stream
+                    error!("Error: {}", e);
+                    yield LoopEvent::Agent(AgentEvent::Message(Message::assistant().with_text(format!("Ran into this error: {e}.\n\nPlease retry if you think this is a transient or recoverable error."))));
+                    break;
+                }
+            }
+
+            // Yield control back to the scheduler to prevent blocking
+            tokio::task::yield_now().await;
+        }
+    })
+}
+
 #[async_trait]
 impl Agent for TruncateAgent {
     async fn add_extension(&mut self, extension: ExtensionConfig) -> ExtensionResult<()> {
@@ -116,67 +394,25 @@ impl Agent for TruncateAgent {
             .expect("Failed to list extensions")
     }
 
-    async fn passthrough(&self, _extension: &str, _request: Value) -> ExtensionResult<Value> {
-        // TODO implement
-        Ok(Value::Null)
+    async fn passthrough(&self, extension: &str, request: Value) -> ExtensionResult<Value> {
+        let capabilities = self.capabilities.lock().await;
+        capabilities.passthrough(extension, request).await
+    }
+
+    async fn extension_clients(&self) -> ExtensionClients {
+        let capabilities = self.capabilities.lock().await;
+        capabilities.extension_clients()
     }
 
     #[instrument(skip(self, messages), fields(user_message))]
     async fn reply(
         &self,
         messages: &[Message],
-    ) -> anyhow::Result<BoxStream<'_, anyhow::Result<Message>>> {
+    ) -> anyhow::Result<BoxStream<'_, anyhow::Result<AgentEvent>>> {
         let mut messages = messages.to_vec();
         let reply_span = tracing::Span::current();
         let mut capabilities = self.capabilities.lock().await;
-        let mut tools = capabilities.get_prefixed_tools().await?;
-        let mut truncation_attempt: usize = 0;
-
-        // we add in the read_resource tool by default
-        // TODO: make sure there is no collision with another extension's tool name
-        let read_resource_tool = Tool::new(
-            "platform__read_resource".to_string(),
-            indoc! {r#"
-                Read a resource from an extension.
-
-                Resources allow extensions to share data that provide context to LLMs, such as
-                files, database schemas, or application-specific information. This tool searches for the
-                resource URI in the provided extension, and reads in the resource content. If no extension
-                is provided, the tool will search all extensions for the resource.
-            "#}.to_string(),
-            json!({
-                "type": "object",
-                "required": ["uri"],
-                "properties": {
-                    "uri": {"type": "string", "description": "Resource URI"},
-                    "extension_name": {"type": "string", "description": "Optional extension name"}
-                }
-            }),
-        );
-
-        let list_resources_tool = Tool::new(
-            "platform__list_resources".to_string(),
-            indoc! {r#"
-                List resources from an extension(s).
-
-                Resources allow extensions to share data that provide context to LLMs, such as
-                files, database schemas, or application-specific information. This tool lists resources
-                in the provided extension, and returns a list for the user to browse. If no extension
-                is provided, the tool will search all extensions for the resource.
-            "#}.to_string(),
-            json!({
-                "type": "object",
-                "properties": {
-                    "extension_name": {"type": "string", "description": "Optional extension name"}
-                }
-            }),
-        );
-
-        if capabilities.supports_resources() {
-            tools.push(read_resource_tool);
-            tools.push(list_resources_tool);
-        }
-
+        let tools = reply_tools(&mut capabilities).await?;
         let system_prompt = capabilities.get_system_prompt().await;
 
         // Set the user_message field in the span instead of creating a new event
@@ -190,104 +426,28 @@ impl Agent for TruncateAgent {
 
         Ok(Box::pin(async_stream::try_stream! {
             let _reply_guard = reply_span.enter();
-            loop {
-                // Attempt to get completion from provider
-                match capabilities.provider().complete(
-                    &system_prompt,
-                    &messages,
-                    &tools,
-                ).await {
-                    Ok((response, usage)) => {
-                        capabilities.record_usage(usage).await;
-
-                        // Reset truncation attempt
-                        truncation_attempt = 0;
-
-                        // Yield the assistant's response
-                        yield response.clone();
-
-                        tokio::task::yield_now().await;
-
-                        // First collect any tool requests
-                        let tool_requests: Vec<&ToolRequest> = response.content
-                            .iter()
-                            .filter_map(|content| content.as_tool_request())
-                            .collect();
-
-                        if tool_requests.is_empty() {
-                            break;
-                        }
-
-                        // Then dispatch each in parallel
-                        let futures: Vec<_> = tool_requests
-                            .iter()
-                            .filter_map(|request| request.tool_call.clone().ok())
-                            .map(|tool_call| capabilities.dispatch_tool_call(tool_call))
-                            .collect();
-
-                        // Process all the futures in parallel but wait until all are finished
-                        let outputs = futures::future::join_all(futures).await;
-
-                        // Create a message with the responses
-                        let mut message_tool_response = Message::user();
-                        // Now combine these into MessageContent::ToolResponse using the original ID
-                        for (request, output) in tool_requests.iter().zip(outputs.into_iter()) {
-                            message_tool_response = message_tool_response.with_tool_response(
-                                request.id.clone(),
-                                output,
-                            );
-                        }
-
-                        yield message_tool_response.clone();
-
-                        messages.push(response);
-                        messages.push(message_tool_response);
-                    },
-                    Err(ProviderError::ContextLengthExceeded(_)) => {
-                        if truncation_attempt >= MAX_TRUNCATION_ATTEMPTS {
-                            // Create an error message & terminate the stream
-                            // the previous message would have been a user message (e.g. before any tool calls, this is just after the input message.
-                            // at the start of a loop after a tool call, it would be after a tool_use assistant followed by a tool_result user)
-                            yield Message::assistant().with_text("Error: Context length exceeds limits even after multiple attempts to truncate. Please start a new session with fresh context and try again.");
-                            break;
-                        }
-
-                        truncation_attempt += 1;
-                        warn!("Context length exceeded. Truncation Attempt: {}/{}.", truncation_attempt, MAX_TRUNCATION_ATTEMPTS);
-
-                        // Decay the estimate factor as we make more truncation attempts
-                        // Estimate factor decays like this over time: 0.9, 0.81, 0.729, ...
-                        let estimate_factor: f32 = ESTIMATE_FACTOR_DECAY.powi(truncation_attempt as i32);
-
-                        // release the lock before truncation to prevent deadlock
-                        drop(capabilities);
-
-                        if let Err(err) = self.truncate_messages(&mut messages, estimate_factor, &system_prompt, &mut tools).await {
-                            yield Message::assistant().with_text(format!("Error: Unable to truncate messages to stay within context limit. \n\nRan into this error: {}.\n\nPlease start a new session with fresh context and try again.", err));
-                            break;
-                        }
-
-
-                        // Re-acquire the lock
-                        capabilities = self.capabilities.lock().await;
-
-                        // Retry the loop after truncation
-                        continue;
-                    },
-                    Err(e) => {
-                        // Create an error message & terminate the stream
-                        error!("Error: {}", e);
-                        yield Message::assistant().with_text(format!("Ran into this error: {e}.\n\nPlease retry if you think this is a transient or recoverable error."));
-                        break;
-                    }
+            let context = TruncateToFit(&self.token_counter);
+            let mut events = reply_loop(
+                &capabilities,
+                &self.scheduler,
+                &context,
+                &system_prompt,
+                &mut messages,
+                &tools,
+            );
+            while let Some(event) = events.next().await {
+                if let LoopEvent::Agent(event) = event? {
+                    yield event;
                 }
-
-                // Yield control back to the scheduler to prevent blocking
-                tokio::task::yield_now().await;
             }
         }))
     }
 
+    async fn set_budget(&mut self, budget: Budget) {
+        let mut capabilities = self.capabilities.lock().await;
+        capabilities.set_budget(budget);
+    }
+
     async fn usage(&self) -> Vec<ProviderUsage> {
         let capabilities = self.capabilities.lock().await;
         capabilities.get_usage().await
@@ -295,3 +455,44 @@ impl Agent for TruncateAgent {
 }
 
 register_agent!("truncate", TruncateAgent);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mcp_core::tool::ToolCall;
+    use serde_json::json;
+
+    #[test]
+    fn test_tool_response_message_with_malformed_call() {
+        let malformed = ToolRequest {
+            id: "bad".to_string(),
+            tool_call: Err(ToolError::InvalidParameters("not JSON".to_string())),
+        };
+        let valid = ToolRequest {
+            id: "good".to_string(),
+            tool_call: Ok(ToolCall::new("developer__shell", json!({"command": "ls"}))),
+        };
+
+        // Only the valid call reaches the scheduler, so there is a single output
+        let message = tool_response_message(
+            &[&malformed, &valid],
+            vec![Ok(vec![Content::text("README.md")])],
+        );
+        let responses: Vec<_> = message
+            .content
+            .iter()
+            .filter_map(|content| content.as_tool_response())
+            .collect();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].id, "bad");
+        assert!(matches!(
+            responses[0].tool_result,
+            Err(ToolError::InvalidParameters(_))
+        ));
+        assert_eq!(responses[1].id, "good");
+        assert_eq!(
+            responses[1].tool_result,
+            Ok(vec![Content::text("README.md")])
+        );
+    }
+}
@@ -25,6 +25,19 @@ pub struct ToolResponse {
     pub tool_result: ToolResult<Vec<Content>>,
 }
 
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+/// The reasoning a model did before its reply
+pub struct ThinkingContent {
+    pub thinking: String,
+    /// The signature providers such as Anthropic use to verify the thinking when it's sent
+    /// back, for redacted thinking this holds the encrypted thinking instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// Whether the provider redacted the thinking, leaving only the signature
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub redacted: bool,
+}
+
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 /// Content passed inside a message, which can be both simple content and tool content
 pub enum MessageContent {
@@ -32,6 +45,7 @@ pub enum MessageContent {
     Image(ImageContent),
     ToolRequest(ToolRequest),
     ToolResponse(ToolResponse),
+    Thinking(ThinkingContent),
 }
 
 impl MessageContent {
@@ -64,6 +78,22 @@ impl MessageContent {
         })
     }
 
+    pub fn thinking<S: Into<String>>(thinking: S, signature: Option<String>) -> Self {
+        MessageContent::Thinking(ThinkingContent {
+            thinking: thinking.into(),
+            signature,
+            redacted: false,
+        })
+    }
+
+    pub fn redacted_thinking<S: Into<String>>(data: S) -> Self {
+        MessageContent::Thinking(ThinkingContent {
+            thinking: String::new(),
+            signature: Some(data.into()),
+            redacted: true,
+        })
+    }
+
     pub fn as_tool_request(&self) -> Option<&ToolRequest> {
         if let MessageContent::ToolRequest(ref tool_request) = self {
             Some(tool_request)
@@ -102,6 +132,13 @@ impl MessageContent {
             _ => None,
         }
     }
+
+    pub fn as_thinking(&self) -> Option<&ThinkingContent> {
+        match self {
+            MessageContent::Thinking(thinking) => Some(thinking),
+            _ => None,
+        }
+    }
 }
 
 impl From<Content> for MessageContent {
@@ -160,6 +197,11 @@ impl Message {
         self.with_content(MessageContent::image(data, mime_type))
     }
 
+    /// Add the model's thinking to the message
+    pub fn with_thinking<S: Into<String>>(self, thinking: S, signature: Option<String>) -> Self {
+        self.with_content(MessageContent::thinking(thinking, signature))
+    }
+
     /// Add a tool request to the message
     pub fn with_tool_request<S: Into<String>>(
         self,
@@ -241,6 +283,15 @@ impl Message {
             .collect()
     }
 
+    /// A copy of the message without the model's thinking
+    pub fn without_thinking(&self) -> Self {
+        let mut message = self.clone();
+        message
+            .content
+            .retain(|content| !matches!(content, MessageContent::Thinking(_)));
+        message
+    }
+
     /// Check if the message has only TextContent
     pub fn has_only_text_content(&self) -> bool {
         self.content
@@ -21,6 +21,10 @@ pub struct ModelConfig {
     pub temperature: Option<f32>,
     /// Optional maximum tokens to generate
     pub max_tokens: Option<i32>,
+    /// Optional context window to load the model with, for local runners such as Ollama
+    pub num_ctx: Option<usize>,
+    /// Optional time to keep a local model loaded after a request, e.g. "10m", or "-1" for ever
+    pub keep_alive: Option<String>,
 }
 
 impl ModelConfig {
@@ -40,6 +44,8 @@ impl ModelConfig {
             context_limit,
             temperature: None,
             max_tokens: None,
+            num_ctx: None,
+            keep_alive: None,
         }
     }
 
@@ -93,6 +99,18 @@ impl ModelConfig {
         self
     }
 
+    /// Set the context window of a local model
+    pub fn with_num_ctx(mut self, num_ctx: Option<usize>) -> Self {
+        self.num_ctx = num_ctx;
+        self
+    }
+
+    /// Set how long a local model stays loaded after a request
+    pub fn with_keep_alive(mut self, keep_alive: Option<String>) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
     // Get the tokenizer name
     pub fn tokenizer_name(&self) -> &str {
         &self.tokenizer_name
@@ -133,10 +151,14 @@ mod tests {
         let config = ModelConfig::new("test-model".to_string())
             .with_temperature(Some(0.7))
             .with_max_tokens(Some(1000))
-            .with_context_limit(Some(50_000));
+            .with_context_limit(Some(50_000))
+            .with_num_ctx(Some(16_384))
+            .with_keep_alive(Some("10m".to_string()));
 
         assert_eq!(config.temperature, Some(0.7));
         assert_eq!(config.max_tokens, Some(1000));
         assert_eq!(config.context_limit, Some(50_000));
+        assert_eq!(config.num_ctx, Some(16_384));
+        assert_eq!(config.keep_alive.as_deref(), Some("10m"));
     }
 }
@@ -1,13 +1,18 @@
 use anyhow::Result;
 use async_trait::async_trait;
-use reqwest::{Client, StatusCode};
-use serde_json::Value;
-use std::time::Duration;
+use futures::StreamExt;
+use reqwest::{Client, Response, StatusCode};
+use serde_json::{json, Value};
 
-use super::base::{ConfigKey, Provider, ProviderMetadata, ProviderUsage};
+use super::base::{
+    ConfigKey, Provider, ProviderMetadata, ProviderStream, ProviderUsage, StreamEvent,
+};
 use super::errors::ProviderError;
-use super::formats::anthropic::{create_request, get_usage, response_to_message};
-use super::utils::{emit_debug_trace, get_model};
+use super::formats::anthropic::{
+    create_request, create_structured_request, get_usage, response_to_message,
+    structured_response_to_message, with_thinking, StreamState,
+};
+use super::utils::{emit_debug_trace, get_model, get_retry_after, http_client, sse_json_stream};
 use crate::message::Message;
 use crate::model::ModelConfig;
 use mcp_core::tool::Tool;
@@ -28,6 +33,8 @@ pub struct AnthropicProvider {
     host: String,
     api_key: String,
     model: ModelConfig,
+    /// The extended thinking budget in tokens, thinking is off when this isn't set
+    thinking_budget: Option<i32>,
 }
 
 impl Default for AnthropicProvider {
@@ -44,68 +51,95 @@ impl AnthropicProvider {
         let host: String = config
             .get("ANTHROPIC_HOST")
             .unwrap_or_else(|_| "https://api.anthropic.com".to_string());
+        let thinking_budget: Option<i32> = config.get("ANTHROPIC_THINKING_BUDGET").ok();
 
-        let client = Client::builder()
-            .timeout(Duration::from_secs(600))
-            .build()?;
+        let client = http_client()?;
 
         Ok(Self {
             client,
             host,
             api_key,
             model,
+            thinking_budget,
         })
     }
 
-    async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
+    /// Build the messages request, enabling extended thinking when a budget is configured
+    fn create_request(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<Value, ProviderError> {
+        let payload = create_request(&self.model, system, messages, tools)?;
+        Ok(match self.thinking_budget {
+            Some(budget) => with_thinking(payload, budget),
+            None => payload,
+        })
+    }
+
+    async fn send(&self, payload: &Value) -> Result<Response, ProviderError> {
         let url = format!("{}/v1/messages", self.host.trim_end_matches('/'));
 
-        let response = self
+        Ok(self
             .client
             .post(&url)
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", "2023-06-01")
-            .json(&payload)
+            .json(payload)
             .send()
-            .await?;
+            .await?)
+    }
 
-        let status = response.status();
-        let payload: Option<Value> = response.json().await.ok();
+    async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
+        let response = self.send(&payload).await?;
+        handle_response(response).await
+    }
+}
 
-        // https://docs.anthropic.com/en/api/errors
-        match status {
-            StatusCode::OK => payload.ok_or_else( || ProviderError::RequestFailed("Response body is not valid JSON".to_string()) ),
-            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
-                Err(ProviderError::Authentication(format!("Authentication failed. Please ensure your API keys are valid and have the required permissions. \
-                    Status: {}. Response: {:?}", status, payload)))
-            }
-            StatusCode::BAD_REQUEST => {
-                let mut error_msg = "Unknown error".to_string();
-                if let Some(payload) = &payload {
-                    if let Some(error) = payload.get("error") {
-                    tracing::debug!("Bad Request Error: {error:?}");
-                    error_msg = error.get("message").and_then(|m| m.as_str()).unwrap_or("Unknown error").to_string();
-                    if error_msg.to_lowercase().contains("too long") || error_msg.to_lowercase().contains("too many") {
-                        return Err(ProviderError::ContextLengthExceeded(error_msg.to_string()));
-                    }
-                }}
-                tracing::debug!(
-                    "{}", format!("Provider request failed with status: {}. Payload: {:?}", status, payload)
-                );
-                Err(ProviderError::RequestFailed(format!("Request failed with status: {}. Message: {}", status, error_msg)))
-            }
-            StatusCode::TOO_MANY_REQUESTS => {
-                Err(ProviderError::RateLimitExceeded(format!("{:?}", payload)))
-            }
-            StatusCode::INTERNAL_SERVER_ERROR | StatusCode::SERVICE_UNAVAILABLE => {
-                Err(ProviderError::ServerError(format!("{:?}", payload)))
-            }
-            _ => {
-                tracing::debug!(
-                    "{}", format!("Provider request failed with status: {}. Payload: {:?}", status, payload)
-                );
-                Err(ProviderError::RequestFailed(format!("Request failed with status: {}", status)))
-            }
+/// Map a response from the messages API to its JSON payload or the matching ProviderError
+async fn handle_response(response: Response) -> Result<Value, ProviderError> {
+    let status = response.status();
+    let retry_after = get_retry_after(response.headers());
+    let payload: Option<Value> = response.json().await.ok();
+
+    // https://docs.anthropic.com/en/api/errors
+    match status {
+        StatusCode::OK => payload.ok_or_else( || ProviderError::RequestFailed("Response body is not valid JSON".to_string()) ),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            Err(ProviderError::Authentication(format!("Authentication failed. Please ensure your API keys are valid and have the required permissions. \
+                Status: {}. Response: {:?}", status, payload)))
+        }
+        StatusCode::BAD_REQUEST => {
+            let mut error_msg = "Unknown error".to_string();
+            if let Some(payload) = &payload {
+                if let Some(error) = payload.get("error") {
+                tracing::debug!("Bad Request Error: {error:?}");
+                error_msg = error.get("message").and_then(|m| m.as_str()).unwrap_or("Unknown error").to_string();
+                if error_msg.to_lowercase().contains("too long") || error_msg.to_lowercase().contains("too many") {
+                    return Err(ProviderError::ContextLengthExceeded(error_msg.to_string()));
+                }
+            }}
+            tracing::debug!(
+                "{}", format!("Provider request failed with status: {}. Payload: {:?}", status, payload)
+            );
+            Err(ProviderError::RequestFailed(format!("Request failed with status: {}. Message: {}", status, error_msg)))
+        }
+        StatusCode::TOO_MANY_REQUESTS => {
+            Err(ProviderError::RateLimitExceeded {
+                details: format!("{:?}", payload),
+                retry_after,
+            })
+        }
+        // Includes 529 when the API is overloaded
+        status if status.is_server_error() => {
+            Err(ProviderError::ServerError(format!("{:?}", payload)))
+        }
+        _ => {
+            tracing::debug!(
+                "{}", format!("Provider request failed with status: {}. Payload: {:?}", status, payload)
+            );
+            Err(ProviderError::RequestFailed(format!("Request failed with status: {}", status)))
         }
     }
 }
@@ -131,6 +165,7 @@ impl Provider for AnthropicProvider {
                     false,
                     Some("https://api.anthropic.com"),
                 ),
+                ConfigKey::new("ANTHROPIC_THINKING_BUDGET", false, false, None),
             ],
         )
     }
@@ -149,7 +184,7 @@ impl Provider for AnthropicProvider {
         messages: &[Message],
         tools: &[Tool],
     ) -> Result<(Message, ProviderUsage), ProviderError> {
-        let payload = create_request(&self.model, system, messages, tools)?;
+        let payload = self.create_request(system, messages, tools)?;
 
         // Make request
         let response = self.post(payload.clone()).await?;
@@ -162,4 +197,83 @@ impl Provider for AnthropicProvider {
         emit_debug_trace(self, &payload, &response, &usage);
         Ok((message, ProviderUsage::new(model, usage)))
     }
+
+    async fn complete_json(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let payload = create_structured_request(&self.model, system, messages, schema)?;
+
+        let response = self.post(payload.clone()).await?;
+
+        let message = structured_response_to_message(response.clone(), schema)?;
+        let usage = get_usage(&response)?;
+
+        let model = get_model(&response);
+        emit_debug_trace(self, &payload, &response, &usage);
+        Ok((message, ProviderUsage::new(model, usage)))
+    }
+
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<ProviderStream, ProviderError> {
+        let mut payload = self.create_request(system, messages, tools)?;
+        payload["stream"] = json!(true);
+
+        let response = self.send(&payload).await?;
+        if !response.status().is_success() {
+            return Err(handle_response(response).await.err().unwrap_or_else(|| {
+                ProviderError::RequestFailed("Unexpected response status".to_string())
+            }));
+        }
+
+        Ok(Box::pin(async_stream::try_stream! {
+            let mut state = StreamState::default();
+            let mut events = sse_json_stream(response);
+            while let Some(event) = events.next().await {
+                for delta in state.apply(&event?)? {
+                    yield StreamEvent::Delta(delta);
+                }
+            }
+
+            let response = state.into_response()?;
+            let message = response_to_message(response.clone())?;
+            let usage = get_usage(&response)?;
+            yield StreamEvent::Done(message, ProviderUsage::new(get_model(&response), usage));
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_overloaded_is_server_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(529).set_body_json(serde_json::json!({
+                "type": "error",
+                "error": {"type": "overloaded_error", "message": "Overloaded"}
+            })))
+            .mount(&server)
+            .await;
+
+        let response = reqwest::Client::new()
+            .post(server.uri())
+            .send()
+            .await
+            .unwrap();
+        assert!(matches!(
+            handle_response(response).await,
+            Err(ProviderError::ServerError(_))
+        ));
+    }
 }
@@ -0,0 +1,499 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Credentials for signing requests to AWS
+#[derive(Debug, Clone, PartialEq)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl AwsCredentials {
+    pub fn new(access_key_id: &str, secret_access_key: &str, session_token: Option<&str>) -> Self {
+        Self {
+            access_key_id: access_key_id.to_string(),
+            secret_access_key: secret_access_key.to_string(),
+            session_token: session_token.map(str::to_string),
+        }
+    }
+
+    /// Load credentials from the standard sources, in the order the AWS CLI checks them:
+    /// the `AWS_ACCESS_KEY_ID` environment variables, then the profile in the shared
+    /// credentials file
+    pub fn load(profile: &str) -> Result<Self> {
+        if let Some(credentials) = Self::from_env() {
+            return Ok(credentials);
+        }
+        let path = std::env::var("AWS_SHARED_CREDENTIALS_FILE")
+            .map(PathBuf::from)
+            .ok()
+            .or_else(|| dirs::home_dir().map(|home| home.join(".aws").join("credentials")))
+            .ok_or_else(|| anyhow!("Could not determine home directory"))?;
+        Self::from_profile(&path, profile)
+    }
+
+    /// Read credentials from the `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+    /// `AWS_SESSION_TOKEN` environment variables
+    pub fn from_env() -> Option<Self> {
+        let access_key_id = std::env::var("AWS_ACCESS_KEY_ID").ok()?;
+        let secret_access_key = std::env::var("AWS_SECRET_ACCESS_KEY").ok()?;
+        let session_token = std::env::var("AWS_SESSION_TOKEN").ok();
+        Some(Self::new(
+            &access_key_id,
+            &secret_access_key,
+            session_token.as_deref(),
+        ))
+    }
+
+    /// Read the credentials of a profile from a shared credentials file
+    pub fn from_profile(path: &Path, profile: &str) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            anyhow!(
+                "No AWS credentials in the environment and failed to read {}: {}",
+                path.display(),
+                e
+            )
+        })?;
+        Self::parse_profile(&contents, profile).ok_or_else(|| {
+            anyhow!(
+                "No credentials for the AWS profile '{}' in {}",
+                profile,
+                path.display()
+            )
+        })
+    }
+
+    fn parse_profile(contents: &str, profile: &str) -> Option<Self> {
+        let mut in_profile = false;
+        let mut access_key_id = None;
+        let mut secret_access_key = None;
+        let mut session_token = None;
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                in_profile = section.trim() == profile;
+                continue;
+            }
+            if !in_profile {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                let value = value.trim().to_string();
+                match key.trim() {
+                    "aws_access_key_id" => access_key_id = Some(value),
+                    "aws_secret_access_key" => secret_access_key = Some(value),
+                    "aws_session_token" => session_token = Some(value),
+                    _ => {}
+                }
+            }
+        }
+        Some(Self {
+            access_key_id: access_key_id?,
+            secret_access_key: secret_access_key?,
+            session_token,
+        })
+    }
+}
+
+/// Signs requests with AWS Signature Version 4
+/// https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_sigv.html
+#[derive(Debug, Clone)]
+pub struct SigV4Signer {
+    credentials: AwsCredentials,
+    region: String,
+    service: String,
+}
+
+impl SigV4Signer {
+    pub fn new(credentials: AwsCredentials, region: &str, service: &str) -> Self {
+        Self {
+            credentials,
+            region: region.to_string(),
+            service: service.to_string(),
+        }
+    }
+
+    /// The headers to add to a request so that it is signed, including `x-amz-date` and
+    /// `authorization`
+    ///
+    /// The given headers are signed along with the host, so they must be sent unchanged.
+    pub fn sign(
+        &self,
+        method: &str,
+        url: &Url,
+        headers: &HeaderMap,
+        payload: &[u8],
+        time: DateTime<Utc>,
+    ) -> Result<HeaderMap> {
+        let amz_date = time.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = time.format("%Y%m%d").to_string();
+
+        let mut signed = headers.clone();
+        signed.insert("x-amz-date", HeaderValue::from_str(&amz_date)?);
+        if let Some(token) = &self.credentials.session_token {
+            signed.insert("x-amz-security-token", HeaderValue::from_str(token)?);
+        }
+
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow!("URL has no host: {}", url))?;
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+        let mut canonical_headers: Vec<(String, String)> = signed
+            .iter()
+            .map(|(name, value)| {
+                Ok((
+                    name.as_str().to_lowercase(),
+                    value.to_str()?.trim().to_string(),
+                ))
+            })
+            .collect::<Result<_>>()?;
+        canonical_headers.push(("host".to_string(), host));
+        canonical_headers.sort();
+        let signed_headers = canonical_headers
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method,
+            canonical_uri(url),
+            canonical_query(url),
+            canonical_headers
+                .iter()
+                .map(|(name, value)| format!("{}:{}\n", name, value))
+                .collect::<String>(),
+            signed_headers,
+            hex::encode(Sha256::digest(payload)),
+        );
+
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let key = format!("AWS4{}", self.credentials.secret_access_key);
+        let key = hmac_sha256(key.as_bytes(), date.as_bytes());
+        let key = hmac_sha256(&key, self.region.as_bytes());
+        let key = hmac_sha256(&key, self.service.as_bytes());
+        let key = hmac_sha256(&key, b"aws4_request");
+        let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+
+        let mut output = HeaderMap::new();
+        for name in ["x-amz-date", "x-amz-security-token"] {
+            if let Some(value) = signed.get(name) {
+                output.insert(HeaderName::from_static(name), value.clone());
+            }
+        }
+        output.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.credentials.access_key_id, scope, signed_headers, signature
+            ))?,
+        );
+        Ok(output)
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent encode everything except the unreserved characters, as SigV4 requires
+pub(crate) fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// The path with each segment encoded again, since services other than S3 sign the
+/// encoded form of the path
+fn canonical_uri(url: &Url) -> String {
+    let path = url.path();
+    if path.is_empty() {
+        return "/".to_string();
+    }
+    path.split('/')
+        .map(uri_encode)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn canonical_query(url: &Url) -> String {
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(key, value)| (uri_encode(&key), uri_encode(&value)))
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// The length of the prelude (total length, headers length and prelude CRC) plus the message CRC
+const EVENT_STREAM_OVERHEAD: usize = 16;
+
+/// A message of the `application/vnd.amazon.eventstream` encoding of streaming responses
+/// https://docs.aws.amazon.com/transcribe/latest/dg/streaming-setting-up.html#streaming-event-stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventStreamMessage {
+    /// The headers with string values, such as `:message-type` and `:event-type`
+    pub headers: HashMap<String, String>,
+    pub payload: Vec<u8>,
+}
+
+impl EventStreamMessage {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+/// Splits the bytes of an event stream into messages as they arrive
+#[derive(Debug, Default)]
+pub struct EventStreamDecoder {
+    buffer: Vec<u8>,
+}
+
+impl EventStreamDecoder {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Take the next complete message from the buffer, if there is one
+    pub fn next_message(&mut self) -> Result<Option<EventStreamMessage>> {
+        if self.buffer.len() < 12 {
+            return Ok(None);
+        }
+        let total_length = read_u32(&self.buffer[0..4]) as usize;
+        let headers_length = read_u32(&self.buffer[4..8]) as usize;
+        if total_length < EVENT_STREAM_OVERHEAD + headers_length {
+            return Err(anyhow!(
+                "Invalid event stream message length {}",
+                total_length
+            ));
+        }
+        if read_u32(&self.buffer[8..12]) != crc32(&self.buffer[0..8]) {
+            return Err(anyhow!("Event stream prelude checksum mismatch"));
+        }
+        if self.buffer.len() < total_length {
+            return Ok(None);
+        }
+
+        let message: Vec<u8> = self.buffer.drain(..total_length).collect();
+        let crc_offset = total_length - 4;
+        if read_u32(&message[crc_offset..]) != crc32(&message[..crc_offset]) {
+            return Err(anyhow!("Event stream message checksum mismatch"));
+        }
+        let headers = parse_headers(&message[12..12 + headers_length])?;
+        let payload = message[12 + headers_length..crc_offset].to_vec();
+        Ok(Some(EventStreamMessage { headers, payload }))
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Read the headers of a message, keeping only those with string values
+fn parse_headers(mut bytes: &[u8]) -> Result<HashMap<String, String>> {
+    fn take<'a>(bytes: &mut &'a [u8], length: usize) -> Result<&'a [u8]> {
+        if bytes.len() < length {
+            return Err(anyhow!("Truncated event stream header"));
+        }
+        let (taken, rest) = bytes.split_at(length);
+        *bytes = rest;
+        Ok(taken)
+    }
+
+    let mut headers = HashMap::new();
+    while !bytes.is_empty() {
+        let name_length = take(&mut bytes, 1)?[0] as usize;
+        let name = String::from_utf8_lossy(take(&mut bytes, name_length)?).to_string();
+        let value_type = take(&mut bytes, 1)?[0];
+        let value_length = match value_type {
+            // true and false are encoded in the type alone
+            0 | 1 => 0,
+            2 => 1,
+            3 => 2,
+            4 => 4,
+            5 | 8 => 8,
+            9 => 16,
+            6 | 7 => {
+                let length = take(&mut bytes, 2)?;
+                u16::from_be_bytes([length[0], length[1]]) as usize
+            }
+            _ => return Err(anyhow!("Unknown event stream header type {}", value_type)),
+        };
+        let value = take(&mut bytes, value_length)?;
+        if value_type == 7 {
+            headers.insert(name, String::from_utf8_lossy(value).to_string());
+        }
+    }
+    Ok(headers)
+}
+
+/// The CRC-32 checksum (IEEE) that event stream messages carry
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+impl EventStreamMessage {
+    /// Encode the message, so tests can serve event streams
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut headers = Vec::new();
+        for (name, value) in &self.headers {
+            headers.push(name.len() as u8);
+            headers.extend_from_slice(name.as_bytes());
+            headers.push(7);
+            headers.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            headers.extend_from_slice(value.as_bytes());
+        }
+        let total_length = EVENT_STREAM_OVERHEAD + headers.len() + self.payload.len();
+
+        let mut message = Vec::with_capacity(total_length);
+        message.extend_from_slice(&(total_length as u32).to_be_bytes());
+        message.extend_from_slice(&(headers.len() as u32).to_be_bytes());
+        message.extend_from_slice(&crc32(&message).to_be_bytes());
+        message.extend_from_slice(&headers);
+        message.extend_from_slice(&self.payload);
+        message.extend_from_slice(&crc32(&message).to_be_bytes());
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_sign_matches_aws_example() -> Result<()> {
+        // The example from the AWS documentation for signing an IAM ListUsers request
+        let signer = SigV4Signer::new(
+            AwsCredentials::new(
+                "AKIDEXAMPLE",
+                "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+                None,
+            ),
+            "us-east-1",
+            "iam",
+        );
+        let url = Url::parse("https://iam.amazonaws.com/?Action=ListUsers&Version=2010-05-08")?;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "content-type",
+            HeaderValue::from_static("application/x-www-form-urlencoded; charset=utf-8"),
+        );
+        let time = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+
+        let signed = signer.sign("GET", &url, &headers, b"", time)?;
+        assert_eq!(signed["x-amz-date"], "20150830T123600Z");
+        assert_eq!(
+            signed[AUTHORIZATION],
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_canonical_uri_encodes_twice() -> Result<()> {
+        let url = Url::parse(
+            "https://bedrock-runtime.us-east-1.amazonaws.com/model/anthropic.claude-v2%3A1/converse",
+        )?;
+        assert_eq!(
+            canonical_uri(&url),
+            "/model/anthropic.claude-v2%253A1/converse"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let contents =
+            "[default]\naws_access_key_id = AKIDDEFAULT\naws_secret_access_key = secret\n\n\
+                        [work]\n# temporary credentials\naws_access_key_id=AKIDWORK\n\
+                        aws_secret_access_key=worksecret\naws_session_token=token\n";
+
+        assert_eq!(
+            AwsCredentials::parse_profile(contents, "default"),
+            Some(AwsCredentials::new("AKIDDEFAULT", "secret", None))
+        );
+        assert_eq!(
+            AwsCredentials::parse_profile(contents, "work"),
+            Some(AwsCredentials::new("AKIDWORK", "worksecret", Some("token")))
+        );
+        assert_eq!(AwsCredentials::parse_profile(contents, "missing"), None);
+    }
+
+    #[test]
+    fn test_event_stream_decoder() -> Result<()> {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let message = EventStreamMessage {
+            headers: HashMap::from([
+                (":message-type".to_string(), "event".to_string()),
+                (":event-type".to_string(), "contentBlockDelta".to_string()),
+            ]),
+            payload: br#"{"contentBlockIndex":0,"delta":{"text":"Hi"}}"#.to_vec(),
+        };
+        let bytes = [message.encode(), message.encode()].concat();
+
+        // Messages are only returned once all of their bytes have arrived
+        let mut decoder = EventStreamDecoder::default();
+        decoder.push(&bytes[..20]);
+        assert_eq!(decoder.next_message()?, None);
+        decoder.push(&bytes[20..]);
+        assert_eq!(decoder.next_message()?, Some(message.clone()));
+        assert_eq!(decoder.next_message()?, Some(message.clone()));
+        assert_eq!(decoder.next_message()?, None);
+
+        let mut corrupted = message.encode();
+        let last = corrupted.len() - 5;
+        corrupted[last] ^= 1;
+        let mut decoder = EventStreamDecoder::default();
+        decoder.push(&corrupted);
+        assert!(decoder.next_message().is_err());
+        Ok(())
+    }
+}
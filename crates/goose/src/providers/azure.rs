@@ -0,0 +1,279 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::{Client, Response};
+use serde_json::{json, Value};
+
+use super::base::{ConfigKey, Provider, ProviderMetadata, ProviderStream, ProviderUsage};
+use super::errors::ProviderError;
+use super::formats::openai::{create_request, create_structured_request};
+use super::openai::openai_stream;
+use super::utils::{
+    check_stream_response_openai_compat, handle_response_openai_compat, http_client,
+    openai_completion, ImageFormat,
+};
+use crate::message::Message;
+use crate::model::ModelConfig;
+use mcp_core::tool::Tool;
+
+pub const AZURE_DEFAULT_MODEL: &str = "gpt-4o";
+pub const AZURE_KNOWN_MODELS: &[&str] = &["gpt-4o", "gpt-4o-mini", "gpt-4"];
+pub const AZURE_DEFAULT_API_VERSION: &str = "2024-10-21";
+pub const AZURE_DOC_URL: &str =
+    "https://learn.microsoft.com/en-us/azure/ai-services/openai/concepts/models";
+
+/// A provider for Azure OpenAI, where requests go to a deployment of a model in a resource
+#[derive(Debug, serde::Serialize)]
+pub struct AzureProvider {
+    #[serde(skip)]
+    client: Client,
+    endpoint: String,
+    deployment: String,
+    api_version: String,
+    #[serde(skip)]
+    api_key: String,
+    model: ModelConfig,
+}
+
+impl AzureProvider {
+    pub fn new(
+        endpoint: &str,
+        deployment: &str,
+        api_version: &str,
+        api_key: &str,
+        model: ModelConfig,
+    ) -> Result<Self> {
+        let client = http_client()?;
+
+        Ok(Self {
+            client,
+            endpoint: endpoint.to_string(),
+            deployment: deployment.to_string(),
+            api_version: api_version.to_string(),
+            api_key: api_key.to_string(),
+            model,
+        })
+    }
+
+    pub fn from_env(model: ModelConfig) -> Result<Self> {
+        let config = crate::config::Config::global();
+        let endpoint: String = config.get("AZURE_OPENAI_ENDPOINT")?;
+        let deployment: String = config.get("AZURE_OPENAI_DEPLOYMENT_NAME")?;
+        let api_version: String = config
+            .get("AZURE_OPENAI_API_VERSION")
+            .unwrap_or_else(|_| AZURE_DEFAULT_API_VERSION.to_string());
+        let api_key: String = config.get_secret("AZURE_OPENAI_API_KEY")?;
+        Self::new(&endpoint, &deployment, &api_version, &api_key, model)
+    }
+
+    async fn send(&self, payload: &Value) -> Result<Response, ProviderError> {
+        let url = format!(
+            "{}/openai/deployments/{}/chat/completions",
+            self.endpoint.trim_end_matches('/'),
+            self.deployment
+        );
+
+        Ok(self
+            .client
+            .post(&url)
+            .query(&[("api-version", &self.api_version)])
+            .header("api-key", &self.api_key)
+            .json(payload)
+            .send()
+            .await?)
+    }
+
+    async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
+        let response = self.send(&payload).await?;
+        handle_response_openai_compat(response).await
+    }
+}
+
+#[async_trait]
+impl Provider for AzureProvider {
+    fn metadata() -> ProviderMetadata {
+        ProviderMetadata::new(
+            "azure_openai",
+            "Azure OpenAI",
+            "OpenAI models deployed in an Azure OpenAI resource",
+            AZURE_DEFAULT_MODEL,
+            AZURE_KNOWN_MODELS.iter().map(|&s| s.to_string()).collect(),
+            AZURE_DOC_URL,
+            vec![
+                ConfigKey::new("AZURE_OPENAI_ENDPOINT", true, false, None),
+                ConfigKey::new("AZURE_OPENAI_DEPLOYMENT_NAME", true, false, None),
+                ConfigKey::new(
+                    "AZURE_OPENAI_API_VERSION",
+                    false,
+                    false,
+                    Some(AZURE_DEFAULT_API_VERSION),
+                ),
+                ConfigKey::new("AZURE_OPENAI_API_KEY", true, true, None),
+            ],
+        )
+    }
+
+    fn get_model_config(&self) -> ModelConfig {
+        self.model.clone()
+    }
+
+    #[tracing::instrument(
+        skip(self, system, messages, tools),
+        fields(model_config, input, output, input_tokens, output_tokens, total_tokens)
+    )]
+    async fn complete(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let payload = create_request(&self.model, system, messages, tools, &ImageFormat::OpenAi)?;
+        let response = self.post(payload.clone()).await?;
+        openai_completion(self, &payload, response)
+    }
+
+    async fn complete_json(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let payload =
+            create_structured_request(&self.model, system, messages, schema, &ImageFormat::OpenAi)?;
+        let response = self.post(payload.clone()).await?;
+        openai_completion(self, &payload, response)
+    }
+
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<ProviderStream, ProviderError> {
+        let mut payload =
+            create_request(&self.model, system, messages, tools, &ImageFormat::OpenAi)?;
+        payload["stream"] = json!(true);
+        payload["stream_options"] = json!({"include_usage": true});
+
+        let response = self.send(&payload).await?;
+        let response = check_stream_response_openai_compat(response).await?;
+        Ok(openai_stream(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::MessageContent;
+    use wiremock::matchers::{body_partial_json, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_complete_routes_to_deployment() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/openai/deployments/goose-gpt4o/chat/completions"))
+            .and(query_param("api-version", "2024-10-21"))
+            .and(header("api-key", "secret"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "model": "gpt-4o-2024-08-06",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "Hello from Azure"},
+                    "finish_reason": "stop"
+                }],
+                "usage": {"prompt_tokens": 10, "completion_tokens": 3, "total_tokens": 13}
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let provider = AzureProvider::new(
+            &format!("{}/", server.uri()),
+            "goose-gpt4o",
+            AZURE_DEFAULT_API_VERSION,
+            "secret",
+            ModelConfig::new("gpt-4o".to_string()),
+        )?;
+        let (message, usage) = provider
+            .complete("system", &[Message::user().with_text("Hi")], &[])
+            .await?;
+        assert!(matches!(
+            &message.content[0],
+            MessageContent::Text(text) if text.text == "Hello from Azure"
+        ));
+        assert_eq!(usage.model, "gpt-4o-2024-08-06");
+        assert_eq!(usage.usage.total_tokens, Some(13));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_error_response() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+                "error": {"code": "401", "message": "Access denied due to invalid subscription key"}
+            })))
+            .mount(&server)
+            .await;
+
+        let provider = AzureProvider::new(
+            &server.uri(),
+            "goose-gpt4o",
+            AZURE_DEFAULT_API_VERSION,
+            "wrong",
+            ModelConfig::new("gpt-4o".to_string()),
+        )?;
+        let result = provider
+            .complete("system", &[Message::user().with_text("Hi")], &[])
+            .await;
+        assert!(matches!(result, Err(ProviderError::Authentication(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_complete_structured_uses_response_format() -> Result<()> {
+        let schema = json!({
+            "type": "object",
+            "properties": {"city": {"type": "string"}},
+            "required": ["city"]
+        });
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(json!({
+                "response_format": {
+                    "type": "json_schema",
+                    "json_schema": {"name": "response", "schema": schema}
+                }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "model": "gpt-4o-2024-08-06",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "{\"city\": \"Paris\"}"},
+                    "finish_reason": "stop"
+                }],
+                "usage": {"prompt_tokens": 20, "completion_tokens": 6, "total_tokens": 26}
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let provider = AzureProvider::new(
+            &server.uri(),
+            "goose-gpt4o",
+            AZURE_DEFAULT_API_VERSION,
+            "secret",
+            ModelConfig::new("gpt-4o".to_string()),
+        )?;
+        let (value, usage) = provider
+            .complete_structured(
+                "system",
+                &[Message::user().with_text("What is the capital of France?")],
+                &schema,
+            )
+            .await?;
+        assert_eq!(value, json!({"city": "Paris"}));
+        assert_eq!(usage.usage.total_tokens, Some(26));
+        Ok(())
+    }
+}
@@ -1,7 +1,12 @@
 use anyhow::Result;
+use futures::stream::BoxStream;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 use super::errors::ProviderError;
+use super::structured::{
+    self, add_usage, parse_and_validate, repair_prompt, schema_prompt, STRUCTURED_REPAIR_ATTEMPTS,
+};
 use crate::message::Message;
 use crate::model::ModelConfig;
 use mcp_core::tool::Tool;
@@ -83,19 +88,37 @@ impl ConfigKey {
 pub struct ProviderUsage {
     pub model: String,
     pub usage: Usage,
+    /// The cost in dollars, when the price of the model is known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    /// The provider that served the request, when it went through a fallback chain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
 }
 
 impl ProviderUsage {
     pub fn new(model: String, usage: Usage) -> Self {
-        Self { model, usage }
+        Self {
+            model,
+            usage,
+            cost: None,
+            provider: None,
+        }
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Usage {
+    /// All the prompt tokens, including those read from or written to the prompt cache
     pub input_tokens: Option<i32>,
     pub output_tokens: Option<i32>,
     pub total_tokens: Option<i32>,
+    /// The prompt tokens that were read from the provider's prompt cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read_input_tokens: Option<i32>,
+    /// The prompt tokens that were written to the provider's prompt cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_write_input_tokens: Option<i32>,
 }
 
 impl Usage {
@@ -108,10 +131,79 @@ impl Usage {
             input_tokens,
             output_tokens,
             total_tokens,
+            cache_read_input_tokens: None,
+            cache_write_input_tokens: None,
         }
     }
+
+    pub fn with_cache_tokens(mut self, read: Option<i32>, write: Option<i32>) -> Self {
+        self.cache_read_input_tokens = read;
+        self.cache_write_input_tokens = write;
+        self
+    }
+}
+
+/// A model a provider serves, as reported by its models endpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    /// The name to configure the model with
+    pub id: String,
+    /// The context window in tokens, when the provider reports it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_limit: Option<usize>,
+    /// Whether the model supports tool calling, when the provider reports it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supports_tools: Option<bool>,
 }
 
+impl ModelInfo {
+    pub fn new<S: Into<String>>(id: S) -> Self {
+        Self {
+            id: id.into(),
+            context_limit: None,
+            supports_tools: None,
+        }
+    }
+
+    pub fn with_context_limit(mut self, context_limit: Option<usize>) -> Self {
+        self.context_limit = context_limit;
+        self
+    }
+
+    pub fn with_supports_tools(mut self, supports_tools: Option<bool>) -> Self {
+        self.supports_tools = supports_tools;
+        self
+    }
+}
+
+/// An incremental piece of an assistant message, emitted while the model is still generating
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum MessageDelta {
+    /// A chunk of text to append to the message
+    Text { text: String },
+    /// A chunk of the (JSON encoded) arguments of a tool call
+    /// The name is only present on the first delta for a given tool call id
+    ToolCall {
+        id: String,
+        name: Option<String>,
+        arguments: String,
+    },
+    /// A chunk of the model's thinking, which comes before the text of the message
+    Thinking { thinking: String },
+}
+
+/// Events yielded by a streaming completion
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    /// Content that has been generated so far
+    Delta(MessageDelta),
+    /// The complete message and usage, always the last event in the stream
+    Done(Message, ProviderUsage),
+}
+
+pub type ProviderStream = BoxStream<'static, Result<StreamEvent, ProviderError>>;
+
 use async_trait::async_trait;
 
 /// Base trait for AI providers (OpenAI, Anthropic, etc)
@@ -142,10 +234,168 @@ pub trait Provider: Send + Sync {
         tools: &[Tool],
     ) -> Result<(Message, ProviderUsage), ProviderError>;
 
+    /// Generate the next message, yielding deltas as the model produces them
+    ///
+    /// The stream ends with a single `StreamEvent::Done` holding the same message and usage
+    /// that `complete` would have returned. Providers without native streaming support fall
+    /// back to `complete` and only emit the final event.
+    ///
+    /// # Errors
+    /// Errors that occur before any output is generated (including ContextLengthExceeded)
+    /// are returned directly rather than through the stream
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<ProviderStream, ProviderError> {
+        let (message, usage) = self.complete(system, messages, tools).await?;
+        Ok(Box::pin(futures::stream::once(async move {
+            Ok(StreamEvent::Done(message, usage))
+        })))
+    }
+
+    /// Generate a message whose text is a JSON value constrained by `schema`
+    ///
+    /// Providers with native structured output (a `json_schema` response format, a forced
+    /// tool or a response schema) override this. The default asks for the JSON in the
+    /// system prompt. The reply isn't validated here, use `complete_structured` for that.
+    async fn complete_json(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        self.complete(&schema_prompt(system, schema), messages, &[])
+            .await
+    }
+
+    /// Generate a JSON value that is valid against `schema`
+    ///
+    /// A reply that doesn't match the schema is sent back to the model with the validation
+    /// errors, up to `STRUCTURED_REPAIR_ATTEMPTS` times. The usage covers every attempt.
+    ///
+    /// # Errors
+    /// ProviderError::ExecutionError when the schema is invalid or no reply matches it
+    async fn complete_structured(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Value, ProviderUsage), ProviderError> {
+        let validator = structured::validator(schema)?;
+        let mut messages = messages.to_vec();
+        let mut total: Option<ProviderUsage> = None;
+        let mut attempt = 0;
+        loop {
+            let (message, usage) = self.complete_json(system, &messages, schema).await?;
+            let total_usage = match total {
+                Some(total) => add_usage(total, &usage),
+                None => usage,
+            };
+            let text = message.as_concat_text();
+            match parse_and_validate(&validator, &text) {
+                Ok(value) => return Ok((value, total_usage)),
+                Err(error) if attempt < STRUCTURED_REPAIR_ATTEMPTS => {
+                    tracing::debug!(attempt, error = %error, "Reply did not match the schema");
+                    let reply = if text.is_empty() {
+                        "(empty reply)"
+                    } else {
+                        &text
+                    };
+                    messages.push(Message::assistant().with_text(reply));
+                    messages.push(Message::user().with_text(repair_prompt(&error)));
+                    total = Some(total_usage);
+                    attempt += 1;
+                }
+                Err(error) => {
+                    return Err(ProviderError::ExecutionError(format!(
+                        "The response did not match the schema: {}",
+                        error
+                    )))
+                }
+            }
+        }
+    }
+
+    /// List the models the provider serves, from its models endpoint
+    ///
+    /// Returns None for providers that can't list their models, in which case
+    /// `ProviderMetadata::known_models` is the best there is.
+    async fn list_models(&self) -> Result<Option<Vec<ModelInfo>>, ProviderError> {
+        Ok(None)
+    }
+
+    /// Details of a single model, from the models the provider lists
+    ///
+    /// Providers that can look up one model override this to skip listing all of them.
+    async fn model_info(&self, model_name: &str) -> Result<Option<ModelInfo>, ProviderError> {
+        Ok(self
+            .list_models()
+            .await?
+            .and_then(|models| models.into_iter().find(|model| model.id == model_name)))
+    }
+
     /// Get the model config from the provider
     fn get_model_config(&self) -> ModelConfig;
 }
 
+/// A provider shared between agents, such as a parent agent and the child agents it delegates to
+#[async_trait]
+impl<P: Provider + ?Sized> Provider for std::sync::Arc<P> {
+    fn metadata() -> ProviderMetadata {
+        ProviderMetadata::empty()
+    }
+
+    async fn complete(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        (**self).complete(system, messages, tools).await
+    }
+
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<ProviderStream, ProviderError> {
+        (**self).stream(system, messages, tools).await
+    }
+
+    async fn complete_json(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        (**self).complete_json(system, messages, schema).await
+    }
+
+    async fn complete_structured(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Value, ProviderUsage), ProviderError> {
+        (**self).complete_structured(system, messages, schema).await
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<ModelInfo>>, ProviderError> {
+        (**self).list_models().await
+    }
+
+    async fn model_info(&self, model_name: &str) -> Result<Option<ModelInfo>, ProviderError> {
+        (**self).model_info(model_name).await
+    }
+
+    fn get_model_config(&self) -> ModelConfig {
+        (**self).get_model_config()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -175,6 +425,12 @@ mod tests {
         assert_eq!(json_value["input_tokens"], json!(10));
         assert_eq!(json_value["output_tokens"], json!(20));
         assert_eq!(json_value["total_tokens"], json!(30));
+        assert!(json_value.get("cache_read_input_tokens").is_none());
+
+        let cached = usage.with_cache_tokens(Some(8), None);
+        let deserialized: Usage = serde_json::from_str(&serde_json::to_string(&cached)?)?;
+        assert_eq!(deserialized.cache_read_input_tokens, Some(8));
+        assert_eq!(deserialized.cache_write_input_tokens, None);
 
         Ok(())
     }
@@ -0,0 +1,519 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::StreamExt;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use reqwest::{Client, Response, StatusCode};
+use serde_json::Value;
+use std::time::Duration;
+use url::Url;
+
+use super::aws::{uri_encode, AwsCredentials, EventStreamDecoder, EventStreamMessage, SigV4Signer};
+use super::base::{
+    ConfigKey, Provider, ProviderMetadata, ProviderStream, ProviderUsage, StreamEvent,
+};
+use super::errors::ProviderError;
+use super::formats::bedrock::{create_request, get_usage, response_to_message, StreamState};
+use super::utils::{emit_debug_trace, get_retry_after, http_client};
+use crate::message::Message;
+use crate::model::ModelConfig;
+use mcp_core::tool::Tool;
+
+pub const BEDROCK_DEFAULT_MODEL: &str = "anthropic.claude-3-5-sonnet-20241022-v2:0";
+pub const BEDROCK_KNOWN_MODELS: &[&str] = &[
+    "anthropic.claude-3-5-sonnet-20241022-v2:0",
+    "anthropic.claude-3-5-haiku-20241022-v1:0",
+    "meta.llama3-1-70b-instruct-v1:0",
+    "mistral.mistral-large-2407-v1:0",
+];
+pub const BEDROCK_DEFAULT_REGION: &str = "us-east-1";
+pub const BEDROCK_DOC_URL: &str =
+    "https://docs.aws.amazon.com/bedrock/latest/userguide/conversation-inference-supported-models-features.html";
+
+/// A provider for models on Amazon Bedrock, through the Converse API
+///
+/// Credentials come from the standard AWS sources, see `AwsCredentials::load`.
+#[derive(Debug, serde::Serialize)]
+pub struct BedrockProvider {
+    #[serde(skip)]
+    client: Client,
+    endpoint: String,
+    #[serde(skip)]
+    signer: SigV4Signer,
+    model: ModelConfig,
+}
+
+impl BedrockProvider {
+    pub fn new(
+        endpoint: &str,
+        region: &str,
+        credentials: AwsCredentials,
+        model: ModelConfig,
+    ) -> Result<Self> {
+        let client = http_client()?;
+
+        Ok(Self {
+            client,
+            endpoint: endpoint.to_string(),
+            signer: SigV4Signer::new(credentials, region, "bedrock"),
+            model,
+        })
+    }
+
+    pub fn from_env(model: ModelConfig) -> Result<Self> {
+        let config = crate::config::Config::global();
+        let region: String = config
+            .get("AWS_REGION")
+            .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| BEDROCK_DEFAULT_REGION.to_string());
+        let endpoint: String = config
+            .get("BEDROCK_ENDPOINT")
+            .unwrap_or_else(|_| format!("https://bedrock-runtime.{}.amazonaws.com", region));
+        let profile: String = config
+            .get("AWS_PROFILE")
+            .unwrap_or_else(|_| "default".to_string());
+        Self::new(&endpoint, &region, AwsCredentials::load(&profile)?, model)
+    }
+
+    /// Send a signed request to an action of the model, such as `converse`
+    async fn send(&self, action: &str, payload: &Value) -> Result<Response, ProviderError> {
+        let url = format!(
+            "{}/model/{}/{}",
+            self.endpoint.trim_end_matches('/'),
+            uri_encode(&self.model.model_name),
+            action
+        );
+        let url = Url::parse(&url)
+            .map_err(|e| ProviderError::RequestFailed(format!("Invalid Bedrock URL: {}", e)))?;
+        let body =
+            serde_json::to_vec(payload).map_err(|e| ProviderError::RequestFailed(e.to_string()))?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let signature = self
+            .signer
+            .sign("POST", &url, &headers, &body, Utc::now())?;
+        headers.extend(signature);
+
+        Ok(self
+            .client
+            .post(url)
+            .headers(headers)
+            .body(body)
+            .send()
+            .await?)
+    }
+}
+
+/// The ProviderError for an error type of the Converse API, given without the `Exception`
+/// suffix and in lowercase, since streams name the types in camel case
+fn error_from_type(
+    error_type: Option<&str>,
+    status: StatusCode,
+    message: String,
+    retry_after: Option<Duration>,
+) -> ProviderError {
+    match (error_type, status) {
+        (Some("throttling"), _) | (_, StatusCode::TOO_MANY_REQUESTS) => {
+            ProviderError::RateLimitExceeded {
+                details: message,
+                retry_after,
+            }
+        }
+        (Some("validation"), _) | (_, StatusCode::BAD_REQUEST) => {
+            let lowercase = message.to_lowercase();
+            if lowercase.contains("too long")
+                || lowercase.contains("too many")
+                || lowercase.contains("context length")
+            {
+                ProviderError::ContextLengthExceeded(message)
+            } else {
+                ProviderError::RequestFailed(format!(
+                    "Request failed with status: {}. Message: {}",
+                    status, message
+                ))
+            }
+        }
+        (_, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => {
+            ProviderError::Authentication(format!(
+                "Authentication failed. Please ensure your AWS credentials are valid and have access to the model. \
+                 Status: {}. Message: {}",
+                status, message
+            ))
+        }
+        (Some("internalserver" | "serviceunavailable" | "modelstreamerror"), _) => {
+            ProviderError::ServerError(message)
+        }
+        (_, status) if status.is_server_error() => ProviderError::ServerError(message),
+        _ => ProviderError::RequestFailed(format!(
+            "Request failed with status: {}. Message: {}",
+            status, message
+        )),
+    }
+}
+
+/// Normalize "ThrottlingException:http://internal.amazon.com/coral/..." or
+/// "throttlingException" to "throttling"
+fn normalize_error_type(error_type: &str) -> String {
+    let error_type = error_type.split(':').next().unwrap_or_default();
+    error_type
+        .strip_suffix("Exception")
+        .unwrap_or(error_type)
+        .to_lowercase()
+}
+
+/// Read the event type and payload of a ConverseStream message, failing on exceptions
+fn stream_event(message: &EventStreamMessage) -> Result<(String, Value), ProviderError> {
+    let payload: Value = serde_json::from_slice(&message.payload).map_err(|e| {
+        ProviderError::RequestFailed(format!("Invalid event in response stream: {}", e))
+    })?;
+    match message.header(":message-type") {
+        Some("event") => Ok((
+            message
+                .header(":event-type")
+                .unwrap_or_default()
+                .to_string(),
+            payload,
+        )),
+        _ => {
+            let error_type = message
+                .header(":exception-type")
+                .or_else(|| message.header(":error-code"))
+                .map(normalize_error_type);
+            let text = payload
+                .get("message")
+                .or_else(|| payload.get("Message"))
+                .and_then(|m| m.as_str())
+                .unwrap_or("Unknown error")
+                .to_string();
+            Err(error_from_type(
+                error_type.as_deref(),
+                StatusCode::OK,
+                text,
+                None,
+            ))
+        }
+    }
+}
+
+/// Map a response from the Converse API to its JSON payload or the matching ProviderError
+/// https://docs.aws.amazon.com/bedrock/latest/APIReference/API_runtime_Converse.html#API_runtime_Converse_Errors
+async fn handle_response(response: Response) -> Result<Value, ProviderError> {
+    let status = response.status();
+    let retry_after = get_retry_after(response.headers());
+    let error_type = response
+        .headers()
+        .get("x-amzn-errortype")
+        .and_then(|value| value.to_str().ok())
+        .map(normalize_error_type);
+    let payload: Option<Value> = response.json().await.ok();
+
+    if status == StatusCode::OK {
+        return payload.ok_or_else(|| {
+            ProviderError::RequestFailed("Response body is not valid JSON".to_string())
+        });
+    }
+
+    let message = payload
+        .as_ref()
+        .and_then(|p| p.get("message").or_else(|| p.get("Message")))
+        .and_then(|m| m.as_str())
+        .unwrap_or("Unknown error")
+        .to_string();
+    tracing::debug!(
+        "Bedrock request failed with status: {}. Type: {:?}. Payload: {:?}",
+        status,
+        error_type,
+        payload
+    );
+
+    Err(error_from_type(
+        error_type.as_deref(),
+        status,
+        message,
+        retry_after,
+    ))
+}
+
+#[async_trait]
+impl Provider for BedrockProvider {
+    fn metadata() -> ProviderMetadata {
+        ProviderMetadata::new(
+            "bedrock",
+            "Amazon Bedrock",
+            "Models on Amazon Bedrock, using your AWS credentials",
+            BEDROCK_DEFAULT_MODEL,
+            BEDROCK_KNOWN_MODELS
+                .iter()
+                .map(|&s| s.to_string())
+                .collect(),
+            BEDROCK_DOC_URL,
+            vec![
+                ConfigKey::new("AWS_PROFILE", true, false, Some("default")),
+                ConfigKey::new("AWS_REGION", true, false, Some(BEDROCK_DEFAULT_REGION)),
+                ConfigKey::new("BEDROCK_ENDPOINT", false, false, None),
+            ],
+        )
+    }
+
+    fn get_model_config(&self) -> ModelConfig {
+        self.model.clone()
+    }
+
+    #[tracing::instrument(
+        skip(self, system, messages, tools),
+        fields(model_config, input, output, input_tokens, output_tokens, total_tokens)
+    )]
+    async fn complete(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let payload = create_request(&self.model, system, messages, tools)?;
+
+        let response = handle_response(self.send("converse", &payload).await?).await?;
+
+        let message = response_to_message(response.clone())?;
+        let usage = get_usage(&response);
+        // Converse responses don't name the model, so report the one that was requested
+        let model = self.model.model_name.clone();
+        emit_debug_trace(self, &payload, &response, &usage);
+        Ok((message, ProviderUsage::new(model, usage)))
+    }
+
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<ProviderStream, ProviderError> {
+        let payload = create_request(&self.model, system, messages, tools)?;
+
+        let response = self.send("converse-stream", &payload).await?;
+        if !response.status().is_success() {
+            return Err(handle_response(response).await.err().unwrap_or_else(|| {
+                ProviderError::RequestFailed("Unexpected response status".to_string())
+            }));
+        }
+
+        let model = self.model.model_name.clone();
+        Ok(Box::pin(async_stream::try_stream! {
+            let mut state = StreamState::default();
+            let mut decoder = EventStreamDecoder::default();
+            let mut body = response.bytes_stream();
+            while let Some(chunk) = body.next().await {
+                decoder.push(&chunk?);
+                while let Some(message) = decoder.next_message()? {
+                    let (event_type, event) = stream_event(&message)?;
+                    for delta in state.apply(&event_type, &event) {
+                        yield StreamEvent::Delta(delta);
+                    }
+                }
+            }
+
+            let response = state.into_response()?;
+            let message = response_to_message(response.clone())?;
+            yield StreamEvent::Done(message, ProviderUsage::new(model, get_usage(&response)));
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::MessageContent;
+    use serde_json::json;
+    use wiremock::matchers::{header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn provider(server: &MockServer) -> BedrockProvider {
+        BedrockProvider::new(
+            &server.uri(),
+            "us-west-2",
+            AwsCredentials::new("AKIDEXAMPLE", "secret", Some("session")),
+            ModelConfig::new(BEDROCK_DEFAULT_MODEL.to_string()),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_complete_with_tool_use() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(
+                "/model/anthropic.claude-3-5-sonnet-20241022-v2%3A0/converse",
+            ))
+            .and(header("x-amz-security-token", "session"))
+            .and(header_exists("x-amz-date"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "output": {"message": {"role": "assistant", "content": [
+                    {"toolUse": {"toolUseId": "tooluse_1", "name": "developer__shell", "input": {"command": "ls"}}}
+                ]}},
+                "stopReason": "tool_use",
+                "usage": {"inputTokens": 20, "outputTokens": 5, "totalTokens": 25}
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let (message, usage) = provider(&server)
+            .complete("system", &[Message::user().with_text("List files")], &[])
+            .await?;
+        assert!(matches!(
+            &message.content[0],
+            MessageContent::ToolRequest(request) if request.id == "tooluse_1"
+        ));
+        assert_eq!(usage.model, BEDROCK_DEFAULT_MODEL);
+        assert_eq!(usage.usage.total_tokens, Some(25));
+
+        let requests = server.received_requests().await.unwrap();
+        let authorization = requests[0].headers["authorization"].to_str()?;
+        assert!(authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+        assert!(authorization.contains("/us-west-2/bedrock/aws4_request"));
+        assert!(authorization
+            .contains("SignedHeaders=content-type;host;x-amz-date;x-amz-security-token"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_error_mapping() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(429)
+                    .insert_header(
+                        "x-amzn-errortype",
+                        "ThrottlingException:http://internal.amazon.com/coral/com.amazon.bedrock/",
+                    )
+                    .set_body_json(
+                        json!({"message": "Too many requests, please wait before trying again."}),
+                    ),
+            )
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(400)
+                    .insert_header(
+                        "x-amzn-errortype",
+                        "ValidationException:http://internal.amazon.com/coral/com.amazon.bedrock/",
+                    )
+                    .set_body_json(json!({"message": "Input is too long for requested model."})),
+            )
+            .mount(&server)
+            .await;
+
+        let provider = provider(&server);
+        let messages = [Message::user().with_text("Hi")];
+        assert!(matches!(
+            provider.complete("system", &messages, &[]).await,
+            Err(ProviderError::RateLimitExceeded { .. })
+        ));
+        assert!(matches!(
+            provider.complete("system", &messages, &[]).await,
+            Err(ProviderError::ContextLengthExceeded(_))
+        ));
+        Ok(())
+    }
+
+    fn event(message_type: &str, type_header: &str, name: &str, payload: Value) -> Vec<u8> {
+        EventStreamMessage {
+            headers: std::collections::HashMap::from([
+                (":message-type".to_string(), message_type.to_string()),
+                (type_header.to_string(), name.to_string()),
+            ]),
+            payload: payload.to_string().into_bytes(),
+        }
+        .encode()
+    }
+
+    #[tokio::test]
+    async fn test_stream() -> Result<()> {
+        let server = MockServer::start().await;
+        let body = [
+            event(
+                "event",
+                ":event-type",
+                "messageStart",
+                json!({"role": "assistant"}),
+            ),
+            event(
+                "event",
+                ":event-type",
+                "contentBlockDelta",
+                json!({"contentBlockIndex": 0, "delta": {"text": "Hello"}}),
+            ),
+            event(
+                "event",
+                ":event-type",
+                "contentBlockDelta",
+                json!({"contentBlockIndex": 0, "delta": {"text": " there"}}),
+            ),
+            event(
+                "event",
+                ":event-type",
+                "messageStop",
+                json!({"stopReason": "end_turn"}),
+            ),
+            event(
+                "event",
+                ":event-type",
+                "metadata",
+                json!({"usage": {"inputTokens": 10, "outputTokens": 2, "totalTokens": 12}}),
+            ),
+        ]
+        .concat();
+        Mock::given(method("POST"))
+            .and(path(
+                "/model/anthropic.claude-3-5-sonnet-20241022-v2%3A0/converse-stream",
+            ))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "application/vnd.amazon.eventstream")
+                    .set_body_bytes(body),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let events: Vec<StreamEvent> = provider(&server)
+            .stream("system", &[Message::user().with_text("Hi")], &[])
+            .await?
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+        assert_eq!(events.len(), 3);
+        let Some(StreamEvent::Done(message, usage)) = events.last() else {
+            panic!("Expected the stream to end with the message");
+        };
+        assert_eq!(message.as_concat_text(), "Hello there");
+        assert_eq!(usage.model, BEDROCK_DEFAULT_MODEL);
+        assert_eq!(usage.usage.total_tokens, Some(12));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stream_exception() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(event(
+                "exception",
+                ":exception-type",
+                "throttlingException",
+                json!({"message": "Too many requests"}),
+            )))
+            .mount(&server)
+            .await;
+
+        let mut stream = provider(&server)
+            .stream("system", &[Message::user().with_text("Hi")], &[])
+            .await?;
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(ProviderError::RateLimitExceeded { .. }))
+        ));
+        Ok(())
+    }
+}
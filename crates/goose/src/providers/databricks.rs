@@ -3,13 +3,12 @@ use async_trait::async_trait;
 use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::time::Duration;
 
-use super::base::{ConfigKey, Provider, ProviderMetadata, ProviderUsage, Usage};
+use super::base::{ConfigKey, Provider, ProviderMetadata, ProviderUsage};
 use super::errors::ProviderError;
-use super::formats::openai::{create_request, get_usage, response_to_message};
+use super::formats::openai::{create_request, create_structured_request};
 use super::oauth;
-use super::utils::{get_model, ImageFormat};
+use super::utils::{get_retry_after, http_client, openai_completion, ImageFormat};
 use crate::config::ConfigError;
 use crate::message::Message;
 use crate::model::ModelConfig;
@@ -95,9 +94,7 @@ impl DatabricksProvider {
 
         let host = host?;
 
-        let client = Client::builder()
-            .timeout(Duration::from_secs(600))
-            .build()?;
+        let client = http_client()?;
 
         // If we find a databricks token we prefer that
         if let Ok(api_key) = config.get_secret("DATABRICKS_TOKEN") {
@@ -153,6 +150,7 @@ impl DatabricksProvider {
             .await?;
 
         let status = response.status();
+        let retry_after = get_retry_after(response.headers());
         let payload: Option<Value> = response.json().await.ok();
 
         match status {
@@ -188,9 +186,12 @@ impl DatabricksProvider {
                 Err(ProviderError::RequestFailed(format!("Request failed with status: {}. Message: {}", status, error_msg)))
             }
             StatusCode::TOO_MANY_REQUESTS => {
-                Err(ProviderError::RateLimitExceeded(format!("{:?}", payload)))
+                Err(ProviderError::RateLimitExceeded {
+                    details: format!("{:?}", payload),
+                    retry_after,
+                })
             }
-            StatusCode::INTERNAL_SERVER_ERROR | StatusCode::SERVICE_UNAVAILABLE => {
+            status if status.is_server_error() => {
                 Err(ProviderError::ServerError(format!("{:?}", payload)))
             }
             _ => {
@@ -203,6 +204,15 @@ impl DatabricksProvider {
     }
 }
 
+/// Remove the model key which is part of the url with databricks
+fn without_model(mut payload: Value) -> Value {
+    payload
+        .as_object_mut()
+        .expect("payload should have model key")
+        .remove("model");
+    payload
+}
+
 #[async_trait]
 impl Provider for DatabricksProvider {
     fn metadata() -> ProviderMetadata {
@@ -237,28 +247,22 @@ impl Provider for DatabricksProvider {
         messages: &[Message],
         tools: &[Tool],
     ) -> Result<(Message, ProviderUsage), ProviderError> {
-        let mut payload = create_request(&self.model, system, messages, tools, &self.image_format)?;
-        // Remove the model key which is part of the url with databricks
-        payload
-            .as_object_mut()
-            .expect("payload should have model key")
-            .remove("model");
-
+        let payload = create_request(&self.model, system, messages, tools, &self.image_format)?;
+        let payload = without_model(payload);
         let response = self.post(payload.clone()).await?;
+        openai_completion(self, &payload, response)
+    }
 
-        // Parse response
-        let message = response_to_message(response.clone())?;
-        let usage = match get_usage(&response) {
-            Ok(usage) => usage,
-            Err(ProviderError::UsageError(e)) => {
-                tracing::warn!("Failed to get usage data: {}", e);
-                Usage::default()
-            }
-            Err(e) => return Err(e),
-        };
-        let model = get_model(&response);
-        super::utils::emit_debug_trace(self, &payload, &response, &usage);
-
-        Ok((message, ProviderUsage::new(model, usage)))
+    async fn complete_json(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let payload =
+            create_structured_request(&self.model, system, messages, schema, &self.image_format)?;
+        let payload = without_model(payload);
+        let response = self.post(payload.clone()).await?;
+        openai_completion(self, &payload, response)
     }
 }
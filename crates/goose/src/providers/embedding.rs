@@ -0,0 +1,241 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use std::future::Future;
+
+use super::base::{ProviderUsage, Usage};
+use super::errors::ProviderError;
+use super::structured::add_usage;
+
+/// An embedding for each text, in the order the texts were given
+pub type Embeddings = Vec<Vec<f32>>;
+
+/// Providers that can turn text into vectors, for semantic search and retrieval
+///
+/// Embeddings are only comparable with embeddings from the same model, which is
+/// reported in the usage.
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Embed a batch of texts, splitting it into as many requests as the provider needs
+    async fn embed(&self, texts: &[String]) -> Result<(Embeddings, ProviderUsage), ProviderError>;
+}
+
+/// Embed `texts` with requests of at most `batch_size` texts, adding up their usage
+pub async fn embed_in_batches<'a, F, Fut>(
+    texts: &'a [String],
+    batch_size: usize,
+    model: &str,
+    embed_batch: F,
+) -> Result<(Embeddings, ProviderUsage), ProviderError>
+where
+    F: Fn(&'a [String]) -> Fut,
+    Fut: Future<Output = Result<(Embeddings, ProviderUsage), ProviderError>>,
+{
+    let mut embeddings = Vec::with_capacity(texts.len());
+    let mut total = ProviderUsage::new(model.to_string(), Usage::default());
+    for batch in texts.chunks(batch_size.max(1)) {
+        let (batch_embeddings, usage) = embed_batch(batch).await?;
+        if batch_embeddings.len() != batch.len() {
+            return Err(ProviderError::RequestFailed(format!(
+                "Expected {} embeddings, got {}",
+                batch.len(),
+                batch_embeddings.len()
+            )));
+        }
+        embeddings.extend(batch_embeddings);
+        total = add_usage(total, &usage);
+        total.model = usage.model;
+    }
+    Ok((embeddings, total))
+}
+
+/// Read a JSON array of numbers as an embedding
+pub fn value_to_embedding(value: &Value) -> Result<Vec<f32>, ProviderError> {
+    value
+        .as_array()
+        .and_then(|values| {
+            values
+                .iter()
+                .map(|v| v.as_f64().map(|v| v as f32))
+                .collect()
+        })
+        .ok_or_else(|| ProviderError::RequestFailed("Invalid embedding in response".to_string()))
+}
+
+/// The cosine similarity of two embeddings, 0.0 if either is all zeros
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    let norm_a = a.iter().map(|a| a * a).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|b| b * b).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+pub const LOCAL_EMBEDDING_MODEL: &str = "local-hashed-bow";
+pub const LOCAL_EMBEDDING_DIMENSIONS: usize = 512;
+
+/// Embeddings computed locally by hashing the words of a text into a fixed number of buckets
+///
+/// They only capture which words texts share, not what they mean, but need no model or network,
+/// so retrieval keeps working offline. The hash is stable, so stored embeddings stay comparable.
+#[derive(Debug, Clone)]
+pub struct LocalEmbedding {
+    dimensions: usize,
+}
+
+impl Default for LocalEmbedding {
+    fn default() -> Self {
+        Self::new(LOCAL_EMBEDDING_DIMENSIONS)
+    }
+}
+
+impl LocalEmbedding {
+    pub fn new(dimensions: usize) -> Self {
+        Self {
+            dimensions: dimensions.max(1),
+        }
+    }
+
+    /// FNV-1a, which unlike the std hasher is the same across Rust versions
+    fn hash(word: &str) -> u64 {
+        word.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+
+    /// The normalized embedding of a single text
+    pub fn embed_text(&self, text: &str) -> Vec<f32> {
+        let mut embedding = vec![0.0; self.dimensions];
+        let words = text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase);
+        for word in words {
+            let hash = Self::hash(&word);
+            // The top bit of the hash picks the sign, so collisions tend to cancel out
+            let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+            embedding[(hash % self.dimensions as u64) as usize] += sign;
+        }
+
+        let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            embedding.iter_mut().for_each(|v| *v /= norm);
+        }
+        embedding
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for LocalEmbedding {
+    async fn embed(&self, texts: &[String]) -> Result<(Embeddings, ProviderUsage), ProviderError> {
+        let embeddings = texts.iter().map(|text| self.embed_text(text)).collect();
+        Ok((
+            embeddings,
+            ProviderUsage::new(LOCAL_EMBEDDING_MODEL.to_string(), Usage::default()),
+        ))
+    }
+}
+
+/// An embedding provider that falls back to local embeddings when a request to it fails,
+/// for example because its host can't be reached
+///
+/// The model in the usage is the one that embedded the texts, so embeddings from the two
+/// aren't compared with each other.
+pub struct LocalFallbackEmbedding {
+    provider: Box<dyn EmbeddingProvider>,
+    local: LocalEmbedding,
+}
+
+impl LocalFallbackEmbedding {
+    pub fn new(provider: Box<dyn EmbeddingProvider>) -> Self {
+        Self {
+            provider,
+            local: LocalEmbedding::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for LocalFallbackEmbedding {
+    async fn embed(&self, texts: &[String]) -> Result<(Embeddings, ProviderUsage), ProviderError> {
+        match self.provider.embed(texts).await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                tracing::warn!(error = %e, "Embedding failed, using local embeddings instead");
+                self.local.embed(texts).await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_local_embedding() -> Result<(), ProviderError> {
+        let texts = vec![
+            "Read the contents of a file".to_string(),
+            "read FILE contents".to_string(),
+            "Take a screenshot of the screen".to_string(),
+            String::new(),
+        ];
+        let (embeddings, usage) = LocalEmbedding::default().embed(&texts).await?;
+        assert_eq!(usage.model, LOCAL_EMBEDDING_MODEL);
+        assert_eq!(embeddings.len(), 4);
+        assert!(embeddings
+            .iter()
+            .all(|e| e.len() == LOCAL_EMBEDDING_DIMENSIONS));
+
+        let related = cosine_similarity(&embeddings[0], &embeddings[1]);
+        let unrelated = cosine_similarity(&embeddings[1], &embeddings[2]);
+        assert!(related > 0.5);
+        assert!(related > unrelated);
+        assert_eq!(cosine_similarity(&embeddings[0], &embeddings[3]), 0.0);
+
+        // The same text always has the same embedding
+        assert_eq!(
+            LocalEmbedding::default().embed_text(&texts[0]),
+            embeddings[0]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_embed_in_batches() -> Result<(), ProviderError> {
+        let texts: Vec<String> = (0..5).map(|i| i.to_string()).collect();
+        let (embeddings, usage) = embed_in_batches(&texts, 2, "test", |batch| async move {
+            let embeddings = batch.iter().map(|t| vec![t.parse().unwrap()]).collect();
+            let usage = Usage::new(Some(batch.len() as i32), None, Some(batch.len() as i32));
+            Ok((
+                embeddings,
+                ProviderUsage::new("test-001".to_string(), usage),
+            ))
+        })
+        .await?;
+        assert_eq!(
+            embeddings,
+            vec![vec![0.0], vec![1.0], vec![2.0], vec![3.0], vec![4.0]]
+        );
+        assert_eq!(usage.model, "test-001");
+        assert_eq!(usage.usage.input_tokens, Some(5));
+
+        let (embeddings, usage) = embed_in_batches(&[], 2, "test", |_| async {
+            Ok((
+                vec![vec![0.0]],
+                ProviderUsage::new(String::new(), Usage::default()),
+            ))
+        })
+        .await?;
+        assert!(embeddings.is_empty());
+        assert_eq!(usage.model, "test");
+
+        let result = embed_in_batches(&texts, 2, "test", |_| async {
+            Ok((vec![], ProviderUsage::new(String::new(), Usage::default())))
+        })
+        .await;
+        assert!(result.is_err());
+        Ok(())
+    }
+}
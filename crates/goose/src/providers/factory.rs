@@ -1,32 +1,180 @@
 use super::{
     anthropic::AnthropicProvider,
+    azure::AzureProvider,
     base::{Provider, ProviderMetadata},
+    bedrock::BedrockProvider,
     databricks::DatabricksProvider,
+    embedding::{EmbeddingProvider, LocalEmbedding, LocalFallbackEmbedding},
+    fallback::FallbackProvider,
     google::GoogleProvider,
     groq::GroqProvider,
     ollama::OllamaProvider,
     openai::OpenAiProvider,
+    openai_compatible::OpenAiCompatibleProvider,
     openrouter::OpenRouterProvider,
+    pricing::PricedProvider,
+    replay::{RecordingProvider, ReplayProvider},
+    retry::RetryProvider,
 };
 use crate::model::ModelConfig;
 use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
 
 pub fn providers() -> Vec<ProviderMetadata> {
     vec![
         AnthropicProvider::metadata(),
+        AzureProvider::metadata(),
+        BedrockProvider::metadata(),
         DatabricksProvider::metadata(),
+        FallbackProvider::metadata(),
         GoogleProvider::metadata(),
         GroqProvider::metadata(),
         OllamaProvider::metadata(),
         OpenAiProvider::metadata(),
+        OpenAiCompatibleProvider::metadata(),
         OpenRouterProvider::metadata(),
     ]
 }
 
+/// Create a provider by name, with retries on transient errors configured through `RetryConfig`
+/// and the cost of each completion added to its usage
 pub fn create(name: &str, model: ModelConfig) -> Result<Box<dyn Provider + Send + Sync>> {
+    // The chain moves on to the next provider rather than retrying one, so only the whole
+    // chain is retried once every provider in it has failed
+    if name == "fallback" {
+        let provider = Box::new(FallbackProvider::from_config(model)?);
+        return Ok(Box::new(RetryProvider::from_config(name, provider)));
+    }
+    // Recording wraps a provider created here, replays keep the usage and cost as recorded
+    if name == "record" {
+        return Ok(Box::new(RecordingProvider::from_config(model)?));
+    }
+    if name == "replay" {
+        return Ok(Box::new(ReplayProvider::from_config(model)?));
+    }
+    let provider = create_provider(name, model)?;
+    Ok(with_retry_and_pricing(name, provider))
+}
+
+fn with_retry_and_pricing(
+    name: &str,
+    provider: Box<dyn Provider + Send + Sync>,
+) -> Box<dyn Provider + Send + Sync> {
+    let provider = Box::new(RetryProvider::from_config(name, provider));
+    Box::new(PricedProvider::from_config(name, provider))
+}
+
+/// Create a provider by name with the cost of each completion added to its usage, without
+/// retrying it, for providers in a fallback chain
+pub(super) fn create_without_retry(
+    name: &str,
+    model: ModelConfig,
+) -> Result<Box<dyn Provider + Send + Sync>> {
+    let provider = create_provider(name, model)?;
+    Ok(Box::new(PricedProvider::from_config(name, provider)))
+}
+
+/// The context limit looked up for each provider and model name
+type ContextLimits = HashMap<(String, String), Option<usize>>;
+
+/// The context limits looked up by `create_with_discovery`, so that each model is looked up
+/// once however many agents are created with it
+static DISCOVERED_CONTEXT_LIMITS: LazyLock<Mutex<ContextLimits>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Create a provider like `create`, taking the context limit from the details the provider
+/// reports for the model when neither the model config nor the provider knows it
+///
+/// The model is looked up once, without retries, so a provider that can't report it doesn't
+/// hold up creating the agent.
+pub async fn create_with_discovery(
+    name: &str,
+    model: ModelConfig,
+) -> Result<Box<dyn Provider + Send + Sync>> {
+    if model.context_limit.is_some() || matches!(name, "fallback" | "record" | "replay") {
+        return create(name, model);
+    }
+
+    let key = (name.to_string(), model.model_name.clone());
+    let cached = DISCOVERED_CONTEXT_LIMITS
+        .lock()
+        .expect("context limits lock poisoned")
+        .get(&key)
+        .copied();
+    let limit = match cached {
+        Some(limit) => limit,
+        None => {
+            let provider = create_provider(name, model.clone())?;
+            // Some providers take the limit from their own config, e.g. OLLAMA_NUM_CTX
+            if provider.get_model_config().context_limit.is_some() {
+                return Ok(with_retry_and_pricing(name, provider));
+            }
+            let limit = discover_context_limit(provider.as_ref(), &model.model_name).await;
+            DISCOVERED_CONTEXT_LIMITS
+                .lock()
+                .expect("context limits lock poisoned")
+                .insert(key, limit);
+            if limit.is_none() {
+                return Ok(with_retry_and_pricing(name, provider));
+            }
+            limit
+        }
+    };
+    create(name, model.with_context_limit(limit))
+}
+
+/// The context limit the provider reports for a model, if it can look the model up
+pub async fn discover_context_limit(provider: &dyn Provider, model_name: &str) -> Option<usize> {
+    match provider.model_info(model_name).await {
+        Ok(model) => model?.context_limit,
+        Err(e) => {
+            tracing::debug!(model = %model_name, error = %e, "Could not look up the model");
+            None
+        }
+    }
+}
+
+/// Create the embedding provider of a provider by name, `local` for local embeddings
+///
+/// Providers that can't embed text, or that fail to initialize, for example because
+/// their API key isn't set, fall back to local embeddings so retrieval keeps working offline,
+/// as do requests to a provider that fail.
+pub fn create_embedding(name: &str) -> Box<dyn EmbeddingProvider> {
+    match create_embedding_provider(name) {
+        Ok(provider) => Box::new(LocalFallbackEmbedding::new(provider)),
+        Err(e) => {
+            if name != "local" {
+                tracing::warn!(provider = %name, error = %e, "Using local embeddings instead");
+            }
+            Box::new(LocalEmbedding::default())
+        }
+    }
+}
+
+fn create_embedding_provider(name: &str) -> Result<Box<dyn EmbeddingProvider>> {
+    // The chat model isn't used for embeddings
+    match name {
+        "openai" => Ok(Box::new(OpenAiProvider::from_env(ModelConfig::new(
+            OpenAiProvider::metadata().default_model,
+        ))?)),
+        "ollama" => Ok(Box::new(OllamaProvider::from_env(ModelConfig::new(
+            OllamaProvider::metadata().default_model,
+        ))?)),
+        "google" => Ok(Box::new(GoogleProvider::from_env(ModelConfig::new(
+            GoogleProvider::metadata().default_model,
+        ))?)),
+        _ => Err(anyhow::anyhow!("The {} provider can't embed text", name)),
+    }
+}
+
+fn create_provider(name: &str, model: ModelConfig) -> Result<Box<dyn Provider + Send + Sync>> {
     match name {
         "openai" => Ok(Box::new(OpenAiProvider::from_env(model)?)),
+        "openai_compatible" => Ok(Box::new(OpenAiCompatibleProvider::from_env(model)?)),
         "anthropic" => Ok(Box::new(AnthropicProvider::from_env(model)?)),
+        "azure_openai" => Ok(Box::new(AzureProvider::from_env(model)?)),
+        "bedrock" => Ok(Box::new(BedrockProvider::from_env(model)?)),
         "databricks" => Ok(Box::new(DatabricksProvider::from_env(model)?)),
         "groq" => Ok(Box::new(GroqProvider::from_env(model)?)),
         "ollama" => Ok(Box::new(OllamaProvider::from_env(model)?)),
@@ -35,3 +183,74 @@ pub fn create(name: &str, model: ModelConfig) -> Result<Box<dyn Provider + Send
         _ => Err(anyhow::anyhow!("Unknown provider: {}", name)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Message;
+    use crate::providers::base::{ModelInfo, ProviderUsage};
+    use crate::providers::errors::ProviderError;
+    use async_trait::async_trait;
+    use mcp_core::tool::Tool;
+
+    struct MockProvider {
+        models: Option<Vec<ModelInfo>>,
+    }
+
+    #[async_trait]
+    impl Provider for MockProvider {
+        fn metadata() -> ProviderMetadata {
+            ProviderMetadata::empty()
+        }
+
+        fn get_model_config(&self) -> ModelConfig {
+            ModelConfig::new("mock".to_string())
+        }
+
+        async fn complete(
+            &self,
+            _system: &str,
+            _messages: &[Message],
+            _tools: &[Tool],
+        ) -> Result<(Message, ProviderUsage), ProviderError> {
+            Err(ProviderError::ExecutionError("not used".to_string()))
+        }
+
+        async fn list_models(&self) -> Result<Option<Vec<ModelInfo>>, ProviderError> {
+            Ok(self.models.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_discover_context_limit() {
+        let provider = MockProvider {
+            models: Some(vec![
+                ModelInfo::new("qwen2.5").with_context_limit(Some(32_768)),
+                ModelInfo::new("phi3"),
+            ]),
+        };
+        assert_eq!(
+            discover_context_limit(&provider, "qwen2.5").await,
+            Some(32_768)
+        );
+        assert_eq!(discover_context_limit(&provider, "phi3").await, None);
+        assert_eq!(discover_context_limit(&provider, "llama3").await, None);
+
+        let provider = MockProvider { models: None };
+        assert_eq!(discover_context_limit(&provider, "qwen2.5").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_create_embedding() -> Result<()> {
+        let texts = vec!["hello".to_string()];
+        // Anthropic has no embeddings, so they are computed locally
+        for name in ["local", "anthropic"] {
+            let (_, usage) = create_embedding(name).embed(&texts).await?;
+            assert_eq!(
+                usage.model,
+                crate::providers::embedding::LOCAL_EMBEDDING_MODEL
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,408 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use super::base::{
+    ConfigKey, ModelInfo, Provider, ProviderMetadata, ProviderStream, ProviderUsage, StreamEvent,
+};
+use super::errors::ProviderError;
+use crate::config::Config;
+use crate::message::Message;
+use crate::model::ModelConfig;
+use mcp_core::tool::Tool;
+use serde_json::Value;
+
+/// The config key for the ordered list of providers to try
+pub const FALLBACK_PROVIDERS_CONFIG_KEY: &str = "GOOSE_FALLBACK_PROVIDERS";
+
+/// One provider and model in a fallback chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FallbackEntry {
+    pub provider: String,
+    pub model: String,
+}
+
+impl FromStr for FallbackEntry {
+    type Err = anyhow::Error;
+
+    /// Parse an entry written as `provider/model`, the model may contain further slashes
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().split_once('/') {
+            Some((provider, model)) if !provider.is_empty() && !model.is_empty() => Ok(Self {
+                provider: provider.to_string(),
+                model: model.to_string(),
+            }),
+            _ => Err(anyhow!(
+                "Invalid fallback entry '{}', expected provider/model",
+                s
+            )),
+        }
+    }
+}
+
+impl FallbackEntry {
+    /// Load the chain from the config, either as a list of entries
+    ///
+    /// ```yaml
+    /// GOOSE_FALLBACK_PROVIDERS:
+    ///   - provider: anthropic
+    ///     model: claude-3-5-sonnet-latest
+    ///   - provider: openai
+    ///     model: gpt-4o
+    /// ```
+    ///
+    /// or as a comma separated string such as `anthropic/claude-3-5-sonnet-latest,openai/gpt-4o`
+    pub fn from_config() -> Result<Vec<Self>> {
+        let config = Config::global();
+        let entries = match config.get::<Vec<Self>>(FALLBACK_PROVIDERS_CONFIG_KEY) {
+            Ok(entries) => entries,
+            Err(_) => config
+                .get::<String>(FALLBACK_PROVIDERS_CONFIG_KEY)?
+                .split(',')
+                .filter(|entry| !entry.trim().is_empty())
+                .map(str::parse)
+                .collect::<Result<_>>()?,
+        };
+        if entries.is_empty() {
+            return Err(anyhow!("{} is empty", FALLBACK_PROVIDERS_CONFIG_KEY));
+        }
+        Ok(entries)
+    }
+}
+
+/// Whether the next provider in the chain should be tried after this error
+///
+/// These are failures of the provider rather than of the request, which would fail the
+/// same way everywhere.
+pub fn is_fallback_error(error: &ProviderError) -> bool {
+    matches!(
+        error,
+        ProviderError::ServerError(_)
+            | ProviderError::RateLimitExceeded { .. }
+            | ProviderError::Authentication(_)
+    )
+}
+
+/// A provider that tries each of a list of providers in turn, moving on to the next
+/// when one is down, rate limited or rejects its credentials
+///
+/// The usage of each completion names the provider that served it.
+pub struct FallbackProvider {
+    providers: Vec<(String, Box<dyn Provider + Send + Sync>)>,
+}
+
+impl FallbackProvider {
+    /// Create a chain from providers and their names, in the order they are tried
+    pub fn new(providers: Vec<(String, Box<dyn Provider + Send + Sync>)>) -> Result<Self> {
+        if providers.is_empty() {
+            return Err(anyhow!("A fallback chain needs at least one provider"));
+        }
+        Ok(Self { providers })
+    }
+
+    /// Create the chain set in the config, with each provider built by the factory
+    ///
+    /// The providers are priced but not retried, since a failure moves on to the next one.
+    /// The temperature and max tokens of `model` apply to every entry. Entries that fail to
+    /// initialize, for example because their API key isn't set, are logged and left out.
+    pub fn from_config(model: ModelConfig) -> Result<Self> {
+        let mut providers = Vec::new();
+        for entry in FallbackEntry::from_config()? {
+            if entry.provider == "fallback" {
+                return Err(anyhow!(
+                    "A fallback chain can't contain another fallback chain"
+                ));
+            }
+            let model_config = ModelConfig::new(entry.model.clone())
+                .with_temperature(model.temperature)
+                .with_max_tokens(model.max_tokens);
+            match super::factory::create_without_retry(&entry.provider, model_config) {
+                Ok(provider) => providers.push((entry.provider, provider)),
+                Err(e) => tracing::warn!(
+                    provider = %entry.provider,
+                    model = %entry.model,
+                    error = %e,
+                    "Leaving the provider out of the fallback chain"
+                ),
+            }
+        }
+        Self::new(providers)
+    }
+}
+
+#[async_trait]
+impl Provider for FallbackProvider {
+    fn metadata() -> ProviderMetadata {
+        ProviderMetadata::new(
+            "fallback",
+            "Fallback Chain",
+            "Try a list of providers in order, moving on when one is unavailable",
+            "default",
+            vec![],
+            "https://block.github.io/goose/docs/getting-started/providers",
+            vec![ConfigKey::new(
+                FALLBACK_PROVIDERS_CONFIG_KEY,
+                true,
+                false,
+                None,
+            )],
+        )
+    }
+
+    /// The model of the first provider, with the smallest context limit in the chain so
+    /// the conversation fits whichever provider serves it
+    fn get_model_config(&self) -> ModelConfig {
+        let mut model_config = self.providers[0].1.get_model_config();
+        model_config.context_limit = self
+            .providers
+            .iter()
+            .map(|(_, provider)| provider.get_model_config().context_limit())
+            .min();
+        model_config
+    }
+
+    async fn complete(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let mut last_error = None;
+        for (name, provider) in &self.providers {
+            match provider.complete(system, messages, tools).await {
+                Ok((message, mut usage)) => {
+                    usage.provider = Some(name.clone());
+                    return Ok((message, usage));
+                }
+                Err(error) if is_fallback_error(&error) => {
+                    tracing::warn!(provider = %name, error = %error, "Provider failed, trying the next one");
+                    last_error = Some(error);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Err(last_error.expect("the chain has at least one provider"))
+    }
+
+    async fn complete_json(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let mut last_error = None;
+        for (name, provider) in &self.providers {
+            match provider.complete_json(system, messages, schema).await {
+                Ok((message, mut usage)) => {
+                    usage.provider = Some(name.clone());
+                    return Ok((message, usage));
+                }
+                Err(error) if is_fallback_error(&error) => {
+                    tracing::warn!(provider = %name, error = %error, "Provider failed, trying the next one");
+                    last_error = Some(error);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Err(last_error.expect("the chain has at least one provider"))
+    }
+
+    /// Falls back when a provider fails before its first event, once it has produced
+    /// output a failure is passed on to the caller
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<ProviderStream, ProviderError> {
+        let mut last_error = None;
+        for (name, provider) in &self.providers {
+            let result = match provider.stream(system, messages, tools).await {
+                Ok(mut stream) => match stream.next().await {
+                    Some(Err(error)) => Err(error),
+                    first => Ok((first, stream)),
+                },
+                Err(error) => Err(error),
+            };
+            match result {
+                Ok((first, stream)) => {
+                    let name = name.clone();
+                    let stream = futures::stream::iter(first).chain(stream);
+                    return Ok(Box::pin(stream.map(move |event| match event {
+                        Ok(StreamEvent::Done(message, mut usage)) => {
+                            usage.provider = Some(name.clone());
+                            Ok(StreamEvent::Done(message, usage))
+                        }
+                        event => event,
+                    })));
+                }
+                Err(error) if is_fallback_error(&error) => {
+                    tracing::warn!(provider = %name, error = %error, "Provider failed, trying the next one");
+                    last_error = Some(error);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Err(last_error.expect("the chain has at least one provider"))
+    }
+
+    /// The models of the first provider, whose model the chain reports
+    async fn list_models(&self) -> Result<Option<Vec<ModelInfo>>, ProviderError> {
+        self.providers[0].1.list_models().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::base::Usage;
+    use futures::TryStreamExt;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    struct MockProvider {
+        model: &'static str,
+        error: Option<fn() -> ProviderError>,
+        calls: Arc<AtomicU32>,
+    }
+
+    #[async_trait]
+    impl Provider for MockProvider {
+        fn metadata() -> ProviderMetadata {
+            ProviderMetadata::empty()
+        }
+
+        fn get_model_config(&self) -> ModelConfig {
+            ModelConfig::new(self.model.to_string())
+        }
+
+        async fn complete(
+            &self,
+            _system: &str,
+            _messages: &[Message],
+            _tools: &[Tool],
+        ) -> Result<(Message, ProviderUsage), ProviderError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if let Some(error) = self.error {
+                return Err(error());
+            }
+            Ok((
+                Message::assistant().with_text(self.model),
+                ProviderUsage::new(self.model.to_string(), Usage::default()),
+            ))
+        }
+    }
+
+    fn mock(
+        name: &str,
+        model: &'static str,
+        error: Option<fn() -> ProviderError>,
+    ) -> ((String, Box<dyn Provider + Send + Sync>), Arc<AtomicU32>) {
+        let calls = Arc::new(AtomicU32::new(0));
+        let provider = MockProvider {
+            model,
+            error,
+            calls: calls.clone(),
+        };
+        ((name.to_string(), Box::new(provider)), calls)
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_on_outage() -> Result<()> {
+        let (anthropic, _) = mock(
+            "anthropic",
+            "claude-3-5-sonnet-latest",
+            Some(|| ProviderError::ServerError("overloaded".to_string())),
+        );
+        let (groq, _) = mock(
+            "groq",
+            "llama-3.3-70b-versatile",
+            Some(|| ProviderError::Authentication("invalid key".to_string())),
+        );
+        let (openai, _) = mock("openai", "gpt-4o", None);
+        let provider = FallbackProvider::new(vec![anthropic, groq, openai])?;
+
+        let (message, usage) = provider.complete("system", &[], &[]).await?;
+        assert_eq!(message.as_concat_text(), "gpt-4o");
+        assert_eq!(usage.model, "gpt-4o");
+        assert_eq!(usage.provider.as_deref(), Some("openai"));
+
+        let events: Vec<StreamEvent> = provider
+            .stream("system", &[], &[])
+            .await?
+            .try_collect()
+            .await?;
+        let Some(StreamEvent::Done(_, usage)) = events.last() else {
+            panic!("The stream should end with the final message");
+        };
+        assert_eq!(usage.provider.as_deref(), Some("openai"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_request_errors_are_not_retried_elsewhere() -> Result<()> {
+        let (anthropic, _) = mock(
+            "anthropic",
+            "claude-3-5-sonnet-latest",
+            Some(|| ProviderError::ContextLengthExceeded("too long".to_string())),
+        );
+        let (openai, openai_calls) = mock("openai", "gpt-4o", None);
+        let provider = FallbackProvider::new(vec![anthropic, openai])?;
+
+        let result = provider.complete("system", &[], &[]).await;
+        assert!(matches!(
+            result,
+            Err(ProviderError::ContextLengthExceeded(_))
+        ));
+        assert_eq!(openai_calls.load(Ordering::SeqCst), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_returns_last_error_when_all_fail() -> Result<()> {
+        let (anthropic, _) = mock(
+            "anthropic",
+            "claude-3-5-sonnet-latest",
+            Some(|| ProviderError::ServerError("overloaded".to_string())),
+        );
+        let (openai, _) = mock(
+            "openai",
+            "gpt-4o",
+            Some(|| ProviderError::RateLimitExceeded {
+                details: "slow down".to_string(),
+                retry_after: None,
+            }),
+        );
+        let provider = FallbackProvider::new(vec![anthropic, openai])?;
+
+        let result = provider.complete("system", &[], &[]).await;
+        assert!(matches!(
+            result,
+            Err(ProviderError::RateLimitExceeded { .. })
+        ));
+        assert_eq!(
+            provider.get_model_config().model_name,
+            "claude-3-5-sonnet-latest"
+        );
+        assert_eq!(provider.get_model_config().context_limit, Some(128_000));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_entry() {
+        assert_eq!(
+            "openrouter/anthropic/claude-3.5-sonnet"
+                .parse::<FallbackEntry>()
+                .unwrap(),
+            FallbackEntry {
+                provider: "openrouter".to_string(),
+                model: "anthropic/claude-3.5-sonnet".to_string(),
+            }
+        );
+        assert!("gpt-4o".parse::<FallbackEntry>().is_err());
+        assert!("openai/".parse::<FallbackEntry>().is_err());
+    }
+}
@@ -1,12 +1,13 @@
 use crate::message::{Message, MessageContent};
 use crate::model::ModelConfig;
-use crate::providers::base::Usage;
+use crate::providers::base::{MessageDelta, Usage};
 use crate::providers::errors::ProviderError;
+use crate::providers::structured::{object_schema, STRUCTURED_OUTPUT_NAME};
 use anyhow::{anyhow, Result};
 use mcp_core::content::Content;
 use mcp_core::role::Role;
 use mcp_core::tool::{Tool, ToolCall};
-use serde_json::{json, Value};
+use serde_json::{json, Map, Value};
 use std::collections::HashSet;
 
 /// Convert internal Message format to Anthropic's API message specification
@@ -58,6 +59,22 @@ pub fn format_messages(messages: &[Message]) -> Vec<Value> {
                     }
                 }
                 MessageContent::Image(_) => continue, // Anthropic doesn't support image content yet
+                MessageContent::Thinking(thinking) => {
+                    // Thinking is only accepted back with the signature Anthropic gave it,
+                    // so thinking from other providers is left out
+                    match (&thinking.signature, thinking.redacted) {
+                        (Some(data), true) => content.push(json!({
+                            "type": "redacted_thinking",
+                            "data": data
+                        })),
+                        (Some(signature), false) => content.push(json!({
+                            "type": "thinking",
+                            "thinking": thinking.thinking,
+                            "signature": signature
+                        })),
+                        (None, _) => continue,
+                    }
+                }
             }
         }
 
@@ -160,6 +177,19 @@ pub fn response_to_message(response: Value) -> Result<Message> {
                     message = message.with_text(text.to_string());
                 }
             }
+            Some("thinking") => {
+                let thinking = block.get("thinking").and_then(|t| t.as_str());
+                let signature = block.get("signature").and_then(|s| s.as_str());
+                message = message.with_thinking(
+                    thinking.unwrap_or_default(),
+                    signature.map(|s| s.to_string()),
+                );
+            }
+            Some("redacted_thinking") => {
+                if let Some(data) = block.get("data").and_then(|d| d.as_str()) {
+                    message = message.with_content(MessageContent::redacted_thinking(data));
+                }
+            }
             Some("tool_use") => {
                 let id = block
                     .get("id")
@@ -187,20 +217,20 @@ pub fn response_to_message(response: Value) -> Result<Message> {
 pub fn get_usage(data: &Value) -> Result<Usage> {
     // Extract usage data if available
     if let Some(usage) = data.get("usage") {
-        let input_tokens = usage
-            .get("input_tokens")
-            .and_then(|v| v.as_u64())
-            .map(|v| v as i32);
-        let output_tokens = usage
-            .get("output_tokens")
-            .and_then(|v| v.as_u64())
-            .map(|v| v as i32);
+        let tokens = |key: &str| usage.get(key).and_then(|v| v.as_u64()).map(|v| v as i32);
+        // input_tokens only counts the tokens after the last cache breakpoint
+        let cache_read_tokens = tokens("cache_read_input_tokens");
+        let cache_write_tokens = tokens("cache_creation_input_tokens");
+        let input_tokens = tokens("input_tokens")
+            .map(|input| input + cache_read_tokens.unwrap_or(0) + cache_write_tokens.unwrap_or(0));
+        let output_tokens = tokens("output_tokens");
         let total_tokens = match (input_tokens, output_tokens) {
             (Some(i), Some(o)) => Some(i + o),
             _ => None,
         };
 
-        Ok(Usage::new(input_tokens, output_tokens, total_tokens))
+        Ok(Usage::new(input_tokens, output_tokens, total_tokens)
+            .with_cache_tokens(cache_read_tokens, cache_write_tokens))
     } else {
         tracing::warn!(
             "Failed to get usage data: {}",
@@ -211,6 +241,149 @@ pub fn get_usage(data: &Value) -> Result<Usage> {
     }
 }
 
+/// Accumulates the events of a streaming Anthropic response
+/// https://docs.anthropic.com/en/api/messages-streaming
+#[derive(Debug, Default)]
+pub struct StreamState {
+    model: Option<String>,
+    blocks: Vec<Value>,
+    // The partial JSON input of tool_use blocks, by block index
+    tool_inputs: Vec<(usize, String)>,
+    usage: Map<String, Value>,
+}
+
+impl StreamState {
+    /// Apply a single event of the stream, returning the deltas it contained
+    pub fn apply(&mut self, event: &Value) -> Result<Vec<MessageDelta>, ProviderError> {
+        let mut deltas = Vec::new();
+        match event.get("type").and_then(|t| t.as_str()) {
+            Some("message_start") => {
+                let message = &event["message"];
+                if let Some(model) = message.get("model").and_then(|m| m.as_str()) {
+                    self.model = Some(model.to_string());
+                }
+                self.merge_usage(&message["usage"]);
+            }
+            Some("content_block_start") => {
+                let block = event["content_block"].clone();
+                if block.get("type").and_then(|t| t.as_str()) == Some("tool_use") {
+                    self.tool_inputs.push((self.blocks.len(), String::new()));
+                    deltas.push(MessageDelta::ToolCall {
+                        id: block["id"].as_str().unwrap_or_default().to_string(),
+                        name: block["name"].as_str().map(|n| n.to_string()),
+                        arguments: String::new(),
+                    });
+                }
+                self.blocks.push(block);
+            }
+            Some("content_block_delta") => {
+                let index = event["index"].as_u64().unwrap_or_default() as usize;
+                let delta = &event["delta"];
+                let block = self.blocks.get_mut(index).ok_or_else(|| {
+                    ProviderError::RequestFailed(format!(
+                        "Delta for unknown content block {}",
+                        index
+                    ))
+                })?;
+                match delta.get("type").and_then(|t| t.as_str()) {
+                    Some("text_delta") => {
+                        let text = delta["text"].as_str().unwrap_or_default();
+                        let current = block["text"].as_str().unwrap_or_default();
+                        block["text"] = json!(format!("{}{}", current, text));
+                        deltas.push(MessageDelta::Text {
+                            text: text.to_string(),
+                        });
+                    }
+                    Some("thinking_delta") => {
+                        let thinking = delta["thinking"].as_str().unwrap_or_default();
+                        let current = block["thinking"].as_str().unwrap_or_default();
+                        block["thinking"] = json!(format!("{}{}", current, thinking));
+                        deltas.push(MessageDelta::Thinking {
+                            thinking: thinking.to_string(),
+                        });
+                    }
+                    Some("signature_delta") => {
+                        let signature = delta["signature"].as_str().unwrap_or_default();
+                        let current = block["signature"].as_str().unwrap_or_default();
+                        block["signature"] = json!(format!("{}{}", current, signature));
+                    }
+                    Some("input_json_delta") => {
+                        let partial = delta["partial_json"].as_str().unwrap_or_default();
+                        if let Some((_, input)) =
+                            self.tool_inputs.iter_mut().find(|(i, _)| *i == index)
+                        {
+                            input.push_str(partial);
+                        }
+                        deltas.push(MessageDelta::ToolCall {
+                            id: block["id"].as_str().unwrap_or_default().to_string(),
+                            name: None,
+                            arguments: partial.to_string(),
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            Some("message_delta") => {
+                self.merge_usage(&event["usage"]);
+            }
+            Some("error") => {
+                let error = &event["error"];
+                let message = error["message"]
+                    .as_str()
+                    .unwrap_or("Unknown error")
+                    .to_string();
+                return Err(match error["type"].as_str() {
+                    Some("rate_limit_error") => ProviderError::RateLimitExceeded {
+                        details: message,
+                        retry_after: None,
+                    },
+                    Some("overloaded_error") | Some("api_error") => {
+                        ProviderError::ServerError(message)
+                    }
+                    _ => ProviderError::RequestFailed(message),
+                });
+            }
+            _ => {}
+        }
+        Ok(deltas)
+    }
+
+    fn merge_usage(&mut self, usage: &Value) {
+        if let Some(usage) = usage.as_object() {
+            for (key, value) in usage {
+                if !value.is_null() {
+                    self.usage.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+
+    /// Build the equivalent non-streaming response
+    /// This can be parsed with `response_to_message` and `get_usage` like any other response
+    pub fn into_response(self) -> Result<Value, ProviderError> {
+        let mut blocks = self.blocks;
+        for (index, input) in self.tool_inputs {
+            let input: Value = if input.trim().is_empty() {
+                json!({})
+            } else {
+                serde_json::from_str(&input).map_err(|e| {
+                    ProviderError::RequestFailed(format!(
+                        "Could not parse streamed tool input: {}",
+                        e
+                    ))
+                })?
+            };
+            blocks[index]["input"] = input;
+        }
+
+        Ok(json!({
+            "model": self.model.unwrap_or_else(|| "Unknown".to_string()),
+            "content": blocks,
+            "usage": self.usage,
+        }))
+    }
+}
+
 /// Create a complete request payload for Anthropic's API
 pub fn create_request(
     model_config: &ModelConfig,
@@ -260,6 +433,70 @@ pub fn create_request(
     Ok(payload)
 }
 
+/// Turn on extended thinking for a request, with up to `budget` tokens of thinking
+///
+/// The thinking counts towards max_tokens, which is raised to leave room for the reply, and
+/// a temperature can't be set along with it.
+pub fn with_thinking(mut payload: Value, budget: i32) -> Value {
+    payload["thinking"] = json!({"type": "enabled", "budget_tokens": budget});
+    let max_tokens = payload["max_tokens"].as_i64().unwrap_or(0);
+    if max_tokens <= budget as i64 {
+        payload["max_tokens"] = json!(budget as i64 + max_tokens.max(4096));
+    }
+    if let Some(payload) = payload.as_object_mut() {
+        payload.remove("temperature");
+    }
+    payload
+}
+
+/// Create a request that forces the model to reply through a tool whose input is the schema
+///
+/// Tool inputs must be objects, so other schemas are wrapped in the `value` property of
+/// the tool input, see `structured_response_to_message`.
+pub fn create_structured_request(
+    model_config: &ModelConfig,
+    system: &str,
+    messages: &[Message],
+    schema: &Value,
+) -> Result<Value> {
+    let (input_schema, _) = object_schema(schema);
+    let tool = Tool::new(
+        STRUCTURED_OUTPUT_NAME,
+        "Respond with a value that matches the input schema",
+        input_schema,
+    );
+    let mut payload = create_request(model_config, system, messages, &[tool])?;
+    payload["tool_choice"] = json!({"type": "tool", "name": STRUCTURED_OUTPUT_NAME});
+    Ok(payload)
+}
+
+/// Convert the forced tool call of a structured response to a message holding the JSON text
+///
+/// A response without the tool call is converted as usual, so it can be validated and repaired.
+pub fn structured_response_to_message(response: Value, schema: &Value) -> Result<Message> {
+    let input = response
+        .get("content")
+        .and_then(|c| c.as_array())
+        .and_then(|blocks| {
+            blocks.iter().find(|block| {
+                block.get("type").and_then(|t| t.as_str()) == Some("tool_use")
+                    && block.get("name").and_then(|n| n.as_str()) == Some(STRUCTURED_OUTPUT_NAME)
+            })
+        })
+        .and_then(|block| block.get("input"));
+
+    match input {
+        Some(input) => {
+            let value = match object_schema(schema) {
+                (_, true) => input.get("value").unwrap_or(&Value::Null),
+                (_, false) => input,
+            };
+            Ok(Message::assistant().with_text(value.to_string()))
+        }
+        None => response_to_message(response),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -295,9 +532,11 @@ mod tests {
             panic!("Expected Text content");
         }
 
-        assert_eq!(usage.input_tokens, Some(12));
+        assert_eq!(usage.input_tokens, Some(24));
         assert_eq!(usage.output_tokens, Some(15));
-        assert_eq!(usage.total_tokens, Some(27));
+        assert_eq!(usage.total_tokens, Some(39));
+        assert_eq!(usage.cache_read_input_tokens, Some(0));
+        assert_eq!(usage.cache_write_input_tokens, Some(12));
 
         Ok(())
     }
@@ -338,9 +577,9 @@ mod tests {
             panic!("Expected ToolRequest content");
         }
 
-        assert_eq!(usage.input_tokens, Some(15));
+        assert_eq!(usage.input_tokens, Some(30));
         assert_eq!(usage.output_tokens, Some(20));
-        assert_eq!(usage.total_tokens, Some(35));
+        assert_eq!(usage.total_tokens, Some(50));
 
         Ok(())
     }
@@ -420,4 +659,218 @@ mod tests {
         assert_eq!(spec_array[0]["text"], system);
         assert!(spec_array[0].get("cache_control").is_some());
     }
+
+    #[test]
+    fn test_stream_state() -> Result<()> {
+        let events = [
+            json!({"type": "message_start", "message": {"model": "claude-3-5-sonnet-20241022", "usage": {"input_tokens": 25, "cache_read_input_tokens": 2000, "cache_creation_input_tokens": 100, "output_tokens": 1}}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Let me "}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "check."}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "calculator", "input": {}}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"expression\": "}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "\"2 + 2\"}"}}),
+            json!({"type": "content_block_stop", "index": 1}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"output_tokens": 40}}),
+            json!({"type": "message_stop"}),
+        ];
+
+        let mut state = StreamState::default();
+        let mut deltas = Vec::new();
+        for event in &events {
+            deltas.extend(state.apply(event)?);
+        }
+        assert_eq!(deltas.len(), 5);
+        assert_eq!(
+            deltas[0],
+            MessageDelta::Text {
+                text: "Let me ".to_string()
+            }
+        );
+        assert_eq!(
+            deltas[2],
+            MessageDelta::ToolCall {
+                id: "toolu_1".to_string(),
+                name: Some("calculator".to_string()),
+                arguments: "".to_string(),
+            }
+        );
+
+        let response = state.into_response()?;
+        let message = response_to_message(response.clone())?;
+        assert_eq!(message.content.len(), 2);
+        assert_eq!(message.as_concat_text(), "Let me check.");
+        if let MessageContent::ToolRequest(tool_request) = &message.content[1] {
+            let tool_call = tool_request.tool_call.as_ref().unwrap();
+            assert_eq!(tool_call.name, "calculator");
+            assert_eq!(tool_call.arguments, json!({"expression": "2 + 2"}));
+        } else {
+            panic!("Expected ToolRequest content");
+        }
+
+        let usage = get_usage(&response)?;
+        assert_eq!(usage.input_tokens, Some(2125));
+        assert_eq!(usage.output_tokens, Some(40));
+        assert_eq!(usage.cache_read_input_tokens, Some(2000));
+        assert_eq!(usage.cache_write_input_tokens, Some(100));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_thinking_round_trip() -> Result<()> {
+        let response = json!({
+            "id": "msg_123",
+            "type": "message",
+            "role": "assistant",
+            "content": [
+                {"type": "thinking", "thinking": "I should use the calculator", "signature": "sig_1"},
+                {"type": "redacted_thinking", "data": "encrypted"},
+                {"type": "tool_use", "id": "toolu_1", "name": "calculator", "input": {"expression": "2 + 2"}}
+            ],
+            "model": "claude-3-7-sonnet-latest",
+            "usage": {"input_tokens": 12, "output_tokens": 15}
+        });
+
+        let message = response_to_message(response)?;
+        assert_eq!(
+            message.content[0],
+            MessageContent::thinking("I should use the calculator", Some("sig_1".to_string()))
+        );
+        assert_eq!(
+            message.content[1],
+            MessageContent::redacted_thinking("encrypted")
+        );
+
+        let spec = format_messages(&[
+            message,
+            Message::assistant().with_thinking("Unsigned thinking", None),
+        ]);
+        let content = spec[0]["content"].as_array().unwrap();
+        assert_eq!(
+            content[0],
+            json!({"type": "thinking", "thinking": "I should use the calculator", "signature": "sig_1"})
+        );
+        assert_eq!(
+            content[1],
+            json!({"type": "redacted_thinking", "data": "encrypted"})
+        );
+        assert_eq!(content[2]["type"], "tool_use");
+        // Thinking without a signature can't be sent back
+        assert_eq!(spec.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_state_thinking() -> Result<()> {
+        let events = [
+            json!({"type": "message_start", "message": {"model": "claude-3-7-sonnet-latest", "usage": {"input_tokens": 25, "output_tokens": 1}}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "thinking", "thinking": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "Let me "}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "think."}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "signature_delta", "signature": "sig_1"}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "text_delta", "text": "Done."}}),
+            json!({"type": "content_block_stop", "index": 1}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 20}}),
+        ];
+
+        let mut state = StreamState::default();
+        let mut deltas = Vec::new();
+        for event in &events {
+            deltas.extend(state.apply(event)?);
+        }
+        assert_eq!(
+            deltas,
+            vec![
+                MessageDelta::Thinking {
+                    thinking: "Let me ".to_string()
+                },
+                MessageDelta::Thinking {
+                    thinking: "think.".to_string()
+                },
+                MessageDelta::Text {
+                    text: "Done.".to_string()
+                },
+            ]
+        );
+
+        let message = response_to_message(state.into_response()?)?;
+        assert_eq!(
+            message.content[0],
+            MessageContent::thinking("Let me think.", Some("sig_1".to_string()))
+        );
+        assert_eq!(message.as_concat_text(), "Done.");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_thinking() -> Result<()> {
+        let model_config = ModelConfig::new("claude-3-7-sonnet-latest".to_string())
+            .with_temperature(Some(0.7))
+            .with_max_tokens(Some(1000));
+        let payload = create_request(
+            &model_config,
+            "system",
+            &[Message::user().with_text("Hello")],
+            &[],
+        )?;
+
+        let payload = with_thinking(payload, 2048);
+        assert_eq!(
+            payload["thinking"],
+            json!({"type": "enabled", "budget_tokens": 2048})
+        );
+        assert_eq!(payload["max_tokens"], 2048 + 4096);
+        assert!(payload.get("temperature").is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_state_error() {
+        let mut state = StreamState::default();
+        let result = state.apply(&json!({
+            "type": "error",
+            "error": {"type": "overloaded_error", "message": "Overloaded"}
+        }));
+        assert!(matches!(result, Err(ProviderError::ServerError(_))));
+    }
+
+    #[test]
+    fn test_structured_request_and_response() -> Result<()> {
+        let schema = json!({"type": "array", "items": {"type": "string"}});
+        let payload = create_structured_request(
+            &ModelConfig::new("claude-3-5-sonnet-latest".to_string()),
+            "system",
+            &[Message::user().with_text("List three colors")],
+            &schema,
+        )?;
+        assert_eq!(
+            payload["tool_choice"],
+            json!({"type": "tool", "name": "response"})
+        );
+        assert_eq!(payload["tools"][0]["name"], "response");
+        assert_eq!(
+            payload["tools"][0]["input_schema"]["properties"]["value"],
+            schema
+        );
+
+        let response = json!({
+            "content": [{
+                "type": "tool_use",
+                "id": "toolu_1",
+                "name": "response",
+                "input": {"value": ["red", "green", "blue"]}
+            }],
+            "usage": {"input_tokens": 12, "output_tokens": 20}
+        });
+        let message = structured_response_to_message(response, &schema)?;
+        assert_eq!(message.as_concat_text(), r#"["red","green","blue"]"#);
+        Ok(())
+    }
 }
@@ -0,0 +1,559 @@
+use crate::message::{Message, MessageContent};
+use crate::model::ModelConfig;
+use crate::providers::base::{MessageDelta, Usage};
+use crate::providers::errors::ProviderError;
+use anyhow::{anyhow, Result};
+use mcp_core::content::{Content, ImageContent};
+use mcp_core::role::Role;
+use mcp_core::tool::{Tool, ToolCall};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashSet};
+
+// https://docs.aws.amazon.com/bedrock/latest/APIReference/API_runtime_Converse.html
+
+/// Convert an image to a Bedrock image block, if it's in one of the supported formats
+fn format_image(image: &ImageContent) -> Option<Value> {
+    let format = match image.mime_type.as_str() {
+        "image/png" => "png",
+        "image/jpeg" | "image/jpg" => "jpeg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        _ => return None,
+    };
+    Some(json!({
+        "image": {
+            "format": format,
+            "source": {"bytes": image.data}
+        }
+    }))
+}
+
+/// Convert internal Message format to Bedrock's Converse message specification
+pub fn format_messages(messages: &[Message]) -> Vec<Value> {
+    let mut bedrock_messages = Vec::new();
+
+    for message in messages {
+        let role = match message.role {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        };
+
+        let mut content = Vec::new();
+        for msg_content in &message.content {
+            match msg_content {
+                MessageContent::Text(text) => {
+                    if !text.text.is_empty() {
+                        content.push(json!({"text": text.text}));
+                    }
+                }
+                MessageContent::Image(image) => {
+                    if let Some(block) = format_image(image) {
+                        content.push(block);
+                    }
+                }
+                MessageContent::Thinking(thinking) => {
+                    // Reasoning can only be sent back with the signature it came with
+                    match &thinking.signature {
+                        Some(data) if thinking.redacted => content.push(json!({
+                            "reasoningContent": {"redactedContent": data}
+                        })),
+                        Some(signature) => content.push(json!({
+                            "reasoningContent": {
+                                "reasoningText": {
+                                    "text": thinking.thinking,
+                                    "signature": signature
+                                }
+                            }
+                        })),
+                        None => {}
+                    }
+                }
+                MessageContent::ToolRequest(tool_request) => {
+                    if let Ok(tool_call) = &tool_request.tool_call {
+                        content.push(json!({
+                            "toolUse": {
+                                "toolUseId": tool_request.id,
+                                "name": tool_call.name,
+                                "input": tool_call.arguments
+                            }
+                        }));
+                    }
+                }
+                MessageContent::ToolResponse(tool_response) => {
+                    // Every toolUse needs a toolResult, so errors are sent back as results too
+                    let (status, result) = match &tool_response.tool_result {
+                        Ok(contents) => {
+                            let result: Vec<Value> = contents
+                                .iter()
+                                .filter_map(|c| match c {
+                                    Content::Text(t) => Some(json!({"text": t.text})),
+                                    Content::Image(image) => format_image(image),
+                                    Content::Resource(_) => None,
+                                })
+                                .collect();
+                            ("success", result)
+                        }
+                        Err(e) => ("error", vec![json!({"text": e.to_string()})]),
+                    };
+                    content.push(json!({
+                        "toolResult": {
+                            "toolUseId": tool_response.id,
+                            "content": result,
+                            "status": status
+                        }
+                    }));
+                }
+            }
+        }
+
+        // Skip messages with empty content
+        if !content.is_empty() {
+            bedrock_messages.push(json!({
+                "role": role,
+                "content": content
+            }));
+        }
+    }
+
+    bedrock_messages
+}
+
+/// Convert internal Tool format to Bedrock's tool specification
+pub fn format_tools(tools: &[Tool]) -> Vec<Value> {
+    let mut unique_tools = HashSet::new();
+    tools
+        .iter()
+        .filter(|tool| unique_tools.insert(tool.name.clone()))
+        .map(|tool| {
+            json!({
+                "toolSpec": {
+                    "name": tool.name,
+                    "description": tool.description,
+                    "inputSchema": {"json": tool.input_schema}
+                }
+            })
+        })
+        .collect()
+}
+
+/// Convert Bedrock's Converse response to internal Message format
+pub fn response_to_message(response: Value) -> Result<Message> {
+    let content_blocks = response
+        .pointer("/output/message/content")
+        .and_then(|c| c.as_array())
+        .ok_or_else(|| anyhow!("Invalid response format: missing output message content"))?;
+
+    let mut message = Message::assistant();
+
+    for block in content_blocks {
+        if let Some(text) = block.get("text").and_then(|t| t.as_str()) {
+            message = message.with_text(text.to_string());
+        } else if let Some(reasoning) = block.get("reasoningContent") {
+            if let Some(text) = reasoning.get("reasoningText") {
+                let thinking = text
+                    .get("text")
+                    .and_then(|t| t.as_str())
+                    .unwrap_or_default();
+                let signature = text.get("signature").and_then(|s| s.as_str());
+                message = message.with_thinking(thinking, signature.map(String::from));
+            } else if let Some(data) = reasoning.get("redactedContent").and_then(|d| d.as_str()) {
+                message = message.with_content(MessageContent::redacted_thinking(data));
+            }
+        } else if let Some(tool_use) = block.get("toolUse") {
+            let id = tool_use
+                .get("toolUseId")
+                .and_then(|i| i.as_str())
+                .ok_or_else(|| anyhow!("Missing toolUse id"))?;
+            let name = tool_use
+                .get("name")
+                .and_then(|n| n.as_str())
+                .ok_or_else(|| anyhow!("Missing toolUse name"))?;
+            let input = tool_use
+                .get("input")
+                .ok_or_else(|| anyhow!("Missing toolUse input"))?;
+
+            let tool_call = ToolCall::new(name, input.clone());
+            message = message.with_tool_request(id, Ok(tool_call));
+        }
+    }
+
+    Ok(message)
+}
+
+/// Extract usage information from Bedrock's Converse response
+pub fn get_usage(data: &Value) -> Usage {
+    let usage = &data["usage"];
+    let tokens = |key: &str| usage.get(key).and_then(|v| v.as_u64()).map(|v| v as i32);
+    let input_tokens = tokens("inputTokens");
+    let output_tokens = tokens("outputTokens");
+    let total_tokens = tokens("totalTokens").or(match (input_tokens, output_tokens) {
+        (Some(i), Some(o)) => Some(i + o),
+        _ => None,
+    });
+    Usage::new(input_tokens, output_tokens, total_tokens)
+}
+
+/// Create a complete request payload for Bedrock's Converse API
+pub fn create_request(
+    model_config: &ModelConfig,
+    system: &str,
+    messages: &[Message],
+    tools: &[Tool],
+) -> Result<Value> {
+    let bedrock_messages = format_messages(messages);
+    if bedrock_messages.is_empty() {
+        return Err(anyhow!("No valid messages to send to Bedrock"));
+    }
+
+    let mut inference_config = json!({
+        "maxTokens": model_config.max_tokens.unwrap_or(4096)
+    });
+    if let Some(temp) = model_config.temperature {
+        inference_config["temperature"] = json!(temp);
+    }
+
+    let mut payload = json!({
+        "messages": bedrock_messages,
+        "inferenceConfig": inference_config
+    });
+    if !system.is_empty() {
+        payload["system"] = json!([{"text": system}]);
+    }
+
+    let tool_specs = format_tools(tools);
+    if !tool_specs.is_empty() {
+        payload["toolConfig"] = json!({"tools": tool_specs});
+    }
+
+    Ok(payload)
+}
+
+/// Accumulates the events of a ConverseStream response
+/// https://docs.aws.amazon.com/bedrock/latest/APIReference/API_runtime_ConverseStream.html
+#[derive(Debug, Default)]
+pub struct StreamState {
+    // Content blocks in the format of a Converse response, by block index
+    blocks: BTreeMap<u64, Value>,
+    // The partial JSON input of toolUse blocks, by block index
+    tool_inputs: BTreeMap<u64, String>,
+    stop_reason: Option<String>,
+    usage: Value,
+}
+
+impl StreamState {
+    /// Apply a single event of the stream, returning the deltas it contained
+    pub fn apply(&mut self, event_type: &str, event: &Value) -> Vec<MessageDelta> {
+        let index = event["contentBlockIndex"].as_u64().unwrap_or_default();
+        let mut deltas = Vec::new();
+        match event_type {
+            "contentBlockStart" => {
+                if let Some(tool_use) = event.pointer("/start/toolUse") {
+                    let id = tool_use["toolUseId"].as_str().unwrap_or_default();
+                    let name = tool_use["name"].as_str().unwrap_or_default();
+                    self.blocks
+                        .insert(index, json!({"toolUse": {"toolUseId": id, "name": name}}));
+                    self.tool_inputs.insert(index, String::new());
+                    deltas.push(MessageDelta::ToolCall {
+                        id: id.to_string(),
+                        name: Some(name.to_string()),
+                        arguments: String::new(),
+                    });
+                }
+            }
+            "contentBlockDelta" => {
+                let delta = &event["delta"];
+                let block = self.blocks.entry(index).or_insert_with(|| json!({}));
+                if let Some(text) = delta.get("text").and_then(|t| t.as_str()) {
+                    let current = block["text"].as_str().unwrap_or_default();
+                    block["text"] = json!(format!("{}{}", current, text));
+                    deltas.push(MessageDelta::Text {
+                        text: text.to_string(),
+                    });
+                } else if let Some(input) = delta.pointer("/toolUse/input").and_then(|i| i.as_str())
+                {
+                    if let Some(partial) = self.tool_inputs.get_mut(&index) {
+                        partial.push_str(input);
+                    }
+                    deltas.push(MessageDelta::ToolCall {
+                        id: block["toolUse"]["toolUseId"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string(),
+                        name: None,
+                        arguments: input.to_string(),
+                    });
+                } else if let Some(reasoning) = delta.get("reasoningContent") {
+                    if let Some(data) = reasoning.get("redactedContent") {
+                        block["reasoningContent"] = json!({"redactedContent": data});
+                    } else {
+                        let text = &mut block["reasoningContent"]["reasoningText"];
+                        if let Some(thinking) = reasoning.get("text").and_then(|t| t.as_str()) {
+                            let current = text["text"].as_str().unwrap_or_default();
+                            text["text"] = json!(format!("{}{}", current, thinking));
+                            deltas.push(MessageDelta::Thinking {
+                                thinking: thinking.to_string(),
+                            });
+                        }
+                        if let Some(signature) = reasoning.get("signature") {
+                            text["signature"] = signature.clone();
+                        }
+                    }
+                }
+            }
+            "messageStop" => {
+                self.stop_reason = event["stopReason"].as_str().map(String::from);
+            }
+            "metadata" => {
+                self.usage = event["usage"].clone();
+            }
+            _ => {}
+        }
+        deltas
+    }
+
+    /// Build the equivalent Converse response
+    /// This can be parsed with `response_to_message` and `get_usage` like any other response
+    pub fn into_response(self) -> Result<Value, ProviderError> {
+        let mut blocks = self.blocks;
+        for (index, input) in self.tool_inputs {
+            let input: Value = if input.trim().is_empty() {
+                json!({})
+            } else {
+                serde_json::from_str(&input).map_err(|e| {
+                    ProviderError::RequestFailed(format!(
+                        "Could not parse streamed tool input: {}",
+                        e
+                    ))
+                })?
+            };
+            if let Some(block) = blocks.get_mut(&index) {
+                block["toolUse"]["input"] = input;
+            }
+        }
+
+        Ok(json!({
+            "output": {"message": {
+                "role": "assistant",
+                "content": blocks.into_values().collect::<Vec<_>>()
+            }},
+            "stopReason": self.stop_reason,
+            "usage": self.usage
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mcp_core::handler::ToolError;
+
+    #[test]
+    fn test_format_tool_messages() {
+        let messages = vec![
+            Message::user().with_text("List the files"),
+            Message::assistant().with_tool_request(
+                "tooluse_1",
+                Ok(ToolCall::new("developer__shell", json!({"command": "ls"}))),
+            ),
+            Message::user()
+                .with_tool_response("tooluse_1", Ok(vec![Content::text("README.md")]))
+                .with_tool_response(
+                    "tooluse_2",
+                    Err(ToolError::ExecutionError("denied".to_string())),
+                ),
+        ];
+
+        let formatted = format_messages(&messages);
+        assert_eq!(formatted.len(), 3);
+        assert_eq!(
+            formatted[1]["content"][0],
+            json!({"toolUse": {
+                "toolUseId": "tooluse_1",
+                "name": "developer__shell",
+                "input": {"command": "ls"}
+            }})
+        );
+        assert_eq!(
+            formatted[2]["content"][0],
+            json!({"toolResult": {
+                "toolUseId": "tooluse_1",
+                "content": [{"text": "README.md"}],
+                "status": "success"
+            }})
+        );
+        assert_eq!(formatted[2]["content"][1]["toolResult"]["status"], "error");
+    }
+
+    #[test]
+    fn test_create_request() -> Result<()> {
+        let tool = Tool::new(
+            "developer__shell",
+            "Run a command",
+            json!({"type": "object", "properties": {"command": {"type": "string"}}}),
+        );
+        let model_config =
+            ModelConfig::new("anthropic.claude-3-5-sonnet".to_string()).with_temperature(Some(0.5));
+
+        let payload = create_request(
+            &model_config,
+            "You are goose",
+            &[Message::user().with_text("Hi")],
+            &[tool.clone(), tool],
+        )?;
+        assert_eq!(payload["system"], json!([{"text": "You are goose"}]));
+        assert_eq!(payload["inferenceConfig"]["temperature"], 0.5);
+        assert_eq!(payload["toolConfig"]["tools"].as_array().unwrap().len(), 1);
+        assert_eq!(
+            payload["toolConfig"]["tools"][0]["toolSpec"]["inputSchema"]["json"]["type"],
+            "object"
+        );
+
+        assert!(create_request(&model_config, "", &[], &[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reasoning_round_trip() -> Result<()> {
+        let response = json!({
+            "output": {"message": {"role": "assistant", "content": [
+                {"reasoningContent": {"reasoningText": {"text": "List the files", "signature": "sig_1"}}},
+                {"reasoningContent": {"redactedContent": "encrypted"}},
+                {"text": "Done."}
+            ]}}
+        });
+        let message = response_to_message(response)?;
+        assert_eq!(
+            message.content[0],
+            MessageContent::thinking("List the files", Some("sig_1".to_string()))
+        );
+        assert_eq!(
+            message.content[1],
+            MessageContent::redacted_thinking("encrypted")
+        );
+
+        let spec = format_messages(&[message]);
+        let content = spec[0]["content"].as_array().unwrap();
+        assert_eq!(
+            content[0],
+            json!({"reasoningContent": {"reasoningText": {"text": "List the files", "signature": "sig_1"}}})
+        );
+        assert_eq!(
+            content[1],
+            json!({"reasoningContent": {"redactedContent": "encrypted"}})
+        );
+        assert_eq!(content[2], json!({"text": "Done."}));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_tool_response() -> Result<()> {
+        let response = json!({
+            "output": {"message": {"role": "assistant", "content": [
+                {"text": "Let me check."},
+                {"toolUse": {"toolUseId": "tooluse_1", "name": "developer__shell", "input": {"command": "ls"}}}
+            ]}},
+            "stopReason": "tool_use",
+            "usage": {"inputTokens": 30, "outputTokens": 12, "totalTokens": 42}
+        });
+
+        let message = response_to_message(response.clone())?;
+        assert_eq!(message.content.len(), 2);
+        if let MessageContent::ToolRequest(tool_request) = &message.content[1] {
+            let tool_call = tool_request.tool_call.as_ref().unwrap();
+            assert_eq!(tool_request.id, "tooluse_1");
+            assert_eq!(tool_call.name, "developer__shell");
+            assert_eq!(tool_call.arguments, json!({"command": "ls"}));
+        } else {
+            panic!("Expected ToolRequest content");
+        }
+
+        let usage = get_usage(&response);
+        assert_eq!(usage.input_tokens, Some(30));
+        assert_eq!(usage.output_tokens, Some(12));
+        assert_eq!(usage.total_tokens, Some(42));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_state() -> Result<()> {
+        let events = [
+            ("messageStart", json!({"role": "assistant"})),
+            (
+                "contentBlockDelta",
+                json!({"contentBlockIndex": 0, "delta": {"reasoningContent": {"text": "Look at "}}}),
+            ),
+            (
+                "contentBlockDelta",
+                json!({"contentBlockIndex": 0, "delta": {"reasoningContent": {"text": "the files"}}}),
+            ),
+            (
+                "contentBlockDelta",
+                json!({"contentBlockIndex": 0, "delta": {"reasoningContent": {"signature": "sig_1"}}}),
+            ),
+            (
+                "contentBlockDelta",
+                json!({"contentBlockIndex": 1, "delta": {"text": "Let me "}}),
+            ),
+            (
+                "contentBlockDelta",
+                json!({"contentBlockIndex": 1, "delta": {"text": "check."}}),
+            ),
+            (
+                "contentBlockStart",
+                json!({"contentBlockIndex": 2, "start": {"toolUse": {"toolUseId": "tooluse_1", "name": "developer__shell"}}}),
+            ),
+            (
+                "contentBlockDelta",
+                json!({"contentBlockIndex": 2, "delta": {"toolUse": {"input": "{\"command\":"}}}),
+            ),
+            (
+                "contentBlockDelta",
+                json!({"contentBlockIndex": 2, "delta": {"toolUse": {"input": " \"ls\"}"}}}),
+            ),
+            ("contentBlockStop", json!({"contentBlockIndex": 2})),
+            ("messageStop", json!({"stopReason": "tool_use"})),
+            (
+                "metadata",
+                json!({"usage": {"inputTokens": 20, "outputTokens": 5, "totalTokens": 25}}),
+            ),
+        ];
+
+        let mut state = StreamState::default();
+        let deltas: Vec<MessageDelta> = events
+            .iter()
+            .flat_map(|(event_type, event)| state.apply(event_type, event))
+            .collect();
+        assert_eq!(deltas.len(), 7);
+        assert_eq!(
+            deltas[2],
+            MessageDelta::Text {
+                text: "Let me ".to_string()
+            }
+        );
+        assert_eq!(
+            deltas[4],
+            MessageDelta::ToolCall {
+                id: "tooluse_1".to_string(),
+                name: Some("developer__shell".to_string()),
+                arguments: String::new(),
+            }
+        );
+
+        let response = state.into_response()?;
+        assert_eq!(get_usage(&response).total_tokens, Some(25));
+        let message = response_to_message(response)?;
+        assert_eq!(
+            message.content[0],
+            MessageContent::thinking("Look at the files", Some("sig_1".to_string()))
+        );
+        assert_eq!(message.content[1], MessageContent::text("Let me check."));
+        let MessageContent::ToolRequest(request) = &message.content[2] else {
+            panic!("Expected a tool request");
+        };
+        assert_eq!(
+            request.tool_call.as_ref().unwrap().arguments,
+            json!({"command": "ls"})
+        );
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use crate::message::{Message, MessageContent};
 use crate::model::ModelConfig;
-use crate::providers::base::Usage;
+use crate::providers::base::{MessageDelta, ModelInfo, Usage};
+use crate::providers::embedding::{value_to_embedding, Embeddings};
 use crate::providers::errors::ProviderError;
 use crate::providers::utils::{is_valid_function_name, sanitize_function_name};
 use anyhow::Result;
@@ -98,6 +99,20 @@ pub fn format_messages(messages: &[Message]) -> Vec<Value> {
         .collect()
 }
 
+/// The subset of JSON schema attributes Google accepts in tool parameters and response schemas
+const ACCEPTED_SCHEMA_ATTRIBUTES: &[&str] = &[
+    "type",
+    "format",
+    "description",
+    "nullable",
+    "enum",
+    "maxItems",
+    "minItems",
+    "properties",
+    "required",
+    "items",
+];
+
 /// Convert internal Tool format to Google's API tool specification
 pub fn format_tools(tools: &[Tool]) -> Vec<Value> {
     tools
@@ -114,23 +129,11 @@ pub fn format_tools(tools: &[Tool]) -> Vec<Value> {
                 .unwrap()
                 .clone();
             if !tool_input_schema_properties.is_empty() {
-                let accepted_tool_schema_attributes = vec![
-                    "type".to_string(),
-                    "format".to_string(),
-                    "description".to_string(),
-                    "nullable".to_string(),
-                    "enum".to_string(),
-                    "maxItems".to_string(),
-                    "minItems".to_string(),
-                    "properties".to_string(),
-                    "required".to_string(),
-                    "items".to_string(),
-                ];
                 parameters.insert(
                     "parameters".to_string(),
                     json!(process_map(
                         tool_input_schema,
-                        &accepted_tool_schema_attributes,
+                        ACCEPTED_SCHEMA_ATTRIBUTES,
                         None
                     )),
                 );
@@ -143,13 +146,14 @@ pub fn format_tools(tools: &[Tool]) -> Vec<Value> {
 /// Process a JSON map to filter out unsupported attributes
 fn process_map(
     map: &Map<String, Value>,
-    accepted_keys: &[String],
+    accepted_keys: &[&str],
     parent_key: Option<&str>,
 ) -> Value {
     let mut filtered_map: Map<String, serde_json::Value> = map
         .iter()
         .filter_map(|(key, value)| {
-            let should_remove = !accepted_keys.contains(key) && parent_key != Some("properties");
+            let should_remove =
+                !accepted_keys.contains(&key.as_str()) && parent_key != Some("properties");
             if should_remove {
                 return None;
             }
@@ -202,8 +206,14 @@ pub fn response_to_message(response: Value) -> Result<Message> {
         .unwrap_or(&binding);
 
     for part in parts {
+        let is_thought = part.get("thought").and_then(|v| v.as_bool()) == Some(true);
         if let Some(text) = part.get("text").and_then(|v| v.as_str()) {
-            content.push(MessageContent::text(text.to_string()));
+            if is_thought {
+                let signature = part.get("thoughtSignature").and_then(|v| v.as_str());
+                content.push(MessageContent::thinking(text, signature.map(String::from)));
+            } else {
+                content.push(MessageContent::text(text.to_string()));
+            }
         } else if let Some(function_call) = part.get("functionCall") {
             let id: String = rand::thread_rng()
                 .sample_iter(&Alphanumeric)
@@ -264,6 +274,140 @@ pub fn get_usage(data: &Value) -> Result<Usage> {
     }
 }
 
+/// Convert the response of the models endpoint to the models that can generate content
+pub fn response_to_models(response: &Value) -> Vec<ModelInfo> {
+    let models = response
+        .get("models")
+        .and_then(|m| m.as_array())
+        .cloned()
+        .unwrap_or_default();
+    models
+        .iter()
+        .filter(|model| {
+            model["supportedGenerationMethods"]
+                .as_array()
+                .is_some_and(|methods| methods.contains(&json!("generateContent")))
+        })
+        .filter_map(|model| {
+            let name = model.get("name").and_then(|n| n.as_str())?;
+            let context_limit = model
+                .get("inputTokenLimit")
+                .and_then(|l| l.as_u64())
+                .map(|l| l as usize);
+            Some(
+                ModelInfo::new(name.trim_start_matches("models/"))
+                    .with_context_limit(context_limit),
+            )
+        })
+        .collect()
+}
+
+/// Create a request that embeds each text with `model`, for `batchEmbedContents`
+pub fn create_embedding_request(model: &str, texts: &[String]) -> Value {
+    let requests: Vec<Value> = texts
+        .iter()
+        .map(|text| {
+            json!({
+                "model": format!("models/{}", model),
+                "content": {"parts": [{"text": text}]}
+            })
+        })
+        .collect();
+    json!({"requests": requests})
+}
+
+/// Convert the response of `batchEmbedContents` to one embedding per request
+pub fn response_to_embeddings(response: &Value) -> Result<Embeddings, ProviderError> {
+    response
+        .get("embeddings")
+        .and_then(|e| e.as_array())
+        .ok_or_else(|| ProviderError::RequestFailed("No embeddings in response".to_string()))?
+        .iter()
+        .map(|embedding| value_to_embedding(&embedding["values"]))
+        .collect()
+}
+
+/// Accumulates the chunks of a streaming Google response
+/// Each chunk is a partial response, function calls always arrive whole so only text is
+/// emitted as deltas and tool requests are part of the final message
+#[derive(Debug, Default)]
+pub struct StreamState {
+    parts: Vec<Value>,
+    usage_metadata: Option<Value>,
+    model_version: Option<String>,
+}
+
+impl StreamState {
+    /// Apply a single chunk of the stream, returning the deltas it contained
+    pub fn apply(&mut self, chunk: &Value) -> Result<Vec<MessageDelta>, ProviderError> {
+        if let Some(error) = chunk.get("error") {
+            let message = error["message"].as_str().unwrap_or("Unknown error");
+            return Err(ProviderError::ServerError(message.to_string()));
+        }
+        if let Some(usage_metadata) = chunk.get("usageMetadata") {
+            self.usage_metadata = Some(usage_metadata.clone());
+        }
+        if let Some(model_version) = chunk.get("modelVersion").and_then(|m| m.as_str()) {
+            self.model_version = Some(model_version.to_string());
+        }
+
+        let mut deltas = Vec::new();
+        let parts = chunk["candidates"][0]["content"]["parts"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        for part in parts {
+            let thought = part.get("thought").and_then(|t| t.as_bool()) == Some(true);
+            match part.get("text").and_then(|t| t.as_str()) {
+                Some(text) => {
+                    deltas.push(if thought {
+                        MessageDelta::Thinking {
+                            thinking: text.to_string(),
+                        }
+                    } else {
+                        MessageDelta::Text {
+                            text: text.to_string(),
+                        }
+                    });
+                    // Merge consecutive text, or consecutive thoughts, into a single part
+                    let is_thought = |part: &Value| part.get("thought") == Some(&json!(true));
+                    match self.parts.last_mut() {
+                        Some(last) if last.get("text").is_some() && is_thought(last) == thought => {
+                            let current = last["text"].as_str().unwrap_or_default();
+                            last["text"] = json!(format!("{}{}", current, text));
+                        }
+                        _ => self.parts.push(part),
+                    }
+                }
+                None => self.parts.push(part),
+            }
+        }
+        Ok(deltas)
+    }
+
+    /// The model version reported by the stream, if any
+    pub fn model_version(&self) -> Option<&str> {
+        self.model_version.as_deref()
+    }
+
+    /// Build the equivalent non-streaming response
+    /// This can be parsed with `response_to_message` and `get_usage` like any other response
+    pub fn into_response(self) -> Value {
+        let mut response = json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": self.parts}
+            }]
+        });
+        if let Some(usage_metadata) = self.usage_metadata {
+            response["usageMetadata"] = usage_metadata;
+        }
+        if let Some(model_version) = self.model_version {
+            response["modelVersion"] = json!(model_version);
+        }
+        response
+    }
+}
+
 /// Create a complete request payload for Google's API
 pub fn create_request(
     model_config: &ModelConfig,
@@ -297,6 +441,26 @@ pub fn create_request(
     Ok(Value::Object(payload))
 }
 
+/// Create a request whose reply is JSON constrained by `responseSchema`
+///
+/// Attributes Google doesn't support are dropped from the schema, the reply is still
+/// validated against the full schema.
+pub fn create_structured_request(
+    model_config: &ModelConfig,
+    system: &str,
+    messages: &[Message],
+    schema: &Value,
+) -> Result<Value> {
+    let mut payload = create_request(model_config, system, messages, &[])?;
+    let response_schema = match schema.as_object() {
+        Some(schema) => process_map(schema, ACCEPTED_SCHEMA_ATTRIBUTES, None),
+        None => schema.clone(),
+    };
+    payload["generationConfig"]["responseMimeType"] = json!("application/json");
+    payload["generationConfig"]["responseSchema"] = response_schema;
+    Ok(payload)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -354,6 +518,46 @@ mod tests {
         assert_eq!(usage.total_tokens, Some(3));
     }
 
+    #[test]
+    fn test_response_to_models() {
+        let response = json!({
+            "models": [
+                {
+                    "name": "models/gemini-1.5-pro",
+                    "inputTokenLimit": 2000000,
+                    "supportedGenerationMethods": ["generateContent", "countTokens"]
+                },
+                {
+                    "name": "models/text-embedding-004",
+                    "inputTokenLimit": 2048,
+                    "supportedGenerationMethods": ["embedContent"]
+                }
+            ]
+        });
+        assert_eq!(
+            response_to_models(&response),
+            vec![ModelInfo::new("gemini-1.5-pro").with_context_limit(Some(2_000_000))]
+        );
+    }
+
+    #[test]
+    fn test_embeddings() -> Result<()> {
+        let texts = vec!["first".to_string(), "second".to_string()];
+        let payload = create_embedding_request("text-embedding-004", &texts);
+        assert_eq!(
+            payload["requests"][1],
+            json!({"model": "models/text-embedding-004", "content": {"parts": [{"text": "second"}]}})
+        );
+
+        let response = json!({"embeddings": [{"values": [0.5, -0.25]}, {"values": [0.125, 1.0]}]});
+        assert_eq!(
+            response_to_embeddings(&response)?,
+            vec![vec![0.5, -0.25], vec![0.125, 1.0]]
+        );
+        assert!(response_to_embeddings(&json!({})).is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_message_to_google_spec_text_message() {
         let messages = vec![
@@ -486,6 +690,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_response_to_message_with_thought() -> Result<()> {
+        let response = json!({
+            "candidates": [{
+                "content": {
+                    "parts": [
+                        {"text": "The user wants a greeting", "thought": true},
+                        {"text": "Hello, world!"}
+                    ]
+                }
+            }]
+        });
+        let message = response_to_message(response)?;
+        assert_eq!(
+            message.content[0],
+            MessageContent::thinking("The user wants a greeting", None)
+        );
+        assert_eq!(message.as_concat_text(), "Hello, world!");
+
+        // Thoughts aren't sent back to the model
+        let spec = format_messages(&[message]);
+        assert_eq!(spec[0]["parts"], json!([{"text": "Hello, world!"}]));
+        Ok(())
+    }
+
     #[test]
     fn test_response_to_message_with_invalid_function_name() {
         let response = json!({
@@ -536,4 +765,78 @@ mod tests {
             panic!("Expected valid tool request");
         }
     }
+
+    #[test]
+    fn test_stream_state() -> Result<()> {
+        let chunks = [
+            json!({"candidates": [{"content": {"role": "model", "parts": [{"text": "Greet them", "thought": true}]}}]}),
+            json!({"candidates": [{"content": {"role": "model", "parts": [{"text": "Hello"}]}}], "modelVersion": "gemini-2.0-flash-exp"}),
+            json!({"candidates": [{"content": {"role": "model", "parts": [{"text": " there"}]}}]}),
+            json!({
+                "candidates": [{"content": {"role": "model", "parts": [{"functionCall": {"name": "valid_name", "args": {"param": "value"}}}]}}],
+                "usageMetadata": {"promptTokenCount": 3, "candidatesTokenCount": 4, "totalTokenCount": 7}
+            }),
+        ];
+
+        let mut state = StreamState::default();
+        let mut deltas = Vec::new();
+        for chunk in &chunks {
+            deltas.extend(state.apply(chunk)?);
+        }
+        assert_eq!(deltas.len(), 3);
+        assert_eq!(
+            deltas[0],
+            MessageDelta::Thinking {
+                thinking: "Greet them".to_string()
+            }
+        );
+        assert_eq!(state.model_version(), Some("gemini-2.0-flash-exp"));
+
+        let response = state.into_response();
+        let message = response_to_message(response.clone())?;
+        assert_eq!(message.content.len(), 3);
+        assert_eq!(
+            message.content[0],
+            MessageContent::thinking("Greet them", None)
+        );
+        assert_eq!(message.as_concat_text(), "Hello there");
+        assert!(message.content[2].as_tool_request().is_some());
+
+        let usage = get_usage(&response)?;
+        assert_eq!(usage.total_tokens, Some(7));
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_structured_request() -> Result<()> {
+        let schema = json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "type": "object",
+            "properties": {
+                "steps": {"type": "array", "items": {"type": "string"}, "uniqueItems": true}
+            },
+            "required": ["steps"],
+            "additionalProperties": false
+        });
+        let payload = create_structured_request(
+            &ModelConfig::new("gemini-2.0-flash-exp".to_string()).with_temperature(Some(0.5)),
+            "system",
+            &[set_up_text_message("Plan the task", Role::User)],
+            &schema,
+        )?;
+
+        let generation_config = &payload["generationConfig"];
+        assert_eq!(generation_config["temperature"], 0.5);
+        assert_eq!(generation_config["responseMimeType"], "application/json");
+        assert_eq!(
+            generation_config["responseSchema"],
+            json!({
+                "type": "object",
+                "properties": {"steps": {"type": "array", "items": {"type": "string"}}},
+                "required": ["steps"]
+            })
+        );
+        assert!(payload.get("tools").is_none());
+        Ok(())
+    }
 }
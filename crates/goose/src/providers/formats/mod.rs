@@ -1,3 +1,5 @@
 pub mod anthropic;
+pub mod bedrock;
 pub mod google;
+pub mod ollama;
 pub mod openai;
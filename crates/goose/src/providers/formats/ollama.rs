@@ -0,0 +1,681 @@
+use crate::message::{Message, MessageContent};
+use crate::model::ModelConfig;
+use crate::providers::base::{MessageDelta, Usage};
+use crate::providers::embedding::{value_to_embedding, Embeddings};
+use crate::providers::errors::ProviderError;
+use crate::providers::formats::openai::{format_tools, split_think_tags};
+use anyhow::Result;
+use mcp_core::content::Content;
+use mcp_core::role::Role;
+use mcp_core::tool::{Tool, ToolCall};
+use rand::{distributions::Alphanumeric, Rng};
+use regex::Regex;
+use serde_json::{json, Map, Value};
+use std::sync::LazyLock;
+
+// https://github.com/ollama/ollama/blob/main/docs/api.md#generate-a-chat-completion
+
+/// Stands in for a tool call cut from the text, so only the wrappers around calls are removed
+const CUT_MARKER: char = '\u{0}';
+
+/// A list of tool calls, e.g. `[{..}, {..}]`
+static CALL_LIST: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[\s*\x00(?:[\s,]*\x00)*[\s,]*\]").unwrap());
+
+/// A code fence or tag around tool calls
+static CALL_BLOCK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?s)```(?:json)?\s*\x00(?:\s*\x00)*\s*```|<tool_call>\s*\x00(?:\s*\x00)*\s*</tool_call>",
+    )
+    .unwrap()
+});
+
+/// Ollama doesn't give tool calls an id, so we make one up to match requests with responses
+fn tool_call_id() -> String {
+    let suffix: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(8)
+        .map(char::from)
+        .collect();
+    format!("call_{}", suffix)
+}
+
+/// Convert internal Message format to Ollama's chat messages
+///
+/// Every text content is joined into the message content and images go in its `images` list.
+/// Tool results become `tool` messages, with the images they contain attached to them.
+pub fn format_messages(messages: &[Message]) -> Vec<Value> {
+    let mut messages_spec = Vec::new();
+    for message in messages {
+        let mut text = Vec::new();
+        let mut images = Vec::new();
+        let mut tool_calls = Vec::new();
+        let mut tool_messages = Vec::new();
+
+        for content in &message.content {
+            match content {
+                MessageContent::Text(t) => {
+                    if !t.text.is_empty() {
+                        text.push(t.text.clone());
+                    }
+                }
+                MessageContent::Image(image) => images.push(json!(image.data)),
+                MessageContent::Thinking(_) => {
+                    // The model doesn't need its earlier thinking to continue
+                    continue;
+                }
+                MessageContent::ToolRequest(request) => match &request.tool_call {
+                    Ok(tool_call) => tool_calls.push(json!({
+                        "function": {
+                            "name": tool_call.name,
+                            "arguments": tool_call.arguments,
+                        }
+                    })),
+                    Err(e) => tool_messages.push(json!({
+                        "role": "tool",
+                        "content": format!("Error: {}", e),
+                    })),
+                },
+                MessageContent::ToolResponse(response) => match &response.tool_result {
+                    Ok(contents) => {
+                        // Send only contents with no audience or with Assistant in the audience
+                        let mut tool_text = Vec::new();
+                        let mut tool_images = Vec::new();
+                        for content in contents.iter().filter(|content| {
+                            content
+                                .audience()
+                                .is_none_or(|audience| audience.contains(&Role::Assistant))
+                        }) {
+                            match content {
+                                Content::Text(t) => tool_text.push(t.text.clone()),
+                                Content::Image(image) => tool_images.push(json!(image.data)),
+                                Content::Resource(resource) => tool_text.push(resource.get_text()),
+                            }
+                        }
+                        let mut tool_message = json!({
+                            "role": "tool",
+                            "content": tool_text.join("\n"),
+                        });
+                        if !tool_images.is_empty() {
+                            tool_message["images"] = json!(tool_images);
+                        }
+                        tool_messages.push(tool_message);
+                    }
+                    Err(e) => tool_messages.push(json!({
+                        "role": "tool",
+                        "content": format!("The tool call returned the following error:\n{}", e),
+                    })),
+                },
+            }
+        }
+
+        if !text.is_empty() || !images.is_empty() || !tool_calls.is_empty() {
+            let mut converted = json!({
+                "role": message.role,
+                "content": text.join("\n"),
+            });
+            if !images.is_empty() {
+                converted["images"] = json!(images);
+            }
+            if !tool_calls.is_empty() {
+                converted["tool_calls"] = json!(tool_calls);
+            }
+            messages_spec.push(converted);
+        }
+        messages_spec.extend(tool_messages);
+    }
+    messages_spec
+}
+
+/// The arguments of a tool call written out as JSON, which are sometimes a string of JSON
+fn parse_arguments(arguments: Option<&Value>) -> Option<Value> {
+    match arguments {
+        None => Some(json!({})),
+        Some(Value::Object(arguments)) => Some(Value::Object(arguments.clone())),
+        Some(Value::String(arguments)) => serde_json::from_str::<Value>(arguments)
+            .ok()
+            .filter(|arguments| arguments.is_object()),
+        Some(_) => None,
+    }
+}
+
+/// Read a tool call written out as JSON, in the shapes small models commonly use:
+/// `{"name": ..., "arguments": {...}}`, with `parameters` instead of `arguments`,
+/// or either of those wrapped in `{"function": ...}`
+fn json_to_tool_call(value: &Value, tools: &[Tool]) -> Option<ToolCall> {
+    let call = value.get("function").unwrap_or(value);
+    let name = call.get("name")?.as_str()?;
+    if !tools.iter().any(|tool| tool.name == name) {
+        return None;
+    }
+    let arguments = parse_arguments(call.get("arguments").or(call.get("parameters")))?;
+    Some(ToolCall::new(name, arguments))
+}
+
+/// Pull tool calls that a model wrote out as JSON in its text, for models without tool support
+///
+/// Only calls to one of `tools` are taken, so JSON that happens to be in an answer is left
+/// alone. Returns the text without the calls, and the calls in the order they were written.
+pub fn parse_text_tool_calls(text: &str, tools: &[Tool]) -> (String, Vec<ToolCall>) {
+    let mut remaining = String::new();
+    let mut tool_calls = Vec::new();
+    let mut position = 0;
+    while let Some(offset) = text[position..].find('{') {
+        let start = position + offset;
+        let mut values = serde_json::Deserializer::from_str(&text[start..]).into_iter::<Value>();
+        match values.next() {
+            Some(Ok(value)) => {
+                let end = start + values.byte_offset();
+                if let Some(tool_call) = json_to_tool_call(&value, tools) {
+                    remaining.push_str(&text[position..start]);
+                    remaining.push(CUT_MARKER);
+                    tool_calls.push(tool_call);
+                } else {
+                    remaining.push_str(&text[position..end]);
+                }
+                position = end;
+            }
+            _ => {
+                remaining.push_str(&text[position..=start]);
+                position = start + 1;
+            }
+        }
+    }
+    remaining.push_str(&text[position..]);
+
+    if tool_calls.is_empty() {
+        return (text.to_string(), tool_calls);
+    }
+    // Drop the list brackets, fences and tags the calls were wrapped in
+    let remaining = CALL_LIST.replace_all(&remaining, CUT_MARKER.to_string());
+    let remaining = CALL_BLOCK.replace_all(&remaining, "");
+    let remaining = remaining.replace(CUT_MARKER, "").trim().to_string();
+    (remaining, tool_calls)
+}
+
+/// Convert Ollama's chat response to internal Message format
+///
+/// If the model answered in text with tool calls written out as JSON, they are parsed
+/// into tool requests as long as they name one of `tools`.
+pub fn response_to_message(response: &Value, tools: &[Tool]) -> Result<Message> {
+    let original = &response["message"];
+    let mut message = Message::assistant();
+
+    let content = original["content"].as_str().unwrap_or_default();
+    let (think_tag, text) = split_think_tags(content);
+    if let Some(thinking) = original["thinking"].as_str().filter(|t| !t.is_empty()) {
+        message = message.with_thinking(thinking, None);
+    } else if let Some(thinking) = think_tag.filter(|t| !t.is_empty()) {
+        message = message.with_thinking(thinking, None);
+    }
+
+    let mut tool_calls: Vec<(String, ToolCall)> = Vec::new();
+    let mut text = text.to_string();
+    if let Some(calls) = original["tool_calls"].as_array() {
+        for call in calls {
+            let function = &call["function"];
+            let id = call["id"]
+                .as_str()
+                .map(String::from)
+                .unwrap_or_else(tool_call_id);
+            let name = function["name"].as_str().unwrap_or_default();
+            let arguments = parse_arguments(function.get("arguments")).unwrap_or(json!({}));
+            tool_calls.push((id, ToolCall::new(name, arguments)));
+        }
+    } else if !tools.is_empty() {
+        let (remaining, calls) = parse_text_tool_calls(&text, tools);
+        text = remaining;
+        tool_calls.extend(calls.into_iter().map(|call| (tool_call_id(), call)));
+    }
+
+    if !text.is_empty() {
+        message = message.with_text(text);
+    }
+    for (id, tool_call) in tool_calls {
+        message = message.with_tool_request(id, Ok(tool_call));
+    }
+    Ok(message)
+}
+
+/// Extract usage information from Ollama's chat response
+pub fn get_usage(data: &Value) -> Usage {
+    let tokens = |key: &str| data.get(key).and_then(|v| v.as_u64()).map(|v| v as i32);
+    let input_tokens = tokens("prompt_eval_count");
+    let output_tokens = tokens("eval_count");
+    let total_tokens = match (input_tokens, output_tokens) {
+        (Some(i), Some(o)) => Some(i + o),
+        _ => None,
+    };
+    Usage::new(input_tokens, output_tokens, total_tokens)
+}
+
+/// Convert the response of `/api/embed` to one embedding per input
+pub fn response_to_embeddings(response: &Value) -> Result<Embeddings, ProviderError> {
+    response
+        .get("embeddings")
+        .and_then(|e| e.as_array())
+        .ok_or_else(|| ProviderError::RequestFailed("No embeddings in response".to_string()))?
+        .iter()
+        .map(value_to_embedding)
+        .collect()
+}
+
+/// Accumulates the chunks of a streaming chat response
+///
+/// Each chunk is a JSON line with part of the message, and the last one is marked `done`
+/// and has the token counts. Tool calls arrive whole, and are given their ids here so the
+/// deltas and the final message agree.
+#[derive(Debug, Default)]
+pub struct StreamState {
+    content: String,
+    thinking: String,
+    tool_calls: Vec<Value>,
+    last: Option<Value>,
+}
+
+impl StreamState {
+    /// Apply a single chunk of the stream, returning the deltas it contained
+    pub fn apply(&mut self, chunk: &Value) -> Result<Vec<MessageDelta>, ProviderError> {
+        if let Some(error) = chunk.get("error").and_then(|e| e.as_str()) {
+            return Err(ProviderError::ServerError(error.to_string()));
+        }
+
+        let mut deltas = Vec::new();
+        let message = &chunk["message"];
+        if let Some(thinking) = message["thinking"].as_str().filter(|t| !t.is_empty()) {
+            self.thinking.push_str(thinking);
+            deltas.push(MessageDelta::Thinking {
+                thinking: thinking.to_string(),
+            });
+        }
+        if let Some(text) = message["content"].as_str().filter(|t| !t.is_empty()) {
+            self.content.push_str(text);
+            deltas.push(MessageDelta::Text {
+                text: text.to_string(),
+            });
+        }
+        for call in message["tool_calls"].as_array().into_iter().flatten() {
+            let mut call = call.clone();
+            let id = tool_call_id();
+            call["id"] = json!(id);
+            deltas.push(MessageDelta::ToolCall {
+                id,
+                name: call["function"]["name"].as_str().map(String::from),
+                arguments: call["function"]["arguments"].to_string(),
+            });
+            self.tool_calls.push(call);
+        }
+
+        if chunk["done"].as_bool() == Some(true) {
+            self.last = Some(chunk.clone());
+        }
+        Ok(deltas)
+    }
+
+    /// Build the equivalent non-streaming response
+    /// This can be parsed with `response_to_message` and `get_usage` like any other response
+    pub fn into_response(self) -> Value {
+        let mut response = self.last.unwrap_or_else(|| json!({}));
+        let mut message = json!({
+            "role": "assistant",
+            "content": self.content,
+        });
+        if !self.thinking.is_empty() {
+            message["thinking"] = json!(self.thinking);
+        }
+        if !self.tool_calls.is_empty() {
+            message["tool_calls"] = json!(self.tool_calls);
+        }
+        response["message"] = message;
+        response
+    }
+}
+
+/// Create a complete request payload for Ollama's chat API
+///
+/// The temperature, max tokens and context window are sent as model options.
+pub fn create_request(
+    model_config: &ModelConfig,
+    system: &str,
+    messages: &[Message],
+    tools: &[Tool],
+) -> Result<Value> {
+    let mut messages_spec = vec![json!({"role": "system", "content": system})];
+    messages_spec.extend(format_messages(messages));
+
+    let mut payload = json!({
+        "model": model_config.model_name,
+        "messages": messages_spec,
+        "stream": false,
+    });
+    if !tools.is_empty() {
+        payload["tools"] = json!(format_tools(tools)?);
+    }
+
+    let mut options = Map::new();
+    if let Some(temperature) = model_config.temperature {
+        options.insert("temperature".to_string(), json!(temperature));
+    }
+    if let Some(tokens) = model_config.max_tokens {
+        options.insert("num_predict".to_string(), json!(tokens));
+    }
+    if let Some(num_ctx) = model_config.num_ctx {
+        options.insert("num_ctx".to_string(), json!(num_ctx));
+    }
+    if !options.is_empty() {
+        payload["options"] = Value::Object(options);
+    }
+
+    // A number is a duration in seconds, anything else is a duration string such as "10m"
+    if let Some(keep_alive) = &model_config.keep_alive {
+        payload["keep_alive"] = match keep_alive.parse::<i64>() {
+            Ok(seconds) => json!(seconds),
+            Err(_) => json!(keep_alive),
+        };
+    }
+    Ok(payload)
+}
+
+/// Create a request for a model without tool support, describing `tools` in the system prompt
+///
+/// The model is asked to write its tool calls out as JSON, which `response_to_message` parses.
+/// Earlier tool calls and results are turned into text, as the model's template can't show them.
+pub fn create_text_tools_request(
+    model_config: &ModelConfig,
+    system: &str,
+    messages: &[Message],
+    tools: &[Tool],
+) -> Result<Value> {
+    let mut system = format!(
+        "{}\n\n# Tools\n\nYou can call the tools below. To call one, reply with a JSON object \
+         on its own line in the form {{\"name\": \"<tool name>\", \"arguments\": {{...}}}}, \
+         and wait for its result before going on.\n",
+        system
+    );
+    for tool in tools {
+        system.push_str(&format!(
+            "\n## {}\n{}\nArguments: {}\n",
+            tool.name, tool.description, tool.input_schema
+        ));
+    }
+
+    let mut payload = create_request(model_config, &system, messages, &[])?;
+    for message in payload["messages"].as_array_mut().into_iter().flatten() {
+        let Some(message) = message.as_object_mut() else {
+            continue;
+        };
+        if let Some(Value::Array(calls)) = message.remove("tool_calls") {
+            let mut content = message["content"].as_str().unwrap_or_default().to_string();
+            for call in calls {
+                if !content.is_empty() {
+                    content.push('\n');
+                }
+                content.push_str(&call["function"].to_string());
+            }
+            message.insert("content".to_string(), json!(content));
+        }
+        if message["role"] == "tool" {
+            let content = message["content"].as_str().unwrap_or_default();
+            let content = format!("The tool returned:\n{}", content);
+            message.insert("role".to_string(), json!("user"));
+            message.insert("content".to_string(), json!(content));
+        }
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mcp_core::handler::ToolError;
+
+    fn shell_tool() -> Tool {
+        Tool::new(
+            "developer__shell",
+            "Run a command",
+            json!({"type": "object", "properties": {"command": {"type": "string"}}}),
+        )
+    }
+
+    #[test]
+    fn test_format_messages() {
+        let messages = vec![
+            Message::user()
+                .with_text("What is in this image?")
+                .with_image("aGVsbG8=", "image/png"),
+            Message::assistant().with_tool_request(
+                "call_1",
+                Ok(ToolCall::new("developer__shell", json!({"command": "ls"}))),
+            ),
+            Message::user()
+                .with_tool_response("call_1", Ok(vec![Content::text("README.md")]))
+                .with_tool_response(
+                    "call_2",
+                    Err(ToolError::ExecutionError("denied".to_string())),
+                ),
+        ];
+
+        let spec = format_messages(&messages);
+        assert_eq!(spec.len(), 4);
+        assert_eq!(
+            spec[0],
+            json!({"role": "user", "content": "What is in this image?", "images": ["aGVsbG8="]})
+        );
+        assert_eq!(
+            spec[1],
+            json!({"role": "assistant", "content": "", "tool_calls": [
+                {"function": {"name": "developer__shell", "arguments": {"command": "ls"}}}
+            ]})
+        );
+        assert_eq!(spec[2], json!({"role": "tool", "content": "README.md"}));
+        assert_eq!(spec[3]["role"], "tool");
+        assert!(spec[3]["content"].as_str().unwrap().contains("denied"));
+    }
+
+    #[test]
+    fn test_create_request() -> Result<()> {
+        let model_config = ModelConfig::new("qwen2.5".to_string())
+            .with_temperature(Some(0.2))
+            .with_num_ctx(Some(16_384))
+            .with_keep_alive(Some("-1".to_string()));
+        let payload = create_request(
+            &model_config,
+            "You are goose",
+            &[Message::user().with_text("Hi")],
+            &[shell_tool()],
+        )?;
+        assert_eq!(payload["model"], "qwen2.5");
+        assert_eq!(
+            payload["messages"][0],
+            json!({"role": "system", "content": "You are goose"})
+        );
+        assert_eq!(payload["stream"], false);
+        assert_eq!(payload["tools"][0]["function"]["name"], "developer__shell");
+        assert_eq!(payload["options"]["num_ctx"], 16_384);
+        assert_eq!(payload["keep_alive"], -1);
+
+        let model_config =
+            ModelConfig::new("qwen2.5".to_string()).with_keep_alive(Some("10m".to_string()));
+        let payload = create_request(&model_config, "", &[], &[])?;
+        assert_eq!(payload["keep_alive"], "10m");
+        assert!(payload.get("options").is_none());
+        assert!(payload.get("tools").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_text_tools_request() -> Result<()> {
+        let messages = vec![
+            Message::user().with_text("List the files"),
+            Message::assistant().with_tool_request(
+                "call_1",
+                Ok(ToolCall::new("developer__shell", json!({"command": "ls"}))),
+            ),
+            Message::user().with_tool_response("call_1", Ok(vec![Content::text("README.md")])),
+        ];
+        let payload = create_text_tools_request(
+            &ModelConfig::new("gemma".to_string()),
+            "You are goose",
+            &messages,
+            &[shell_tool()],
+        )?;
+        assert!(payload.get("tools").is_none());
+
+        let system = payload["messages"][0]["content"].as_str().unwrap();
+        assert!(system.starts_with("You are goose"));
+        assert!(system.contains("## developer__shell\nRun a command"));
+
+        // The earlier call and its result are written out as text
+        let call = &payload["messages"][2];
+        assert!(call.get("tool_calls").is_none());
+        let (_, calls) = parse_text_tool_calls(call["content"].as_str().unwrap(), &[shell_tool()]);
+        assert_eq!(
+            calls,
+            vec![ToolCall::new("developer__shell", json!({"command": "ls"}))]
+        );
+        assert_eq!(
+            payload["messages"][3],
+            json!({"role": "user", "content": "The tool returned:\nREADME.md"})
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_response_to_message() -> Result<()> {
+        let response = json!({
+            "model": "qwen2.5",
+            "message": {
+                "role": "assistant",
+                "content": "",
+                "tool_calls": [
+                    {"function": {"name": "developer__shell", "arguments": {"command": "ls"}}}
+                ]
+            },
+            "done": true,
+            "prompt_eval_count": 120,
+            "eval_count": 18
+        });
+        let message = response_to_message(&response, &[shell_tool()])?;
+        assert_eq!(message.content.len(), 1);
+        let request = message.content[0].as_tool_request().unwrap();
+        assert!(request.id.starts_with("call_"));
+        let tool_call = request.tool_call.as_ref().unwrap();
+        assert_eq!(tool_call.name, "developer__shell");
+        assert_eq!(tool_call.arguments, json!({"command": "ls"}));
+
+        let usage = get_usage(&response);
+        assert_eq!(usage.input_tokens, Some(120));
+        assert_eq!(usage.output_tokens, Some(18));
+        assert_eq!(usage.total_tokens, Some(138));
+
+        let response = json!({
+            "message": {"role": "assistant", "content": "<think>Say hi</think>Hello!"}
+        });
+        let message = response_to_message(&response, &[])?;
+        assert_eq!(message.content[0], MessageContent::thinking("Say hi", None));
+        assert_eq!(message.content[1], MessageContent::text("Hello!"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_tool_calls() -> Result<()> {
+        let tools = [shell_tool()];
+        let text = "I'll list the files.\n```json\n{\"name\": \"developer__shell\", \"arguments\": {\"command\": \"ls\"}}\n```";
+        let (remaining, calls) = parse_text_tool_calls(text, &tools);
+        assert_eq!(remaining, "I'll list the files.");
+        assert_eq!(
+            calls,
+            vec![ToolCall::new("developer__shell", json!({"command": "ls"}))]
+        );
+
+        let text = "<tool_call>\n{\"function\": {\"name\": \"developer__shell\", \"parameters\": \"{\\\"command\\\": \\\"pwd\\\"}\"}}\n</tool_call>";
+        let (remaining, calls) = parse_text_tool_calls(text, &tools);
+        assert_eq!(remaining, "");
+        assert_eq!(
+            calls,
+            vec![ToolCall::new("developer__shell", json!({"command": "pwd"}))]
+        );
+
+        // JSON that doesn't call one of the tools is part of the answer
+        let text = "The config is {\"name\": \"goose\", \"arguments\": {}} and {broken";
+        let (remaining, calls) = parse_text_tool_calls(text, &tools);
+        assert_eq!(remaining, text);
+        assert!(calls.is_empty());
+
+        // Empty brackets in the answer itself are kept
+        let text = "An empty list is written [] in JSON.\n```json\n[{\"name\": \"developer__shell\", \"arguments\": {\"command\": \"ls\"}}, {\"name\": \"developer__shell\", \"arguments\": {\"command\": \"pwd\"}}]\n```";
+        let (remaining, calls) = parse_text_tool_calls(text, &tools);
+        assert_eq!(remaining, "An empty list is written [] in JSON.");
+        assert_eq!(calls.len(), 2);
+
+        let response = json!({
+            "message": {"role": "assistant", "content": "[{\"name\": \"developer__shell\", \"arguments\": {\"command\": \"ls\"}}]"}
+        });
+        let message = response_to_message(&response, &tools)?;
+        assert_eq!(message.content.len(), 1);
+        assert!(message.content[0].as_tool_request().is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_response_to_embeddings() -> Result<()> {
+        let response = json!({
+            "model": "nomic-embed-text",
+            "embeddings": [[0.5, -0.25], [0.125, 1.0]],
+            "prompt_eval_count": 6
+        });
+        assert_eq!(
+            response_to_embeddings(&response)?,
+            vec![vec![0.5, -0.25], vec![0.125, 1.0]]
+        );
+        assert_eq!(get_usage(&response).input_tokens, Some(6));
+        assert!(response_to_embeddings(&json!({"embeddings": [["a"]]})).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_state() -> Result<()> {
+        let chunks = [
+            json!({"model": "qwen2.5", "message": {"role": "assistant", "content": "Let me "}, "done": false}),
+            json!({"model": "qwen2.5", "message": {"role": "assistant", "content": "check."}, "done": false}),
+            json!({"model": "qwen2.5", "message": {"role": "assistant", "content": "", "tool_calls": [
+                {"function": {"name": "developer__shell", "arguments": {"command": "ls"}}}
+            ]}, "done": false}),
+            json!({"model": "qwen2.5", "message": {"role": "assistant", "content": ""}, "done": true,
+                "prompt_eval_count": 40, "eval_count": 12}),
+        ];
+        let mut state = StreamState::default();
+        let mut deltas = Vec::new();
+        for chunk in &chunks {
+            deltas.extend(state.apply(chunk)?);
+        }
+        assert_eq!(deltas.len(), 3);
+        assert_eq!(
+            deltas[0],
+            MessageDelta::Text {
+                text: "Let me ".to_string()
+            }
+        );
+        let MessageDelta::ToolCall {
+            id,
+            name,
+            arguments,
+        } = &deltas[2]
+        else {
+            panic!("Expected a tool call delta");
+        };
+        assert_eq!(name.as_deref(), Some("developer__shell"));
+        assert_eq!(arguments, "{\"command\":\"ls\"}");
+
+        let response = state.into_response();
+        assert_eq!(response["model"], "qwen2.5");
+        let message = response_to_message(&response, &[shell_tool()])?;
+        assert_eq!(message.content[0], MessageContent::text("Let me check."));
+        assert_eq!(&message.content[1].as_tool_request().unwrap().id, id);
+        assert_eq!(get_usage(&response).total_tokens, Some(52));
+
+        let mut state = StreamState::default();
+        assert!(state.apply(&json!({"error": "model not found"})).is_err());
+        Ok(())
+    }
+}
@@ -1,7 +1,9 @@
 use crate::message::{Message, MessageContent};
 use crate::model::ModelConfig;
-use crate::providers::base::Usage;
+use crate::providers::base::{MessageDelta, ModelInfo, Usage};
+use crate::providers::embedding::{value_to_embedding, Embeddings};
 use crate::providers::errors::ProviderError;
+use crate::providers::structured::STRUCTURED_OUTPUT_NAME;
 use crate::providers::utils::{
     convert_image, is_valid_function_name, sanitize_function_name, ImageFormat,
 };
@@ -125,6 +127,10 @@ pub fn format_messages(messages: &[Message], image_format: &ImageFormat) -> Vec<
                     // Handle direct image content
                     converted["content"] = json!([convert_image(image, image_format)]);
                 }
+                MessageContent::Thinking(_) => {
+                    // Chat completions don't take reasoning back, and some endpoints reject it
+                    continue;
+                }
             }
         }
 
@@ -164,13 +170,42 @@ pub fn format_tools(tools: &[Tool]) -> anyhow::Result<Vec<Value>> {
     Ok(result)
 }
 
+const THINK_START: &str = "<think>";
+const THINK_END: &str = "</think>";
+
+/// Split the `<think>` block that reasoning models such as DeepSeek-R1 start their reply
+/// with from the rest of the text
+pub fn split_think_tags(text: &str) -> (Option<&str>, &str) {
+    match text.trim_start().strip_prefix(THINK_START) {
+        Some(rest) => match rest.split_once(THINK_END) {
+            Some((thinking, text)) => (Some(thinking.trim()), text.trim_start()),
+            // The reply was cut off while the model was still thinking
+            None => (Some(rest.trim()), ""),
+        },
+        None => (None, text),
+    }
+}
+
 /// Convert OpenAI's API response to internal Message format
 pub fn response_to_message(response: Value) -> anyhow::Result<Message> {
     let original = response["choices"][0]["message"].clone();
     let mut content = Vec::new();
 
+    // DeepSeek and vLLM return reasoning as reasoning_content, OpenRouter as reasoning
+    let reasoning = ["reasoning_content", "reasoning"]
+        .iter()
+        .find_map(|key| original.get(*key).and_then(|r| r.as_str()))
+        .filter(|reasoning| !reasoning.is_empty());
+    if let Some(reasoning) = reasoning {
+        content.push(MessageContent::thinking(reasoning, None));
+    }
+
     if let Some(text) = original.get("content") {
         if let Some(text_str) = text.as_str() {
+            let (thinking, text_str) = split_think_tags(text_str);
+            if let Some(thinking) = thinking {
+                content.push(MessageContent::thinking(thinking, None));
+            }
             content.push(MessageContent::text(text_str));
         }
     }
@@ -246,7 +281,265 @@ pub fn get_usage(data: &Value) -> Result<Usage, ProviderError> {
             _ => None,
         });
 
-    Ok(Usage::new(input_tokens, output_tokens, total_tokens))
+    // The prompt tokens include the cached ones, caching is automatic so there's no write count
+    let cached_tokens = usage
+        .pointer("/prompt_tokens_details/cached_tokens")
+        .and_then(|v| v.as_i64())
+        .map(|v| v as i32);
+
+    Ok(Usage::new(input_tokens, output_tokens, total_tokens).with_cache_tokens(cached_tokens, None))
+}
+
+/// Convert the response of the `/v1/models` endpoint to the models it lists
+///
+/// OpenAI only reports the IDs, but some compatible servers also report the context
+/// window, as `context_window` (Groq), `context_length` or `max_model_len` (vLLM).
+pub fn response_to_models(response: &Value) -> Vec<ModelInfo> {
+    let models = response
+        .get("data")
+        .and_then(|d| d.as_array())
+        .cloned()
+        .unwrap_or_default();
+    models
+        .iter()
+        .filter_map(|model| {
+            let id = model.get("id").and_then(|i| i.as_str())?;
+            let context_limit = ["context_window", "context_length", "max_model_len"]
+                .iter()
+                .find_map(|key| model.get(*key).and_then(|v| v.as_u64()))
+                .map(|limit| limit as usize);
+            Some(ModelInfo::new(id).with_context_limit(context_limit))
+        })
+        .collect()
+}
+
+/// Convert the response of the embeddings endpoint to one embedding per input, in input order
+pub fn response_to_embeddings(response: &Value) -> Result<Embeddings, ProviderError> {
+    let mut data = response
+        .get("data")
+        .and_then(|d| d.as_array())
+        .cloned()
+        .ok_or_else(|| ProviderError::RequestFailed("No embeddings in response".to_string()))?;
+    data.sort_by_key(|item| item.get("index").and_then(|i| i.as_u64()));
+    data.iter()
+        .map(|item| value_to_embedding(&item["embedding"]))
+        .collect()
+}
+
+/// Accumulates the chunks of a streaming chat completion
+#[derive(Debug, Default)]
+pub struct StreamState {
+    model: Option<String>,
+    content: String,
+    reasoning: String,
+    think: ThinkTag,
+    // How much of the content has been emitted as deltas
+    emitted: usize,
+    tool_calls: Vec<StreamToolCall>,
+    usage: Option<Value>,
+}
+
+/// Where the stream is relative to a `<think>` block at the start of the content
+#[derive(Debug, Default, PartialEq)]
+enum ThinkTag {
+    #[default]
+    Undecided,
+    Inside,
+    // The block has closed, the whitespace before the text is dropped
+    Closed,
+    Done,
+}
+
+#[derive(Debug, Default)]
+struct StreamToolCall {
+    index: Option<u64>,
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+impl StreamState {
+    /// Apply a single chunk of the stream, returning the deltas it contained
+    pub fn apply(&mut self, chunk: &Value) -> Result<Vec<MessageDelta>, ProviderError> {
+        if let Some(error) = chunk.get("error") {
+            let message = error
+                .get("message")
+                .and_then(|m| m.as_str())
+                .unwrap_or("Unknown error");
+            return Err(ProviderError::ServerError(message.to_string()));
+        }
+
+        if let Some(model) = chunk.get("model").and_then(|m| m.as_str()) {
+            self.model = Some(model.to_string());
+        }
+        if let Some(usage) = chunk.get("usage").filter(|u| !u.is_null()) {
+            self.usage = Some(usage.clone());
+        }
+
+        let mut deltas = Vec::new();
+        let delta = &chunk["choices"][0]["delta"];
+
+        let reasoning = ["reasoning_content", "reasoning"]
+            .iter()
+            .find_map(|key| delta.get(*key).and_then(|r| r.as_str()));
+        if let Some(reasoning) = reasoning.filter(|r| !r.is_empty()) {
+            self.reasoning.push_str(reasoning);
+            deltas.push(MessageDelta::Thinking {
+                thinking: reasoning.to_string(),
+            });
+        }
+
+        if let Some(text) = delta.get("content").and_then(|c| c.as_str()) {
+            if !text.is_empty() {
+                self.content.push_str(text);
+                deltas.extend(self.content_deltas());
+            }
+        }
+
+        if let Some(tool_calls) = delta.get("tool_calls").and_then(|t| t.as_array()) {
+            for tool_call in tool_calls {
+                let index = tool_call.get("index").and_then(|i| i.as_u64());
+                let id = tool_call.get("id").and_then(|i| i.as_str());
+                let name = tool_call["function"]["name"].as_str();
+                let arguments = tool_call["function"]["arguments"]
+                    .as_str()
+                    .unwrap_or_default();
+
+                // Chunks after the first for a tool call usually only carry the index
+                let existing = self.tool_calls.iter().position(|call| match (index, id) {
+                    (Some(index), _) => call.index == Some(index),
+                    (None, Some(id)) => call.id == id,
+                    (None, None) => false,
+                });
+                let position = match existing {
+                    Some(position) => position,
+                    None => {
+                        self.tool_calls.push(StreamToolCall {
+                            index,
+                            ..Default::default()
+                        });
+                        self.tool_calls.len() - 1
+                    }
+                };
+
+                let call = &mut self.tool_calls[position];
+                if let Some(id) = id {
+                    call.id = id.to_string();
+                }
+                if let Some(name) = name {
+                    call.name.push_str(name);
+                }
+                call.arguments.push_str(arguments);
+
+                deltas.push(MessageDelta::ToolCall {
+                    id: call.id.clone(),
+                    name: name.map(|n| n.to_string()),
+                    arguments: arguments.to_string(),
+                });
+            }
+        }
+
+        Ok(deltas)
+    }
+
+    /// The deltas for the content that hasn't been emitted yet, separating a `<think>` block
+    /// at the start from the text
+    fn content_deltas(&mut self) -> Vec<MessageDelta> {
+        let mut deltas = Vec::new();
+        if self.think == ThinkTag::Undecided {
+            let start = self.content.len() - self.content.trim_start().len();
+            let trimmed = &self.content[start..];
+            if trimmed.starts_with(THINK_START) {
+                self.think = ThinkTag::Inside;
+                self.emitted = start + THINK_START.len();
+            } else if THINK_START.starts_with(trimmed) {
+                // Wait until it's clear whether this is the start of a tag
+                return deltas;
+            } else {
+                self.think = ThinkTag::Done;
+            }
+        }
+
+        if self.think == ThinkTag::Inside {
+            let pending = &self.content[self.emitted..];
+            let (thinking, consumed) = match pending.find(THINK_END) {
+                Some(end) => {
+                    self.think = ThinkTag::Closed;
+                    (&pending[..end], end + THINK_END.len())
+                }
+                None => {
+                    // Hold back what could be the start of the closing tag
+                    let held = (1..THINK_END.len())
+                        .rev()
+                        .find(|&n| pending.ends_with(&THINK_END[..n]))
+                        .unwrap_or(0);
+                    let end = pending.len() - held;
+                    (&pending[..end], end)
+                }
+            };
+            if !thinking.is_empty() {
+                deltas.push(MessageDelta::Thinking {
+                    thinking: thinking.to_string(),
+                });
+            }
+            self.emitted += consumed;
+        }
+
+        if self.think == ThinkTag::Closed {
+            let pending = &self.content[self.emitted..];
+            self.emitted += pending.len() - pending.trim_start().len();
+            if self.emitted < self.content.len() {
+                self.think = ThinkTag::Done;
+            }
+        }
+
+        if self.think == ThinkTag::Done && self.emitted < self.content.len() {
+            deltas.push(MessageDelta::Text {
+                text: self.content[self.emitted..].to_string(),
+            });
+            self.emitted = self.content.len();
+        }
+        deltas
+    }
+
+    /// Build the equivalent non-streaming response
+    /// This can be parsed with `response_to_message` and `get_usage` like any other response
+    pub fn into_response(self) -> Value {
+        let tool_calls: Vec<Value> = self
+            .tool_calls
+            .into_iter()
+            .map(|call| {
+                json!({
+                    "id": call.id,
+                    "type": "function",
+                    "function": {
+                        "name": call.name,
+                        "arguments": call.arguments,
+                    }
+                })
+            })
+            .collect();
+
+        let mut message = json!({ "role": "assistant" });
+        if !self.reasoning.is_empty() {
+            message["reasoning_content"] = json!(self.reasoning);
+        }
+        if !self.content.is_empty() {
+            message["content"] = json!(self.content);
+        }
+        if !tool_calls.is_empty() {
+            message["tool_calls"] = json!(tool_calls);
+        }
+
+        let mut response = json!({
+            "model": self.model.unwrap_or_else(|| "Unknown".to_string()),
+            "choices": [{ "message": message }],
+        });
+        if let Some(usage) = self.usage {
+            response["usage"] = usage;
+        }
+        response
+    }
 }
 
 pub fn create_request(
@@ -315,6 +608,25 @@ pub fn create_request(
     Ok(payload)
 }
 
+/// Create a request whose reply is constrained to a JSON schema through `response_format`
+pub fn create_structured_request(
+    model_config: &ModelConfig,
+    system: &str,
+    messages: &[Message],
+    schema: &Value,
+    image_format: &ImageFormat,
+) -> anyhow::Result<Value, Error> {
+    let mut payload = create_request(model_config, system, messages, &[], image_format)?;
+    payload["response_format"] = json!({
+        "type": "json_schema",
+        "json_schema": {
+            "name": STRUCTURED_OUTPUT_NAME,
+            "schema": schema
+        }
+    });
+    Ok(payload)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -580,4 +892,247 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_stream_state_text() -> anyhow::Result<()> {
+        let mut state = StreamState::default();
+        let chunks = [
+            json!({"model": "gpt-4o", "choices": [{"delta": {"role": "assistant", "content": ""}}]}),
+            json!({"model": "gpt-4o", "choices": [{"delta": {"content": "Hello"}}]}),
+            json!({"model": "gpt-4o", "choices": [{"delta": {"content": " world"}}]}),
+            json!({"model": "gpt-4o", "choices": [], "usage": {"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7, "prompt_tokens_details": {"cached_tokens": 3}}}),
+        ];
+
+        let mut deltas = Vec::new();
+        for chunk in &chunks {
+            deltas.extend(state.apply(chunk)?);
+        }
+        assert_eq!(
+            deltas,
+            vec![
+                MessageDelta::Text {
+                    text: "Hello".to_string()
+                },
+                MessageDelta::Text {
+                    text: " world".to_string()
+                },
+            ]
+        );
+
+        let response = state.into_response();
+        let message = response_to_message(response.clone())?;
+        assert_eq!(message.as_concat_text(), "Hello world");
+        let usage = get_usage(&response)?;
+        assert_eq!(usage.total_tokens, Some(7));
+        assert_eq!(usage.cache_read_input_tokens, Some(3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_state_tool_calls() -> anyhow::Result<()> {
+        let mut state = StreamState::default();
+        let chunks = [
+            json!({"choices": [{"delta": {"tool_calls": [{"index": 0, "id": "call_1", "type": "function", "function": {"name": "example_fn", "arguments": ""}}]}}]}),
+            json!({"choices": [{"delta": {"tool_calls": [{"index": 0, "function": {"arguments": "{\"param1\": "}}]}}]}),
+            json!({"choices": [{"delta": {"tool_calls": [{"index": 0, "function": {"arguments": "\"value1\"}"}}]}}]}),
+        ];
+
+        let mut deltas = Vec::new();
+        for chunk in &chunks {
+            deltas.extend(state.apply(chunk)?);
+        }
+        assert_eq!(deltas.len(), 3);
+        assert_eq!(
+            deltas[0],
+            MessageDelta::ToolCall {
+                id: "call_1".to_string(),
+                name: Some("example_fn".to_string()),
+                arguments: "".to_string(),
+            }
+        );
+
+        let message = response_to_message(state.into_response())?;
+        assert_eq!(message.content.len(), 1);
+        if let MessageContent::ToolRequest(request) = &message.content[0] {
+            assert_eq!(request.id, "call_1");
+            let tool_call = request.tool_call.as_ref().unwrap();
+            assert_eq!(tool_call.name, "example_fn");
+            assert_eq!(tool_call.arguments, json!({"param1": "value1"}));
+        } else {
+            panic!("Expected ToolRequest content");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_response_to_message_thinking() -> anyhow::Result<()> {
+        let response = json!({
+            "choices": [{
+                "message": {
+                    "role": "assistant",
+                    "reasoning_content": "The user said hi",
+                    "content": "Hello!"
+                }
+            }]
+        });
+        let message = response_to_message(response)?;
+        assert_eq!(
+            message.content[0],
+            MessageContent::thinking("The user said hi", None)
+        );
+        assert_eq!(message.as_concat_text(), "Hello!");
+
+        let response = json!({
+            "choices": [{
+                "message": {
+                    "role": "assistant",
+                    "content": "<think>\nThe user said hi\n</think>\n\nHello!"
+                }
+            }]
+        });
+        let message = response_to_message(response)?;
+        assert_eq!(
+            message.content[0],
+            MessageContent::thinking("The user said hi", None)
+        );
+        assert_eq!(message.as_concat_text(), "Hello!");
+
+        // Thinking isn't sent back to the model
+        let spec = format_messages(&[message], &ImageFormat::OpenAi);
+        assert_eq!(spec[0]["content"], "Hello!");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_state_think_tags() -> anyhow::Result<()> {
+        let mut state = StreamState::default();
+        let chunks = ["<th", "ink>Let me", " think</th", "ink>\n\nHello", " world"]
+            .map(|content| json!({"choices": [{"delta": {"content": content}}]}));
+
+        let mut deltas = Vec::new();
+        for chunk in &chunks {
+            deltas.extend(state.apply(chunk)?);
+        }
+        assert_eq!(
+            deltas,
+            vec![
+                MessageDelta::Thinking {
+                    thinking: "Let me".to_string()
+                },
+                MessageDelta::Thinking {
+                    thinking: " think".to_string()
+                },
+                MessageDelta::Text {
+                    text: "Hello".to_string()
+                },
+                MessageDelta::Text {
+                    text: " world".to_string()
+                },
+            ]
+        );
+
+        let message = response_to_message(state.into_response())?;
+        assert_eq!(
+            message.content[0],
+            MessageContent::thinking("Let me think", None)
+        );
+        assert_eq!(message.as_concat_text(), "Hello world");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_state_reasoning() -> anyhow::Result<()> {
+        let mut state = StreamState::default();
+        let chunks = [
+            json!({"choices": [{"delta": {"reasoning_content": "Hmm"}}]}),
+            json!({"choices": [{"delta": {"content": "Hi"}}]}),
+        ];
+
+        let mut deltas = Vec::new();
+        for chunk in &chunks {
+            deltas.extend(state.apply(chunk)?);
+        }
+        assert_eq!(
+            deltas[0],
+            MessageDelta::Thinking {
+                thinking: "Hmm".to_string()
+            }
+        );
+
+        let message = response_to_message(state.into_response())?;
+        assert_eq!(message.content[0], MessageContent::thinking("Hmm", None));
+        assert_eq!(message.as_concat_text(), "Hi");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_state_error() {
+        let mut state = StreamState::default();
+        let result = state.apply(&json!({"error": {"message": "overloaded"}}));
+        assert!(matches!(result, Err(ProviderError::ServerError(_))));
+    }
+
+    #[test]
+    fn test_response_to_models() {
+        let response = json!({
+            "object": "list",
+            "data": [
+                {"id": "gpt-4o", "object": "model", "owned_by": "system"},
+                {"id": "llama-3.3-70b-versatile", "object": "model", "context_window": 131072},
+                {"object": "model"}
+            ]
+        });
+        assert_eq!(
+            response_to_models(&response),
+            vec![
+                ModelInfo::new("gpt-4o"),
+                ModelInfo::new("llama-3.3-70b-versatile").with_context_limit(Some(131_072)),
+            ]
+        );
+        assert!(response_to_models(&json!({})).is_empty());
+    }
+
+    #[test]
+    fn test_response_to_embeddings() -> anyhow::Result<()> {
+        let response = json!({
+            "object": "list",
+            "data": [
+                {"object": "embedding", "index": 1, "embedding": [0.5, -0.25]},
+                {"object": "embedding", "index": 0, "embedding": [0.125, 1.0]}
+            ],
+            "model": "text-embedding-3-small",
+            "usage": {"prompt_tokens": 8, "total_tokens": 8}
+        });
+        assert_eq!(
+            response_to_embeddings(&response)?,
+            vec![vec![0.125, 1.0], vec![0.5, -0.25]]
+        );
+        assert_eq!(get_usage(&response)?.input_tokens, Some(8));
+        assert!(response_to_embeddings(&json!({})).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_structured_request() -> anyhow::Result<()> {
+        let model_config = ModelConfig::new("gpt-4o".to_string());
+        let schema = json!({"type": "object", "properties": {"answer": {"type": "string"}}});
+        let payload = create_structured_request(
+            &model_config,
+            "system",
+            &[Message::user().with_text("Hello")],
+            &schema,
+            &ImageFormat::OpenAi,
+        )?;
+
+        assert_eq!(payload["response_format"]["type"], "json_schema");
+        assert_eq!(payload["response_format"]["json_schema"]["name"], "response");
+        assert_eq!(payload["response_format"]["json_schema"]["schema"], schema);
+        assert!(payload.get("tools").is_none());
+        Ok(())
+    }
 }
@@ -1,15 +1,24 @@
 use super::errors::ProviderError;
 use crate::message::Message;
 use crate::model::ModelConfig;
-use crate::providers::base::{ConfigKey, Provider, ProviderMetadata, ProviderUsage};
-use crate::providers::formats::google::{create_request, get_usage, response_to_message};
-use crate::providers::utils::{emit_debug_trace, unescape_json_values};
+use crate::providers::base::{
+    ConfigKey, ModelInfo, Provider, ProviderMetadata, ProviderStream, ProviderUsage, StreamEvent,
+    Usage,
+};
+use crate::providers::embedding::{embed_in_batches, EmbeddingProvider, Embeddings};
+use crate::providers::formats::google::{
+    create_embedding_request, create_request, create_structured_request, get_usage,
+    response_to_embeddings, response_to_message, response_to_models, StreamState,
+};
+use crate::providers::utils::{
+    emit_debug_trace, get_retry_after, http_client, sse_json_stream, unescape_json_values,
+};
 use anyhow::Result;
 use async_trait::async_trait;
+use futures::StreamExt;
 use mcp_core::tool::Tool;
-use reqwest::{Client, StatusCode};
+use reqwest::{Client, Response, StatusCode};
 use serde_json::Value;
-use std::time::Duration;
 
 pub const GOOGLE_API_HOST: &str = "https://generativelanguage.googleapis.com";
 pub const GOOGLE_DEFAULT_MODEL: &str = "gemini-2.0-flash-exp";
@@ -23,6 +32,9 @@ pub const GOOGLE_KNOWN_MODELS: &[&str] = &[
 ];
 
 pub const GOOGLE_DOC_URL: &str = "https://ai.google/get-started/our-models/";
+pub const GOOGLE_DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-004";
+// The most requests batchEmbedContents takes at once
+const GOOGLE_EMBEDDING_BATCH_SIZE: usize = 100;
 
 #[derive(Debug, serde::Serialize)]
 pub struct GoogleProvider {
@@ -31,6 +43,7 @@ pub struct GoogleProvider {
     host: String,
     api_key: String,
     model: ModelConfig,
+    embedding_model: String,
 }
 
 impl Default for GoogleProvider {
@@ -47,72 +60,116 @@ impl GoogleProvider {
         let host: String = config
             .get("GOOGLE_HOST")
             .unwrap_or_else(|_| GOOGLE_API_HOST.to_string());
+        let embedding_model: String = config
+            .get("GOOGLE_EMBEDDING_MODEL")
+            .unwrap_or_else(|_| GOOGLE_DEFAULT_EMBEDDING_MODEL.to_string());
 
-        let client = Client::builder()
-            .timeout(Duration::from_secs(600))
-            .build()?;
+        let client = http_client()?;
 
         Ok(Self {
             client,
             host,
             api_key,
             model,
+            embedding_model,
         })
     }
 
-    async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
+    async fn send(&self, method: &str, payload: &Value) -> Result<Response, ProviderError> {
         let url = format!(
-            "{}/v1beta/models/{}:generateContent?key={}",
+            "{}/v1beta/models/{}:{}",
             self.host.trim_end_matches('/'),
             self.model.model_name,
-            self.api_key
+            method,
         );
 
+        let mut request = self.client.post(&url).query(&[("key", &self.api_key)]);
+        if method == "streamGenerateContent" {
+            request = request.query(&[("alt", "sse")]);
+        }
+
+        Ok(request
+            .header("CONTENT_TYPE", "application/json")
+            .json(payload)
+            .send()
+            .await?)
+    }
+
+    async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
+        let response = self.send("generateContent", &payload).await?;
+        handle_response(response).await
+    }
+
+    async fn embed_batch(
+        &self,
+        texts: &[String],
+    ) -> Result<(Embeddings, ProviderUsage), ProviderError> {
+        let url = format!(
+            "{}/v1beta/models/{}:batchEmbedContents",
+            self.host.trim_end_matches('/'),
+            self.embedding_model,
+        );
+        let payload = create_embedding_request(&self.embedding_model, texts);
         let response = self
             .client
             .post(&url)
-            .header("CONTENT_TYPE", "application/json")
+            .query(&[("key", &self.api_key)])
             .json(&payload)
             .send()
             .await?;
+        let response = handle_response(response).await?;
 
-        let status = response.status();
-        let payload: Option<Value> = response.json().await.ok();
+        // Google doesn't report the tokens used for embeddings
+        let embeddings = response_to_embeddings(&response)?;
+        Ok((
+            embeddings,
+            ProviderUsage::new(self.embedding_model.clone(), Usage::default()),
+        ))
+    }
+}
 
-        match status {
-            StatusCode::OK =>  payload.ok_or_else( || ProviderError::RequestFailed("Response body is not valid JSON".to_string()) ),
-            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
-                Err(ProviderError::Authentication(format!("Authentication failed. Please ensure your API keys are valid and have the required permissions. \
-                    Status: {}. Response: {:?}", status, payload )))
-            }
-            StatusCode::BAD_REQUEST => {
-                let mut error_msg = "Unknown error".to_string();
-                if let Some(payload) = &payload {
-                    if let Some(error) = payload.get("error") {
-                        error_msg = error.get("message").and_then(|m| m.as_str()).unwrap_or("Unknown error").to_string();
-                        let error_status = error.get("status").and_then(|s| s.as_str()).unwrap_or("Unknown status");
-                        if error_status == "INVALID_ARGUMENT" && error_msg.to_lowercase().contains("exceeds") {
-                            return Err(ProviderError::ContextLengthExceeded(error_msg.to_string()));
-                        }
+/// Map a response from the Gemini API to its JSON payload or the matching ProviderError
+async fn handle_response(response: Response) -> Result<Value, ProviderError> {
+    let status = response.status();
+    let retry_after = get_retry_after(response.headers());
+    let payload: Option<Value> = response.json().await.ok();
+
+    match status {
+        StatusCode::OK =>  payload.ok_or_else( || ProviderError::RequestFailed("Response body is not valid JSON".to_string()) ),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            Err(ProviderError::Authentication(format!("Authentication failed. Please ensure your API keys are valid and have the required permissions. \
+                Status: {}. Response: {:?}", status, payload )))
+        }
+        StatusCode::BAD_REQUEST => {
+            let mut error_msg = "Unknown error".to_string();
+            if let Some(payload) = &payload {
+                if let Some(error) = payload.get("error") {
+                    error_msg = error.get("message").and_then(|m| m.as_str()).unwrap_or("Unknown error").to_string();
+                    let error_status = error.get("status").and_then(|s| s.as_str()).unwrap_or("Unknown status");
+                    if error_status == "INVALID_ARGUMENT" && error_msg.to_lowercase().contains("exceeds") {
+                        return Err(ProviderError::ContextLengthExceeded(error_msg.to_string()));
                     }
                 }
-                tracing::debug!(
-                    "{}", format!("Provider request failed with status: {}. Payload: {:?}", status, payload)
-                );
-                Err(ProviderError::RequestFailed(format!("Request failed with status: {}. Message: {}", status, error_msg)))
-            }
-            StatusCode::TOO_MANY_REQUESTS => {
-                Err(ProviderError::RateLimitExceeded(format!("{:?}", payload)))
-            }
-            StatusCode::INTERNAL_SERVER_ERROR | StatusCode::SERVICE_UNAVAILABLE => {
-                Err(ProviderError::ServerError(format!("{:?}", payload)))
-            }
-            _ => {
-                tracing::debug!(
-                    "{}", format!("Provider request failed with status: {}. Payload: {:?}", status, payload)
-                );
-                Err(ProviderError::RequestFailed(format!("Request failed with status: {}", status)))
             }
+            tracing::debug!(
+                "{}", format!("Provider request failed with status: {}. Payload: {:?}", status, payload)
+            );
+            Err(ProviderError::RequestFailed(format!("Request failed with status: {}. Message: {}", status, error_msg)))
+        }
+        StatusCode::TOO_MANY_REQUESTS => {
+            Err(ProviderError::RateLimitExceeded {
+                details: format!("{:?}", payload),
+                retry_after,
+            })
+        }
+        status if status.is_server_error() => {
+            Err(ProviderError::ServerError(format!("{:?}", payload)))
+        }
+        _ => {
+            tracing::debug!(
+                "{}", format!("Provider request failed with status: {}. Payload: {:?}", status, payload)
+            );
+            Err(ProviderError::RequestFailed(format!("Request failed with status: {}", status)))
         }
     }
 }
@@ -130,6 +187,12 @@ impl Provider for GoogleProvider {
             vec![
                 ConfigKey::new("GOOGLE_API_KEY", true, true, None),
                 ConfigKey::new("GOOGLE_HOST", false, false, Some(GOOGLE_API_HOST)),
+                ConfigKey::new(
+                    "GOOGLE_EMBEDDING_MODEL",
+                    false,
+                    false,
+                    Some(GOOGLE_DEFAULT_EMBEDDING_MODEL),
+                ),
             ],
         )
     }
@@ -164,4 +227,83 @@ impl Provider for GoogleProvider {
         let provider_usage = ProviderUsage::new(model, usage);
         Ok((message, provider_usage))
     }
+
+    async fn complete_json(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let payload = create_structured_request(&self.model, system, messages, schema)?;
+
+        let response = self.post(payload.clone()).await?;
+
+        // The text is the JSON itself, so it's left escaped
+        let message = response_to_message(response.clone())?;
+        let usage = get_usage(&response)?;
+        let model = match response.get("modelVersion") {
+            Some(model_version) => model_version.as_str().unwrap_or_default().to_string(),
+            None => self.model.model_name.clone(),
+        };
+        emit_debug_trace(self, &payload, &response, &usage);
+        Ok((message, ProviderUsage::new(model, usage)))
+    }
+
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<ProviderStream, ProviderError> {
+        let payload = create_request(&self.model, system, messages, tools)?;
+
+        let response = self.send("streamGenerateContent", &payload).await?;
+        if !response.status().is_success() {
+            return Err(handle_response(response).await.err().unwrap_or_else(|| {
+                ProviderError::RequestFailed("Unexpected response status".to_string())
+            }));
+        }
+
+        let model_name = self.model.model_name.clone();
+        Ok(Box::pin(async_stream::try_stream! {
+            let mut state = StreamState::default();
+            let mut chunks = sse_json_stream(response);
+            while let Some(chunk) = chunks.next().await {
+                for delta in state.apply(&chunk?)? {
+                    yield StreamEvent::Delta(delta);
+                }
+            }
+
+            let model = state.model_version().unwrap_or(&model_name).to_string();
+            let response = state.into_response();
+            let message = response_to_message(unescape_json_values(&response))?;
+            let usage = get_usage(&response)?;
+            yield StreamEvent::Done(message, ProviderUsage::new(model, usage));
+        }))
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<ModelInfo>>, ProviderError> {
+        let url = format!("{}/v1beta/models", self.host.trim_end_matches('/'));
+        let response = self
+            .client
+            .get(&url)
+            .query(&[("key", self.api_key.as_str()), ("pageSize", "1000")])
+            .send()
+            .await?;
+        let response = handle_response(response).await?;
+        Ok(Some(response_to_models(&response)))
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for GoogleProvider {
+    async fn embed(&self, texts: &[String]) -> Result<(Embeddings, ProviderUsage), ProviderError> {
+        embed_in_batches(
+            texts,
+            GOOGLE_EMBEDDING_BATCH_SIZE,
+            &self.embedding_model,
+            |batch| self.embed_batch(batch),
+        )
+        .await
+    }
 }
@@ -1,15 +1,14 @@
 use super::errors::ProviderError;
 use crate::message::Message;
 use crate::model::ModelConfig;
-use crate::providers::base::{ConfigKey, Provider, ProviderMetadata, ProviderUsage, Usage};
-use crate::providers::formats::openai::{create_request, get_usage, response_to_message};
-use crate::providers::utils::get_model;
+use crate::providers::base::{ConfigKey, Provider, ProviderMetadata, ProviderUsage};
+use crate::providers::formats::openai::{create_request, create_structured_request};
+use crate::providers::utils::{get_retry_after, http_client, openai_completion, ImageFormat};
 use anyhow::Result;
 use async_trait::async_trait;
 use mcp_core::Tool;
 use reqwest::{Client, StatusCode};
 use serde_json::Value;
-use std::time::Duration;
 
 pub const GROQ_API_HOST: &str = "https://api.groq.com";
 pub const GROQ_DEFAULT_MODEL: &str = "llama-3.3-70b-versatile";
@@ -41,9 +40,7 @@ impl GroqProvider {
             .get("GROQ_HOST")
             .unwrap_or_else(|_| GROQ_API_HOST.to_string());
 
-        let client = Client::builder()
-            .timeout(Duration::from_secs(600))
-            .build()?;
+        let client = http_client()?;
 
         Ok(Self {
             client,
@@ -68,6 +65,7 @@ impl GroqProvider {
             .await?;
 
         let status = response.status();
+        let retry_after = get_retry_after(response.headers());
         let payload: Option<Value> = response.json().await.ok();
 
         match status {
@@ -80,9 +78,12 @@ impl GroqProvider {
                 Err(ProviderError::ContextLengthExceeded(format!("{:?}", payload)))
             }
             StatusCode::TOO_MANY_REQUESTS => {
-                Err(ProviderError::RateLimitExceeded(format!("{:?}", payload)))
+                Err(ProviderError::RateLimitExceeded {
+                    details: format!("{:?}", payload),
+                    retry_after,
+                })
             }
-            StatusCode::INTERNAL_SERVER_ERROR | StatusCode::SERVICE_UNAVAILABLE => {
+            status if status.is_server_error() => {
                 Err(ProviderError::ServerError(format!("{:?}", payload)))
             }
             _ => {
@@ -126,27 +127,20 @@ impl Provider for GroqProvider {
         messages: &[Message],
         tools: &[Tool],
     ) -> anyhow::Result<(Message, ProviderUsage), ProviderError> {
-        let payload = create_request(
-            &self.model,
-            system,
-            messages,
-            tools,
-            &super::utils::ImageFormat::OpenAi,
-        )?;
-
+        let payload = create_request(&self.model, system, messages, tools, &ImageFormat::OpenAi)?;
         let response = self.post(payload.clone()).await?;
+        openai_completion(self, &payload, response)
+    }
 
-        let message = response_to_message(response.clone())?;
-        let usage = match get_usage(&response) {
-            Ok(usage) => usage,
-            Err(ProviderError::UsageError(e)) => {
-                tracing::warn!("Failed to get usage data: {}", e);
-                Usage::default()
-            }
-            Err(e) => return Err(e),
-        };
-        let model = get_model(&response);
-        super::utils::emit_debug_trace(self, &payload, &response, &usage);
-        Ok((message, ProviderUsage::new(model, usage)))
+    async fn complete_json(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> anyhow::Result<(Message, ProviderUsage), ProviderError> {
+        let payload =
+            create_structured_request(&self.model, system, messages, schema, &ImageFormat::OpenAi)?;
+        let response = self.post(payload.clone()).await?;
+        openai_completion(self, &payload, response)
     }
 }
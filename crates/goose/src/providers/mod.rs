@@ -1,15 +1,27 @@
 pub mod anthropic;
+pub mod aws;
+pub mod azure;
 pub mod base;
+pub mod bedrock;
 pub mod databricks;
+pub mod embedding;
 pub mod errors;
 mod factory;
+pub mod fallback;
 pub mod formats;
 pub mod google;
 pub mod groq;
 pub mod oauth;
 pub mod ollama;
 pub mod openai;
+pub mod openai_compatible;
 pub mod openrouter;
+pub mod pricing;
+pub mod replay;
+pub mod retry;
+pub mod structured;
 pub mod utils;
 
-pub use factory::{create, providers};
+pub use factory::{
+    create, create_embedding, create_with_discovery, discover_context_limit, providers,
+};
@@ -9,6 +9,8 @@ use sha2::Digest;
 use std::{collections::HashMap, fs, net::SocketAddr, path::PathBuf, sync::Arc};
 use tokio::sync::{oneshot, Mutex as TokioMutex};
 
+use super::utils::http_client;
+
 lazy_static! {
     static ref OAUTH_MUTEX: TokioMutex<()> = TokioMutex::new(());
 }
@@ -79,7 +81,7 @@ async fn get_workspace_endpoints(host: &str) -> Result<OidcEndpoints> {
     let host = host.trim_end_matches('/');
     let oidc_url = format!("{}/oidc/.well-known/oauth-authorization-server", host);
 
-    let client = reqwest::Client::new();
+    let client = http_client()?;
     let resp = client.get(&oidc_url).send().await?;
 
     if !resp.status().is_success() {
@@ -167,7 +169,7 @@ impl OAuthFlow {
             ("client_id", &self.client_id),
         ];
 
-        let client = reqwest::Client::new();
+        let client = http_client()?;
         let resp = client
             .post(&self.endpoints.token_endpoint)
             .header("Content-Type", "application/x-www-form-urlencoded")
@@ -1,21 +1,33 @@
-use super::base::{ConfigKey, Provider, ProviderMetadata, ProviderUsage, Usage};
+use super::base::{
+    ConfigKey, ModelInfo, Provider, ProviderMetadata, ProviderStream, ProviderUsage, StreamEvent,
+};
+use super::embedding::{embed_in_batches, EmbeddingProvider, Embeddings};
 use super::errors::ProviderError;
-use super::utils::{get_model, handle_response_openai_compat};
+use super::formats::ollama::{
+    create_request, create_text_tools_request, get_usage, response_to_embeddings,
+    response_to_message, StreamState,
+};
+use super::utils::{emit_debug_trace, http_client, response_lines};
 use crate::message::Message;
 use crate::model::ModelConfig;
-use crate::providers::formats::openai::{create_request, get_usage, response_to_message};
 use anyhow::Result;
 use async_trait::async_trait;
+use futures::StreamExt;
 use mcp_core::tool::Tool;
-use reqwest::Client;
-use serde_json::Value;
-use std::time::Duration;
+use reqwest::{Client, Response, StatusCode};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 pub const OLLAMA_HOST: &str = "http://localhost:11434";
 pub const OLLAMA_DEFAULT_MODEL: &str = "qwen2.5";
 // Ollama can run many models, we only provide the default
 pub const OLLAMA_KNOWN_MODELS: &[&str] = &[OLLAMA_DEFAULT_MODEL];
 pub const OLLAMA_DOC_URL: &str = "https://ollama.com/library";
+pub const OLLAMA_DEFAULT_EMBEDDING_MODEL: &str = "nomic-embed-text";
+// Keeps each request to a size a local model gets through quickly
+const OLLAMA_EMBEDDING_BATCH_SIZE: usize = 64;
+// The context Ollama allocates when neither the request nor the Modelfile sets num_ctx
+const OLLAMA_DEFAULT_NUM_CTX: usize = 2048;
 
 #[derive(serde::Serialize)]
 pub struct OllamaProvider {
@@ -23,6 +35,10 @@ pub struct OllamaProvider {
     client: Client,
     host: String,
     model: ModelConfig,
+    embedding_model: String,
+    /// Set once the model turned down a request with tools, they're described in text from then on
+    #[serde(skip)]
+    text_tools: AtomicBool,
 }
 
 impl Default for OllamaProvider {
@@ -38,25 +54,227 @@ impl OllamaProvider {
         let host: String = config
             .get("OLLAMA_HOST")
             .unwrap_or_else(|_| OLLAMA_HOST.to_string());
+        let embedding_model: String = config
+            .get("OLLAMA_EMBEDDING_MODEL")
+            .unwrap_or_else(|_| OLLAMA_DEFAULT_EMBEDDING_MODEL.to_string());
+
+        // The model options set in code take precedence over the configured ones
+        let num_ctx = model.num_ctx.or(config.get("OLLAMA_NUM_CTX").ok());
+        let keep_alive = model.keep_alive.clone().or_else(|| {
+            // A keep alive in seconds is read as a number
+            config
+                .get::<Value>("OLLAMA_KEEP_ALIVE")
+                .ok()
+                .map(|value| match value {
+                    Value::String(value) => value,
+                    value => value.to_string(),
+                })
+        });
+        // The model only sees as much of the conversation as fits in its context window
+        let model = model
+            .with_context_limit(num_ctx)
+            .with_num_ctx(num_ctx)
+            .with_keep_alive(keep_alive);
 
-        let client = Client::builder()
-            .timeout(Duration::from_secs(600))
-            .build()?;
+        let client = http_client()?;
 
         Ok(Self {
             client,
             host,
             model,
+            embedding_model,
+            text_tools: AtomicBool::new(false),
         })
     }
 
+    async fn send(&self, payload: &Value) -> Result<Response, ProviderError> {
+        let url = format!("{}/api/chat", self.host.trim_end_matches('/'));
+
+        Ok(self.client.post(&url).json(payload).send().await?)
+    }
+
     async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
-        let url = format!("{}/v1/chat/completions", self.host.trim_end_matches('/'));
+        let response = self.send(&payload).await?;
+        handle_response(response).await
+    }
 
+    /// Send a chat request with `tools`, or with the tools described in the system prompt
+    /// for models that don't support tool calling
+    ///
+    /// Ollama turns down requests with tools for those models with a 400, so the request is sent
+    /// again without them, and the tools are described in text for the rest of the session.
+    async fn send_chat(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+        stream: bool,
+    ) -> Result<(Value, Response), ProviderError> {
+        if !tools.is_empty() && !self.text_tools.load(Ordering::Relaxed) {
+            let mut payload = create_request(&self.model, system, messages, tools)?;
+            payload["stream"] = json!(stream);
+            let response = self.send(&payload).await?;
+            if response.status() != StatusCode::BAD_REQUEST {
+                return Ok((payload, response));
+            }
+            match handle_response(response).await {
+                Err(ProviderError::RequestFailed(message))
+                    if message.contains("does not support tools") =>
+                {
+                    tracing::info!(
+                        model = %self.model.model_name,
+                        "The model doesn't support tools, describing them in the system prompt"
+                    );
+                    self.text_tools.store(true, Ordering::Relaxed);
+                }
+                Err(e) => return Err(e),
+                Ok(_) => {
+                    return Err(ProviderError::RequestFailed(
+                        "Unexpected response status".to_string(),
+                    ))
+                }
+            }
+        }
+
+        let mut payload = if tools.is_empty() {
+            create_request(&self.model, system, messages, tools)?
+        } else {
+            create_text_tools_request(&self.model, system, messages, tools)?
+        };
+        payload["stream"] = json!(stream);
+        let response = self.send(&payload).await?;
+        Ok((payload, response))
+    }
+
+    /// Parse the reply to a chat request, with text tool calls to `tools` if any
+    fn completion(
+        &self,
+        payload: &Value,
+        response: Value,
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let message = response_to_message(&response, tools)?;
+        let usage = get_usage(&response);
+        let model = response["model"]
+            .as_str()
+            .unwrap_or(&self.model.model_name)
+            .to_string();
+        emit_debug_trace(self, payload, &response, &usage);
+        Ok((message, ProviderUsage::new(model, usage)))
+    }
+
+    /// The details of a model from `/api/show`, None if it isn't pulled
+    async fn show(&self, name: &str) -> Result<Option<ModelInfo>, ProviderError> {
+        let url = format!("{}/api/show", self.host.trim_end_matches('/'));
+        let response = self
+            .client
+            .post(&url)
+            .json(&json!({"model": name}))
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let details = handle_response(response).await?;
+        Ok(Some(details_to_model(name, &details)))
+    }
+
+    async fn embed_batch(
+        &self,
+        texts: &[String],
+    ) -> Result<(Embeddings, ProviderUsage), ProviderError> {
+        let url = format!("{}/api/embed", self.host.trim_end_matches('/'));
+        let payload = json!({"model": self.embedding_model, "input": texts});
         let response = self.client.post(&url).json(&payload).send().await?;
+        let response = handle_response(response).await?;
+
+        let embeddings = response_to_embeddings(&response)?;
+        let model = response["model"]
+            .as_str()
+            .unwrap_or(&self.embedding_model)
+            .to_string();
+        Ok((embeddings, ProviderUsage::new(model, get_usage(&response))))
+    }
+}
 
-        handle_response_openai_compat(response).await
+/// Ollama reports errors as `{"error": "..."}` with the status of the request
+async fn handle_response(response: Response) -> Result<Value, ProviderError> {
+    let status = response.status();
+    let payload: Option<Value> = response.json().await.ok();
+    if status.is_success() {
+        return payload.ok_or_else(|| {
+            ProviderError::RequestFailed("Response body is not valid JSON".to_string())
+        });
     }
+
+    let message = payload
+        .as_ref()
+        .and_then(|payload| payload["error"].as_str())
+        .unwrap_or("Unknown error")
+        .to_string();
+    match status {
+        StatusCode::TOO_MANY_REQUESTS => Err(ProviderError::RateLimitExceeded {
+            details: message,
+            retry_after: None,
+        }),
+        status if status.is_server_error() => Err(ProviderError::ServerError(message)),
+        _ => Err(ProviderError::RequestFailed(format!(
+            "Request failed with status: {}. Message: {}",
+            status, message
+        ))),
+    }
+}
+
+/// The names of the models in the response of `/api/tags`
+///
+/// The `latest` tag is left out, as Ollama uses it when a model is named without a tag.
+fn response_to_model_names(response: &Value) -> Vec<String> {
+    response["models"]
+        .as_array()
+        .map(|models| {
+            models
+                .iter()
+                .filter_map(|model| model["name"].as_str())
+                .map(|name| name.trim_end_matches(":latest").to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The context window and tool support of a model, from the response of `/api/show`
+///
+/// The context window is the one Ollama runs the model with, the `num_ctx` of its Modelfile
+/// or Ollama's default, rather than the longer context the model was trained with.
+fn details_to_model(name: &str, details: &Value) -> ModelInfo {
+    // The parameters are the Modelfile lines, e.g. "num_ctx 8192\nstop <|im_end|>"
+    let num_ctx = details["parameters"].as_str().and_then(|parameters| {
+        parameters.lines().find_map(|line| {
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some("num_ctx"), Some(value)) => value.parse::<usize>().ok(),
+                _ => None,
+            }
+        })
+    });
+    // The trained context length is keyed by the architecture, e.g. qwen2.context_length
+    let trained = details["model_info"].as_object().and_then(|info| {
+        info.iter()
+            .find(|(key, _)| key.ends_with(".context_length"))
+            .and_then(|(_, limit)| limit.as_u64())
+            .map(|limit| limit as usize)
+    });
+    let context_limit = match (num_ctx, trained) {
+        (Some(num_ctx), _) => Some(num_ctx),
+        (None, Some(trained)) => Some(trained.min(OLLAMA_DEFAULT_NUM_CTX)),
+        (None, None) => None,
+    };
+    // Older versions of Ollama don't report capabilities
+    let supports_tools = details["capabilities"]
+        .as_array()
+        .map(|capabilities| capabilities.contains(&json!("tools")));
+    ModelInfo::new(name)
+        .with_context_limit(context_limit)
+        .with_supports_tools(supports_tools)
 }
 
 #[async_trait]
@@ -69,12 +287,17 @@ impl Provider for OllamaProvider {
             OLLAMA_DEFAULT_MODEL,
             OLLAMA_KNOWN_MODELS.iter().map(|&s| s.to_string()).collect(),
             OLLAMA_DOC_URL,
-            vec![ConfigKey::new(
-                "OLLAMA_HOST",
-                true,
-                false,
-                Some(OLLAMA_HOST),
-            )],
+            vec![
+                ConfigKey::new("OLLAMA_HOST", true, false, Some(OLLAMA_HOST)),
+                ConfigKey::new("OLLAMA_NUM_CTX", false, false, None),
+                ConfigKey::new("OLLAMA_KEEP_ALIVE", false, false, None),
+                ConfigKey::new(
+                    "OLLAMA_EMBEDDING_MODEL",
+                    false,
+                    false,
+                    Some(OLLAMA_DEFAULT_EMBEDDING_MODEL),
+                ),
+            ],
         )
     }
 
@@ -92,28 +315,272 @@ impl Provider for OllamaProvider {
         messages: &[Message],
         tools: &[Tool],
     ) -> Result<(Message, ProviderUsage), ProviderError> {
-        let payload = create_request(
-            &self.model,
-            system,
-            messages,
-            tools,
-            &super::utils::ImageFormat::OpenAi,
-        )?;
+        let (payload, response) = self.send_chat(system, messages, tools, false).await?;
+        let response = handle_response(response).await?;
+        self.completion(&payload, response, tools)
+    }
 
-        let response = self.post(payload.clone()).await?;
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<ProviderStream, ProviderError> {
+        let (_, response) = self.send_chat(system, messages, tools, true).await?;
+        if !response.status().is_success() {
+            return Err(handle_response(response).await.err().unwrap_or_else(|| {
+                ProviderError::RequestFailed("Unexpected response status".to_string())
+            }));
+        }
 
-        // Parse response
-        let message = response_to_message(response.clone())?;
-        let usage = match get_usage(&response) {
-            Ok(usage) => usage,
-            Err(ProviderError::UsageError(e)) => {
-                tracing::warn!("Failed to get usage data: {}", e);
-                Usage::default()
+        let model_name = self.model.model_name.clone();
+        let tools = tools.to_vec();
+        Ok(Box::pin(async_stream::try_stream! {
+            // The body is one JSON object per line
+            let mut state = StreamState::default();
+            let mut lines = response_lines(response);
+            while let Some(line) = lines.next().await {
+                let line = line?;
+                if line.is_empty() {
+                    continue;
+                }
+                let chunk: Value = serde_json::from_str(&line).map_err(|e| {
+                    ProviderError::RequestFailed(format!("Invalid chunk in response stream: {}", e))
+                })?;
+                for delta in state.apply(&chunk)? {
+                    yield StreamEvent::Delta(delta);
+                }
             }
-            Err(e) => return Err(e),
-        };
-        let model = get_model(&response);
-        super::utils::emit_debug_trace(self, &payload, &response, &usage);
-        Ok((message, ProviderUsage::new(model, usage)))
+
+            let response = state.into_response();
+            let message = response_to_message(&response, &tools)?;
+            let model = response["model"].as_str().unwrap_or(&model_name).to_string();
+            yield StreamEvent::Done(message, ProviderUsage::new(model, get_usage(&response)));
+        }))
+    }
+
+    /// Constrains the reply with a JSON schema through the `format` field
+    async fn complete_json(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let mut payload = create_request(&self.model, system, messages, &[])?;
+        payload["format"] = schema.clone();
+
+        let response = self.post(payload.clone()).await?;
+        self.completion(&payload, response, &[])
+    }
+
+    /// Lists the local models, with their details from `/api/show`
+    async fn list_models(&self) -> Result<Option<Vec<ModelInfo>>, ProviderError> {
+        let host = self.host.trim_end_matches('/');
+        let response = self.client.get(format!("{}/api/tags", host)).send().await?;
+        let tags = handle_response(response).await?;
+
+        let mut models = Vec::new();
+        for name in response_to_model_names(&tags) {
+            let model = match self.show(&name).await {
+                Ok(Some(model)) => model,
+                Ok(None) => ModelInfo::new(name.as_str()),
+                Err(e) => {
+                    tracing::debug!(model = %name, error = %e, "Could not get the model details");
+                    ModelInfo::new(name.as_str())
+                }
+            };
+            models.push(model);
+        }
+        Ok(Some(models))
+    }
+
+    /// Looks up the one model through `/api/show`, without listing the others
+    async fn model_info(&self, model_name: &str) -> Result<Option<ModelInfo>, ProviderError> {
+        self.show(model_name).await
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OllamaProvider {
+    async fn embed(&self, texts: &[String]) -> Result<(Embeddings, ProviderUsage), ProviderError> {
+        embed_in_batches(
+            texts,
+            OLLAMA_EMBEDDING_BATCH_SIZE,
+            &self.embedding_model,
+            |batch| self.embed_batch(batch),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::embedding::{LocalFallbackEmbedding, LOCAL_EMBEDDING_MODEL};
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn provider(server: &MockServer) -> OllamaProvider {
+        OllamaProvider {
+            client: Client::new(),
+            host: server.uri(),
+            model: ModelConfig::new(OLLAMA_DEFAULT_MODEL.to_string()),
+            embedding_model: OLLAMA_DEFAULT_EMBEDDING_MODEL.to_string(),
+            text_tools: AtomicBool::new(false),
+        }
+    }
+
+    #[test]
+    fn test_list_models_responses() {
+        let tags = json!({
+            "models": [
+                {"name": "qwen2.5:latest", "model": "qwen2.5:latest", "size": 4683087332u64},
+                {"name": "llama3.2:1b", "model": "llama3.2:1b", "size": 1321098329}
+            ]
+        });
+        assert_eq!(
+            response_to_model_names(&tags),
+            vec!["qwen2.5", "llama3.2:1b"]
+        );
+
+        let details = json!({
+            "model_info": {
+                "general.architecture": "qwen2",
+                "qwen2.context_length": 32768
+            },
+            "capabilities": ["completion", "tools"]
+        });
+        // Ollama doesn't run the model with the context it was trained with unless asked to
+        assert_eq!(
+            details_to_model("qwen2.5", &details),
+            ModelInfo::new("qwen2.5")
+                .with_context_limit(Some(OLLAMA_DEFAULT_NUM_CTX))
+                .with_supports_tools(Some(true))
+        );
+        let mut details = details;
+        details["parameters"] = json!("stop \"<|im_end|>\"\nnum_ctx 8192");
+        assert_eq!(
+            details_to_model("qwen2.5", &details).context_limit,
+            Some(8192)
+        );
+        assert_eq!(
+            details_to_model("qwen2.5", &json!({})),
+            ModelInfo::new("qwen2.5")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_embed_unreachable_host() -> Result<()> {
+        let server = MockServer::start().await;
+        let mut provider = provider(&server);
+        // Nothing listens on port 1
+        provider.host = "http://127.0.0.1:1".to_string();
+        let texts = vec!["hello".to_string()];
+        assert!(provider.embed(&texts).await.is_err());
+
+        let (embeddings, usage) = LocalFallbackEmbedding::new(Box::new(provider))
+            .embed(&texts)
+            .await?;
+        assert_eq!(embeddings.len(), 1);
+        assert_eq!(usage.model, LOCAL_EMBEDDING_MODEL);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_model_info() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/show"))
+            .and(body_partial_json(json!({"model": "qwen2.5"})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "parameters": "num_ctx 16384",
+                "model_info": {"qwen2.context_length": 32768}
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/show"))
+            .and(body_partial_json(json!({"model": "llama3"})))
+            .respond_with(
+                ResponseTemplate::new(404).set_body_json(json!({"error": "model not found"})),
+            )
+            .mount(&server)
+            .await;
+
+        // Only the one model is looked up, the others aren't listed
+        let provider = provider(&server);
+        let model = provider.model_info("qwen2.5").await?;
+        assert_eq!(model.and_then(|model| model.context_limit), Some(16_384));
+        assert_eq!(provider.model_info("llama3").await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_complete_without_tool_support() -> Result<()> {
+        let server = MockServer::start().await;
+        // Requests with tools are turned down, the same request without them is answered
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .and(|request: &wiremock::Request| {
+                serde_json::from_slice::<Value>(&request.body)
+                    .is_ok_and(|body| body.get("tools").is_some())
+            })
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                "error": "registry.ollama.ai/library/gemma:2b does not support tools"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "model": "gemma:2b",
+                "message": {
+                    "role": "assistant",
+                    "content": "{\"name\": \"developer__shell\", \"arguments\": {\"command\": \"ls\"}}"
+                },
+                "done": true,
+                "prompt_eval_count": 40,
+                "eval_count": 12
+            })))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let tool = Tool::new(
+            "developer__shell",
+            "Run a command",
+            json!({"type": "object", "properties": {"command": {"type": "string"}}}),
+        );
+        let provider = provider(&server);
+        let messages = [Message::user().with_text("List the files")];
+        for _ in 0..2 {
+            let (message, usage) = provider
+                .complete("You are goose", &messages, std::slice::from_ref(&tool))
+                .await?;
+            let requests: Vec<_> = message
+                .content
+                .iter()
+                .filter_map(|content| content.as_tool_request())
+                .collect();
+            assert_eq!(requests.len(), 1);
+            assert_eq!(
+                requests[0].tool_call.as_ref().unwrap().name,
+                "developer__shell"
+            );
+            assert_eq!(usage.model, "gemma:2b");
+        }
+
+        // The tools were described in the system prompt, and only tried once
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 3);
+        let body: Value = serde_json::from_slice(&requests[1].body)?;
+        assert!(body.get("tools").is_none());
+        assert!(body["messages"][0]["content"]
+            .as_str()
+            .unwrap()
+            .contains("developer__shell"));
+        Ok(())
     }
 }
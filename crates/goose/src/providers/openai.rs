@@ -1,13 +1,23 @@
 use anyhow::Result;
 use async_trait::async_trait;
-use reqwest::Client;
-use serde_json::Value;
-use std::time::Duration;
+use futures::StreamExt;
+use reqwest::{Client, Response};
+use serde_json::{json, Value};
 
-use super::base::{ConfigKey, Provider, ProviderMetadata, ProviderUsage, Usage};
+use super::base::{
+    ConfigKey, ModelInfo, Provider, ProviderMetadata, ProviderStream, ProviderUsage, StreamEvent,
+    Usage,
+};
+use super::embedding::{embed_in_batches, EmbeddingProvider, Embeddings};
 use super::errors::ProviderError;
-use super::formats::openai::{create_request, get_usage, response_to_message};
-use super::utils::{emit_debug_trace, get_model, handle_response_openai_compat, ImageFormat};
+use super::formats::openai::{
+    create_request, create_structured_request, get_usage, response_to_embeddings,
+    response_to_message, response_to_models, StreamState,
+};
+use super::utils::{
+    check_stream_response_openai_compat, get_model, handle_response_openai_compat, http_client,
+    openai_completion, sse_json_stream, ImageFormat,
+};
 use crate::message::Message;
 use crate::model::ModelConfig;
 use mcp_core::tool::Tool;
@@ -22,6 +32,9 @@ pub const OPEN_AI_KNOWN_MODELS: &[&str] = &[
 ];
 
 pub const OPEN_AI_DOC_URL: &str = "https://platform.openai.com/docs/models";
+pub const OPEN_AI_DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+// The most inputs the embeddings endpoint takes in a single request
+const OPEN_AI_EMBEDDING_BATCH_SIZE: usize = 2048;
 
 #[derive(Debug, serde::Serialize)]
 pub struct OpenAiProvider {
@@ -30,6 +43,7 @@ pub struct OpenAiProvider {
     host: String,
     api_key: String,
     model: ModelConfig,
+    embedding_model: String,
 }
 
 impl Default for OpenAiProvider {
@@ -46,33 +60,78 @@ impl OpenAiProvider {
         let host: String = config
             .get("OPENAI_HOST")
             .unwrap_or_else(|_| "https://api.openai.com".to_string());
-        let client = Client::builder()
-            .timeout(Duration::from_secs(600))
-            .build()?;
+        let embedding_model: String = config
+            .get("OPENAI_EMBEDDING_MODEL")
+            .unwrap_or_else(|_| OPEN_AI_DEFAULT_EMBEDDING_MODEL.to_string());
+        let client = http_client()?;
 
         Ok(Self {
             client,
             host,
             api_key,
             model,
+            embedding_model,
         })
     }
 
-    async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
+    async fn send(&self, payload: &Value) -> Result<Response, ProviderError> {
         let url = format!("{}/v1/chat/completions", self.host.trim_end_matches('/'));
 
+        Ok(self
+            .client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .json(payload)
+            .send()
+            .await?)
+    }
+
+    async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
+        let response = self.send(&payload).await?;
+        handle_response_openai_compat(response).await
+    }
+
+    async fn embed_batch(
+        &self,
+        texts: &[String],
+    ) -> Result<(Embeddings, ProviderUsage), ProviderError> {
+        let url = format!("{}/v1/embeddings", self.host.trim_end_matches('/'));
         let response = self
             .client
             .post(&url)
             .header("Authorization", format!("Bearer {}", self.api_key))
-            .json(&payload)
+            .json(&json!({"model": self.embedding_model, "input": texts}))
             .send()
             .await?;
+        let response = handle_response_openai_compat(response).await?;
 
-        handle_response_openai_compat(response).await
+        let embeddings = response_to_embeddings(&response)?;
+        let usage = get_usage(&response).unwrap_or_default();
+        Ok((embeddings, ProviderUsage::new(get_model(&response), usage)))
     }
 }
 
+/// Turn a streaming chat completion response into a provider stream
+pub(crate) fn openai_stream(response: Response) -> ProviderStream {
+    Box::pin(async_stream::try_stream! {
+        let mut state = StreamState::default();
+        let mut chunks = sse_json_stream(response);
+        while let Some(chunk) = chunks.next().await {
+            for delta in state.apply(&chunk?)? {
+                yield StreamEvent::Delta(delta);
+            }
+        }
+
+        let response = state.into_response();
+        let message = response_to_message(response.clone())?;
+        let usage = get_usage(&response).unwrap_or_else(|e| {
+            tracing::warn!("Failed to get usage data: {}", e);
+            Usage::default()
+        });
+        yield StreamEvent::Done(message, ProviderUsage::new(get_model(&response), usage));
+    })
+}
+
 #[async_trait]
 impl Provider for OpenAiProvider {
     fn metadata() -> ProviderMetadata {
@@ -89,6 +148,12 @@ impl Provider for OpenAiProvider {
             vec![
                 ConfigKey::new("OPENAI_API_KEY", true, true, None),
                 ConfigKey::new("OPENAI_HOST", false, false, Some("https://api.openai.com")),
+                ConfigKey::new(
+                    "OPENAI_EMBEDDING_MODEL",
+                    false,
+                    false,
+                    Some(OPEN_AI_DEFAULT_EMBEDDING_MODEL),
+                ),
             ],
         )
     }
@@ -108,22 +173,60 @@ impl Provider for OpenAiProvider {
         tools: &[Tool],
     ) -> Result<(Message, ProviderUsage), ProviderError> {
         let payload = create_request(&self.model, system, messages, tools, &ImageFormat::OpenAi)?;
+        let response = self.post(payload.clone()).await?;
+        openai_completion(self, &payload, response)
+    }
 
-        // Make request
+    async fn complete_json(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let payload =
+            create_structured_request(&self.model, system, messages, schema, &ImageFormat::OpenAi)?;
         let response = self.post(payload.clone()).await?;
+        openai_completion(self, &payload, response)
+    }
 
-        // Parse response
-        let message = response_to_message(response.clone())?;
-        let usage = match get_usage(&response) {
-            Ok(usage) => usage,
-            Err(ProviderError::UsageError(e)) => {
-                tracing::warn!("Failed to get usage data: {}", e);
-                Usage::default()
-            }
-            Err(e) => return Err(e),
-        };
-        let model = get_model(&response);
-        emit_debug_trace(self, &payload, &response, &usage);
-        Ok((message, ProviderUsage::new(model, usage)))
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<ProviderStream, ProviderError> {
+        let mut payload =
+            create_request(&self.model, system, messages, tools, &ImageFormat::OpenAi)?;
+        payload["stream"] = json!(true);
+        payload["stream_options"] = json!({"include_usage": true});
+
+        let response = self.send(&payload).await?;
+        let response = check_stream_response_openai_compat(response).await?;
+        Ok(openai_stream(response))
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<ModelInfo>>, ProviderError> {
+        let url = format!("{}/v1/models", self.host.trim_end_matches('/'));
+        let response = self
+            .client
+            .get(&url)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .send()
+            .await?;
+        let response = handle_response_openai_compat(response).await?;
+        Ok(Some(response_to_models(&response)))
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAiProvider {
+    async fn embed(&self, texts: &[String]) -> Result<(Embeddings, ProviderUsage), ProviderError> {
+        embed_in_batches(
+            texts,
+            OPEN_AI_EMBEDDING_BATCH_SIZE,
+            &self.embedding_model,
+            |batch| self.embed_batch(batch),
+        )
+        .await
     }
 }
@@ -0,0 +1,494 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use reqwest::{Client, Response};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use super::base::{
+    ConfigKey, ModelInfo, Provider, ProviderMetadata, ProviderStream, ProviderUsage,
+};
+use super::errors::ProviderError;
+use super::formats::openai::{create_request, create_structured_request, response_to_models};
+use super::openai::openai_stream;
+use super::utils::{
+    check_stream_response_openai_compat, handle_response_openai_compat, http_client,
+    openai_completion, ImageFormat,
+};
+use crate::config::{Config, ConfigError};
+use crate::message::Message;
+use crate::model::ModelConfig;
+use mcp_core::tool::Tool;
+
+pub const OPENAI_COMPATIBLE_DEFAULT_PATH: &str = "v1/chat/completions";
+// The models depend on the server, e.g. whatever vLLM was started with
+pub const OPENAI_COMPATIBLE_DEFAULT_MODEL: &str = "default";
+pub const OPENAI_COMPATIBLE_DOC_URL: &str =
+    "https://platform.openai.com/docs/api-reference/chat/create";
+
+/// A provider for servers with an OpenAI compatible chat completions API,
+/// such as vLLM, the llama.cpp server or a LiteLLM proxy
+#[derive(Debug, serde::Serialize)]
+pub struct OpenAiCompatibleProvider {
+    #[serde(skip)]
+    client: Client,
+    host: String,
+    path: String,
+    #[serde(skip)]
+    headers: HeaderMap,
+    supports_tools: bool,
+    model: ModelConfig,
+}
+
+impl OpenAiCompatibleProvider {
+    /// Create a provider for the server at `host`, using the default path and no API key
+    pub fn new(host: &str, model: ModelConfig) -> Result<Self> {
+        let client = http_client()?;
+
+        Ok(Self {
+            client,
+            host: host.to_string(),
+            path: OPENAI_COMPATIBLE_DEFAULT_PATH.to_string(),
+            headers: HeaderMap::new(),
+            supports_tools: true,
+            model,
+        })
+    }
+
+    pub fn from_env(model: ModelConfig) -> Result<Self> {
+        Self::from_config(Config::global(), model)
+    }
+
+    fn from_config(config: &Config, model: ModelConfig) -> Result<Self> {
+        let host: String = config.get("OPENAI_COMPATIBLE_HOST")?;
+        let mut provider = Self::new(&host, model)?;
+
+        if let Ok(path) = config.get::<String>("OPENAI_COMPATIBLE_PATH") {
+            provider = provider.with_path(&path);
+        }
+        if let Ok(api_key) = config.get_secret::<String>("OPENAI_COMPATIBLE_API_KEY") {
+            provider = provider.with_api_key(&api_key)?;
+        }
+        // goose configure stores every value as a string, while an environment variable
+        // can also hold the JSON form, so both are accepted here
+        if let Some(headers) = optional(config.get::<Value>("OPENAI_COMPATIBLE_HEADERS"))? {
+            provider = provider.with_headers(&parse_headers(headers)?)?;
+        }
+        if let Some(supports_tools) =
+            optional(config.get::<Value>("OPENAI_COMPATIBLE_SUPPORTS_TOOLS"))?
+        {
+            provider = provider.with_tool_support(parse_flag(supports_tools)?);
+        }
+        Ok(provider)
+    }
+
+    /// Set the path of the chat completions endpoint, relative to the host
+    pub fn with_path(mut self, path: &str) -> Self {
+        self.path = path.trim_start_matches('/').to_string();
+        self
+    }
+
+    /// Send the API key as a bearer token
+    pub fn with_api_key(mut self, api_key: &str) -> Result<Self> {
+        let value = HeaderValue::from_str(&format!("Bearer {}", api_key))?;
+        self.headers.insert(AUTHORIZATION, value);
+        Ok(self)
+    }
+
+    /// Add headers that are sent with every request
+    pub fn with_headers(mut self, headers: &HashMap<String, String>) -> Result<Self> {
+        for (name, value) in headers {
+            self.headers.insert(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+        Ok(self)
+    }
+
+    /// Set whether the served model supports tool calling, tools aren't sent when it doesn't
+    pub fn with_tool_support(mut self, supports_tools: bool) -> Self {
+        self.supports_tools = supports_tools;
+        self
+    }
+
+    fn create_request(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<Value, ProviderError> {
+        let tools = if self.supports_tools { tools } else { &[] };
+        Ok(create_request(
+            &self.model,
+            system,
+            messages,
+            tools,
+            &ImageFormat::OpenAi,
+        )?)
+    }
+
+    async fn send(&self, payload: &Value) -> Result<Response, ProviderError> {
+        let url = format!("{}/{}", self.host.trim_end_matches('/'), self.path);
+
+        Ok(self
+            .client
+            .post(&url)
+            .headers(self.headers.clone())
+            .json(payload)
+            .send()
+            .await?)
+    }
+
+    async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
+        let response = self.send(&payload).await?;
+        handle_response_openai_compat(response).await
+    }
+}
+
+/// Treat a missing key as unset, while still failing on a value that can't be read
+fn optional(value: Result<Value, ConfigError>) -> Result<Option<Value>, ConfigError> {
+    match value {
+        Ok(value) => Ok(Some(value)),
+        Err(ConfigError::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Parse the extra headers, given either as a JSON object or as `name=value,name=value`
+fn parse_headers(value: Value) -> Result<HashMap<String, String>, ConfigError> {
+    let text = match value {
+        Value::String(text) => text,
+        Value::Object(_) => return Ok(serde_json::from_value(value)?),
+        other => {
+            return Err(ConfigError::DeserializeError(format!(
+                "OPENAI_COMPATIBLE_HEADERS must be a JSON object or name=value pairs, got {}",
+                other
+            )))
+        }
+    };
+    if text.trim_start().starts_with('{') {
+        return Ok(serde_json::from_str(&text)?);
+    }
+
+    text.split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').ok_or_else(|| {
+                ConfigError::DeserializeError(format!(
+                    "OPENAI_COMPATIBLE_HEADERS entry '{}' is not of the form name=value",
+                    pair
+                ))
+            })?;
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Parse the tool support flag, given either as a boolean or as "true"/"false"
+fn parse_flag(value: Value) -> Result<bool, ConfigError> {
+    match &value {
+        Value::Bool(flag) => Ok(*flag),
+        Value::String(text) if text.trim().eq_ignore_ascii_case("true") => Ok(true),
+        Value::String(text) if text.trim().eq_ignore_ascii_case("false") => Ok(false),
+        _ => Err(ConfigError::DeserializeError(format!(
+            "OPENAI_COMPATIBLE_SUPPORTS_TOOLS must be true or false, got {}",
+            value
+        ))),
+    }
+}
+
+#[async_trait]
+impl Provider for OpenAiCompatibleProvider {
+    fn metadata() -> ProviderMetadata {
+        ProviderMetadata::new(
+            "openai_compatible",
+            "OpenAI Compatible",
+            "Any server with an OpenAI compatible API, such as vLLM, llama.cpp or LiteLLM",
+            OPENAI_COMPATIBLE_DEFAULT_MODEL,
+            vec![OPENAI_COMPATIBLE_DEFAULT_MODEL.to_string()],
+            OPENAI_COMPATIBLE_DOC_URL,
+            vec![
+                ConfigKey::new("OPENAI_COMPATIBLE_HOST", true, false, None),
+                ConfigKey::new(
+                    "OPENAI_COMPATIBLE_PATH",
+                    false,
+                    false,
+                    Some(OPENAI_COMPATIBLE_DEFAULT_PATH),
+                ),
+                ConfigKey::new("OPENAI_COMPATIBLE_API_KEY", false, true, None),
+                ConfigKey::new("OPENAI_COMPATIBLE_HEADERS", false, false, None),
+                ConfigKey::new(
+                    "OPENAI_COMPATIBLE_SUPPORTS_TOOLS",
+                    false,
+                    false,
+                    Some("true"),
+                ),
+            ],
+        )
+    }
+
+    fn get_model_config(&self) -> ModelConfig {
+        self.model.clone()
+    }
+
+    #[tracing::instrument(
+        skip(self, system, messages, tools),
+        fields(model_config, input, output, input_tokens, output_tokens, total_tokens)
+    )]
+    async fn complete(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let payload = self.create_request(system, messages, tools)?;
+        let response = self.post(payload.clone()).await?;
+        openai_completion(self, &payload, response)
+    }
+
+    async fn complete_json(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let payload =
+            create_structured_request(&self.model, system, messages, schema, &ImageFormat::OpenAi)?;
+        let response = self.post(payload.clone()).await?;
+        openai_completion(self, &payload, response)
+    }
+
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<ProviderStream, ProviderError> {
+        let mut payload = self.create_request(system, messages, tools)?;
+        payload["stream"] = json!(true);
+        payload["stream_options"] = json!({"include_usage": true});
+
+        let response = self.send(&payload).await?;
+        let response = check_stream_response_openai_compat(response).await?;
+        Ok(openai_stream(response))
+    }
+
+    /// Lists the models next to the chat completions endpoint, `/v1/models` by default
+    async fn list_models(&self) -> Result<Option<Vec<ModelInfo>>, ProviderError> {
+        let Some(prefix) = self.path.strip_suffix("chat/completions") else {
+            return Ok(None);
+        };
+        let url = format!("{}/{}models", self.host.trim_end_matches('/'), prefix);
+        let response = self
+            .client
+            .get(&url)
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        let response = handle_response_openai_compat(response).await?;
+        Ok(Some(response_to_models(&response)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::MessageContent;
+    use crate::providers::base::StreamEvent;
+    use futures::TryStreamExt;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn tool() -> Tool {
+        Tool::new(
+            "developer__shell",
+            "Run a command",
+            json!({"type": "object", "properties": {"command": {"type": "string"}}}),
+        )
+    }
+
+    fn provider(server: &MockServer) -> OpenAiCompatibleProvider {
+        OpenAiCompatibleProvider::new(&server.uri(), ModelConfig::new("llama-3".to_string()))
+            .unwrap()
+            .with_path("/api/chat/completions")
+            .with_api_key("secret")
+            .unwrap()
+            .with_headers(&HashMap::from([(
+                "X-Team".to_string(),
+                "infra".to_string(),
+            )]))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_complete() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat/completions"))
+            .and(header("authorization", "Bearer secret"))
+            .and(header("x-team", "infra"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "model": "llama-3",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "Hello from vLLM"},
+                    "finish_reason": "stop"
+                }],
+                "usage": {"prompt_tokens": 12, "completion_tokens": 4, "total_tokens": 16}
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let provider = provider(&server);
+        let (message, usage) = provider
+            .complete("system", &[Message::user().with_text("Hi")], &[tool()])
+            .await?;
+        assert!(matches!(
+            &message.content[0],
+            MessageContent::Text(text) if text.text == "Hello from vLLM"
+        ));
+        assert_eq!(usage.model, "llama-3");
+        assert_eq!(usage.usage.total_tokens, Some(16));
+
+        let requests = server.received_requests().await.unwrap();
+        let body: Value = serde_json::from_slice(&requests[0].body)?;
+        assert_eq!(body["model"], "llama-3");
+        assert_eq!(body["tools"][0]["function"]["name"], "developer__shell");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stream_without_tool_support() -> Result<()> {
+        let server = MockServer::start().await;
+        let events = [
+            json!({"model": "llama-3", "choices": [{"index": 0, "delta": {"role": "assistant", "content": "Hel"}}]}),
+            json!({"model": "llama-3", "choices": [{"index": 0, "delta": {"content": "lo"}, "finish_reason": "stop"}]}),
+            json!({"model": "llama-3", "choices": [], "usage": {"prompt_tokens": 3, "completion_tokens": 2, "total_tokens": 5}}),
+        ];
+        let body: String = events
+            .iter()
+            .map(|event| format!("data: {}\n\n", event))
+            .chain(std::iter::once("data: [DONE]\n\n".to_string()))
+            .collect();
+        Mock::given(method("POST"))
+            .and(path("/api/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "text/event-stream")
+                    .set_body_string(body),
+            )
+            .mount(&server)
+            .await;
+
+        let provider = provider(&server).with_tool_support(false);
+        let events: Vec<_> = provider
+            .stream("system", &[Message::user().with_text("Hi")], &[tool()])
+            .await?
+            .try_collect()
+            .await?;
+        let Some(StreamEvent::Done(message, usage)) = events.last() else {
+            panic!("The stream should end with the final message");
+        };
+        assert!(matches!(
+            &message.content[0],
+            MessageContent::Text(text) if text.text == "Hello"
+        ));
+        assert_eq!(usage.usage.total_tokens, Some(5));
+
+        let requests = server.received_requests().await.unwrap();
+        let body: Value = serde_json::from_slice(&requests[0].body)?;
+        assert_eq!(body["stream"], true);
+        assert!(body.get("tools").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_models() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/models"))
+            .and(header("authorization", "Bearer secret"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "data": [{"id": "llama-3", "object": "model", "max_model_len": 8192}]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let models = provider(&server).list_models().await?;
+        assert_eq!(
+            models,
+            Some(vec![
+                ModelInfo::new("llama-3").with_context_limit(Some(8192))
+            ])
+        );
+
+        let custom = provider(&server).with_path("/generate");
+        assert_eq!(custom.list_models().await?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_config_with_string_values() -> Result<()> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let config = Config::new(temp_file.path(), "goose-test")?;
+        config.set("OPENAI_COMPATIBLE_HOST", json!("http://localhost:8000"))?;
+        config.set(
+            "OPENAI_COMPATIBLE_HEADERS",
+            json!("X-Team=infra, X-Region=eu"),
+        )?;
+        config.set("OPENAI_COMPATIBLE_SUPPORTS_TOOLS", json!("false"))?;
+
+        let provider = OpenAiCompatibleProvider::from_config(
+            &config,
+            ModelConfig::new("llama-3".to_string()),
+        )?;
+        assert_eq!(provider.headers["x-team"], "infra");
+        assert_eq!(provider.headers["x-region"], "eu");
+        assert!(!provider.supports_tools);
+
+        config.set("OPENAI_COMPATIBLE_HEADERS", json!(r#"{"X-Team": "data"}"#))?;
+        config.set("OPENAI_COMPATIBLE_SUPPORTS_TOOLS", json!("true"))?;
+        let provider = OpenAiCompatibleProvider::from_config(
+            &config,
+            ModelConfig::new("llama-3".to_string()),
+        )?;
+        assert_eq!(provider.headers["x-team"], "data");
+        assert!(provider.supports_tools);
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_config_with_invalid_values() -> Result<()> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let config = Config::new(temp_file.path(), "goose-test")?;
+        config.set("OPENAI_COMPATIBLE_HOST", json!("http://localhost:8000"))?;
+        let model = ModelConfig::new("llama-3".to_string());
+
+        config.set("OPENAI_COMPATIBLE_HEADERS", json!("X-Team"))?;
+        assert!(OpenAiCompatibleProvider::from_config(&config, model.clone()).is_err());
+
+        config.set("OPENAI_COMPATIBLE_HEADERS", json!("X-Team=infra"))?;
+        config.set("OPENAI_COMPATIBLE_SUPPORTS_TOOLS", json!("sometimes"))?;
+        assert!(OpenAiCompatibleProvider::from_config(&config, model).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let result = OpenAiCompatibleProvider::new(
+            "http://localhost:8000",
+            ModelConfig::new("llama-3".to_string()),
+        )
+        .unwrap()
+        .with_headers(&HashMap::from([(
+            "Bad Header".to_string(),
+            "value".to_string(),
+        )]));
+        assert!(result.is_err());
+    }
+}
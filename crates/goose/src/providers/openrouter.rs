@@ -2,14 +2,13 @@ use anyhow::{Error, Result};
 use async_trait::async_trait;
 use reqwest::Client;
 use serde_json::{json, Value};
-use std::time::Duration;
 
-use super::base::{ConfigKey, Provider, ProviderMetadata, ProviderUsage, Usage};
+use super::base::{ConfigKey, ModelInfo, Provider, ProviderMetadata, ProviderUsage};
 use super::errors::ProviderError;
-use super::utils::{emit_debug_trace, get_model, handle_response_openai_compat};
+use super::utils::{handle_response_openai_compat, http_client, openai_completion, ImageFormat};
 use crate::message::Message;
 use crate::model::ModelConfig;
-use crate::providers::formats::openai::{create_request, get_usage, response_to_message};
+use crate::providers::formats::openai::{create_request, create_structured_request};
 use mcp_core::tool::Tool;
 
 pub const OPENROUTER_DEFAULT_MODEL: &str = "anthropic/claude-3.5-sonnet";
@@ -43,9 +42,7 @@ impl OpenRouterProvider {
             .get("OPENROUTER_HOST")
             .unwrap_or_else(|_| "https://openrouter.ai".to_string());
 
-        let client = Client::builder()
-            .timeout(Duration::from_secs(600))
-            .build()?;
+        let client = http_client()?;
 
         Ok(Self {
             client,
@@ -138,22 +135,41 @@ fn create_request_based_on_model(
     messages: &[Message],
     tools: &[Tool],
 ) -> anyhow::Result<Value, Error> {
-    let mut payload = create_request(
-        model_config,
-        system,
-        messages,
-        tools,
-        &super::utils::ImageFormat::OpenAi,
-    )?;
+    let payload = create_request(model_config, system, messages, tools, &ImageFormat::OpenAi)?;
+    Ok(update_request_for_model(model_config, payload))
+}
 
+/// Enable prompt caching for anthropic models
+fn update_request_for_model(model_config: &ModelConfig, payload: Value) -> Value {
     if model_config
         .model_name
         .starts_with(OPENROUTER_MODEL_PREFIX_ANTHROPIC)
     {
-        payload = update_request_for_anthropic(&payload);
+        update_request_for_anthropic(&payload)
+    } else {
+        payload
     }
+}
 
-    Ok(payload)
+/// Convert the response of the models endpoint, which reports the context window and the
+/// supported request parameters of each model
+fn response_to_models(response: &Value) -> Vec<ModelInfo> {
+    let models = response["data"].as_array().cloned().unwrap_or_default();
+    models
+        .iter()
+        .filter_map(|model| {
+            let id = model["id"].as_str()?;
+            let context_limit = model["context_length"].as_u64().map(|l| l as usize);
+            let supports_tools = model["supported_parameters"]
+                .as_array()
+                .map(|parameters| parameters.contains(&json!("tools")));
+            Some(
+                ModelInfo::new(id)
+                    .with_context_limit(context_limit)
+                    .with_supports_tools(supports_tools),
+            )
+        })
+        .collect()
 }
 
 #[async_trait]
@@ -200,19 +216,62 @@ impl Provider for OpenRouterProvider {
 
         // Make request
         let response = self.post(payload.clone()).await?;
+        openai_completion(self, &payload, response)
+    }
 
-        // Parse response
-        let message = response_to_message(response.clone())?;
-        let usage = match get_usage(&response) {
-            Ok(usage) => usage,
-            Err(ProviderError::UsageError(e)) => {
-                tracing::warn!("Failed to get usage data: {}", e);
-                Usage::default()
-            }
-            Err(e) => return Err(e),
-        };
-        let model = get_model(&response);
-        emit_debug_trace(self, &payload, &response, &usage);
-        Ok((message, ProviderUsage::new(model, usage)))
+    async fn complete_json(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let payload =
+            create_structured_request(&self.model, system, messages, schema, &ImageFormat::OpenAi)?;
+        let payload = update_request_for_model(&self.model, payload);
+        let response = self.post(payload.clone()).await?;
+        openai_completion(self, &payload, response)
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<ModelInfo>>, ProviderError> {
+        let url = format!("{}/api/v1/models", self.host.trim_end_matches('/'));
+        let response = self
+            .client
+            .get(&url)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .send()
+            .await?;
+        let response = handle_response_openai_compat(response).await?;
+        Ok(Some(response_to_models(&response)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_to_models() {
+        let response = json!({
+            "data": [{
+                "id": "anthropic/claude-3.5-sonnet",
+                "context_length": 200000,
+                "supported_parameters": ["tools", "tool_choice", "max_tokens"]
+            }, {
+                "id": "meta-llama/llama-3-8b-instruct",
+                "context_length": 8192,
+                "supported_parameters": ["max_tokens"]
+            }]
+        });
+        assert_eq!(
+            response_to_models(&response),
+            vec![
+                ModelInfo::new("anthropic/claude-3.5-sonnet")
+                    .with_context_limit(Some(200_000))
+                    .with_supports_tools(Some(true)),
+                ModelInfo::new("meta-llama/llama-3-8b-instruct")
+                    .with_context_limit(Some(8192))
+                    .with_supports_tools(Some(false)),
+            ]
+        );
     }
 }
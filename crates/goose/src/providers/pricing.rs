@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::base::{
+    ModelInfo, Provider, ProviderMetadata, ProviderStream, ProviderUsage, StreamEvent,
+};
+use super::errors::ProviderError;
+use crate::config::{Config, ConfigError};
+use crate::message::Message;
+use crate::model::ModelConfig;
+use mcp_core::tool::Tool;
+
+/// The config key for prices that override or extend the built in table
+pub const MODEL_PRICING_CONFIG_KEY: &str = "model_pricing";
+
+/// The price of a model in dollars per million tokens
+///
+/// Prompt tokens read from or written to the prompt cache are charged at their own price
+/// when the model has one, and at the input price otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPricing {
+    pub input: f64,
+    pub output: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_write: Option<f64>,
+}
+
+impl ModelPricing {
+    pub const fn new(input: f64, output: f64) -> Self {
+        Self {
+            input,
+            output,
+            cache_read: None,
+            cache_write: None,
+        }
+    }
+
+    pub const fn with_cache(mut self, read: f64, write: f64) -> Self {
+        self.cache_read = Some(read);
+        self.cache_write = Some(write);
+        self
+    }
+
+    /// The cost in dollars of the tokens in the usage, if it has any token counts
+    pub fn cost(&self, usage: &ProviderUsage) -> Option<f64> {
+        let usage = &usage.usage;
+        if usage.input_tokens.is_none() && usage.output_tokens.is_none() {
+            return None;
+        }
+        let tokens = |count: Option<i32>| count.unwrap_or(0).max(0) as f64;
+        let cache_read = tokens(usage.cache_read_input_tokens);
+        let cache_write = tokens(usage.cache_write_input_tokens);
+        let input = (tokens(usage.input_tokens) - cache_read - cache_write).max(0.0);
+        let output = tokens(usage.output_tokens);
+        Some(
+            (input * self.input
+                + cache_read * self.cache_read.unwrap_or(self.input)
+                + cache_write * self.cache_write.unwrap_or(self.input)
+                + output * self.output)
+                / 1_000_000.0,
+        )
+    }
+}
+
+/// Published prices, keyed by `provider/model`
+const BUILT_IN_PRICES: &[(&str, ModelPricing)] = &[
+    (
+        "anthropic/claude-3-5-sonnet",
+        ModelPricing::new(3.0, 15.0).with_cache(0.3, 3.75),
+    ),
+    (
+        "anthropic/claude-3-5-haiku",
+        ModelPricing::new(0.8, 4.0).with_cache(0.08, 1.0),
+    ),
+    (
+        "anthropic/claude-3-opus",
+        ModelPricing::new(15.0, 75.0).with_cache(1.5, 18.75),
+    ),
+    (
+        "anthropic/claude-3-haiku",
+        ModelPricing::new(0.25, 1.25).with_cache(0.03, 0.3),
+    ),
+    (
+        "openai/gpt-4o",
+        ModelPricing::new(2.5, 10.0).with_cache(1.25, 2.5),
+    ),
+    (
+        "azure_openai/gpt-4o",
+        ModelPricing::new(2.5, 10.0).with_cache(1.25, 2.5),
+    ),
+    (
+        "azure_openai/gpt-4o-mini",
+        ModelPricing::new(0.15, 0.6).with_cache(0.075, 0.15),
+    ),
+    (
+        "openai/gpt-4o-mini",
+        ModelPricing::new(0.15, 0.6).with_cache(0.075, 0.15),
+    ),
+    ("openai/gpt-4-turbo", ModelPricing::new(10.0, 30.0)),
+    (
+        "openai/o1",
+        ModelPricing::new(15.0, 60.0).with_cache(7.5, 15.0),
+    ),
+    (
+        "openai/o1-mini",
+        ModelPricing::new(3.0, 12.0).with_cache(1.5, 3.0),
+    ),
+    (
+        "openai/o3-mini",
+        ModelPricing::new(1.1, 4.4).with_cache(0.55, 1.1),
+    ),
+    ("google/gemini-1.5-pro", ModelPricing::new(1.25, 5.0)),
+    ("google/gemini-1.5-flash", ModelPricing::new(0.075, 0.3)),
+    ("google/gemini-2.0-flash", ModelPricing::new(0.1, 0.4)),
+    (
+        "groq/llama-3.3-70b-versatile",
+        ModelPricing::new(0.59, 0.79),
+    ),
+    (
+        "bedrock/anthropic.claude-3-5-sonnet",
+        ModelPricing::new(3.0, 15.0),
+    ),
+    (
+        "bedrock/anthropic.claude-3-5-haiku",
+        ModelPricing::new(0.8, 4.0),
+    ),
+];
+
+/// Prices of models by provider, used to work out the cost of completions
+///
+/// Prices are looked up by `provider/model`. A model without an exact entry uses the longest
+/// entry its name starts with, so dated versions like `gpt-4o-2024-08-06` use the `gpt-4o` price.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceTable {
+    prices: HashMap<String, ModelPricing>,
+}
+
+impl Default for PriceTable {
+    fn default() -> Self {
+        Self {
+            prices: BUILT_IN_PRICES
+                .iter()
+                .map(|(key, price)| (key.to_string(), *price))
+                .collect(),
+        }
+    }
+}
+
+impl PriceTable {
+    /// A table with only the given prices
+    pub fn new(prices: HashMap<String, ModelPricing>) -> Self {
+        Self { prices }
+    }
+
+    /// The built in prices along with those set under `model_pricing` in the config, e.g.
+    ///
+    /// ```yaml
+    /// model_pricing:
+    ///   openai/gpt-4o:
+    ///     input: 2.5
+    ///     output: 10.0
+    ///     cache_read: 1.25
+    /// ```
+    pub fn from_config() -> Result<Self, ConfigError> {
+        let mut table = Self::default();
+        match Config::global().get::<HashMap<String, ModelPricing>>(MODEL_PRICING_CONFIG_KEY) {
+            Ok(prices) => table.prices.extend(prices),
+            Err(ConfigError::NotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+        Ok(table)
+    }
+
+    /// Find the price of a model from a provider
+    pub fn price(&self, provider: &str, model: &str) -> Option<ModelPricing> {
+        let key = format!("{}/{}", provider, model);
+        if let Some(price) = self.prices.get(&key) {
+            return Some(*price);
+        }
+        self.prices
+            .iter()
+            .filter(|(prefix, _)| key.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, price)| *price)
+    }
+
+    /// The cost in dollars of the usage of a provider, if the model has a known price
+    pub fn cost(&self, provider: &str, usage: &ProviderUsage) -> Option<f64> {
+        self.price(provider, &usage.model)?.cost(usage)
+    }
+}
+
+/// A provider that adds the cost of each completion to its usage
+pub struct PricedProvider {
+    name: String,
+    inner: Box<dyn Provider + Send + Sync>,
+    prices: Arc<PriceTable>,
+}
+
+impl PricedProvider {
+    pub fn new(name: &str, inner: Box<dyn Provider + Send + Sync>, prices: PriceTable) -> Self {
+        Self {
+            name: name.to_string(),
+            inner,
+            prices: Arc::new(prices),
+        }
+    }
+
+    /// Wrap a provider using the prices from the config
+    ///
+    /// Invalid prices in the config are logged and the built in prices are used instead.
+    pub fn from_config(name: &str, inner: Box<dyn Provider + Send + Sync>) -> Self {
+        let prices = PriceTable::from_config().unwrap_or_else(|e| {
+            tracing::warn!("Failed to read the model prices, using the defaults: {}", e);
+            PriceTable::default()
+        });
+        Self::new(name, inner, prices)
+    }
+}
+
+#[async_trait]
+impl Provider for PricedProvider {
+    fn metadata() -> ProviderMetadata {
+        ProviderMetadata::empty()
+    }
+
+    fn get_model_config(&self) -> ModelConfig {
+        self.inner.get_model_config()
+    }
+
+    async fn complete(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let (message, mut usage) = self.inner.complete(system, messages, tools).await?;
+        usage.cost = self.prices.cost(&self.name, &usage);
+        Ok((message, usage))
+    }
+
+    async fn complete_json(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let (message, mut usage) = self.inner.complete_json(system, messages, schema).await?;
+        usage.cost = self.prices.cost(&self.name, &usage);
+        Ok((message, usage))
+    }
+
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<ProviderStream, ProviderError> {
+        let stream = self.inner.stream(system, messages, tools).await?;
+        let name = self.name.clone();
+        let prices = self.prices.clone();
+        Ok(Box::pin(stream.map(move |event| match event {
+            Ok(StreamEvent::Done(message, mut usage)) => {
+                usage.cost = prices.cost(&name, &usage);
+                Ok(StreamEvent::Done(message, usage))
+            }
+            event => event,
+        })))
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<ModelInfo>>, ProviderError> {
+        self.inner.list_models().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::base::Usage;
+    use futures::TryStreamExt;
+
+    fn usage(model: &str, input: i32, output: i32) -> ProviderUsage {
+        ProviderUsage::new(
+            model.to_string(),
+            Usage::new(Some(input), Some(output), Some(input + output)),
+        )
+    }
+
+    #[test]
+    fn test_price_lookup() {
+        let table = PriceTable::default();
+        assert_eq!(
+            table.price("openai", "gpt-4o"),
+            Some(ModelPricing::new(2.5, 10.0).with_cache(1.25, 2.5))
+        );
+        // The longest matching prefix wins
+        assert_eq!(
+            table.price("openai", "gpt-4o-mini-2024-07-18"),
+            Some(ModelPricing::new(0.15, 0.6).with_cache(0.075, 0.15))
+        );
+        assert_eq!(
+            table.price("anthropic", "claude-3-5-sonnet-latest"),
+            Some(ModelPricing::new(3.0, 15.0).with_cache(0.3, 3.75))
+        );
+        assert_eq!(table.price("ollama", "qwen2.5"), None);
+        assert_eq!(table.price("groq", "gpt-4o"), None);
+    }
+
+    #[test]
+    fn test_cost() {
+        let table = PriceTable::new(HashMap::from([(
+            "openai/gpt-4o".to_string(),
+            ModelPricing::new(2.0, 10.0),
+        )]));
+        let cost = table
+            .cost("openai", &usage("gpt-4o", 1_000_000, 500_000))
+            .unwrap();
+        assert!((cost - 7.0).abs() < 1e-9);
+
+        let no_tokens = ProviderUsage::new("gpt-4o".to_string(), Usage::default());
+        assert_eq!(table.cost("openai", &no_tokens), None);
+        assert_eq!(table.cost("openai", &usage("o1", 10, 10)), None);
+    }
+
+    #[test]
+    fn test_cost_with_cache() {
+        let price = ModelPricing::new(3.0, 15.0).with_cache(0.3, 3.75);
+        // 1M prompt tokens, of which 800k were read from the cache and 100k written to it
+        let cached = ProviderUsage::new(
+            "claude-3-5-sonnet-latest".to_string(),
+            Usage::new(Some(1_000_000), Some(0), Some(1_000_000))
+                .with_cache_tokens(Some(800_000), Some(100_000)),
+        );
+        let cost = price.cost(&cached).unwrap();
+        assert!((cost - (0.3 + 0.24 + 0.375)).abs() < 1e-9);
+
+        // Without cache prices the cached tokens cost the same as the rest
+        let cost = ModelPricing::new(3.0, 15.0).cost(&cached).unwrap();
+        assert!((cost - 3.0).abs() < 1e-9);
+
+        let config: ModelPricing =
+            serde_json::from_str(r#"{"input": 1.0, "output": 2.0}"#).unwrap();
+        assert_eq!(config, ModelPricing::new(1.0, 2.0));
+    }
+
+    struct MockProvider;
+
+    #[async_trait]
+    impl Provider for MockProvider {
+        fn metadata() -> ProviderMetadata {
+            ProviderMetadata::empty()
+        }
+
+        fn get_model_config(&self) -> ModelConfig {
+            ModelConfig::new("gpt-4o".to_string())
+        }
+
+        async fn complete(
+            &self,
+            _system: &str,
+            _messages: &[Message],
+            _tools: &[Tool],
+        ) -> Result<(Message, ProviderUsage), ProviderError> {
+            Ok((
+                Message::assistant().with_text("done"),
+                usage("gpt-4o-2024-08-06", 400_000, 100_000),
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_priced_provider() -> anyhow::Result<()> {
+        let provider = PricedProvider::new("openai", Box::new(MockProvider), PriceTable::default());
+
+        let (_, usage) = provider.complete("system", &[], &[]).await?;
+        assert!((usage.cost.unwrap() - 2.0).abs() < 1e-9);
+
+        let events: Vec<StreamEvent> = provider
+            .stream("system", &[], &[])
+            .await?
+            .try_collect()
+            .await?;
+        let Some(StreamEvent::Done(_, usage)) = events.last() else {
+            panic!("The stream should end with the final message");
+        };
+        assert!((usage.cost.unwrap() - 2.0).abs() < 1e-9);
+        Ok(())
+    }
+}
@@ -0,0 +1,505 @@
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use super::base::{
+    ModelInfo, Provider, ProviderMetadata, ProviderStream, ProviderUsage, StreamEvent,
+};
+use super::errors::ProviderError;
+use super::structured::schema_prompt;
+use crate::config::Config;
+use crate::message::{Message, MessageContent};
+use crate::model::ModelConfig;
+use mcp_core::tool::Tool;
+
+/// The config keys for recording, `GOOSE_PROVIDER=record` wraps the provider named by
+/// `GOOSE_RECORD_PROVIDER` and appends its exchanges to `GOOSE_RECORD_FILE`
+pub const RECORD_PROVIDER_CONFIG_KEY: &str = "GOOSE_RECORD_PROVIDER";
+pub const RECORD_FILE_CONFIG_KEY: &str = "GOOSE_RECORD_FILE";
+/// The config keys for replaying, `GOOSE_PROVIDER=replay` serves the exchanges saved in
+/// `GOOSE_REPLAY_FILE`, matched according to `GOOSE_REPLAY_MODE`
+pub const REPLAY_FILE_CONFIG_KEY: &str = "GOOSE_REPLAY_FILE";
+pub const REPLAY_MODE_CONFIG_KEY: &str = "GOOSE_REPLAY_MODE";
+
+/// A request to a provider and the answer it gave, stored one per line in a fixture file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Exchange {
+    pub system: String,
+    pub messages: Vec<Message>,
+    pub tools: Vec<Tool>,
+    pub message: Message,
+    pub usage: ProviderUsage,
+}
+
+/// How a replayed request is matched to a recorded one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplayMode {
+    /// The system prompt, messages and tools must all be the same as when recording
+    #[default]
+    Strict,
+    /// Only the text and tool calls of the messages have to match, so changes to the
+    /// system prompt or tool descriptions don't break a fixture. A request that still
+    /// matches nothing gets the next recorded answer that hasn't been served.
+    Fuzzy,
+}
+
+impl FromStr for ReplayMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "strict" => Ok(ReplayMode::Strict),
+            "fuzzy" => Ok(ReplayMode::Fuzzy),
+            _ => Err(anyhow!(
+                "Invalid replay mode '{}', expected strict or fuzzy",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ReplayMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayMode::Strict => write!(f, "strict"),
+            ReplayMode::Fuzzy => write!(f, "fuzzy"),
+        }
+    }
+}
+
+/// The hash a request is looked up by
+///
+/// Message timestamps are left out in both modes since they differ between runs.
+pub fn request_hash(
+    mode: ReplayMode,
+    system: &str,
+    messages: &[Message],
+    tools: &[Tool],
+) -> String {
+    let request = match mode {
+        ReplayMode::Strict => json!({
+            "system": system,
+            "messages": messages
+                .iter()
+                .map(|m| json!({"role": m.role, "content": m.content}))
+                .collect::<Vec<_>>(),
+            "tools": tools,
+        }),
+        ReplayMode::Fuzzy => json!({
+            "messages": messages
+                .iter()
+                .map(|m| {
+                    let tool_calls: Vec<_> = m
+                        .content
+                        .iter()
+                        .filter_map(|c| match c {
+                            MessageContent::ToolRequest(request) => request
+                                .tool_call
+                                .as_ref()
+                                .ok()
+                                .map(|call| json!([call.name, call.arguments])),
+                            _ => None,
+                        })
+                        .collect();
+                    json!({"role": m.role, "text": m.as_concat_text().trim(), "tool_calls": tool_calls})
+                })
+                .collect::<Vec<_>>(),
+        }),
+    };
+    hex::encode(Sha256::digest(request.to_string().as_bytes()))
+}
+
+fn replay_error(message: String) -> ProviderError {
+    ProviderError::ExecutionError(message)
+}
+
+/// Appends exchanges to a fixture file, one JSON object per line
+struct FixtureWriter {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FixtureWriter {
+    fn append(&self, exchange: &Exchange) -> Result<(), ProviderError> {
+        let line = serde_json::to_string(exchange)
+            .map_err(|e| replay_error(format!("Failed to serialize the exchange: {}", e)))?;
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{}", line))
+            .map_err(|e| {
+                replay_error(format!(
+                    "Failed to record to {}: {}",
+                    self.path.display(),
+                    e
+                ))
+            })
+    }
+}
+
+/// A provider that passes requests on to another and saves every exchange to a fixture file
+/// that `ReplayProvider` can serve later
+pub struct RecordingProvider {
+    inner: Box<dyn Provider + Send + Sync>,
+    writer: Arc<FixtureWriter>,
+}
+
+impl RecordingProvider {
+    /// Record the exchanges of `inner`, appending to the file at `path`
+    pub fn new(inner: Box<dyn Provider + Send + Sync>, path: &Path) -> Self {
+        Self {
+            inner,
+            writer: Arc::new(FixtureWriter {
+                path: path.to_path_buf(),
+                lock: Mutex::new(()),
+            }),
+        }
+    }
+
+    /// Record the provider named in the config, created by the factory with `model`
+    pub fn from_config(model: ModelConfig) -> Result<Self> {
+        let config = Config::global();
+        let name: String = config.get(RECORD_PROVIDER_CONFIG_KEY)?;
+        let path: String = config.get(RECORD_FILE_CONFIG_KEY)?;
+        if matches!(name.as_str(), "record" | "replay") {
+            return Err(anyhow!("Can't record the {} provider", name));
+        }
+        Ok(Self::new(super::create(&name, model)?, Path::new(&path)))
+    }
+}
+
+#[async_trait]
+impl Provider for RecordingProvider {
+    fn metadata() -> ProviderMetadata {
+        ProviderMetadata::empty()
+    }
+
+    fn get_model_config(&self) -> ModelConfig {
+        self.inner.get_model_config()
+    }
+
+    async fn complete(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let (message, usage) = self.inner.complete(system, messages, tools).await?;
+        self.writer.append(&Exchange {
+            system: system.to_string(),
+            messages: messages.to_vec(),
+            tools: tools.to_vec(),
+            message: message.clone(),
+            usage: usage.clone(),
+        })?;
+        Ok((message, usage))
+    }
+
+    /// Saved under the prompt the default `complete_json` sends, which is what a replay
+    /// looks up
+    async fn complete_json(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let (message, usage) = self.inner.complete_json(system, messages, schema).await?;
+        self.writer.append(&Exchange {
+            system: schema_prompt(system, schema),
+            messages: messages.to_vec(),
+            tools: vec![],
+            message: message.clone(),
+            usage: usage.clone(),
+        })?;
+        Ok((message, usage))
+    }
+
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<ProviderStream, ProviderError> {
+        let stream = self.inner.stream(system, messages, tools).await?;
+        let writer = self.writer.clone();
+        let (system, messages, tools) = (system.to_string(), messages.to_vec(), tools.to_vec());
+        Ok(Box::pin(stream.map(move |event| match event {
+            Ok(StreamEvent::Done(message, usage)) => {
+                writer.append(&Exchange {
+                    system: system.clone(),
+                    messages: messages.clone(),
+                    tools: tools.clone(),
+                    message: message.clone(),
+                    usage: usage.clone(),
+                })?;
+                Ok(StreamEvent::Done(message, usage))
+            }
+            event => event,
+        })))
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<ModelInfo>>, ProviderError> {
+        self.inner.list_models().await
+    }
+}
+
+/// A provider that answers from a fixture file saved by `RecordingProvider`, without
+/// any network access
+pub struct ReplayProvider {
+    path: PathBuf,
+    exchanges: Vec<Exchange>,
+    hashes: Vec<String>,
+    mode: ReplayMode,
+    served: Mutex<Vec<bool>>,
+    model: ModelConfig,
+}
+
+impl ReplayProvider {
+    /// Load the exchanges recorded in the file at `path`
+    pub fn new(path: &Path, mode: ReplayMode, model: ModelConfig) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read the fixture {}: {}", path.display(), e))?;
+        let exchanges = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line).map_err(|e| {
+                    anyhow!(
+                        "Invalid exchange on line {} of {}: {}",
+                        index + 1,
+                        path.display(),
+                        e
+                    )
+                })
+            })
+            .collect::<Result<Vec<Exchange>>>()?;
+        let hashes = exchanges
+            .iter()
+            .map(|e| request_hash(mode, &e.system, &e.messages, &e.tools))
+            .collect();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            served: Mutex::new(vec![false; exchanges.len()]),
+            exchanges,
+            hashes,
+            mode,
+            model,
+        })
+    }
+
+    pub fn from_config(model: ModelConfig) -> Result<Self> {
+        let config = Config::global();
+        let path: String = config.get(REPLAY_FILE_CONFIG_KEY)?;
+        let mode = match config.get::<String>(REPLAY_MODE_CONFIG_KEY) {
+            Ok(mode) => mode.parse()?,
+            Err(_) => ReplayMode::default(),
+        };
+        Self::new(Path::new(&path), mode, model)
+    }
+
+    /// The recorded exchange to answer a request with
+    ///
+    /// Exchanges are served in recorded order, so a request made twice gets each of its
+    /// answers in turn, and the last one again once they have all been served.
+    fn find(&self, hash: &str) -> Option<&Exchange> {
+        let mut served = self.served.lock().unwrap_or_else(|e| e.into_inner());
+        let matches: Vec<usize> = (0..self.exchanges.len())
+            .filter(|&i| self.hashes[i] == hash)
+            .collect();
+        let index = matches
+            .iter()
+            .find(|&&i| !served[i])
+            .or(matches.last())
+            .copied()
+            .or_else(|| match self.mode {
+                ReplayMode::Strict => None,
+                ReplayMode::Fuzzy => served.iter().position(|s| !s),
+            })?;
+        served[index] = true;
+        Some(&self.exchanges[index])
+    }
+}
+
+#[async_trait]
+impl Provider for ReplayProvider {
+    fn metadata() -> ProviderMetadata {
+        ProviderMetadata::empty()
+    }
+
+    fn get_model_config(&self) -> ModelConfig {
+        self.model.clone()
+    }
+
+    async fn complete(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let hash = request_hash(self.mode, system, messages, tools);
+        let exchange = self.find(&hash).ok_or_else(|| {
+            replay_error(format!(
+                "No recorded response in {} matches the request {} ({} mode)",
+                self.path.display(),
+                hash,
+                self.mode
+            ))
+        })?;
+        Ok((exchange.message.clone(), exchange.usage.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::base::Usage;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    struct MockProvider {
+        calls: Arc<AtomicU32>,
+    }
+
+    #[async_trait]
+    impl Provider for MockProvider {
+        fn metadata() -> ProviderMetadata {
+            ProviderMetadata::empty()
+        }
+
+        fn get_model_config(&self) -> ModelConfig {
+            ModelConfig::new("mock".to_string())
+        }
+
+        async fn complete(
+            &self,
+            _system: &str,
+            messages: &[Message],
+            _tools: &[Tool],
+        ) -> Result<(Message, ProviderUsage), ProviderError> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            let text = format!("answer {} to {}", call, messages[0].as_concat_text());
+            Ok((
+                Message::assistant().with_text(text),
+                ProviderUsage::new("mock".to_string(), Usage::new(Some(10), Some(5), Some(15))),
+            ))
+        }
+    }
+
+    fn tool() -> Tool {
+        Tool::new(
+            "developer__shell",
+            "Run a command",
+            json!({"type": "object"}),
+        )
+    }
+
+    async fn record(path: &Path) -> Result<()> {
+        let recorder = RecordingProvider::new(
+            Box::new(MockProvider {
+                calls: Arc::new(AtomicU32::new(0)),
+            }),
+            path,
+        );
+        let messages = [Message::user().with_text("hello")];
+        recorder.complete("system", &messages, &[tool()]).await?;
+        recorder.complete("system", &messages, &[tool()]).await?;
+        // Streaming falls back to complete for the mock, the final message is still recorded
+        let _events: Vec<_> = recorder
+            .stream("system", &[Message::user().with_text("bye")], &[])
+            .await?
+            .collect()
+            .await;
+        Ok(())
+    }
+
+    fn text(message: &Message) -> String {
+        message.as_concat_text()
+    }
+
+    #[tokio::test]
+    async fn test_strict_replay() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("session.jsonl");
+        record(&path).await?;
+
+        let replay =
+            ReplayProvider::new(&path, ReplayMode::Strict, ModelConfig::new("mock".into()))?;
+        // New messages have a different timestamp, which doesn't affect the match
+        let mut messages = [Message::user().with_text("hello")];
+        messages[0].created += 100;
+        let (first, usage) = replay.complete("system", &messages, &[tool()]).await?;
+        let (second, _) = replay.complete("system", &messages, &[tool()]).await?;
+        let (third, _) = replay.complete("system", &messages, &[tool()]).await?;
+        assert_eq!(text(&first), "answer 0 to hello");
+        assert_eq!(text(&second), "answer 1 to hello");
+        assert_eq!(text(&third), "answer 1 to hello");
+        assert_eq!(usage.usage.total_tokens, Some(15));
+
+        let (bye, _) = replay
+            .complete("system", &[Message::user().with_text("bye")], &[])
+            .await?;
+        assert_eq!(text(&bye), "answer 2 to bye");
+
+        let result = replay.complete("other system", &messages, &[tool()]).await;
+        assert!(matches!(result, Err(ProviderError::ExecutionError(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fuzzy_replay() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("session.jsonl");
+        record(&path).await?;
+
+        let replay =
+            ReplayProvider::new(&path, ReplayMode::Fuzzy, ModelConfig::new("mock".into()))?;
+        // The system prompt and tools differ from the recording
+        let (bye, _) = replay
+            .complete(
+                "today is another day",
+                &[Message::user().with_text("bye")],
+                &[],
+            )
+            .await?;
+        assert_eq!(text(&bye), "answer 2 to bye");
+
+        // Unmatched requests get the next answer that hasn't been served
+        let (first, _) = replay
+            .complete("system", &[Message::user().with_text("something new")], &[])
+            .await?;
+        let (second, _) = replay
+            .complete("system", &[Message::user().with_text("and again")], &[])
+            .await?;
+        assert_eq!(text(&first), "answer 0 to hello");
+        assert_eq!(text(&second), "answer 1 to hello");
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_fixture() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("broken.jsonl");
+        std::fs::write(&path, "{\"system\": \"missing fields\"}\n")?;
+        let error = ReplayProvider::new(&path, ReplayMode::Strict, ModelConfig::new("m".into()))
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("line 1"));
+        assert_eq!("Fuzzy".parse::<ReplayMode>()?, ReplayMode::Fuzzy);
+        assert!("loose".parse::<ReplayMode>().is_err());
+        Ok(())
+    }
+}
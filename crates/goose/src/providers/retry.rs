@@ -0,0 +1,446 @@
+use async_trait::async_trait;
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+
+use super::base::{ModelInfo, Provider, ProviderMetadata, ProviderStream, ProviderUsage};
+use super::errors::ProviderError;
+use crate::message::Message;
+use crate::model::ModelConfig;
+use mcp_core::tool::Tool;
+use serde_json::Value;
+
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+pub const DEFAULT_INITIAL_INTERVAL_MS: u64 = 1_000;
+pub const DEFAULT_MAX_INTERVAL_MS: u64 = 60_000;
+pub const DEFAULT_BACKOFF_MULTIPLIER: f64 = 2.0;
+
+/// Settings for retrying provider requests that failed with a transient error
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryConfig {
+    /// Maximum number of retries after the initial attempt, 0 disables retrying
+    pub max_retries: u32,
+    /// Backoff before the first retry
+    pub initial_interval: Duration,
+    /// Upper bound for the backoff between retries
+    pub max_interval: Duration,
+    /// Factor the backoff grows by after each retry
+    pub backoff_multiplier: f64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_interval: Duration::from_millis(DEFAULT_INITIAL_INTERVAL_MS),
+            max_interval: Duration::from_millis(DEFAULT_MAX_INTERVAL_MS),
+            backoff_multiplier: DEFAULT_BACKOFF_MULTIPLIER,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Load the retry settings for a provider
+    ///
+    /// Each setting is read from the provider specific key first (e.g. `ANTHROPIC_MAX_RETRIES`)
+    /// and then from the `GOOSE_` prefixed key (e.g. `GOOSE_MAX_RETRIES`), falling back to the default.
+    pub fn from_config(provider: &str) -> Self {
+        let config = crate::config::Config::global();
+        let prefix = provider.to_uppercase();
+        let get = |name: &str| -> Option<f64> {
+            config
+                .get::<f64>(&format!("{}_{}", prefix, name))
+                .or_else(|_| config.get::<f64>(&format!("GOOSE_{}", name)))
+                .ok()
+        };
+
+        let default = Self::default();
+        Self {
+            max_retries: get("MAX_RETRIES")
+                .map(|v| v.max(0.0) as u32)
+                .unwrap_or(default.max_retries),
+            initial_interval: get("RETRY_INITIAL_INTERVAL_MS")
+                .map(|v| Duration::from_millis(v.max(0.0) as u64))
+                .unwrap_or(default.initial_interval),
+            max_interval: get("RETRY_MAX_INTERVAL_MS")
+                .map(|v| Duration::from_millis(v.max(0.0) as u64))
+                .unwrap_or(default.max_interval),
+            backoff_multiplier: get("RETRY_BACKOFF_MULTIPLIER")
+                .filter(|v| *v >= 1.0)
+                .unwrap_or(default.backoff_multiplier),
+        }
+    }
+
+    /// The backoff before the given retry (starting at 1), without jitter
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1) as i32;
+        let backoff = self.initial_interval.as_secs_f64() * self.backoff_multiplier.powi(exponent);
+        Duration::from_secs_f64(backoff.min(self.max_interval.as_secs_f64()))
+    }
+
+    /// How long to wait before the given retry (starting at 1) after the error
+    ///
+    /// A delay requested by the provider is honored in full, as retrying any sooner would fail
+    /// again. When it is longer than the remaining retries could wait in total, at the maximum
+    /// interval each, there is no point in waiting and `None` is returned. Otherwise the backoff
+    /// is jittered to a random point in its upper half so concurrent clients don't retry in
+    /// lockstep.
+    pub fn delay(&self, retry: u32, error: &ProviderError) -> Option<Duration> {
+        if let ProviderError::RateLimitExceeded {
+            retry_after: Some(retry_after),
+            ..
+        } = error
+        {
+            let remaining = self.max_retries.saturating_sub(retry) + 1;
+            return (*retry_after <= self.max_interval * remaining).then_some(*retry_after);
+        }
+
+        let backoff = self.backoff(retry).as_secs_f64();
+        let jittered = rand::thread_rng().gen_range(backoff / 2.0..=backoff);
+        Some(Duration::from_secs_f64(jittered))
+    }
+}
+
+/// Whether a request that failed with this error is worth retrying
+pub fn is_retryable(error: &ProviderError) -> bool {
+    matches!(
+        error,
+        ProviderError::RateLimitExceeded { .. } | ProviderError::ServerError(_)
+    )
+}
+
+/// A provider that retries requests to the wrapped provider on rate limits and server errors
+pub struct RetryProvider {
+    name: String,
+    inner: Box<dyn Provider + Send + Sync>,
+    config: RetryConfig,
+}
+
+impl RetryProvider {
+    pub fn new(name: &str, inner: Box<dyn Provider + Send + Sync>, config: RetryConfig) -> Self {
+        Self {
+            name: name.to_string(),
+            inner,
+            config,
+        }
+    }
+
+    /// Wrap a provider using the retry settings configured for it
+    pub fn from_config(name: &str, inner: Box<dyn Provider + Send + Sync>) -> Self {
+        Self::new(name, inner, RetryConfig::from_config(name))
+    }
+
+    async fn with_retry<T, F, Fut>(&self, mut operation: F) -> Result<T, ProviderError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ProviderError>>,
+    {
+        let mut retry = 0;
+        loop {
+            match operation().await {
+                Err(error) if is_retryable(&error) && retry < self.config.max_retries => {
+                    let Some(delay) = self.config.delay(retry + 1, &error) else {
+                        tracing::error!(
+                            provider = %self.name,
+                            retries = retry,
+                            error = %error,
+                            "Provider asked to wait longer than the retries allow, giving up"
+                        );
+                        return Err(error);
+                    };
+                    retry += 1;
+                    tracing::warn!(
+                        provider = %self.name,
+                        retry,
+                        max_retries = self.config.max_retries,
+                        delay_ms = delay.as_millis() as u64,
+                        error = %error,
+                        "Provider request failed, retrying"
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(error) => {
+                    if retry > 0 {
+                        tracing::error!(
+                            provider = %self.name,
+                            retries = retry,
+                            error = %error,
+                            "Provider request failed after retrying"
+                        );
+                    }
+                    return Err(error);
+                }
+                Ok(result) => {
+                    if retry > 0 {
+                        tracing::info!(
+                            provider = %self.name,
+                            retries = retry,
+                            "Provider request succeeded after retrying"
+                        );
+                    }
+                    return Ok(result);
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for RetryProvider {
+    fn metadata() -> ProviderMetadata {
+        ProviderMetadata::empty()
+    }
+
+    fn get_model_config(&self) -> ModelConfig {
+        self.inner.get_model_config()
+    }
+
+    async fn complete(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        self.with_retry(|| self.inner.complete(system, messages, tools))
+            .await
+    }
+
+    async fn complete_json(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        self.with_retry(|| self.inner.complete_json(system, messages, schema))
+            .await
+    }
+
+    /// Only starting the stream is retried, once deltas have been yielded a failure
+    /// is passed on to the caller
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<ProviderStream, ProviderError> {
+        self.with_retry(|| self.inner.stream(system, messages, tools))
+            .await
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<ModelInfo>>, ProviderError> {
+        self.with_retry(|| self.inner.list_models()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::base::Usage;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    struct FlakyProvider {
+        failures: u32,
+        error: fn() -> ProviderError,
+        calls: Arc<AtomicU32>,
+    }
+
+    #[async_trait]
+    impl Provider for FlakyProvider {
+        fn metadata() -> ProviderMetadata {
+            ProviderMetadata::empty()
+        }
+
+        fn get_model_config(&self) -> ModelConfig {
+            ModelConfig::new("mock".to_string())
+        }
+
+        async fn complete(
+            &self,
+            _system: &str,
+            _messages: &[Message],
+            _tools: &[Tool],
+        ) -> Result<(Message, ProviderUsage), ProviderError> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            if call < self.failures {
+                return Err((self.error)());
+            }
+            Ok((
+                Message::assistant().with_text("done"),
+                ProviderUsage::new("mock".to_string(), Usage::default()),
+            ))
+        }
+    }
+
+    fn fast_config(max_retries: u32) -> RetryConfig {
+        RetryConfig {
+            max_retries,
+            initial_interval: Duration::from_millis(1),
+            max_interval: Duration::from_millis(5),
+            backoff_multiplier: 2.0,
+        }
+    }
+
+    fn flaky(failures: u32, error: fn() -> ProviderError) -> (Box<FlakyProvider>, Arc<AtomicU32>) {
+        let calls = Arc::new(AtomicU32::new(0));
+        let provider = Box::new(FlakyProvider {
+            failures,
+            error,
+            calls: calls.clone(),
+        });
+        (provider, calls)
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_success() {
+        let (inner, calls) = flaky(2, || ProviderError::ServerError("unavailable".to_string()));
+        let provider = RetryProvider::new("mock", inner, fast_config(3));
+
+        let (message, _) = provider.complete("", &[], &[]).await.unwrap();
+        assert_eq!(message.as_concat_text(), "done");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let (inner, calls) = flaky(10, || ProviderError::RateLimitExceeded {
+            details: "slow down".to_string(),
+            retry_after: Some(Duration::from_millis(1)),
+        });
+        let provider = RetryProvider::new("mock", inner, fast_config(2));
+
+        let result = provider.complete("", &[], &[]).await;
+        assert!(matches!(
+            result,
+            Err(ProviderError::RateLimitExceeded { .. })
+        ));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_when_retry_after_exceeds_budget() {
+        let (inner, calls) = flaky(10, || ProviderError::RateLimitExceeded {
+            details: "slow down".to_string(),
+            retry_after: Some(Duration::from_secs(3600)),
+        });
+        let provider = RetryProvider::new("mock", inner, fast_config(3));
+
+        let result = provider.complete("", &[], &[]).await;
+        assert!(matches!(
+            result,
+            Err(ProviderError::RateLimitExceeded { .. })
+        ));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retries_bad_gateway() {
+        use crate::providers::openai_compatible::OpenAiCompatibleProvider;
+        use serde_json::json;
+        use wiremock::matchers::method;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(502))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "model": "mock",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "done"},
+                    "finish_reason": "stop"
+                }]
+            })))
+            .mount(&server)
+            .await;
+
+        let inner =
+            OpenAiCompatibleProvider::new(&server.uri(), ModelConfig::new("mock".to_string()))
+                .unwrap();
+        let provider = RetryProvider::new("mock", Box::new(inner), fast_config(1));
+        let (message, _) = provider.complete("", &[], &[]).await.unwrap();
+        assert_eq!(message.as_concat_text(), "done");
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_other_errors() {
+        let (inner, calls) = flaky(1, || {
+            ProviderError::ContextLengthExceeded("too long".to_string())
+        });
+        let provider = RetryProvider::new("mock", inner, fast_config(3));
+
+        let result = provider.complete("", &[], &[]).await;
+        assert!(matches!(
+            result,
+            Err(ProviderError::ContextLengthExceeded(_))
+        ));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stream_retries() {
+        let (inner, calls) = flaky(1, || ProviderError::ServerError("unavailable".to_string()));
+        let provider = RetryProvider::new("mock", inner, fast_config(1));
+
+        assert!(provider.stream("", &[], &[]).await.is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_config_provider_override() {
+        std::env::set_var("RETRY_TEST_MAX_RETRIES", "7");
+        std::env::set_var("RETRY_TEST_RETRY_INITIAL_INTERVAL_MS", "250");
+
+        let config = RetryConfig::from_config("retry_test");
+        assert_eq!(config.max_retries, 7);
+        assert_eq!(config.initial_interval, Duration::from_millis(250));
+
+        std::env::remove_var("RETRY_TEST_MAX_RETRIES");
+        std::env::remove_var("RETRY_TEST_RETRY_INITIAL_INTERVAL_MS");
+    }
+
+    #[test]
+    fn test_backoff() {
+        let config = RetryConfig {
+            max_retries: 5,
+            initial_interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(5),
+            backoff_multiplier: 2.0,
+        };
+        assert_eq!(config.backoff(1), Duration::from_secs(1));
+        assert_eq!(config.backoff(2), Duration::from_secs(2));
+        assert_eq!(config.backoff(3), Duration::from_secs(4));
+        assert_eq!(config.backoff(4), Duration::from_secs(5));
+
+        let error = ProviderError::ServerError("unavailable".to_string());
+        for _ in 0..20 {
+            let delay = config.delay(3, &error).unwrap();
+            assert!(delay >= Duration::from_secs(2) && delay <= Duration::from_secs(4));
+        }
+
+        let error = ProviderError::RateLimitExceeded {
+            details: "slow down".to_string(),
+            retry_after: Some(Duration::from_secs(3)),
+        };
+        assert_eq!(config.delay(1, &error), Some(Duration::from_secs(3)));
+
+        // Longer than the maximum interval is still honored while the retries left could wait it out
+        let error = ProviderError::RateLimitExceeded {
+            details: "slow down".to_string(),
+            retry_after: Some(Duration::from_secs(20)),
+        };
+        assert_eq!(config.delay(1, &error), Some(Duration::from_secs(20)));
+        assert_eq!(config.delay(5, &error), None);
+
+        let error = ProviderError::RateLimitExceeded {
+            details: "slow down".to_string(),
+            retry_after: Some(Duration::from_secs(3600)),
+        };
+        assert_eq!(config.delay(1, &error), None);
+    }
+}
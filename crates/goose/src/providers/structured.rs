@@ -0,0 +1,294 @@
+use jsonschema::Validator;
+use serde_json::{json, Value};
+
+use super::base::ProviderUsage;
+use super::errors::ProviderError;
+
+/// The name structured output is requested under, as the JSON schema name on OpenAI
+/// and as the forced tool on Anthropic
+pub const STRUCTURED_OUTPUT_NAME: &str = "response";
+/// How many times a reply that doesn't match the schema is sent back to be fixed
+pub const STRUCTURED_REPAIR_ATTEMPTS: usize = 1;
+
+/// Compile a schema to validate replies against
+pub fn validator(schema: &Value) -> Result<Validator, ProviderError> {
+    jsonschema::validator_for(schema)
+        .map_err(|e| ProviderError::ExecutionError(format!("Invalid JSON schema: {}", e)))
+}
+
+/// The system prompt asking for a reply that matches the schema, for providers without
+/// native structured output
+pub fn schema_prompt(system: &str, schema: &Value) -> String {
+    format!(
+        "{}\n\nRespond with only a JSON value that matches the following JSON schema, \
+         without any other text or markdown:\n{}",
+        system, schema
+    )
+}
+
+/// The message sent back to the model when its reply doesn't match the schema
+pub fn repair_prompt(error: &str) -> String {
+    format!(
+        "Your reply did not match the required JSON schema: {}\n\
+         Respond again with only the corrected JSON value.",
+        error
+    )
+}
+
+/// Parse the JSON in a reply, allowing for a markdown code fence or text around it
+pub fn parse_json(text: &str) -> Result<Value, String> {
+    let text = text.trim();
+    if let Ok(value) = serde_json::from_str(text) {
+        return Ok(value);
+    }
+
+    let unfenced = text
+        .split_once("```")
+        .map(|(_, rest)| rest.trim_start_matches("json"))
+        .and_then(|rest| rest.split_once("```"))
+        .map(|(inside, _)| inside.trim());
+    if let Some(Ok(value)) = unfenced.map(serde_json::from_str) {
+        return Ok(value);
+    }
+
+    // Fall back to the outermost object or array in the text
+    let start = text.find(['{', '[']);
+    let end = text.rfind(['}', ']']);
+    match (start, end) {
+        (Some(start), Some(end)) if start < end => serde_json::from_str(&text[start..=end])
+            .map_err(|e| format!("the reply is not valid JSON ({})", e)),
+        _ => Err("the reply does not contain a JSON value".to_string()),
+    }
+}
+
+/// Parse a reply and check it against the schema, describing what's wrong if it doesn't match
+pub fn parse_and_validate(validator: &Validator, text: &str) -> Result<Value, String> {
+    let value = parse_json(text)?;
+    let errors: Vec<String> = validator
+        .iter_errors(&value)
+        .map(|error| {
+            let path = error.instance_path.to_string();
+            if path.is_empty() {
+                error.to_string()
+            } else {
+                format!("{} at {}", error, path)
+            }
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(value)
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+/// Wrap a schema so it can be used as the input of a tool, which must be an object
+///
+/// Returns the schema to use and whether the value has to be unwrapped from the
+/// `value` property of the tool input.
+pub fn object_schema(schema: &Value) -> (Value, bool) {
+    if schema.get("type").and_then(|t| t.as_str()) == Some("object") {
+        (schema.clone(), false)
+    } else {
+        let wrapped = json!({
+            "type": "object",
+            "properties": {"value": schema},
+            "required": ["value"]
+        });
+        (wrapped, true)
+    }
+}
+
+/// The combined usage of several requests to the same provider
+pub fn add_usage(total: ProviderUsage, usage: &ProviderUsage) -> ProviderUsage {
+    let add = |a: Option<i32>, b: Option<i32>| match (a, b) {
+        (None, None) => None,
+        (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+    };
+    let mut combined = total.clone();
+    combined.usage.input_tokens = add(total.usage.input_tokens, usage.usage.input_tokens);
+    combined.usage.output_tokens = add(total.usage.output_tokens, usage.usage.output_tokens);
+    combined.usage.total_tokens = add(total.usage.total_tokens, usage.usage.total_tokens);
+    combined.usage.cache_read_input_tokens = add(
+        total.usage.cache_read_input_tokens,
+        usage.usage.cache_read_input_tokens,
+    );
+    combined.usage.cache_write_input_tokens = add(
+        total.usage.cache_write_input_tokens,
+        usage.usage.cache_write_input_tokens,
+    );
+    combined.cost = match (total.cost, usage.cost) {
+        (None, None) => None,
+        (a, b) => Some(a.unwrap_or(0.0) + b.unwrap_or(0.0)),
+    };
+    combined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Message;
+    use crate::model::ModelConfig;
+    use crate::providers::base::{Provider, ProviderMetadata, Usage};
+    use async_trait::async_trait;
+    use mcp_core::tool::Tool;
+    use std::sync::Mutex;
+
+    /// Replies with each of its texts in turn and remembers the requests it got
+    struct MockProvider {
+        replies: Mutex<Vec<&'static str>>,
+        requests: Mutex<Vec<(String, Vec<Message>)>>,
+    }
+
+    impl MockProvider {
+        fn new(replies: Vec<&'static str>) -> Self {
+            Self {
+                replies: Mutex::new(replies),
+                requests: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Provider for MockProvider {
+        fn metadata() -> ProviderMetadata {
+            ProviderMetadata::empty()
+        }
+
+        fn get_model_config(&self) -> ModelConfig {
+            ModelConfig::new("mock".to_string())
+        }
+
+        async fn complete(
+            &self,
+            system: &str,
+            messages: &[Message],
+            _tools: &[Tool],
+        ) -> Result<(Message, ProviderUsage), ProviderError> {
+            self.requests
+                .lock()
+                .unwrap()
+                .push((system.to_string(), messages.to_vec()));
+            let reply = self.replies.lock().unwrap().remove(0);
+            Ok((
+                Message::assistant().with_text(reply),
+                ProviderUsage::new("mock".to_string(), Usage::new(Some(10), Some(5), Some(15))),
+            ))
+        }
+    }
+
+    fn plan_schema() -> Value {
+        json!({
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {"description": {"type": "string"}},
+                "required": ["description"]
+            },
+            "minItems": 1
+        })
+    }
+
+    #[test]
+    fn test_parse_json() {
+        assert_eq!(parse_json(" {\"a\": 1} ").unwrap(), json!({"a": 1}));
+        assert_eq!(
+            parse_json("Here is the plan:\n```json\n[{\"description\": \"reply\"}]\n```").unwrap(),
+            json!([{"description": "reply"}])
+        );
+        assert_eq!(
+            parse_json("The answer is {\"a\": [1, 2]}.").unwrap(),
+            json!({"a": [1, 2]})
+        );
+        assert!(parse_json("no json here").is_err());
+        assert!(parse_json("{\"a\": }").is_err());
+    }
+
+    #[test]
+    fn test_parse_and_validate() -> anyhow::Result<()> {
+        let validator = validator(&plan_schema())?;
+        assert_eq!(
+            parse_and_validate(&validator, "[{\"description\": \"reply to the user\"}]").unwrap(),
+            json!([{"description": "reply to the user"}])
+        );
+
+        let error = parse_and_validate(&validator, "[{\"task\": \"reply\"}]").unwrap_err();
+        assert!(error.contains("\"description\" is a required property"));
+        assert!(error.contains("/0"));
+        assert!(parse_and_validate(&validator, "[]").is_err());
+
+        assert!(super::validator(&json!({"type": 12})).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_schema() {
+        let (schema, wrapped) = object_schema(&plan_schema());
+        assert!(wrapped);
+        assert_eq!(schema["properties"]["value"], plan_schema());
+
+        let object = json!({"type": "object", "properties": {}});
+        assert_eq!(object_schema(&object), (object, false));
+    }
+
+    #[test]
+    fn test_add_usage() {
+        let first = ProviderUsage {
+            cost: Some(0.5),
+            ..ProviderUsage::new(
+                "gpt-4o".to_string(),
+                Usage::new(Some(10), Some(5), Some(15)),
+            )
+        };
+        let second = ProviderUsage::new("gpt-4o".to_string(), Usage::new(Some(20), None, Some(20)));
+        let total = add_usage(first, &second);
+        assert_eq!(total.usage.input_tokens, Some(30));
+        assert_eq!(total.usage.output_tokens, Some(5));
+        assert_eq!(total.usage.total_tokens, Some(35));
+        assert_eq!(total.cost, Some(0.5));
+    }
+
+    #[tokio::test]
+    async fn test_complete_structured_repairs_reply() -> anyhow::Result<()> {
+        let provider = MockProvider::new(vec![
+            "[{\"task\": \"reply\"}]",
+            "```json\n[{\"description\": \"reply\"}]\n```",
+        ]);
+        let messages = [Message::user().with_text("Plan a reply")];
+
+        let (value, usage) = provider
+            .complete_structured("You are goose", &messages, &plan_schema())
+            .await?;
+        assert_eq!(value, json!([{"description": "reply"}]));
+        assert_eq!(usage.usage.total_tokens, Some(30));
+
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].0.starts_with("You are goose"));
+        assert!(requests[0].0.contains("\"minItems\":1"));
+        let repair = &requests[1].1;
+        assert_eq!(repair.len(), 3);
+        assert_eq!(repair[1].as_concat_text(), "[{\"task\": \"reply\"}]");
+        assert!(repair[2]
+            .as_concat_text()
+            .contains("\"description\" is a required property"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_complete_structured_fails_after_repair() {
+        let provider = MockProvider::new(vec!["I can't plan that", "[]"]);
+        let result = provider
+            .complete_structured(
+                "system",
+                &[Message::user().with_text("Plan")],
+                &plan_schema(),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(ProviderError::ExecutionError(msg)) if msg.starts_with("The response did not match the schema")
+        ));
+        assert_eq!(provider.requests.lock().unwrap().len(), 2);
+    }
+}
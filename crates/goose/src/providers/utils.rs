@@ -1,10 +1,18 @@
-use super::base::Usage;
-use anyhow::Result;
+use super::base::{ProviderUsage, Usage};
+use super::formats::openai::{get_usage, response_to_message};
+use anyhow::{Context, Result};
+use futures::stream::BoxStream;
+use futures::StreamExt;
 use regex::Regex;
-use reqwest::{Response, StatusCode};
+use reqwest::header::HeaderMap;
+use reqwest::{Certificate, Client, Identity, NoProxy, Proxy, Response, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Map, Value};
+use std::path::PathBuf;
+use std::time::Duration;
 
+use crate::config::Config;
+use crate::message::Message;
 use crate::providers::errors::ProviderError;
 use mcp_core::content::ImageContent;
 
@@ -34,12 +42,144 @@ pub fn convert_image(image: &ImageContent, image_format: &ImageFormat) -> Value
     }
 }
 
+/// Read how long a provider asked us to wait before retrying a request
+/// Supports `retry-after-ms` as well as `retry-after` in seconds or as an HTTP date
+pub fn get_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(ms) = headers
+        .get("retry-after-ms")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<f64>().ok())
+    {
+        return Some(Duration::from_millis(ms.max(0.0) as u64));
+    }
+
+    let value = headers.get("retry-after")?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Some(Duration::from_secs_f64(seconds.max(0.0)));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(delay.to_std().unwrap_or(Duration::ZERO))
+}
+
+/// How long to wait for a provider to send more of a response, when not configured
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Network settings shared by the HTTP clients of providers and remote extensions
+///
+/// Configured with these keys, as environment variables or in the config file:
+/// - `GOOSE_HTTPS_PROXY`: the proxy to send requests through, e.g. `http://proxy:3128`
+/// - `GOOSE_NO_PROXY`: comma separated hosts, domains and IP ranges to reach directly
+/// - `GOOSE_CA_CERTS`: comma separated paths of PEM bundles to trust besides the built-in roots
+/// - `GOOSE_CLIENT_CERT`: the path of a PEM client certificate for mTLS
+/// - `GOOSE_CLIENT_KEY`: the path of its PEM private key, when it's not in the certificate file
+/// - `GOOSE_CONNECT_TIMEOUT` and `GOOSE_READ_TIMEOUT`: in seconds
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HttpClientConfig {
+    pub https_proxy: Option<String>,
+    pub no_proxy: Option<String>,
+    pub ca_certs: Vec<PathBuf>,
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    pub connect_timeout: Option<Duration>,
+    /// Unset for long lived streams, which can go quiet for a while
+    pub read_timeout: Option<Duration>,
+}
+
+impl HttpClientConfig {
+    pub fn from_config(config: &Config) -> Self {
+        let string = |key: &str| {
+            config
+                .get::<String>(key)
+                .ok()
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let seconds = |key: &str| config.get::<u64>(key).ok().map(Duration::from_secs);
+
+        Self {
+            https_proxy: string("GOOSE_HTTPS_PROXY"),
+            no_proxy: string("GOOSE_NO_PROXY"),
+            ca_certs: string("GOOSE_CA_CERTS")
+                .map(|paths| {
+                    paths
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|path| !path.is_empty())
+                        .map(PathBuf::from)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            client_cert: string("GOOSE_CLIENT_CERT").map(PathBuf::from),
+            client_key: string("GOOSE_CLIENT_KEY").map(PathBuf::from),
+            connect_timeout: seconds("GOOSE_CONNECT_TIMEOUT"),
+            read_timeout: Some(seconds("GOOSE_READ_TIMEOUT").unwrap_or(DEFAULT_READ_TIMEOUT)),
+        }
+    }
+
+    /// Build a client with these settings, failing if a certificate can't be read
+    pub fn build(&self) -> Result<Client> {
+        let mut builder = Client::builder();
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+
+        if let Some(url) = &self.https_proxy {
+            let proxy = Proxy::https(url)
+                .with_context(|| format!("Invalid GOOSE_HTTPS_PROXY `{}`", url))?
+                .no_proxy(self.no_proxy.as_deref().and_then(NoProxy::from_string));
+            builder = builder.proxy(proxy);
+        }
+
+        for path in &self.ca_certs {
+            let pem = std::fs::read(path).with_context(|| {
+                format!("Failed to read CA certificates from {}", path.display())
+            })?;
+            let certs = Certificate::from_pem_bundle(&pem)
+                .with_context(|| format!("Invalid CA certificates in {}", path.display()))?;
+            if certs.is_empty() {
+                anyhow::bail!("No CA certificates found in {}", path.display());
+            }
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+
+        if let Some(path) = &self.client_cert {
+            // The identity needs the certificate and the private key in a single PEM buffer
+            let mut pem = std::fs::read(path)
+                .with_context(|| format!("Failed to read client certificate {}", path.display()))?;
+            if let Some(key) = &self.client_key {
+                pem.push(b'\n');
+                pem.extend(
+                    std::fs::read(key)
+                        .with_context(|| format!("Failed to read client key {}", key.display()))?,
+                );
+            }
+            let identity = Identity::from_pem(&pem)
+                .with_context(|| format!("Invalid client certificate {}", path.display()))?;
+            builder = builder.identity(identity);
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+/// Build an HTTP client with the network settings from the global config
+pub fn http_client() -> Result<Client> {
+    HttpClientConfig::from_config(Config::global()).build()
+}
+
 /// Handle response from OpenAI compatible endpoints
 /// Error codes: https://platform.openai.com/docs/guides/error-codes
 /// Context window exceeded: https://community.openai.com/t/help-needed-tackling-context-length-limits-in-openai-models/617543
 pub async fn handle_response_openai_compat(response: Response) -> Result<Value, ProviderError> {
     let status = response.status();
     // Try to parse the response body as JSON (if applicable)
+    let retry_after = get_retry_after(response.headers());
     let payload: Option<Value> = response.json().await.ok();
 
     match status {
@@ -71,9 +211,12 @@ pub async fn handle_response_openai_compat(response: Response) -> Result<Value,
             Err(ProviderError::RequestFailed(format!("Request failed with status: {}. Message: {}", status, message)))
         }
         StatusCode::TOO_MANY_REQUESTS => {
-            Err(ProviderError::RateLimitExceeded(format!("{:?}", payload)))
+            Err(ProviderError::RateLimitExceeded {
+                details: format!("{:?}", payload),
+                retry_after,
+            })
         }
-        StatusCode::INTERNAL_SERVER_ERROR | StatusCode::SERVICE_UNAVAILABLE => {
+        status if status.is_server_error() => {
             Err(ProviderError::ServerError(format!("{:?}", payload)))
         }
         _ => {
@@ -85,6 +228,72 @@ pub async fn handle_response_openai_compat(response: Response) -> Result<Value,
     }
 }
 
+/// Check the status of a streaming response from an OpenAI compatible endpoint
+/// Failures are mapped to the same errors as `handle_response_openai_compat`
+pub async fn check_stream_response_openai_compat(
+    response: Response,
+) -> Result<Response, ProviderError> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    match handle_response_openai_compat(response).await {
+        Err(e) => Err(e),
+        Ok(_) => Err(ProviderError::RequestFailed(format!(
+            "Request failed with status: {}",
+            status
+        ))),
+    }
+}
+
+/// Extract the data of a server-sent event line, ignoring comments and other fields
+fn sse_data(line: &str) -> Option<&str> {
+    let data = line.strip_prefix("data:")?;
+    Some(data.strip_prefix(' ').unwrap_or(data))
+}
+
+/// Split a streaming response body into lines, buffering partial lines between chunks
+pub fn response_lines(response: Response) -> BoxStream<'static, Result<String, ProviderError>> {
+    Box::pin(async_stream::try_stream! {
+        let mut body = response.bytes_stream();
+        let mut buffer: Vec<u8> = Vec::new();
+        while let Some(chunk) = body.next().await {
+            buffer.extend_from_slice(&chunk?);
+            while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                yield String::from_utf8_lossy(&line).trim_end().to_string();
+            }
+        }
+        if !buffer.is_empty() {
+            yield String::from_utf8_lossy(&buffer).trim_end().to_string();
+        }
+    })
+}
+
+/// Parse a server-sent events response into the JSON payload of each event
+/// The `[DONE]` sentinel used by OpenAI compatible endpoints ends the stream
+pub fn sse_json_stream(response: Response) -> BoxStream<'static, Result<Value, ProviderError>> {
+    Box::pin(async_stream::try_stream! {
+        let mut lines = response_lines(response);
+        while let Some(line) = lines.next().await {
+            let line = line?;
+            let Some(data) = sse_data(&line) else {
+                continue;
+            };
+            if data == "[DONE]" {
+                break;
+            }
+            if data.is_empty() {
+                continue;
+            }
+            let value: Value = serde_json::from_str(data).map_err(|e| {
+                ProviderError::RequestFailed(format!("Invalid event in response stream: {}", e))
+            })?;
+            yield value;
+        }
+    })
+}
+
 pub fn sanitize_function_name(name: &str) -> String {
     let re = Regex::new(r"[^a-zA-Z0-9_-]").unwrap();
     re.replace_all(name, "_").to_string()
@@ -137,6 +346,27 @@ pub fn unescape_json_values(value: &Value) -> Value {
     }
 }
 
+/// Read the message and usage of an OpenAI compatible chat completion, tracing the request
+/// and response. Missing usage is logged rather than failing the completion.
+pub fn openai_completion<T: serde::Serialize>(
+    provider: &T,
+    payload: &Value,
+    response: Value,
+) -> Result<(Message, ProviderUsage), ProviderError> {
+    let message = response_to_message(response.clone())?;
+    let usage = match get_usage(&response) {
+        Ok(usage) => usage,
+        Err(ProviderError::UsageError(e)) => {
+            tracing::warn!("Failed to get usage data: {}", e);
+            Usage::default()
+        }
+        Err(e) => return Err(e),
+    };
+    let model = get_model(&response);
+    emit_debug_trace(provider, payload, &response, &usage);
+    Ok((message, ProviderUsage::new(model, usage)))
+}
+
 pub fn emit_debug_trace<T: serde::Serialize>(
     model_config: &T,
     payload: &impl serde::Serialize,
@@ -164,6 +394,88 @@ mod tests {
     use super::*;
     use serde_json::json;
 
+    #[test]
+    fn test_get_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(get_retry_after(&headers), None);
+
+        headers.insert("retry-after", "20".parse().unwrap());
+        assert_eq!(get_retry_after(&headers), Some(Duration::from_secs(20)));
+
+        headers.insert("retry-after-ms", "1500".parse().unwrap());
+        assert_eq!(get_retry_after(&headers), Some(Duration::from_millis(1500)));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "retry-after",
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(get_retry_after(&headers), Some(Duration::ZERO));
+
+        headers.insert("retry-after", "soon".parse().unwrap());
+        assert_eq!(get_retry_after(&headers), None);
+    }
+
+    #[test]
+    fn test_http_client_config() -> Result<()> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let config = Config::new(temp_file.path(), "goose-test")?;
+        assert_eq!(
+            HttpClientConfig::from_config(&config),
+            HttpClientConfig {
+                read_timeout: Some(DEFAULT_READ_TIMEOUT),
+                ..Default::default()
+            }
+        );
+        assert!(HttpClientConfig::default().build().is_ok());
+
+        config.set("GOOSE_HTTPS_PROXY", json!("http://proxy.internal:3128"))?;
+        config.set("GOOSE_NO_PROXY", json!("localhost,.internal"))?;
+        config.set(
+            "GOOSE_CA_CERTS",
+            json!("/etc/ca/corp.pem, /etc/ca/extra.pem"),
+        )?;
+        config.set("GOOSE_CONNECT_TIMEOUT", json!(10))?;
+        config.set("GOOSE_READ_TIMEOUT", json!(120))?;
+        let http = HttpClientConfig::from_config(&config);
+        assert_eq!(
+            http.https_proxy.as_deref(),
+            Some("http://proxy.internal:3128")
+        );
+        assert_eq!(http.no_proxy.as_deref(), Some("localhost,.internal"));
+        assert_eq!(
+            http.ca_certs,
+            vec![
+                PathBuf::from("/etc/ca/corp.pem"),
+                PathBuf::from("/etc/ca/extra.pem")
+            ]
+        );
+        assert_eq!(http.connect_timeout, Some(Duration::from_secs(10)));
+        assert_eq!(http.read_timeout, Some(Duration::from_secs(120)));
+
+        // Certificates are read when building, so a missing one fails early
+        let err = http.build().unwrap_err();
+        assert!(err.to_string().contains("/etc/ca/corp.pem"));
+
+        let ca = tempfile::NamedTempFile::new()?;
+        std::fs::write(ca.path(), "not a certificate")?;
+        let http = HttpClientConfig {
+            ca_certs: vec![ca.path().to_path_buf()],
+            ..Default::default()
+        };
+        assert!(http.build().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sse_data() {
+        assert_eq!(sse_data("data: {\"a\":1}"), Some("{\"a\":1}"));
+        assert_eq!(sse_data("data:[DONE]"), Some("[DONE]"));
+        assert_eq!(sse_data("event: message_start"), None);
+        assert_eq!(sse_data(": keep-alive"), None);
+        assert_eq!(sse_data(""), None);
+    }
+
     #[test]
     fn test_sanitize_function_name() {
         assert_eq!(sanitize_function_name("hello-world"), "hello-world");
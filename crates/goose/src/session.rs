@@ -0,0 +1,685 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::message::{Message, MessageContent};
+use crate::providers::base::{ProviderUsage, Usage};
+
+pub mod export;
+
+const SESSION_EXTENSION: &str = "jsonl";
+/// Characters of context shown around each search match
+const SNIPPET_CONTEXT_CHARS: usize = 40;
+
+/// Errors from the session store that callers tell apart, other failures are I/O errors
+#[derive(Error, Debug)]
+pub enum SessionError {
+    #[error("Session '{0}' not found")]
+    NotFound(String),
+    #[error("Session '{0}' already exists")]
+    AlreadyExists(String),
+    #[error("Invalid session name '{0}'")]
+    InvalidName(String),
+    /// The messages of the session can't be cut at the requested index
+    #[error("{0}")]
+    InvalidFork(String),
+}
+
+/// Generate a name for a fork of the named session
+pub fn fork_name(name: &str) -> String {
+    let suffix: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(4)
+        .map(char::from)
+        .collect();
+    format!("{}-fork-{}", name, suffix.to_lowercase())
+}
+
+/// Metadata about a session, stored as the first line of the session file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionMetadata {
+    /// The directory the session was started in
+    pub working_dir: Option<PathBuf>,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    pub message_count: usize,
+    #[serde(default)]
+    pub input_tokens: i64,
+    #[serde(default)]
+    pub output_tokens: i64,
+    #[serde(default)]
+    pub total_tokens: i64,
+    /// The cost in dollars, when the price of at least one of the models used is known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    /// The session this one was forked from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<SessionParent>,
+}
+
+/// Where a forked session branched off
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionParent {
+    /// Name of the session that was forked
+    pub name: String,
+    /// Number of messages that were copied from the parent session
+    pub message_index: usize,
+}
+
+impl Default for SessionMetadata {
+    fn default() -> Self {
+        let now = Utc::now();
+        Self {
+            working_dir: std::env::current_dir().ok(),
+            provider: None,
+            model: None,
+            created: now,
+            updated: now,
+            message_count: 0,
+            input_tokens: 0,
+            output_tokens: 0,
+            total_tokens: 0,
+            cost: None,
+            parent: None,
+        }
+    }
+}
+
+impl SessionMetadata {
+    /// Create metadata for a new session started now in the current directory
+    pub fn new(provider: Option<String>, model: Option<String>) -> Self {
+        Self {
+            provider,
+            model,
+            ..Default::default()
+        }
+    }
+
+    /// Best effort metadata for session files written before metadata was recorded
+    fn from_legacy(path: &Path, messages: &[Message]) -> Self {
+        let modified = fs::metadata(path)
+            .and_then(|m| m.modified())
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_else(|_| Utc::now());
+        let created = messages
+            .first()
+            .and_then(|m| Utc.timestamp_opt(m.created, 0).single())
+            .unwrap_or(modified);
+        Self {
+            working_dir: None,
+            created,
+            updated: modified,
+            message_count: messages.len(),
+            ..Default::default()
+        }
+    }
+
+    /// Add the token usage of a provider to the totals
+    pub fn add_usage(&mut self, usage: &[ProviderUsage]) {
+        for usage in usage {
+            self.input_tokens += usage.usage.input_tokens.unwrap_or(0) as i64;
+            self.output_tokens += usage.usage.output_tokens.unwrap_or(0) as i64;
+            self.total_tokens += usage.usage.total_tokens.unwrap_or(0) as i64;
+            if let Some(cost) = usage.cost {
+                self.cost = Some(self.cost.unwrap_or(0.0) + cost);
+            }
+        }
+    }
+
+    /// The token totals of the session as provider usage for its model
+    pub fn usage(&self) -> ProviderUsage {
+        // Usage counts tokens as i32, saturate the session totals rather than wrapping
+        let tokens = |count: i64| Some(count.clamp(0, i32::MAX as i64) as i32);
+        ProviderUsage {
+            cost: self.cost,
+            ..ProviderUsage::new(
+                self.model.clone().unwrap_or_else(|| "unknown".to_string()),
+                Usage::new(
+                    tokens(self.input_tokens),
+                    tokens(self.output_tokens),
+                    tokens(self.total_tokens),
+                ),
+            )
+        }
+    }
+}
+
+/// A session as stored on disk
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StoredSession {
+    pub name: String,
+    pub metadata: SessionMetadata,
+    pub messages: Vec<Message>,
+}
+
+/// A session in a listing, without its messages
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionInfo {
+    pub name: String,
+    pub path: PathBuf,
+    pub metadata: SessionMetadata,
+}
+
+/// A message in a session that matched a search
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchMatch {
+    /// Index of the message in the session
+    pub message_index: usize,
+    /// The matching text with some context around it
+    pub snippet: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchResult {
+    pub session: SessionInfo,
+    pub matches: Vec<SearchMatch>,
+}
+
+/// Read the metadata and messages from a session file
+///
+/// Files without a metadata line are still supported, their metadata is then derived from the file
+pub fn read_session_file(path: &Path) -> Result<(SessionMetadata, Vec<Message>)> {
+    let reader = io::BufReader::new(File::open(path)?);
+    let mut metadata = None;
+    let mut messages = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if index == 0 {
+            if let Ok(header) = serde_json::from_str::<SessionMetadata>(&line) {
+                metadata = Some(header);
+                continue;
+            }
+        }
+        messages.push(serde_json::from_str::<Message>(&line)?);
+    }
+
+    let metadata = metadata.unwrap_or_else(|| SessionMetadata::from_legacy(path, &messages));
+    Ok((metadata, messages))
+}
+
+/// Read only the metadata line of a session file
+pub fn read_session_metadata(path: &Path) -> Result<SessionMetadata> {
+    let mut reader = io::BufReader::new(File::open(path)?);
+    let mut first_line = String::new();
+    reader.read_line(&mut first_line)?;
+    match serde_json::from_str::<SessionMetadata>(&first_line) {
+        Ok(metadata) => Ok(metadata),
+        Err(_) => read_session_file(path).map(|(metadata, _)| metadata),
+    }
+}
+
+/// Write the metadata and messages to a session file, replacing its contents
+pub fn write_session_file(
+    path: &Path,
+    metadata: &SessionMetadata,
+    messages: &[Message],
+) -> Result<()> {
+    let mut writer = io::BufWriter::new(File::create(path)?);
+
+    serde_json::to_writer(&mut writer, metadata)?;
+    writeln!(writer)?;
+    for message in messages {
+        serde_json::to_writer(&mut writer, message)?;
+        writeln!(writer)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// The text of a message that search looks at
+fn searchable_text(message: &Message) -> String {
+    message
+        .content
+        .iter()
+        .filter_map(|content| match content {
+            MessageContent::Text(text) => Some(text.text.clone()),
+            MessageContent::ToolRequest(request) => request
+                .tool_call
+                .as_ref()
+                .ok()
+                .map(|call| format!("{} {}", call.name, call.arguments)),
+            MessageContent::ToolResponse(response) => response.tool_result.as_ref().ok().map(|c| {
+                c.iter()
+                    .filter_map(|c| c.as_text())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }),
+            MessageContent::Image(_) | MessageContent::Thinking(_) => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Fold a char for case insensitive matching, to a single char so positions stay the same
+fn fold_case(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Find the first case insensitive occurrence of the query and cut a snippet around it
+fn find_snippet(text: &str, query: &str) -> Option<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let folded: Vec<char> = chars.iter().copied().map(fold_case).collect();
+    let needle: Vec<char> = query.chars().map(fold_case).collect();
+    let start_char = if needle.is_empty() {
+        0
+    } else {
+        folded
+            .windows(needle.len())
+            .position(|window| window == needle.as_slice())?
+    };
+    let query_chars = needle.len();
+
+    let to = (start_char + query_chars + SNIPPET_CONTEXT_CHARS).min(chars.len());
+    let from = start_char.saturating_sub(SNIPPET_CONTEXT_CHARS).min(to);
+    let mut snippet: String = chars[from..to].iter().collect();
+    snippet = snippet.replace('\n', " ");
+    if from > 0 {
+        snippet.insert_str(0, "...");
+    }
+    if to < chars.len() {
+        snippet.push_str("...");
+    }
+    Some(snippet)
+}
+
+/// Stores sessions as JSONL files in a directory, named after the session
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The store in ~/.config/goose/sessions, which is shared by the CLI and the server
+    pub fn global() -> Result<Self> {
+        let home_dir = dirs::home_dir().ok_or(anyhow!("Could not determine home directory"))?;
+        let dir = home_dir.join(".config").join("goose").join("sessions");
+        fs::create_dir_all(&dir)?;
+        Ok(Self::new(dir))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The path of the file for the named session
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", name, SESSION_EXTENSION))
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.path(name).exists()
+    }
+
+    /// Whether the name can be used for a session file inside the store
+    pub fn is_valid_name(name: &str) -> bool {
+        !(name.is_empty()
+            || name.contains(['/', '\\'])
+            || name.starts_with('.')
+            || name.contains(".."))
+    }
+
+    fn check_name(name: &str) -> Result<()> {
+        if !Self::is_valid_name(name) {
+            return Err(SessionError::InvalidName(name.to_string()).into());
+        }
+        Ok(())
+    }
+
+    /// Read a session, failing if it doesn't exist
+    pub fn read(&self, name: &str) -> Result<StoredSession> {
+        Self::check_name(name)?;
+        let path = self.path(name);
+        if !path.exists() {
+            return Err(SessionError::NotFound(name.to_string()).into());
+        }
+        let (metadata, messages) = read_session_file(&path)?;
+        Ok(StoredSession {
+            name: name.to_string(),
+            metadata,
+            messages,
+        })
+    }
+
+    /// Write a session, updating the message count and the updated time of its metadata
+    pub fn write(
+        &self,
+        name: &str,
+        metadata: &mut SessionMetadata,
+        messages: &[Message],
+    ) -> Result<()> {
+        Self::check_name(name)?;
+        fs::create_dir_all(&self.dir)?;
+        metadata.message_count = messages.len();
+        metadata.updated = Utc::now();
+        write_session_file(&self.path(name), metadata, messages)
+    }
+
+    /// Copy the first `at` messages of a session into a new session
+    ///
+    /// The new session records the session it was forked from. The fork can't end with a tool
+    /// request whose response is left behind, since providers reject such conversations.
+    pub fn fork(&self, name: &str, new_name: &str, at: usize) -> Result<StoredSession> {
+        Self::check_name(new_name)?;
+        if self.exists(new_name) {
+            return Err(SessionError::AlreadyExists(new_name.to_string()).into());
+        }
+
+        let session = self.read(name)?;
+        if at > session.messages.len() {
+            return Err(SessionError::InvalidFork(format!(
+                "Session '{}' has only {} messages",
+                name,
+                session.messages.len()
+            ))
+            .into());
+        }
+        let messages = session.messages[..at].to_vec();
+        if messages.last().is_some_and(|m| m.is_tool_call()) {
+            return Err(SessionError::InvalidFork(format!(
+                "Message {} is a tool request, fork after its response instead",
+                at - 1
+            ))
+            .into());
+        }
+
+        let now = Utc::now();
+        let mut metadata = SessionMetadata {
+            created: now,
+            input_tokens: 0,
+            output_tokens: 0,
+            total_tokens: 0,
+            cost: None,
+            parent: Some(SessionParent {
+                name: name.to_string(),
+                message_index: at,
+            }),
+            ..session.metadata
+        };
+        self.write(new_name, &mut metadata, &messages)?;
+
+        Ok(StoredSession {
+            name: new_name.to_string(),
+            metadata,
+            messages,
+        })
+    }
+
+    pub fn delete(&self, name: &str) -> Result<()> {
+        Self::check_name(name)?;
+        let path = self.path(name);
+        if !path.exists() {
+            return Err(SessionError::NotFound(name.to_string()).into());
+        }
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// List all sessions, most recently updated first
+    pub fn list(&self) -> Result<Vec<SessionInfo>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut sessions = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != SESSION_EXTENSION) {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            match read_session_metadata(&path) {
+                Ok(metadata) => sessions.push(SessionInfo {
+                    name: name.to_string(),
+                    path: path.clone(),
+                    metadata,
+                }),
+                Err(e) => tracing::warn!("Skipping unreadable session {}: {}", path.display(), e),
+            }
+        }
+
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.metadata.updated));
+        Ok(sessions)
+    }
+
+    /// The name of the most recently updated session
+    pub fn most_recent(&self) -> Result<String> {
+        self.list()?
+            .into_iter()
+            .next()
+            .map(|session| session.name)
+            .ok_or_else(|| anyhow!("No session files found"))
+    }
+
+    /// Find the sessions with messages containing the query, ignoring case
+    pub fn search(&self, query: &str) -> Result<Vec<SearchResult>> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Err(anyhow!("The search query is empty"));
+        }
+
+        let mut results = Vec::new();
+        for session in self.list()? {
+            let (_, messages) = match read_session_file(&session.path) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    tracing::warn!("Skipping unreadable session {}: {}", session.name, e);
+                    continue;
+                }
+            };
+
+            let matches: Vec<SearchMatch> = messages
+                .iter()
+                .enumerate()
+                .filter_map(|(message_index, message)| {
+                    find_snippet(&searchable_text(message), query).map(|snippet| SearchMatch {
+                        message_index,
+                        snippet,
+                    })
+                })
+                .collect();
+
+            let name_matches = session.name.to_lowercase().contains(&query.to_lowercase());
+            if !matches.is_empty() || name_matches {
+                results.push(SearchResult { session, matches });
+            }
+        }
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::base::Usage;
+    use mcp_core::{Content, ToolCall};
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn messages() -> Vec<Message> {
+        vec![
+            Message::user().with_text("Find the flaky test in the parser"),
+            Message::assistant().with_tool_request(
+                "1",
+                Ok(ToolCall::new(
+                    "developer__shell",
+                    json!({"command": "cargo test"}),
+                )),
+            ),
+            Message::user().with_tool_response(
+                "1",
+                Ok(vec![Content::text("test parser::roundtrip ... FAILED")]),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_write_and_read() -> Result<()> {
+        let dir = TempDir::new()?;
+        let store = SessionStore::new(dir.path());
+
+        let mut metadata =
+            SessionMetadata::new(Some("openai".to_string()), Some("gpt-4o".to_string()));
+        metadata.add_usage(&[
+            ProviderUsage::new(
+                "gpt-4o".to_string(),
+                Usage::new(Some(10), Some(5), Some(15)),
+            ),
+            ProviderUsage {
+                cost: Some(0.25),
+                ..ProviderUsage::new("gpt-4o".to_string(), Usage::default())
+            },
+        ]);
+        store.write("parser", &mut metadata, &messages())?;
+
+        let session = store.read("parser")?;
+        assert_eq!(session.messages, messages());
+        assert_eq!(session.metadata.message_count, 3);
+        assert_eq!(session.metadata.total_tokens, 15);
+        assert_eq!(session.metadata.cost, Some(0.25));
+        assert_eq!(session.metadata.model.as_deref(), Some("gpt-4o"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_legacy_file() -> Result<()> {
+        let dir = TempDir::new()?;
+        let store = SessionStore::new(dir.path());
+
+        // Files from before metadata was recorded only contain messages
+        let mut file = File::create(store.path("legacy"))?;
+        for message in messages() {
+            writeln!(file, "{}", serde_json::to_string(&message)?)?;
+        }
+
+        let session = store.read("legacy")?;
+        assert_eq!(session.messages.len(), 3);
+        assert_eq!(session.metadata.message_count, 3);
+        assert_eq!(session.metadata.provider, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_search_and_delete() -> Result<()> {
+        let dir = TempDir::new()?;
+        let store = SessionStore::new(dir.path());
+
+        store.write("parser", &mut SessionMetadata::default(), &messages())?;
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        store.write(
+            "docs",
+            &mut SessionMetadata::default(),
+            &[Message::user().with_text("Update the README")],
+        )?;
+
+        let names: Vec<_> = store.list()?.into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["docs", "parser"]);
+        assert_eq!(store.most_recent()?, "docs");
+
+        let results = store.search("ROUNDTRIP")?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].session.name, "parser");
+        assert_eq!(results[0].matches[0].message_index, 2);
+        assert!(results[0].matches[0].snippet.contains("roundtrip"));
+
+        store.delete("parser")?;
+        assert!(!store.exists("parser"));
+        assert!(store.delete("parser").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fork() -> Result<()> {
+        let dir = TempDir::new()?;
+        let store = SessionStore::new(dir.path());
+        let mut metadata = SessionMetadata::new(None, Some("gpt-4o".to_string()));
+        store.write("original", &mut metadata, &messages())?;
+
+        let fork = store.fork("original", "retry", 1)?;
+        assert_eq!(fork.messages, messages()[..1].to_vec());
+        assert_eq!(fork.metadata.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(
+            fork.metadata.parent,
+            Some(SessionParent {
+                name: "original".to_string(),
+                message_index: 1
+            })
+        );
+        // The original is unchanged
+        assert_eq!(store.read("original")?.messages.len(), 3);
+
+        // A fork of a fork records its direct parent
+        let again = store.fork("retry", "retry-again", 0)?;
+        assert_eq!(
+            again.metadata.parent.map(|parent| parent.name).as_deref(),
+            Some("retry")
+        );
+
+        // Forking can't leave a tool request without its response
+        let error = |result: Result<StoredSession>| result.unwrap_err().downcast::<SessionError>();
+        assert!(matches!(
+            error(store.fork("original", "dangling", 2)),
+            Ok(SessionError::InvalidFork(_))
+        ));
+        assert!(matches!(
+            error(store.fork("original", "too-far", 4)),
+            Ok(SessionError::InvalidFork(_))
+        ));
+        assert!(matches!(
+            error(store.fork("original", "retry", 3)),
+            Ok(SessionError::AlreadyExists(_))
+        ));
+        assert!(matches!(
+            error(store.fork("missing", "missing-fork", 0)),
+            Ok(SessionError::NotFound(_))
+        ));
+
+        let name = fork_name("original");
+        assert!(name.starts_with("original-fork-") && SessionStore::is_valid_name(&name));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        let store = SessionStore::new("/tmp/sessions");
+        assert!(store.read("../config").is_err());
+        assert!(store.delete("").is_err());
+    }
+
+    #[test]
+    fn test_find_snippet() {
+        let text = format!("{}needle{}", "a".repeat(50), "b".repeat(50));
+        let snippet = find_snippet(&text, "NEEDLE").unwrap();
+        assert!(snippet.starts_with("..."));
+        assert!(snippet.ends_with("..."));
+        assert!(snippet.contains("needle"));
+        assert_eq!(find_snippet("nothing here", "needle"), None);
+
+        // Chars that lowercase to more than one char don't shift the snippet
+        let text = format!("{}needle", "İ".repeat(50));
+        let snippet = find_snippet(&text, "Needle").unwrap();
+        assert!(snippet.ends_with("needle"));
+        assert_eq!(
+            find_snippet("İstanbul", "istanbul").as_deref(),
+            Some("İstanbul")
+        );
+    }
+}
@@ -0,0 +1,523 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+use anyhow::Result;
+use mcp_core::content::Content;
+use mcp_core::role::Role;
+use serde_json::json;
+
+use super::{SessionMetadata, StoredSession};
+use crate::message::{Message, MessageContent, ThinkingContent, ToolRequest, ToolResponse};
+use crate::providers::base::ProviderUsage;
+
+/// The formats a session transcript can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Json,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "md" | "markdown" => Ok(ExportFormat::Markdown),
+            "html" => Ok(ExportFormat::Html),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(format!(
+                "Unknown export format '{}', expected md, html or json",
+                s
+            )),
+        }
+    }
+}
+
+/// Render a session as a transcript to share with people
+///
+/// Tool output that isn't meant for the user, by its audience annotation, is left out, as is
+/// output with a priority below `min_priority`. The model's thinking is only included when
+/// `include_thinking` is set. The JSON format otherwise keeps the messages as they are stored
+/// so it can be processed further.
+pub fn export_session(
+    session: &StoredSession,
+    format: ExportFormat,
+    min_priority: f32,
+    include_thinking: bool,
+) -> Result<String> {
+    let without_thinking;
+    let session = if include_thinking {
+        session
+    } else {
+        without_thinking = StoredSession {
+            messages: session
+                .messages
+                .iter()
+                .map(Message::without_thinking)
+                .filter(|message| !message.content.is_empty())
+                .collect(),
+            ..session.clone()
+        };
+        &without_thinking
+    };
+    match format {
+        ExportFormat::Markdown => render_markdown(session, min_priority),
+        ExportFormat::Html => render_html(session, min_priority),
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(&json!({
+            "name": session.name,
+            "metadata": session.metadata,
+            "messages": session.messages,
+            "usage": session.metadata.usage(),
+        }))?),
+    }
+}
+
+/// Whether tool output is shown to the user, following the same rules as the CLI
+fn visible_to_user(content: &Content, min_priority: f32) -> bool {
+    let hidden_by_audience = content
+        .audience()
+        .is_some_and(|audience| !audience.contains(&Role::User));
+    let below_priority = content
+        .priority()
+        .is_some_and(|priority| priority < min_priority);
+    !hidden_by_audience && !below_priority
+}
+
+fn heading(message: &Message) -> &'static str {
+    let only_responses = !message.content.is_empty()
+        && message
+            .content
+            .iter()
+            .all(|content| matches!(content, MessageContent::ToolResponse(_)));
+    match message.role {
+        _ if only_responses => "Tool results",
+        Role::User => "User",
+        Role::Assistant => "Assistant",
+    }
+}
+
+fn pretty_arguments(request: &ToolRequest) -> Option<(String, String)> {
+    let tool_call = request.tool_call.as_ref().ok()?;
+    let arguments = serde_json::to_string_pretty(&tool_call.arguments)
+        .unwrap_or_else(|_| tool_call.arguments.to_string());
+    Some((tool_call.name.clone(), arguments))
+}
+
+fn data_uri(mime_type: &str, data: &str) -> String {
+    format!("data:{};base64,{}", mime_type, data)
+}
+
+fn model_label(metadata: &SessionMetadata) -> Option<String> {
+    match (&metadata.provider, &metadata.model) {
+        (Some(provider), Some(model)) => Some(format!("{}/{}", provider, model)),
+        (None, Some(model)) => Some(model.clone()),
+        (Some(provider), None) => Some(provider.clone()),
+        (None, None) => None,
+    }
+}
+
+/// Session details shown at the top of a transcript
+fn details(metadata: &SessionMetadata) -> Vec<(&'static str, String)> {
+    let mut details = Vec::new();
+    if let Some(model) = model_label(metadata) {
+        details.push(("Model", model));
+    }
+    details.push((
+        "Started",
+        metadata.created.format("%Y-%m-%d %H:%M UTC").to_string(),
+    ));
+    if let Some(working_dir) = &metadata.working_dir {
+        details.push(("Directory", working_dir.display().to_string()));
+    }
+    if let Some(parent) = &metadata.parent {
+        details.push((
+            "Forked from",
+            format!("{} at message {}", parent.name, parent.message_index),
+        ));
+    }
+    details
+}
+
+fn usage_row(usage: &ProviderUsage) -> [String; 4] {
+    let count = |tokens: Option<i32>| tokens.map_or("-".to_string(), |t| t.to_string());
+    [
+        usage.model.clone(),
+        count(usage.usage.input_tokens),
+        count(usage.usage.output_tokens),
+        count(usage.usage.total_tokens),
+    ]
+}
+
+const USAGE_COLUMNS: [&str; 4] = ["Model", "Input tokens", "Output tokens", "Total tokens"];
+
+/// A markdown code fence longer than any run of backticks in the text
+fn fence(text: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+fn write_markdown_block(output: &mut String, language: &str, text: &str) -> Result<()> {
+    let fence = fence(text);
+    writeln!(
+        output,
+        "{}{}\n{}\n{}\n",
+        fence,
+        language,
+        text.trim_end(),
+        fence
+    )?;
+    Ok(())
+}
+
+fn write_markdown_response(
+    output: &mut String,
+    response: &ToolResponse,
+    min_priority: f32,
+) -> Result<()> {
+    match &response.tool_result {
+        Ok(contents) => {
+            writeln!(output, "**Tool result:**\n")?;
+            for content in contents
+                .iter()
+                .filter(|content| visible_to_user(content, min_priority))
+            {
+                match content {
+                    Content::Text(text) => write_markdown_block(output, "", &text.text)?,
+                    Content::Image(image) => writeln!(
+                        output,
+                        "![image]({})\n",
+                        data_uri(&image.mime_type, &image.data)
+                    )?,
+                    Content::Resource(resource) => {
+                        write_markdown_block(output, "", &resource.get_text())?
+                    }
+                }
+            }
+        }
+        Err(e) => writeln!(output, "**Tool error:** {}\n", e)?,
+    }
+    Ok(())
+}
+
+fn write_markdown_thinking(output: &mut String, thinking: &ThinkingContent) -> Result<()> {
+    if thinking.redacted {
+        writeln!(output, "> **Thinking:** (redacted)\n")?;
+    } else {
+        writeln!(output, "> **Thinking:**\n>")?;
+        for line in thinking.thinking.trim_end().lines() {
+            writeln!(output, "> {}", line)?;
+        }
+        writeln!(output)?;
+    }
+    Ok(())
+}
+
+fn render_markdown(session: &StoredSession, min_priority: f32) -> Result<String> {
+    let mut output = String::new();
+    writeln!(output, "# {}\n", session.name)?;
+    for (label, value) in details(&session.metadata) {
+        writeln!(output, "- **{}:** {}", label, value)?;
+    }
+    writeln!(output)?;
+
+    for message in &session.messages {
+        writeln!(output, "## {}\n", heading(message))?;
+        for content in &message.content {
+            match content {
+                MessageContent::Text(text) => writeln!(output, "{}\n", text.text.trim_end())?,
+                MessageContent::Image(image) => writeln!(
+                    output,
+                    "![image]({})\n",
+                    data_uri(&image.mime_type, &image.data)
+                )?,
+                MessageContent::ToolRequest(request) => match pretty_arguments(request) {
+                    Some((name, arguments)) => {
+                        writeln!(output, "**Tool call:** `{}`\n", name)?;
+                        write_markdown_block(&mut output, "json", &arguments)?;
+                    }
+                    None => {
+                        if let Err(e) = &request.tool_call {
+                            writeln!(output, "**Invalid tool call:** {}\n", e)?;
+                        }
+                    }
+                },
+                MessageContent::ToolResponse(response) => {
+                    write_markdown_response(&mut output, response, min_priority)?
+                }
+                MessageContent::Thinking(thinking) => {
+                    write_markdown_thinking(&mut output, thinking)?
+                }
+            }
+        }
+    }
+
+    writeln!(output, "## Usage\n")?;
+    writeln!(output, "| {} |", USAGE_COLUMNS.join(" | "))?;
+    writeln!(output, "|{}", " --- |".repeat(USAGE_COLUMNS.len()))?;
+    writeln!(
+        output,
+        "| {} |",
+        usage_row(&session.metadata.usage()).join(" | ")
+    )?;
+    Ok(output)
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const HTML_STYLE: &str =
+    "body { font-family: sans-serif; max-width: 60rem; margin: 2rem auto; padding: 0 1rem; }
+section { border-top: 1px solid #ddd; padding: 0.5rem 0; }
+.text { white-space: pre-wrap; }
+.thinking { color: #666; }
+pre { background: #f5f5f5; padding: 0.75rem; overflow-x: auto; }
+img { max-width: 100%; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ddd; padding: 0.25rem 0.75rem; text-align: left; }";
+
+fn write_html_image(output: &mut String, mime_type: &str, data: &str) -> Result<()> {
+    writeln!(
+        output,
+        "<img src=\"{}\" alt=\"image\">",
+        escape_html(&data_uri(mime_type, data))
+    )?;
+    Ok(())
+}
+
+fn write_html_response(
+    output: &mut String,
+    response: &ToolResponse,
+    min_priority: f32,
+) -> Result<()> {
+    match &response.tool_result {
+        Ok(contents) => {
+            writeln!(output, "<p><strong>Tool result:</strong></p>")?;
+            for content in contents
+                .iter()
+                .filter(|content| visible_to_user(content, min_priority))
+            {
+                match content {
+                    Content::Text(text) => {
+                        writeln!(output, "<pre>{}</pre>", escape_html(&text.text))?
+                    }
+                    Content::Image(image) => {
+                        write_html_image(output, &image.mime_type, &image.data)?
+                    }
+                    Content::Resource(resource) => {
+                        writeln!(output, "<pre>{}</pre>", escape_html(&resource.get_text()))?
+                    }
+                }
+            }
+        }
+        Err(e) => writeln!(
+            output,
+            "<p><strong>Tool error:</strong> {}</p>",
+            escape_html(&e.to_string())
+        )?,
+    }
+    Ok(())
+}
+
+fn render_html(session: &StoredSession, min_priority: f32) -> Result<String> {
+    let mut output = String::new();
+    let title = escape_html(&session.name);
+    writeln!(
+        output,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"
+    )?;
+    writeln!(output, "<title>{}</title>", title)?;
+    writeln!(output, "<style>\n{}\n</style>\n</head>\n<body>", HTML_STYLE)?;
+    writeln!(output, "<h1>{}</h1>\n<ul>", title)?;
+    for (label, value) in details(&session.metadata) {
+        writeln!(
+            output,
+            "<li><strong>{}:</strong> {}</li>",
+            label,
+            escape_html(&value)
+        )?;
+    }
+    writeln!(output, "</ul>")?;
+
+    for message in &session.messages {
+        let role = match message.role {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        };
+        writeln!(
+            output,
+            "<section class=\"{}\">\n<h2>{}</h2>",
+            role,
+            heading(message)
+        )?;
+        for content in &message.content {
+            match content {
+                MessageContent::Text(text) => writeln!(
+                    output,
+                    "<div class=\"text\">{}</div>",
+                    escape_html(text.text.trim_end())
+                )?,
+                MessageContent::Image(image) => {
+                    write_html_image(&mut output, &image.mime_type, &image.data)?
+                }
+                MessageContent::ToolRequest(request) => match pretty_arguments(request) {
+                    Some((name, arguments)) => writeln!(
+                        output,
+                        "<p><strong>Tool call:</strong> <code>{}</code></p>\n<pre>{}</pre>",
+                        escape_html(&name),
+                        escape_html(&arguments)
+                    )?,
+                    None => {
+                        if let Err(e) = &request.tool_call {
+                            writeln!(
+                                output,
+                                "<p><strong>Invalid tool call:</strong> {}</p>",
+                                escape_html(&e.to_string())
+                            )?;
+                        }
+                    }
+                },
+                MessageContent::ToolResponse(response) => {
+                    write_html_response(&mut output, response, min_priority)?
+                }
+                MessageContent::Thinking(thinking) => writeln!(
+                    output,
+                    "<details class=\"thinking\"><summary>Thinking</summary>\n<div class=\"text\">{}</div>\n</details>",
+                    if thinking.redacted {
+                        "(redacted)".to_string()
+                    } else {
+                        escape_html(thinking.thinking.trim_end())
+                    }
+                )?,
+            }
+        }
+        writeln!(output, "</section>")?;
+    }
+
+    writeln!(output, "<h2>Usage</h2>\n<table>\n<tr>")?;
+    for column in USAGE_COLUMNS {
+        writeln!(output, "<th>{}</th>", column)?;
+    }
+    writeln!(output, "</tr>\n<tr>")?;
+    for cell in usage_row(&session.metadata.usage()) {
+        writeln!(output, "<td>{}</td>", escape_html(&cell))?;
+    }
+    writeln!(output, "</tr>\n</table>\n</body>\n</html>")?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mcp_core::handler::ToolError;
+    use mcp_core::tool::ToolCall;
+
+    fn session() -> StoredSession {
+        let mut metadata =
+            SessionMetadata::new(Some("openai".to_string()), Some("gpt-4o".to_string()));
+        metadata.input_tokens = 120;
+        metadata.output_tokens = 30;
+        metadata.total_tokens = 150;
+        StoredSession {
+            name: "review".to_string(),
+            metadata,
+            messages: vec![
+                Message::user().with_text("List the <src> files"),
+                Message::assistant()
+                    .with_thinking("The user wants <src> listed", Some("sig".to_string()))
+                    .with_tool_request(
+                        "1",
+                        Ok(ToolCall::new(
+                            "developer__shell",
+                            json!({ "command": "ls src" }),
+                        )),
+                    ),
+                Message::user().with_tool_response(
+                    "1",
+                    Ok(vec![
+                        Content::text("main.rs").with_audience(vec![Role::User]),
+                        Content::text("hidden from people").with_audience(vec![Role::Assistant]),
+                        Content::image("aGVsbG8=", "image/png"),
+                        Content::text("low priority").with_priority(0.1),
+                    ]),
+                ),
+                Message::assistant()
+                    .with_tool_request("2", Err(ToolError::NotFound("no such tool".to_string()))),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_export_markdown() -> Result<()> {
+        let markdown = export_session(&session(), ExportFormat::Markdown, 0.5, false)?;
+        assert!(markdown.starts_with("# review\n"));
+        assert!(markdown.contains("- **Model:** openai/gpt-4o"));
+        assert!(markdown.contains("## User\n\nList the <src> files"));
+        assert!(markdown.contains(
+            "**Tool call:** `developer__shell`\n\n```json\n{\n  \"command\": \"ls src\"\n}\n```"
+        ));
+        assert!(markdown.contains("## Tool results"));
+        assert!(markdown.contains("```\nmain.rs\n```"));
+        assert!(markdown.contains("![image](data:image/png;base64,aGVsbG8=)"));
+        assert!(!markdown.contains("hidden from people"));
+        assert!(!markdown.contains("low priority"));
+        assert!(markdown.contains("**Invalid tool call:**"));
+        assert!(markdown.contains("| gpt-4o | 120 | 30 | 150 |"));
+        assert!(!markdown.contains("Thinking"));
+
+        let markdown = export_session(&session(), ExportFormat::Markdown, 0.5, true)?;
+        assert!(markdown.contains("> **Thinking:**\n>\n> The user wants <src> listed\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_html() -> Result<()> {
+        let html = export_session(&session(), ExportFormat::Html, 0.0, true)?;
+        assert!(html.contains("<div class=\"text\">List the &lt;src&gt; files</div>"));
+        assert!(html.contains("&quot;command&quot;: &quot;ls src&quot;"));
+        assert!(html.contains("<img src=\"data:image/png;base64,aGVsbG8=\" alt=\"image\">"));
+        assert!(html.contains("low priority"));
+        assert!(!html.contains("hidden from people"));
+        assert!(html.contains("<td>150</td>"));
+        assert!(html.contains(
+            "<summary>Thinking</summary>\n<div class=\"text\">The user wants &lt;src&gt; listed</div>"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_json() -> Result<()> {
+        let exported: serde_json::Value =
+            serde_json::from_str(&export_session(&session(), ExportFormat::Json, 0.0, false)?)?;
+        assert_eq!(exported["name"], "review");
+        assert_eq!(exported["messages"].as_array().unwrap().len(), 4);
+        assert_eq!(exported["usage"]["usage"]["total_tokens"], 150);
+        assert!(!exported["messages"].to_string().contains("thinking"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_and_fence() {
+        assert_eq!("md".parse(), Ok(ExportFormat::Markdown));
+        assert_eq!("HTML".parse(), Ok(ExportFormat::Html));
+        assert!("pdf".parse::<ExportFormat>().is_err());
+        assert_eq!(fence("no backticks"), "```");
+        assert_eq!(fence("a ```` fence"), "`````");
+    }
+}
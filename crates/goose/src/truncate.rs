@@ -73,6 +73,48 @@ impl TruncationStrategy for OldestFirstTruncation {
     }
 }
 
+/// Strategy to select the oldest messages for summarization rather than removal
+///
+/// The selected messages always form a prefix of the conversation that ends right before a
+/// user text message, so the remaining conversation still starts with a user message and
+/// every tool request stays on the same side of the split as its tool response.
+pub struct OldestFirstSummarization;
+
+impl TruncationStrategy for OldestFirstSummarization {
+    fn determine_indices_to_remove(
+        &self,
+        messages: &[Message],
+        token_counts: &[usize],
+        context_limit: usize,
+    ) -> Result<HashSet<usize>> {
+        // Candidate split points are user messages with only text, which are never part of a
+        // tool request/response pair. The first message can't be one since nothing would be condensed.
+        let candidates: Vec<usize> = messages
+            .iter()
+            .enumerate()
+            .skip(1)
+            .filter(|(_, msg)| msg.role == Role::User && msg.has_only_text_content())
+            .map(|(i, _)| i)
+            .collect();
+
+        // Take the earliest split that leaves the rest within the limit, otherwise condense as much
+        // as possible while keeping the most recent user message
+        let split = candidates
+            .iter()
+            .find(|&&i| token_counts[i..].iter().sum::<usize>() <= context_limit)
+            .or(candidates.last())
+            .copied()
+            .unwrap_or(0);
+
+        debug!(
+            "OldestFirstSummarization: Selected {} of {} messages to summarize",
+            split,
+            messages.len()
+        );
+        Ok((0..split).collect())
+    }
+}
+
 /// Truncates the messages to fit within the model's context window.
 /// Mutates the input messages and token counts in place.
 /// Returns an error if it's impossible to truncate the messages within the context limit.
@@ -186,6 +228,52 @@ mod tests {
     use mcp_core::tool::ToolCall;
     use serde_json::json;
 
+    #[test]
+    fn test_summarization_splits_on_user_text() -> Result<()> {
+        let messages = vec![
+            user_text(0, 10).0,
+            assistant_tool_request("t1", ToolCall::new("read", json!({})), 10).0,
+            user_tool_response("t1", vec![Content::text("file")], 50).0,
+            assistant_text(1, 10).0,
+            user_text(2, 10).0,
+            assistant_tool_request("t2", ToolCall::new("read", json!({})), 10).0,
+            user_tool_response("t2", vec![Content::text("file")], 50).0,
+            assistant_text(3, 10).0,
+            user_text(4, 10).0,
+        ];
+        let token_counts: Vec<usize> = vec![10, 10, 50, 10, 10, 10, 50, 10, 10];
+
+        // Everything before the second user message fits after condensing the first exchange
+        let indices =
+            OldestFirstSummarization.determine_indices_to_remove(&messages, &token_counts, 100)?;
+        assert_eq!(indices, (0..4).collect());
+
+        // Nothing fits, so everything but the last user message is condensed
+        let indices =
+            OldestFirstSummarization.determine_indices_to_remove(&messages, &token_counts, 5)?;
+        assert_eq!(indices, (0..8).collect());
+
+        // Tool pairs are never split
+        for (id, request, response) in [("t1", 1, 2), ("t2", 5, 6)] {
+            assert!(messages[request].get_tool_ids().contains(id));
+            assert_eq!(indices.contains(&request), indices.contains(&response));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_summarization_without_split_point() -> Result<()> {
+        let messages = vec![
+            user_text(0, 10).0,
+            assistant_tool_request("t1", ToolCall::new("read", json!({})), 10).0,
+            user_tool_response("t1", vec![Content::text("file")], 50).0,
+        ];
+        let indices =
+            OldestFirstSummarization.determine_indices_to_remove(&messages, &[10, 10, 50], 20)?;
+        assert!(indices.is_empty());
+        Ok(())
+    }
+
     // Helper function to create a user text message with a specified token count
     fn user_text(index: usize, tokens: usize) -> (Message, usize) {
         let content = format!("User message {}", index);
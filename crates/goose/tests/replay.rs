@@ -0,0 +1,53 @@
+use anyhow::Result;
+use futures::StreamExt;
+use goose::agents::{AgentEvent, AgentFactory};
+use goose::message::Message;
+use goose::model::ModelConfig;
+use goose::providers::replay::{ReplayMode, ReplayProvider};
+use std::path::Path;
+
+const FIXTURE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/replay_session.jsonl"
+);
+
+async fn reply(mode: ReplayMode) -> Result<Vec<Message>> {
+    let provider = ReplayProvider::new(
+        Path::new(FIXTURE),
+        mode,
+        ModelConfig::new("gpt-4o".to_string()),
+    )?;
+    let agent = AgentFactory::create("truncate", Box::new(provider)).unwrap();
+
+    let messages = vec![Message::user().with_text("hi there. what is 2 + 2?")];
+    let reply_stream = agent.reply(&messages).await?;
+    tokio::pin!(reply_stream);
+
+    let mut responses = Vec::new();
+    while let Some(event) = reply_stream.next().await {
+        if let AgentEvent::Message(message) = event? {
+            responses.push(message);
+        }
+    }
+    Ok(responses)
+}
+
+#[tokio::test]
+async fn test_agent_replays_recorded_session() -> Result<()> {
+    // The agent's system prompt differs from the recorded one, which fuzzy matching ignores
+    let responses = reply(ReplayMode::Fuzzy).await?;
+    assert_eq!(responses.len(), 1);
+    assert_eq!(responses[0].as_concat_text(), "2 + 2 is 4.");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_strict_replay_rejects_changed_requests() -> Result<()> {
+    // The agent reports the missing recording as an error message
+    let responses = reply(ReplayMode::Strict).await?;
+    assert_eq!(responses.len(), 1);
+    assert!(responses[0]
+        .as_concat_text()
+        .contains("No recorded response"));
+    Ok(())
+}
@@ -93,6 +93,9 @@ pub trait McpClientTrait: Send + Sync {
     async fn list_tools(&self, next_cursor: Option<String>) -> Result<ListToolsResult, Error>;
 
     async fn call_tool(&self, name: &str, arguments: Value) -> Result<CallToolResult, Error>;
+
+    /// Send a request for any method, such as prompts or completions that have no typed call
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Error>;
 }
 
 /// The MCP client is the interface for MCP operations.
@@ -346,4 +349,11 @@ where
         // https://modelcontextprotocol.io/docs/concepts/tools#error-handling-2
         self.send_request("tools/call", params).await
     }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Error> {
+        if !self.completed_initialization() {
+            return Err(Error::NotInitialized);
+        }
+        self.send_request(method, params).await
+    }
 }
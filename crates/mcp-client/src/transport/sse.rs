@@ -1,7 +1,6 @@
 use crate::transport::{Error, PendingRequests, TransportMessage};
 use async_trait::async_trait;
-use eventsource_client::{Client, SSE};
-use futures::TryStreamExt;
+use futures::StreamExt;
 use mcp_core::protocol::{JsonRpcMessage, JsonRpcRequest};
 use reqwest::Client as HttpClient;
 use std::collections::HashMap;
@@ -15,6 +14,69 @@ use super::{send_message, Transport, TransportHandle};
 
 // Timeout for the endpoint discovery
 const ENDPOINT_TIMEOUT_SECS: u64 = 5;
+// Reconnects to a dropped stream back off from the initial delay up to the max delay,
+// and give up after this many attempts in a row
+const RECONNECT_INITIAL_DELAY_MS: u64 = 500;
+const RECONNECT_MAX_DELAY_MS: u64 = 30_000;
+const RECONNECT_MAX_ATTEMPTS: u32 = 5;
+
+/// A server-sent event, with the fields the MCP SSE protocol uses
+#[derive(Debug, Clone, PartialEq)]
+struct SseEvent {
+    event_type: String,
+    data: String,
+}
+
+/// Parses server-sent events out of a response body, which can arrive split at any byte
+#[derive(Default)]
+struct SseParser {
+    buffer: Vec<u8>,
+    event_type: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    /// Add a chunk of the body, returning the events it completes
+    fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            // A blank line dispatches the event, lines starting with a colon are comments
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(SseEvent {
+                        event_type: self
+                            .event_type
+                            .take()
+                            .unwrap_or_else(|| "message".to_string()),
+                        data: self.data.join("\n"),
+                    });
+                }
+                self.event_type = None;
+                self.data.clear();
+                continue;
+            }
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "event" => self.event_type = Some(value.to_string()),
+                "data" => self.data.push(value.to_string()),
+                _ => {}
+            }
+        }
+        events
+    }
+}
+
+/// How long to wait before the given reconnect attempt, doubling from the initial delay
+fn reconnect_delay(attempt: u32) -> Duration {
+    let delay = RECONNECT_INITIAL_DELAY_MS.saturating_mul(1 << attempt.saturating_sub(1).min(16));
+    Duration::from_millis(delay.min(RECONNECT_MAX_DELAY_MS))
+}
 
 /// The SSE-based actor that continuously:
 /// - Reads incoming events from the SSE stream.
@@ -26,7 +88,7 @@ pub struct SseActor {
     pending_requests: Arc<PendingRequests>,
     /// Base SSE URL
     sse_url: String,
-    /// For sending HTTP POST requests
+    /// For reading the SSE stream and sending HTTP POST requests
     http_client: HttpClient,
     /// The discovered endpoint for POST requests (once "endpoint" SSE event arrives)
     post_endpoint: Arc<RwLock<Option<String>>>,
@@ -38,13 +100,14 @@ impl SseActor {
         pending_requests: Arc<PendingRequests>,
         sse_url: String,
         post_endpoint: Arc<RwLock<Option<String>>>,
+        http_client: HttpClient,
     ) -> Self {
         Self {
             receiver,
             pending_requests,
             sse_url,
             post_endpoint,
-            http_client: HttpClient::new(),
+            http_client,
         }
     }
 
@@ -54,6 +117,7 @@ impl SseActor {
     pub async fn run(self) {
         tokio::join!(
             Self::handle_incoming_messages(
+                self.http_client.clone(),
                 self.sse_url.clone(),
                 Arc::clone(&self.pending_requests),
                 Arc::clone(&self.post_endpoint)
@@ -71,66 +135,99 @@ impl SseActor {
     /// - If an `endpoint` event is received, store it in `post_endpoint`.
     /// - If a `message` event is received, parse it as `JsonRpcMessage`
     ///   and respond to pending requests if it's a `Response`.
+    ///
+    /// When a stream that was connected ends or fails, it reconnects with backoff,
+    /// and the server announces the endpoint again on the new stream.
     async fn handle_incoming_messages(
+        http_client: HttpClient,
         sse_url: String,
         pending_requests: Arc<PendingRequests>,
         post_endpoint: Arc<RwLock<Option<String>>>,
     ) {
-        let client = match eventsource_client::ClientBuilder::for_url(&sse_url) {
-            Ok(builder) => builder.build(),
-            Err(e) => {
-                pending_requests.clear().await;
-                warn!("Failed to connect SSE client: {}", e);
-                return;
-            }
-        };
-        let mut stream = client.stream();
-
-        // First, wait for the "endpoint" event
-        while let Ok(Some(event)) = stream.try_next().await {
-            match event {
-                SSE::Event(e) if e.event_type == "endpoint" => {
-                    // SSE server uses the "endpoint" event to tell us the POST URL
-                    let base_url = Url::parse(&sse_url).expect("Invalid base URL");
-                    let post_url = base_url
-                        .join(&e.data)
-                        .expect("Failed to resolve endpoint URL");
-
-                    println!("Discovered SSE POST endpoint: {}", post_url);
-                    *post_endpoint.write().await = Some(post_url.to_string());
-                    break;
+        let mut connected = false;
+        let mut attempts = 0;
+        loop {
+            let result = match http_client
+                .get(&sse_url)
+                .header("Accept", "text/event-stream")
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+            {
+                Ok(response) => {
+                    connected = true;
+                    attempts = 0;
+                    Self::read_events(response, &sse_url, &pending_requests, &post_endpoint).await
                 }
-                _ => continue,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => warn!("SSE stream closed by the server"),
+                Err(e) => warn!("SSE stream failed: {}", e),
+            }
+
+            // The responses to the requests in flight were lost with the stream,
+            // and messages can't be posted until the server announces the endpoint again
+            *post_endpoint.write().await = None;
+            pending_requests.clear().await;
+
+            // A server that never accepted the stream isn't retried, the transport fails to start
+            attempts += 1;
+            if !connected || attempts > RECONNECT_MAX_ATTEMPTS {
+                warn!("Giving up on the SSE stream after {} attempts", attempts);
+                return;
             }
+            tokio::time::sleep(reconnect_delay(attempts)).await;
         }
+    }
+
+    /// Reads the events of a stream until it ends
+    async fn read_events(
+        response: reqwest::Response,
+        sse_url: &str,
+        pending_requests: &PendingRequests,
+        post_endpoint: &RwLock<Option<String>>,
+    ) -> Result<(), reqwest::Error> {
+        let mut body = response.bytes_stream();
+        let mut parser = SseParser::default();
+
+        while let Some(chunk) = body.next().await {
+            for event in parser.push(&chunk?) {
+                match event.event_type.as_str() {
+                    "endpoint" => {
+                        // SSE server uses the "endpoint" event to tell us the POST URL
+                        let base_url = Url::parse(sse_url).expect("Invalid base URL");
+                        let post_url = base_url
+                            .join(&event.data)
+                            .expect("Failed to resolve endpoint URL");
 
-        // Now handle subsequent events
-        while let Ok(Some(event)) = stream.try_next().await {
-            match event {
-                SSE::Event(e) if e.event_type == "message" => {
-                    // Attempt to parse the SSE data as a JsonRpcMessage
-                    match serde_json::from_str::<JsonRpcMessage>(&e.data) {
-                        Ok(message) => {
-                            // If it's a response, complete the pending request
-                            if let JsonRpcMessage::Response(resp) = &message {
-                                if let Some(id) = &resp.id {
-                                    pending_requests.respond(&id.to_string(), Ok(message)).await;
+                        println!("Discovered SSE POST endpoint: {}", post_url);
+                        *post_endpoint.write().await = Some(post_url.to_string());
+                    }
+                    "message" => {
+                        // Attempt to parse the SSE data as a JsonRpcMessage
+                        match serde_json::from_str::<JsonRpcMessage>(&event.data) {
+                            Ok(message) => {
+                                // If it's a response, complete the pending request
+                                if let JsonRpcMessage::Response(resp) = &message {
+                                    if let Some(id) = &resp.id {
+                                        pending_requests
+                                            .respond(&id.to_string(), Ok(message))
+                                            .await;
+                                    }
                                 }
+                                // If it's something else (notification, etc.), handle as needed
+                            }
+                            Err(err) => {
+                                warn!("Failed to parse SSE message: {err}");
                             }
-                            // If it's something else (notification, etc.), handle as needed
-                        }
-                        Err(err) => {
-                            warn!("Failed to parse SSE message: {err}");
                         }
                     }
+                    _ => { /* ignore other events */ }
                 }
-                _ => { /* ignore other events */ }
             }
         }
-
-        // SSE stream ended or errored; signal any pending requests
-        eprintln!("SSE stream ended or encountered an error; clearing pending requests.");
-        pending_requests.clear().await;
+        Ok(())
     }
 
     /// Continuously receives messages from the `mpsc::Receiver`.
@@ -221,6 +318,7 @@ impl TransportHandle for SseTransportHandle {
 pub struct SseTransport {
     sse_url: String,
     env: HashMap<String, String>,
+    http_client: HttpClient,
 }
 
 /// The SSE transport spawns an `SseActor` on `start()`.
@@ -229,9 +327,17 @@ impl SseTransport {
         Self {
             sse_url: sse_url.into(),
             env,
+            http_client: HttpClient::new(),
         }
     }
 
+    /// Use this client for the SSE stream and the POST requests,
+    /// e.g. to go through a proxy or trust a custom CA
+    pub fn with_http_client(mut self, http_client: HttpClient) -> Self {
+        self.http_client = http_client;
+        self
+    }
+
     /// Waits for the endpoint to be set, up to 10 attempts.
     async fn wait_for_endpoint(
         post_endpoint: Arc<RwLock<Option<String>>>,
@@ -274,6 +380,7 @@ impl Transport for SseTransport {
             Arc::new(PendingRequests::new()),
             self.sse_url.clone(),
             post_endpoint,
+            self.http_client.clone(),
         );
 
         // Spawn the actor task
@@ -297,3 +404,112 @@ impl Transport for SseTransport {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn event(event_type: &str, data: &str) -> SseEvent {
+        SseEvent {
+            event_type: event_type.to_string(),
+            data: data.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parser_multiline_data() {
+        let mut parser = SseParser::default();
+        let events = parser.push(b"event: message\ndata: {\"a\":\ndata: 1}\n\n");
+        assert_eq!(events, vec![event("message", "{\"a\":\n1}")]);
+    }
+
+    #[test]
+    fn test_parser_crlf_and_comments() {
+        let mut parser = SseParser::default();
+        let events = parser.push(b": keep-alive\r\nevent: endpoint\r\ndata: /messages\r\n\r\n");
+        assert_eq!(events, vec![event("endpoint", "/messages")]);
+
+        // A comment on its own is not an event, and events default to `message`
+        assert!(parser.push(b": ping\n\n").is_empty());
+        assert_eq!(parser.push(b"data:1\n\n"), vec![event("message", "1")]);
+    }
+
+    #[test]
+    fn test_parser_split_across_chunks() {
+        let body = "event: endpoint\r\ndata: /messages?session=1\r\n\r\ndata: {\"id\":1}\n\n";
+        // Split at every byte, including between the CR and LF
+        let mut parser = SseParser::default();
+        let events: Vec<SseEvent> = body
+            .as_bytes()
+            .chunks(1)
+            .flat_map(|chunk| parser.push(chunk))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                event("endpoint", "/messages?session=1"),
+                event("message", "{\"id\":1}")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reconnect_delay() {
+        assert_eq!(reconnect_delay(1), Duration::from_millis(500));
+        assert_eq!(reconnect_delay(2), Duration::from_millis(1000));
+        assert_eq!(reconnect_delay(10), Duration::from_millis(30_000));
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_after_stream_ends() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let sse_url = format!("http://{}/sse", listener.local_addr().unwrap());
+        // Each connection announces its own endpoint, the first one is then closed
+        let server = tokio::spawn(async move {
+            for session in 1..=2 {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0u8; 1024];
+                let _ = socket.read(&mut request).await.unwrap();
+                let body = format!("event: endpoint\ndata: /messages?session={}\n\n", session);
+                // The body runs until the connection is closed
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\nconnection: close\r\n\r\n{}",
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+                if session == 2 {
+                    // Keep the second stream open until the test is done
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        });
+
+        let post_endpoint = Arc::new(RwLock::new(None));
+        let (_tx, rx) = mpsc::channel(1);
+        let actor = SseActor::new(
+            rx,
+            Arc::new(PendingRequests::new()),
+            sse_url.clone(),
+            Arc::clone(&post_endpoint),
+            HttpClient::new(),
+        );
+        tokio::spawn(actor.run());
+
+        let reconnected = timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(url) = post_endpoint.read().await.clone() {
+                    if url.ends_with("session=2") {
+                        return url;
+                    }
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("the stream was not reconnected");
+        assert!(reconnected.starts_with(sse_url.trim_end_matches("/sse")));
+        server.abort();
+    }
+}